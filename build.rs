@@ -0,0 +1,31 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ebpf")]
+    ebpf::build();
+}
+
+/// Compile the eBPF programs of the `ebpf` feature, which `bpf.rs` embeds
+#[cfg(feature = "ebpf")]
+mod ebpf {
+    use libbpf_cargo::SkeletonBuilder;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Programs in src/bpf, one object per kind of tracing so each loads on
+    /// its own
+    const PROGRAMS: [&str; 4] = ["files", "devices", "latency", "syncs"];
+
+    pub fn build() {
+        let out = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+        for name in PROGRAMS {
+            let source = format!("src/bpf/{}.bpf.c", name);
+            SkeletonBuilder::new()
+                .source(&source)
+                .obj(out.join(format!("{}.bpf.o", name)))
+                .build()
+                .unwrap_or_else(|e| panic!("Failed to compile {}: {:#}", source, e));
+            println!("cargo:rerun-if-changed={}", source);
+        }
+        println!("cargo:rerun-if-changed=src/bpf/iotop.h");
+    }
+}
@@ -5,7 +5,7 @@ _iotop() {
     COMPREPLY=()
     cur="${COMP_WORDS[COMP_CWORD]}"
     prev="${COMP_WORDS[COMP_CWORD-1]}"
-    opts="-o --only -P --processes -a --accumulated -d --delay -n --iter -b --batch -p --pid -u --user -t --time -q --quiet -k --kilobytes -h --help"
+    opts="-o --only --min-io --blocked --class --where -P --processes --group -a --accumulated --lifetime -d --delay -n --iter -b --batch -p --pid --children -u --user --uid-range -t --time -q --quiet -k --kilobytes --a11y --theme --spike-factor --hot-threads --columns --alert-read --alert-write --quotas --quota-alert --subsamples --rewind --filter --cgroup --unit --container --tty --session --no-kthreads --preset --highlight --devices --stats --trace-files --path --net --trace-devices --device --trace-syncs --latency --enable-delayacct --check -h --help"
 
     case "${prev}" in
         -d|--delay)
@@ -23,6 +23,26 @@ _iotop() {
             COMPREPLY=( $(compgen -W "$(ps -e -o pid= | tr '\n' ' ')" -- ${cur}) )
             return 0
             ;;
+        --group)
+            COMPREPLY=( $(compgen -W "pgid sid" -- ${cur}) )
+            return 0
+            ;;
+        --theme)
+            COMPREPLY=( $(compgen -W "dark light mono" -- ${cur}) )
+            return 0
+            ;;
+        --path)
+            COMPREPLY=( $(compgen -d -- ${cur}) )
+            return 0
+            ;;
+        --device)
+            COMPREPLY=( $(compgen -W "$(awk '{print $3}' /proc/diskstats)" -- ${cur}) )
+            return 0
+            ;;
+        --class)
+            COMPREPLY=( $(compgen -W "rt be idle" -- ${cur}) )
+            return 0
+            ;;
         -u|--user)
             # Complete with usernames
             COMPREPLY=( $(compgen -u -- ${cur}) )
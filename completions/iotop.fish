@@ -2,14 +2,53 @@
 
 # Options
 complete -c iotop -s o -l only -d 'Only show processes or threads actually doing I/O'
+complete -c iotop -l min-io -d 'Only show tasks doing at least this much I/O per second' -x
+complete -c iotop -l blocked -d 'Only show tasks in uninterruptible sleep'
+complete -c iotop -l class -d 'Only show tasks in an I/O priority class' -x -a 'rt be idle'
+complete -c iotop -l where -d 'Only show tasks matching an expression' -x
 complete -c iotop -s P -l processes -d 'Show processes, not all threads'
+complete -c iotop -l group -d 'Show a row per process group or session' -x -a 'pgid sid'
 complete -c iotop -s a -l accumulated -d 'Show accumulated I/O instead of bandwidth'
+complete -c iotop -l lifetime -d 'Start accumulated totals from the I/O since each task started'
 complete -c iotop -s d -l delay -d 'Delay between iterations in seconds' -x -a '0.5 1 2 5 10'
 complete -c iotop -s n -l iter -d 'Number of iterations before ending' -x -a '5 10 20 50 100'
 complete -c iotop -s b -l batch -d 'Batch mode (non-interactive)'
 complete -c iotop -s p -l pid -d 'Processes/threads to monitor' -x -a '(__fish_complete_pids)'
+complete -c iotop -l children -d 'Also monitor the descendants of the -p processes'
 complete -c iotop -s u -l user -d 'Users to monitor' -x -a '(__fish_complete_users)'
+complete -c iotop -l uid-range -d 'Only monitor users with a UID in a range' -x
 complete -c iotop -s t -l time -d 'Add timestamp on each line (implies --batch)'
 complete -c iotop -s q -l quiet -d 'Suppress column names and headers (implies --batch)'
 complete -c iotop -s k -l kilobytes -d 'Use kilobytes instead of human-friendly units'
+complete -c iotop -l a11y -d 'Screen-reader friendly mode'
+complete -c iotop -l theme -d 'Colors of the interactive display' -x -a 'dark light mono'
+complete -c iotop -l spike-factor -d 'Highlight rows whose I/O rate grew by this factor' -x -a '2 4 8'
+complete -c iotop -l hot-threads -d 'With -P, show threads doing more than this percentage of the I/O' -x -a '0 25 50 75'
+complete -c iotop -l columns -d 'Optional columns to show' -x -a 'rchar wchar cancelled writeback majflt minflt iowaits swapins ioavg cpudelay reclaim thrashing compact wpcopy cpu mem swap oom fds age tty unit container nspid ioweight mount device fsync netread netwrite graph'
+complete -c iotop -l alert-read -d 'Log rows whose read rate exceeds this' -x
+complete -c iotop -l alert-write -d 'Log rows whose write rate exceeds this' -x
+complete -c iotop -l quotas -d 'Watch per-user I/O against the budgets in quotas.toml'
+complete -c iotop -l quota-alert -d 'Log users going over their I/O budget'
+complete -c iotop -l subsamples -d 'Samples per delay, averaged over the delay' -x -a '2 4 10'
+complete -c iotop -l rewind -d 'Number of past intervals kept for scrubbing' -x -a '30 60 300'
+complete -c iotop -l filter -d 'Only monitor commands matching a regex' -x
+complete -c iotop -l cgroup -d 'Only monitor processes in a cgroup subtree' -x
+complete -c iotop -l unit -d 'Only monitor the processes of a systemd unit' -x
+complete -c iotop -l container -d 'Only monitor the processes of a container' -x
+complete -c iotop -l tty -d 'Only monitor processes on a terminal' -x
+complete -c iotop -l session -d 'Only monitor the processes of a session' -x
+complete -c iotop -l no-kthreads -d 'Leave kernel threads out'
+complete -c iotop -l preset -d 'Apply the filters of a preset' -x
+complete -c iotop -l highlight -d 'Highlight commands matching a regex' -x
+complete -c iotop -l devices -d 'Show per-device throughput, IOPS and utilization'
+complete -c iotop -l stats -d 'Show the CPU time, taskstats queries and reads iotop itself used'
+complete -c iotop -l trace-files -d 'Trace the files each process reads and writes with eBPF'
+complete -c iotop -l path -d 'Only show processes doing I/O to files under a directory' -x -a '(__fish_complete_directories)'
+complete -c iotop -l net -d 'Count the network traffic of each process with eBPF'
+complete -c iotop -l trace-devices -d 'Show the block devices each process uses with eBPF'
+complete -c iotop -l device -d 'Only show processes doing I/O to a device' -x -a "(awk '{print \$3}' /proc/diskstats)"
+complete -c iotop -l trace-syncs -d 'Count the fsync calls of each process with eBPF'
+complete -c iotop -l latency -d 'Time the block I/O of each process with eBPF'
+complete -c iotop -l enable-delayacct -d 'Turn on delay accounting while iotop runs'
+complete -c iotop -l check -d 'Report the kernel features iotop can use and exit'
 complete -c iotop -s h -l help -d 'Show help information'
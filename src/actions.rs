@@ -0,0 +1,80 @@
+use anyhow::Result;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::collections::HashSet;
+
+use crate::ioprio::{self, Ioprio};
+use crate::proc_reader;
+
+/// Send `sig` to a process (thread group)
+pub fn send_signal(pid: i32, sig: Signal) -> Result<()> {
+    signal::kill(Pid::from_raw(pid), sig)
+        .map_err(|e| anyhow::anyhow!("Failed to send {} to PID {}: {}", sig, pid, e))
+}
+
+/// iotop and its ancestors, up to init: the shell and terminal it runs
+/// under, which must not be stopped from the TUI
+pub fn own_lineage() -> HashSet<i32> {
+    let mut pids = HashSet::new();
+    let mut pid = std::process::id() as i32;
+    while pid > 0 && pids.insert(pid) {
+        pid = proc_reader::parent(pid).unwrap_or(0);
+    }
+    pids
+}
+
+/// Set the nice value of a single thread
+pub fn set_nice(tid: i32, nice: i32) -> Result<()> {
+    // On Linux PRIO_PROCESS with a TID targets just that thread
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) };
+
+    if result < 0 {
+        anyhow::bail!(
+            "Failed to set nice for TID {}: {}",
+            tid,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    Ok(())
+}
+
+/// Parse a nice value as typed into the TUI prompt
+pub fn parse_nice(input: &str) -> Result<i32> {
+    let nice: i32 = input
+        .trim()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid nice value: {}", input.trim()))?;
+
+    if !(-20..=19).contains(&nice) {
+        anyhow::bail!("Nice value must be -20..19, got {}", nice);
+    }
+
+    Ok(nice)
+}
+
+/// Set the I/O priority of a single thread
+pub fn set_ionice(tid: i32, prio: Ioprio) -> Result<()> {
+    ioprio::set_ioprio(tid, prio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nice() {
+        assert_eq!(parse_nice("0").unwrap(), 0);
+        assert_eq!(parse_nice(" -20 ").unwrap(), -20);
+        assert_eq!(parse_nice("19").unwrap(), 19);
+        assert!(parse_nice("20").is_err());
+        assert!(parse_nice("high").is_err());
+    }
+
+    #[test]
+    fn test_own_lineage() {
+        let lineage = own_lineage();
+        assert!(lineage.contains(&(std::process::id() as i32)));
+        assert!(lineage.contains(&(std::os::unix::process::parent_id() as i32)));
+    }
+}
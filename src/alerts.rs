@@ -0,0 +1,291 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::process::ProcessSnapshot;
+use crate::quota::UserUsage;
+use crate::ui::human_size;
+
+/// Number of alerts kept in the log; older ones are dropped
+const MAX_ALERTS: usize = 100;
+
+/// Rates (bytes per second) above which a row is recorded in the alert log
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlertThresholds {
+    pub read: Option<u64>,
+    pub write: Option<u64>,
+}
+
+impl AlertThresholds {
+    pub fn is_enabled(&self) -> bool {
+        self.read.is_some() || self.write.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Read,
+    Write,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Read => "read",
+            Direction::Write => "write",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub time: String,
+    pub message: String,
+}
+
+/// Log of threshold crossings, so short-lived offenders are not lost when the
+/// table refreshes
+///
+/// A row is logged when its rate rises above a threshold, and again only after
+/// it has dropped back below it.
+#[derive(Default)]
+pub struct AlertLog {
+    entries: VecDeque<Alert>,
+    over: HashSet<(i32, Direction)>,
+    /// Users over their `--quotas` budget at the last check
+    over_quota: HashSet<u32>,
+}
+
+impl AlertLog {
+    /// Check a snapshot against the thresholds and return the new alerts
+    pub fn check(
+        &mut self,
+        snapshot: &ProcessSnapshot,
+        thresholds: &AlertThresholds,
+        show_processes: bool,
+    ) -> Vec<Alert> {
+        if !thresholds.is_enabled() || snapshot.duration <= 0.0 {
+            return Vec::new();
+        }
+
+        let time = chrono::Local::now().format("%H:%M:%S").to_string();
+        let mut over = HashSet::new();
+        let mut new_alerts = Vec::new();
+
+        let mut ids: Vec<&i32> = snapshot.processes.keys().collect();
+        ids.sort();
+        for id in ids {
+            let process = &snapshot.processes[id];
+            let stats = &process.stats_delta;
+            let rates = [
+                (Direction::Read, thresholds.read, stats.read_bytes),
+                (
+                    Direction::Write,
+                    thresholds.write,
+                    stats
+                        .write_bytes
+                        .saturating_sub(stats.cancelled_write_bytes),
+                ),
+            ];
+
+            for (direction, threshold, bytes) in rates {
+                let Some(threshold) = threshold else {
+                    continue;
+                };
+                let rate = (bytes as f64 / snapshot.duration) as u64;
+                if rate <= threshold {
+                    continue;
+                }
+
+                over.insert((*id, direction));
+                if self.over.contains(&(*id, direction)) {
+                    continue;
+                }
+
+                let command = process
+                    .cmdline
+                    .as_deref()
+                    .and_then(|cmd| cmd.split_whitespace().next())
+                    .map(|cmd| cmd.rsplit('/').next().unwrap_or(cmd))
+                    .unwrap_or("?");
+                new_alerts.push(Alert {
+                    time: time.clone(),
+                    message: format!(
+                        "{} {} ({}) exceeded {}/s {}: {}/s",
+                        if show_processes { "PID" } else { "TID" },
+                        id,
+                        command,
+                        human_size(threshold as i64),
+                        direction.as_str(),
+                        human_size(rate as i64)
+                    ),
+                });
+            }
+        }
+
+        self.over = over;
+        self.record(&new_alerts);
+        new_alerts
+    }
+
+    /// Check per-user I/O against the users' budgets and return the new
+    /// alerts, logging each user again only once back within budget
+    pub fn check_quotas(&mut self, usage: &[UserUsage]) -> Vec<Alert> {
+        let time = chrono::Local::now().format("%H:%M:%S").to_string();
+        let mut new_alerts = Vec::new();
+        let mut over = HashSet::new();
+        for user in usage.iter().filter(|user| user.is_over()) {
+            over.insert(user.uid);
+            if self.over_quota.contains(&user.uid) {
+                continue;
+            }
+            new_alerts.push(Alert {
+                time: time.clone(),
+                message: format!(
+                    "User {} exceeded quota of {}/s: {}/s",
+                    user.user,
+                    human_size(user.budget.unwrap_or_default() as i64),
+                    human_size(user.rate as i64)
+                ),
+            });
+        }
+
+        self.over_quota = over;
+        self.record(&new_alerts);
+        new_alerts
+    }
+
+    fn record(&mut self, alerts: &[Alert]) {
+        for alert in alerts {
+            if self.entries.len() == MAX_ALERTS {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(alert.clone());
+        }
+    }
+
+    /// Alerts, newest first
+    pub fn entries(&self) -> impl Iterator<Item = &Alert> {
+        self.entries.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Parse a rate such as `200M`, `512k` or `1048576` into bytes per second
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("/s")
+        .unwrap_or(value)
+        .trim_end_matches(['B', 'b']);
+    let (number, multiplier) = match value.chars().last() {
+        Some('k' | 'K') => (&value[..value.len() - 1], 1u64 << 10),
+        Some('m' | 'M') => (&value[..value.len() - 1], 1 << 20),
+        Some('g' | 'G') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rate: {}", value))?;
+    if number < 0.0 {
+        return Err(format!("invalid rate: {}", value));
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::ProcessInfo;
+    use std::collections::HashMap;
+
+    fn snapshot(write_bytes: u64) -> ProcessSnapshot {
+        let mut process = ProcessInfo::new(4213);
+        process.cmdline = Some("/usr/bin/dd if=/dev/zero".to_string());
+        process.stats_delta.write_bytes = write_bytes;
+        ProcessSnapshot {
+            processes: HashMap::from([(4213, process)]),
+            total_io: (0, write_bytes),
+            actual_io: (0, write_bytes),
+            duration: 1.0,
+            devices: Vec::new(),
+            cgroups: Vec::new(),
+            throttled: HashSet::new(),
+            nfs_mounts: Vec::new(),
+            pressure: None,
+            dirty: None,
+            swap: None,
+            iowait: Default::default(),
+            load: None,
+            uninterruptible: 0,
+            files: HashMap::new(),
+            net: HashMap::new(),
+            device_io: HashMap::new(),
+            syncs: HashMap::new(),
+            latency: HashMap::new(),
+            writeback: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("1048576"), Ok(1 << 20));
+        assert_eq!(parse_rate("200M"), Ok(200 << 20));
+        assert_eq!(parse_rate("512k"), Ok(512 << 10));
+        assert_eq!(parse_rate("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_rate("10MB/s"), Ok(10 << 20));
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("-1M").is_err());
+    }
+
+    #[test]
+    fn test_alert_on_crossing() {
+        let thresholds = AlertThresholds {
+            read: None,
+            write: Some(200 << 20),
+        };
+        let mut log = AlertLog::default();
+
+        let alerts = log.check(&snapshot(300 << 20), &thresholds, true);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].message,
+            "PID 4213 (dd) exceeded 200.0 M/s write: 300.0 M/s"
+        );
+
+        // Still above the threshold: not logged again
+        assert!(log
+            .check(&snapshot(300 << 20), &thresholds, true)
+            .is_empty());
+        // Dropped below, then crossed again
+        assert!(log.check(&snapshot(1 << 20), &thresholds, true).is_empty());
+        assert_eq!(log.check(&snapshot(300 << 20), &thresholds, true).len(), 1);
+        assert_eq!(log.len(), 2);
+    }
+
+    #[test]
+    fn test_quota_alerts() {
+        let usage = |rate: u64| {
+            vec![UserUsage {
+                uid: 1000,
+                user: "alice".to_string(),
+                rate,
+                budget: Some(200 << 20),
+            }]
+        };
+        let mut log = AlertLog::default();
+
+        let alerts = log.check_quotas(&usage(300 << 20));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(
+            alerts[0].message,
+            "User alice exceeded quota of 200.0 M/s: 300.0 M/s"
+        );
+        assert!(log.check_quotas(&usage(300 << 20)).is_empty());
+        assert!(log.check_quotas(&usage(100 << 20)).is_empty());
+        assert_eq!(log.check_quotas(&usage(300 << 20)).len(), 1);
+    }
+}
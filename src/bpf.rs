@@ -0,0 +1,405 @@
+use anyhow::{Context, Result};
+use libbpf_rs::{Link, Map, MapFlags, Object, ObjectBuilder};
+use std::fs;
+use std::mem;
+
+use crate::devtrace::DeviceTotal;
+use crate::filetrace::FileTotals;
+use crate::latency::LatencyTotal;
+use crate::synctrace::SyncTotal;
+
+// Programs compiled from src/bpf by build.rs, one object per kind of tracing
+const FILES_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/files.bpf.o"));
+const DEVICES_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/devices.bpf.o"));
+const SYNCS_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/syncs.bpf.o"));
+const LATENCY_OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/latency.bpf.o"));
+
+/// Programs of files.bpf.c with the kernel function each one hooks
+const FILE_PROGRAMS: [(&str, &str); 8] = [
+    ("vfs_read_exit", "vfs_read"),
+    ("vfs_write_exit", "vfs_write"),
+    ("vfs_readv_exit", "vfs_readv"),
+    ("vfs_writev_exit", "vfs_writev"),
+    ("inet_recvmsg_exit", "inet_recvmsg"),
+    ("inet_sendmsg_exit", "inet_sendmsg"),
+    ("inet6_recvmsg_exit", "inet6_recvmsg"),
+    ("inet6_sendmsg_exit", "inet6_sendmsg"),
+];
+
+/// Functions of [`FILE_PROGRAMS`] that not every kernel has: the vectored
+/// ones are static and may be inlined, and the IPv6 ones are missing without
+/// IPv6 or until its module is loaded
+const OPTIONAL_FUNCTIONS: [&str; 4] = ["vfs_readv", "vfs_writev", "inet6_recvmsg", "inet6_sendmsg"];
+
+/// Plain data that can be copied to and from the bytes of a map entry
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]` without padding, with every bit
+/// pattern valid, and laid out as in the BPF programs.
+unsafe trait Pod: Copy {}
+
+unsafe impl Pod for u32 {}
+unsafe impl Pod for u64 {}
+
+fn as_bytes<T: Pod>(value: &T) -> &[u8] {
+    // SAFETY: Pod types have no padding, so all their bytes are initialized
+    unsafe { std::slice::from_raw_parts((value as *const T).cast(), mem::size_of::<T>()) }
+}
+
+fn from_bytes<T: Pod>(bytes: &[u8]) -> Option<T> {
+    if bytes.len() != mem::size_of::<T>() {
+        return None;
+    }
+    // SAFETY: the size matches and every bit pattern is a valid Pod value
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast()) })
+}
+
+/// Open one of the compiled objects and load it without the programs in
+/// `skip`, then attach all the others
+fn load(object: &[u8], programs: &[&str], skip: &[&str]) -> Result<(Object, Vec<Link>)> {
+    let mut open = ObjectBuilder::default()
+        .open_memory(object)
+        .context("Failed to open BPF object")?;
+    for &name in skip {
+        if let Some(program) = open.prog_mut(name) {
+            program.set_autoload(false)?;
+        }
+    }
+    let mut object = open.load().context(
+        "Failed to load BPF programs; does the kernel have BTF (CONFIG_DEBUG_INFO_BTF)?",
+    )?;
+
+    let mut links = Vec::new();
+    for name in programs.iter().filter(|name| !skip.contains(name)) {
+        let program = object
+            .prog_mut(name)
+            .with_context(|| format!("BPF program {} not found", name))?;
+        links.push(
+            program
+                .attach()
+                .with_context(|| format!("Failed to attach BPF program {}", name))?,
+        );
+    }
+    Ok((object, links))
+}
+
+fn map<'a>(object: &'a Object, name: &str) -> Result<&'a Map> {
+    object
+        .map(name)
+        .with_context(|| format!("BPF map {} not found", name))
+}
+
+/// Every entry of a hash map, skipping those deleted while iterating
+fn map_entries<K: Pod, V: Pod>(map: &Map) -> Result<Vec<(K, V)>> {
+    let mut entries = Vec::new();
+    for key in map.keys() {
+        // Deleted since it was listed
+        let Some(value) = map
+            .lookup(&key, MapFlags::ANY)
+            .context("Failed to read BPF map")?
+        else {
+            continue;
+        };
+        if let (Some(key), Some(value)) = (from_bytes(&key), from_bytes(&value)) {
+            entries.push((key, value));
+        }
+    }
+    Ok(entries)
+}
+
+fn map_delete<K: Pod>(map: &Map, key: &K) {
+    let _ = map.delete(as_bytes(key));
+}
+
+/// Whether the kernel has a function `name`, from /proc/kallsyms content
+fn has_function(kallsyms: &str, name: &str) -> bool {
+    kallsyms
+        .lines()
+        .any(|line| line.split_whitespace().nth(2) == Some(name))
+}
+
+/// Key of the file totals map
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct FileKey {
+    tgid: u32,
+    /// Kernel encoding of the device number: major << 20 | minor
+    dev: u32,
+    ino: u64,
+}
+
+unsafe impl Pod for FileKey {}
+
+/// Length of the file names kept by the programs, with the NUL
+const NAME_LEN: usize = 32;
+
+/// Value of the file totals map
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct FileBytes {
+    read: u64,
+    write: u64,
+    /// Open flags
+    flags: u32,
+    /// Set for sockets counted in the inet handlers
+    net: u32,
+    /// Last path component, NUL-terminated
+    name: [u8; NAME_LEN],
+}
+
+unsafe impl Pod for FileBytes {}
+
+/// eBPF programs counting the bytes each process reads and writes through
+/// each file it has open
+///
+/// They hook the return of `vfs_read()` and `vfs_write()`, and of the
+/// vectored variants where the kernel has them, so only I/O that succeeded
+/// counts. Socket traffic is counted in the IPv4 and IPv6 `sendmsg` and
+/// `recvmsg` handlers, which every send and receive call reaches, and
+/// flagged as network traffic. Counts are kept by device and inode, with
+/// the open flags and the file name.
+/// Everything is detached when this is dropped.
+pub struct FileProbes {
+    object: Object,
+    // Kept for as long as the programs run
+    _links: Vec<Link>,
+}
+
+impl FileProbes {
+    pub fn attach() -> Result<Self> {
+        let kallsyms = fs::read_to_string("/proc/kallsyms").unwrap_or_default();
+        let skip: Vec<&str> = FILE_PROGRAMS
+            .iter()
+            .filter(|(_, function)| {
+                OPTIONAL_FUNCTIONS.contains(function) && !has_function(&kallsyms, function)
+            })
+            .map(|&(program, _)| program)
+            .collect();
+        let programs: Vec<&str> = FILE_PROGRAMS.iter().map(|&(program, _)| program).collect();
+        let (object, links) = load(FILES_OBJECT, &programs, &skip)?;
+        Ok(Self {
+            object,
+            _links: links,
+        })
+    }
+
+    /// Bytes counted so far for every file
+    pub fn totals(&self) -> Result<Vec<FileTotals>> {
+        let entries: Vec<(FileKey, FileBytes)> = map_entries(map(&self.object, "totals")?)?;
+        Ok(entries
+            .into_iter()
+            .map(|(key, bytes)| {
+                let end = bytes.name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+                FileTotals {
+                    tgid: key.tgid,
+                    dev: key.dev,
+                    ino: key.ino,
+                    name: String::from_utf8_lossy(&bytes.name[..end]).into_owned(),
+                    direct: bytes.flags & libc::O_DIRECT as u32 != 0,
+                    net: bytes.net != 0,
+                    read: bytes.read,
+                    write: bytes.write,
+                }
+            })
+            .collect())
+    }
+
+    /// Drop the entry of a file whose bytes were accounted for good
+    pub fn forget(&self, total: &FileTotals) {
+        let key = FileKey {
+            tgid: total.tgid,
+            dev: total.dev,
+            ino: total.ino,
+        };
+        if let Ok(totals) = map(&self.object, "totals") {
+            map_delete(totals, &key);
+        }
+    }
+}
+
+/// Key of the device totals map
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct DeviceKey {
+    tgid: u32,
+    /// Kernel encoding of the device number: major << 20 | minor
+    dev: u32,
+}
+
+unsafe impl Pod for DeviceKey {}
+
+/// eBPF program counting the bytes each process submits to each block
+/// device
+///
+/// It hooks `block:block_bio_queue`, which runs in the context of the task
+/// submitting the I/O, unlike the request issue and completion tracepoints
+/// that often fire in kworkers or interrupts. Background writeback of dirty
+/// pages is still charged to the flusher threads doing it.
+/// Everything is detached when this is dropped.
+pub struct DeviceProbes {
+    object: Object,
+    // Kept for as long as the program runs
+    _links: Vec<Link>,
+}
+
+impl DeviceProbes {
+    pub fn attach() -> Result<Self> {
+        let (object, links) = load(DEVICES_OBJECT, &["bio_queue"], &[])?;
+        Ok(Self {
+            object,
+            _links: links,
+        })
+    }
+
+    /// Bytes counted so far for every process and device
+    pub fn totals(&self) -> Result<Vec<DeviceTotal>> {
+        let entries: Vec<(DeviceKey, u64)> = map_entries(map(&self.object, "totals")?)?;
+        Ok(entries
+            .into_iter()
+            .map(|(key, bytes)| DeviceTotal {
+                tgid: key.tgid,
+                dev: key.dev,
+                bytes,
+            })
+            .collect())
+    }
+
+    /// Drop the entry of a process that exited
+    pub fn forget(&self, total: &DeviceTotal) {
+        let key = DeviceKey {
+            tgid: total.tgid,
+            dev: total.dev,
+        };
+        if let Ok(totals) = map(&self.object, "totals") {
+            map_delete(totals, &key);
+        }
+    }
+}
+
+/// eBPF program counting the `fsync` and `fdatasync` calls of each process
+///
+/// It hooks the entry tracepoints of both syscalls, so calls still blocked
+/// flushing count already. Everything is detached when this is dropped.
+pub struct SyncProbes {
+    object: Object,
+    // Kept for as long as the programs run
+    _links: Vec<Link>,
+}
+
+impl SyncProbes {
+    pub fn attach() -> Result<Self> {
+        let (object, links) = load(SYNCS_OBJECT, &["fsync_enter", "fdatasync_enter"], &[])?;
+        Ok(Self {
+            object,
+            _links: links,
+        })
+    }
+
+    /// Calls counted so far for every process
+    pub fn totals(&self) -> Result<Vec<SyncTotal>> {
+        let entries: Vec<(u32, u64)> = map_entries(map(&self.object, "totals")?)?;
+        Ok(entries
+            .into_iter()
+            .map(|(tgid, calls)| SyncTotal { tgid, calls })
+            .collect())
+    }
+
+    /// Drop the entry of a process that exited
+    pub fn forget(&self, total: &SyncTotal) {
+        if let Ok(totals) = map(&self.object, "totals") {
+            map_delete(totals, &total.tgid);
+        }
+    }
+}
+
+/// Key of the latency histograms map
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct BucketKey {
+    tgid: u32,
+    bucket: u32,
+}
+
+unsafe impl Pod for BucketKey {}
+
+/// eBPF programs timing each process's block I/O from queueing to
+/// completion, biolatency-style
+///
+/// `block:block_bio_queue` runs in the submitting task and records the time
+/// under the disk and starting sector; `block:block_rq_complete` looks them
+/// up again and counts the latency in a histogram of log2 microsecond buckets.
+/// Everything is detached when this is dropped.
+pub struct LatencyProbes {
+    object: Object,
+    // Kept for as long as the programs run
+    _links: Vec<Link>,
+}
+
+impl LatencyProbes {
+    pub fn attach() -> Result<Self> {
+        // Completion first, so no I/O is recorded that cannot complete
+        let (object, links) = load(LATENCY_OBJECT, &["rq_complete", "bio_queue"], &[])?;
+        Ok(Self {
+            object,
+            _links: links,
+        })
+    }
+
+    /// I/Os counted so far in every bucket of every process
+    pub fn totals(&self) -> Result<Vec<LatencyTotal>> {
+        let entries: Vec<(BucketKey, u64)> = map_entries(map(&self.object, "histograms")?)?;
+        Ok(entries
+            .into_iter()
+            .map(|(key, count)| LatencyTotal {
+                tgid: key.tgid,
+                bucket: key.bucket as usize,
+                count,
+            })
+            .collect())
+    }
+
+    /// Drop the entry of a process that exited
+    pub fn forget(&self, total: &LatencyTotal) {
+        let key = BucketKey {
+            tgid: total.tgid,
+            bucket: total.bucket as u32,
+        };
+        if let Ok(histograms) = map(&self.object, "histograms") {
+            map_delete(histograms, &key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_function() {
+        let kallsyms = "ffffffff8143a2b0 T vfs_read\n\
+                        ffffffff8143a5c0 t vfs_readv\n\
+                        ffffffffc0a41230 t inet6_recvmsg\t[ipv6]\n\
+                        ffffffff8143a9e0 t vfs_writev.isra.0\n";
+        assert!(has_function(kallsyms, "vfs_readv"));
+        assert!(has_function(kallsyms, "inet6_recvmsg"));
+        // Only a clone the compiler made, which cannot be hooked by name
+        assert!(!has_function(kallsyms, "vfs_writev"));
+        assert!(!has_function(kallsyms, "inet6_sendmsg"));
+    }
+
+    #[test]
+    fn test_map_bytes() {
+        let key = FileKey {
+            tgid: 42,
+            dev: 8 << 20 | 1,
+            ino: 1234,
+        };
+        let bytes = as_bytes(&key).to_vec();
+        assert_eq!(bytes.len(), 16);
+        let back: FileKey = from_bytes(&bytes).unwrap();
+        assert_eq!((back.tgid, back.dev, back.ino), (42, 8 << 20 | 1, 1234));
+        assert!(from_bytes::<FileKey>(&bytes[..8]).is_none());
+        assert_eq!(mem::size_of::<FileBytes>(), 56);
+    }
+}
@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: (MIT OR GPL-2.0)
+/*
+ * Bytes each process submits to each block device, for --trace-devices.
+ *
+ * block:block_bio_queue runs in the context of the task submitting the I/O,
+ * unlike the request issue and completion tracepoints that often fire in
+ * kworkers or interrupts. A bio sent to a partition has been remapped to the
+ * whole disk by then, so partitions count for their disk.
+ */
+#include "iotop.h"
+
+#define DEVICE_ENTRIES 16384
+
+struct device_key {
+	__u32 tgid;
+	/* Kernel encoding of the device number: major << 20 | minor */
+	__u32 dev;
+};
+
+/* Running byte counts, pruned by user space as processes exit */
+struct {
+	__uint(type, BPF_MAP_TYPE_HASH);
+	__uint(max_entries, DEVICE_ENTRIES);
+	__type(key, struct device_key);
+	__type(value, __u64);
+} totals SEC(".maps");
+
+SEC("tp/block/block_bio_queue")
+int bio_queue(struct trace_event_raw_block_bio_queue *ctx)
+{
+	struct device_key key = {};
+	__u64 bytes = (__u64)BPF_CORE_READ(ctx, nr_sector) << 9;
+
+	/* Flushes carry no data */
+	if (!bytes)
+		return 0;
+	key.tgid = bpf_get_current_pid_tgid() >> 32;
+	key.dev = BPF_CORE_READ(ctx, dev);
+	add_to(&totals, &key, &bytes, 0, bytes);
+	return 0;
+}
+
+char LICENSE[] SEC("license") = "Dual MIT/GPL";
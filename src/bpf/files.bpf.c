@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: (MIT OR GPL-2.0)
+/*
+ * Bytes each process reads and writes through each file, for --trace-files
+ * and --net.
+ *
+ * Files are hooked in vfs_read() and vfs_write(), which every read(2),
+ * pread64(2), write(2) and pwrite64(2) goes through, and in vfs_readv() and
+ * vfs_writev() for the vectored calls when the kernel has them. Sockets are
+ * counted in the inet protocol handlers instead, which also see send(2),
+ * recv(2) and their msg variants, so the vfs hooks leave them out, and
+ * flagged as network traffic.
+ */
+#include "iotop.h"
+
+#define TOTAL_ENTRIES 16384
+#define NAME_LEN 32
+
+struct file_key {
+	__u32 tgid;
+	/* Kernel encoding of the device number: major << 20 | minor */
+	__u32 dev;
+	__u64 ino;
+};
+
+struct file_bytes {
+	__u64 read;
+	__u64 write;
+	/* Open flags, for O_DIRECT */
+	__u32 flags;
+	/* Set for TCP, UDP and raw sockets, counted in the inet handlers */
+	__u32 net;
+	/* Last path component, for files closed before they are resolved */
+	char name[NAME_LEN];
+};
+
+/*
+ * Running byte counts, until user space drops those it has accounted; when
+ * more files are busy at once, the least recently used make way for new
+ * ones instead of the new ones going uncounted
+ */
+struct {
+	__uint(type, BPF_MAP_TYPE_LRU_HASH);
+	__uint(max_entries, TOTAL_ENTRIES);
+	__type(key, struct file_key);
+	__type(value, struct file_bytes);
+} totals SEC(".maps");
+
+static __always_inline void count(struct file *file, ssize_t ret, bool write,
+				  bool net)
+{
+	struct file_bytes init = {};
+	struct file_key key = {};
+	struct inode *inode;
+
+	if (ret <= 0 || !file)
+		return;
+	inode = BPF_CORE_READ(file, f_inode);
+	key.tgid = bpf_get_current_pid_tgid() >> 32;
+	key.dev = BPF_CORE_READ(inode, i_sb, s_dev);
+	key.ino = BPF_CORE_READ(inode, i_ino);
+
+	if (write)
+		init.write = ret;
+	else
+		init.read = ret;
+	init.flags = BPF_CORE_READ(file, f_flags);
+	init.net = net;
+	bpf_probe_read_kernel_str(init.name, sizeof(init.name),
+				  BPF_CORE_READ(file, f_path.dentry, d_name.name));
+	add_to(&totals, &key, &init,
+	       write ? __builtin_offsetof(struct file_bytes, write)
+		     : __builtin_offsetof(struct file_bytes, read),
+	       ret);
+}
+
+static __always_inline bool is_socket(struct file *file)
+{
+	return (BPF_CORE_READ(file, f_inode, i_mode) & S_IFMT) == S_IFSOCK;
+}
+
+SEC("fexit/vfs_read")
+int BPF_PROG(vfs_read_exit, struct file *file, char *buf, size_t count_,
+	     loff_t *pos, ssize_t ret)
+{
+	if (!is_socket(file))
+		count(file, ret, false, false);
+	return 0;
+}
+
+SEC("fexit/vfs_write")
+int BPF_PROG(vfs_write_exit, struct file *file, const char *buf, size_t count_,
+	     loff_t *pos, ssize_t ret)
+{
+	if (!is_socket(file))
+		count(file, ret, true, false);
+	return 0;
+}
+
+SEC("fexit/vfs_readv")
+int BPF_PROG(vfs_readv_exit, struct file *file, const struct iovec *vec,
+	     unsigned long vlen, loff_t *pos, int flags, ssize_t ret)
+{
+	if (!is_socket(file))
+		count(file, ret, false, false);
+	return 0;
+}
+
+SEC("fexit/vfs_writev")
+int BPF_PROG(vfs_writev_exit, struct file *file, const struct iovec *vec,
+	     unsigned long vlen, loff_t *pos, int flags, ssize_t ret)
+{
+	if (!is_socket(file))
+		count(file, ret, true, false);
+	return 0;
+}
+
+SEC("fexit/inet_recvmsg")
+int BPF_PROG(inet_recvmsg_exit, struct socket *sock, struct msghdr *msg,
+	     size_t size, int flags, int ret)
+{
+	count(BPF_CORE_READ(sock, file), ret, false, true);
+	return 0;
+}
+
+SEC("fexit/inet_sendmsg")
+int BPF_PROG(inet_sendmsg_exit, struct socket *sock, struct msghdr *msg,
+	     size_t size, int ret)
+{
+	count(BPF_CORE_READ(sock, file), ret, true, true);
+	return 0;
+}
+
+SEC("fexit/inet6_recvmsg")
+int BPF_PROG(inet6_recvmsg_exit, struct socket *sock, struct msghdr *msg,
+	     size_t size, int flags, int ret)
+{
+	count(BPF_CORE_READ(sock, file), ret, false, true);
+	return 0;
+}
+
+SEC("fexit/inet6_sendmsg")
+int BPF_PROG(inet6_sendmsg_exit, struct socket *sock, struct msghdr *msg,
+	     size_t size, int ret)
+{
+	count(BPF_CORE_READ(sock, file), ret, true, true);
+	return 0;
+}
+
+char LICENSE[] SEC("license") = "Dual MIT/GPL";
@@ -0,0 +1,125 @@
+/* SPDX-License-Identifier: (MIT OR GPL-2.0) */
+/*
+ * The few kernel types the iotop programs use, instead of a full vmlinux.h.
+ *
+ * Struct layouts here need not match the kernel's: they are marked
+ * preserve_access_index, so libbpf relocates every field access against the
+ * BTF of the running kernel and only the field names have to exist there.
+ */
+#ifndef __IOTOP_H
+#define __IOTOP_H
+
+typedef unsigned char __u8;
+typedef signed char __s8;
+typedef unsigned short __u16;
+typedef signed short __s16;
+typedef unsigned int __u32;
+typedef signed int __s32;
+typedef unsigned long long __u64;
+typedef signed long long __s64;
+typedef __u16 __be16;
+typedef __u32 __be32;
+typedef __u64 __be64;
+typedef __u16 __sum16;
+typedef __u32 __wsum;
+
+typedef __u32 dev_t;
+typedef __u64 sector_t;
+typedef long long loff_t;
+typedef unsigned long size_t;
+typedef long ssize_t;
+typedef _Bool bool;
+
+enum {
+	BPF_MAP_TYPE_HASH = 1,
+	BPF_MAP_TYPE_LRU_HASH = 9,
+};
+
+enum {
+	BPF_ANY = 0,
+	BPF_NOEXIST = 1,
+};
+
+#include <bpf/bpf_helpers.h>
+#include <bpf/bpf_tracing.h>
+#include <bpf/bpf_core_read.h>
+
+#define S_IFMT 00170000
+#define S_IFSOCK 0140000
+
+#pragma clang attribute push(__attribute__((preserve_access_index)), apply_to = record)
+
+struct super_block {
+	dev_t s_dev;
+};
+
+struct inode {
+	unsigned short i_mode;
+	unsigned long i_ino;
+	struct super_block *i_sb;
+};
+
+struct qstr {
+	const unsigned char *name;
+};
+
+struct dentry {
+	struct qstr d_name;
+};
+
+struct path {
+	struct dentry *dentry;
+};
+
+struct file {
+	struct path f_path;
+	struct inode *f_inode;
+	unsigned int f_flags;
+};
+
+struct socket {
+	struct file *file;
+};
+
+struct msghdr;
+struct iovec;
+
+struct trace_event_raw_block_bio_queue {
+	dev_t dev;
+	sector_t sector;
+	unsigned int nr_sector;
+};
+
+struct trace_event_raw_block_rq_completion {
+	dev_t dev;
+	sector_t sector;
+};
+
+struct trace_event_raw_block_rq_complete {
+	dev_t dev;
+	sector_t sector;
+};
+
+#pragma clang attribute pop
+
+/*
+ * Add `delta` to the u64 at `offset` in the value of `key`, or insert `init`,
+ * which already counts it, when there is no value yet. Another CPU may insert
+ * the same key in between, in which case its value is added to instead.
+ */
+static __always_inline void add_to(void *map, const void *key, const void *init,
+				   unsigned int offset, __u64 delta)
+{
+	void *value = bpf_map_lookup_elem(map, key);
+
+	if (!value) {
+		if (!bpf_map_update_elem(map, key, init, BPF_NOEXIST))
+			return;
+		value = bpf_map_lookup_elem(map, key);
+		if (!value)
+			return;
+	}
+	__sync_fetch_and_add((__u64 *)((char *)value + offset), delta);
+}
+
+#endif /* __IOTOP_H */
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: (MIT OR GPL-2.0)
+/*
+ * Block I/O latency of each process from queueing to completion,
+ * biolatency-style, for --latency.
+ *
+ * block:block_bio_queue runs in the submitting task and records the time
+ * under the disk and starting sector; block:block_rq_complete looks them up
+ * again and counts the latency in a histogram of log2 microsecond buckets.
+ * Both report the whole disk and a sector on it, as a bio sent to a
+ * partition is remapped before it is queued.
+ */
+#include "iotop.h"
+
+/* Must match latency::BUCKETS */
+#define BUCKETS 32
+#define INFLIGHT_ENTRIES 16384
+#define HISTOGRAM_ENTRIES 16384
+
+struct inflight_key {
+	__u32 dev;
+	__u32 pad;
+	__u64 sector;
+};
+
+struct queued {
+	__u64 tgid;
+	__u64 ns;
+};
+
+/*
+ * Block I/O in flight by disk and starting sector; the least recently
+ * queued make way for new ones, as bios merged into a request never
+ * complete under their own sector
+ */
+struct {
+	__uint(type, BPF_MAP_TYPE_LRU_HASH);
+	__uint(max_entries, INFLIGHT_ENTRIES);
+	__type(key, struct inflight_key);
+	__type(value, struct queued);
+} inflight SEC(".maps");
+
+struct bucket_key {
+	__u32 tgid;
+	__u32 bucket;
+};
+
+/* Running counts by process and bucket, pruned by user space as processes exit */
+struct {
+	__uint(type, BPF_MAP_TYPE_HASH);
+	__uint(max_entries, HISTOGRAM_ENTRIES);
+	__type(key, struct bucket_key);
+	__type(value, __u64);
+} histograms SEC(".maps");
+
+SEC("tp/block/block_bio_queue")
+int bio_queue(struct trace_event_raw_block_bio_queue *ctx)
+{
+	struct inflight_key key = {};
+	struct queued queued = {};
+
+	/* Flushes carry no data, nor a meaningful sector */
+	if (!BPF_CORE_READ(ctx, nr_sector))
+		return 0;
+	key.dev = BPF_CORE_READ(ctx, dev);
+	key.sector = BPF_CORE_READ(ctx, sector);
+	queued.tgid = bpf_get_current_pid_tgid() >> 32;
+	queued.ns = bpf_ktime_get_ns();
+	bpf_map_update_elem(&inflight, &key, &queued, BPF_ANY);
+	return 0;
+}
+
+SEC("tp/block/block_rq_complete")
+int rq_complete(void *ctx)
+{
+	struct inflight_key inflight_key = {};
+	struct bucket_key key = {};
+	struct queued *queued;
+	__u64 us, one = 1;
+
+	/* Newer kernels share the record with block_rq_error under another name */
+	if (bpf_core_type_exists(struct trace_event_raw_block_rq_completion)) {
+		struct trace_event_raw_block_rq_completion *rq = ctx;
+
+		inflight_key.dev = BPF_CORE_READ(rq, dev);
+		inflight_key.sector = BPF_CORE_READ(rq, sector);
+	} else {
+		struct trace_event_raw_block_rq_complete *rq = ctx;
+
+		inflight_key.dev = BPF_CORE_READ(rq, dev);
+		inflight_key.sector = BPF_CORE_READ(rq, sector);
+	}
+	queued = bpf_map_lookup_elem(&inflight, &inflight_key);
+	if (!queued)
+		return 0;
+	key.tgid = queued->tgid;
+	us = (bpf_ktime_get_ns() - queued->ns) / 1000;
+	bpf_map_delete_elem(&inflight, &inflight_key);
+
+	while (us > 1 && key.bucket < BUCKETS - 1) {
+		us >>= 1;
+		key.bucket++;
+	}
+	add_to(&histograms, &key, &one, 0, 1);
+	return 0;
+}
+
+char LICENSE[] SEC("license") = "Dual MIT/GPL";
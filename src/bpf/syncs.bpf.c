@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: (MIT OR GPL-2.0)
+/*
+ * fsync(2) and fdatasync(2) calls of each process, for --trace-syncs.
+ *
+ * Counted on syscall entry, so calls still blocked flushing count already.
+ */
+#include "iotop.h"
+
+#define SYNC_ENTRIES 16384
+
+/* Running call counts by process, pruned by user space as processes exit */
+struct {
+	__uint(type, BPF_MAP_TYPE_HASH);
+	__uint(max_entries, SYNC_ENTRIES);
+	__type(key, __u32);
+	__type(value, __u64);
+} totals SEC(".maps");
+
+static __always_inline int count(void)
+{
+	__u32 tgid = bpf_get_current_pid_tgid() >> 32;
+	__u64 one = 1;
+
+	add_to(&totals, &tgid, &one, 0, 1);
+	return 0;
+}
+
+SEC("tp/syscalls/sys_enter_fsync")
+int fsync_enter(void *ctx)
+{
+	return count();
+}
+
+SEC("tp/syscalls/sys_enter_fdatasync")
+int fdatasync_enter(void *ctx)
+{
+	return count();
+}
+
+char LICENSE[] SEC("license") = "Dual MIT/GPL";
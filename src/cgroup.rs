@@ -0,0 +1,445 @@
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::pressure;
+
+/// Mount point of the unified (v2) cgroup hierarchy
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Share of the last 10 seconds, in percent, that tasks of a limited cgroup
+/// must have stalled on I/O for it to count as throttled
+const THROTTLE_PRESSURE: f64 = 1.0;
+
+/// Cumulative counters from one cgroup's io.stat, summed over its devices
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CgroupCounters {
+    pub rbytes: u64,
+    pub wbytes: u64,
+    pub rios: u64,
+    pub wios: u64,
+}
+
+/// Parse io.stat content: one `MAJ:MIN key=value ...` line per device
+///
+/// Unknown keys (discards, cost model statistics) are ignored.
+pub fn parse_io_stat(content: &str) -> CgroupCounters {
+    let mut counters = CgroupCounters::default();
+    for (key, value) in content
+        .split_whitespace()
+        .filter_map(|field| field.split_once('='))
+    {
+        let Ok(value) = value.parse::<u64>() else {
+            continue;
+        };
+        match key {
+            "rbytes" => counters.rbytes += value,
+            "wbytes" => counters.wbytes += value,
+            "rios" => counters.rios += value,
+            "wios" => counters.wios += value,
+            _ => {}
+        }
+    }
+    counters
+}
+
+/// Whether io.max content sets any limit
+///
+/// Each line is `MAJ:MIN rbps=... wbps=... riops=... wiops=...`, where `max`
+/// stands for no limit; devices without limits are not listed at all.
+pub fn has_limits(content: &str) -> bool {
+    content
+        .split_whitespace()
+        .filter_map(|field| field.split_once('='))
+        .any(|(_, value)| value != "max")
+}
+
+/// I/O of one cgroup over the last interval
+///
+/// Unlike the per-task counters this includes buffered writeback, which the
+/// kernel charges to the cgroup that dirtied the pages. Counts are
+/// hierarchical, so a cgroup includes the I/O of its descendants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CgroupRate {
+    /// Path relative to the hierarchy root, e.g. `system.slice/sshd.service`
+    pub path: String,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Whether io.max limits the cgroup, or one of its ancestors, while its
+    /// tasks stall on I/O: it is slow on purpose
+    pub throttled: bool,
+}
+
+impl CgroupRate {
+    fn idle(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 0.0,
+            read_iops: 0.0,
+            write_iops: 0.0,
+            throttled: false,
+        }
+    }
+
+    fn from_delta(path: &str, prev: &CgroupCounters, curr: &CgroupCounters, secs: f64) -> Self {
+        let rate = |curr: u64, prev: u64| curr.saturating_sub(prev) as f64 / secs;
+        Self {
+            path: path.to_string(),
+            read_bytes_per_sec: rate(curr.rbytes, prev.rbytes),
+            write_bytes_per_sec: rate(curr.wbytes, prev.wbytes),
+            read_iops: rate(curr.rios, prev.rios),
+            write_iops: rate(curr.wios, prev.wios),
+            throttled: false,
+        }
+    }
+
+    fn bytes_per_sec(&self) -> f64 {
+        self.read_bytes_per_sec + self.write_bytes_per_sec
+    }
+}
+
+/// Combine the cgroup rates of two consecutive intervals into rates over
+/// both, weighting each by its length
+///
+/// Cgroups missing from one interval count as idle during it, and those
+/// throttled during either stay marked.
+pub fn merge_rates(
+    older: &[CgroupRate],
+    older_secs: f64,
+    newer: &[CgroupRate],
+    newer_secs: f64,
+) -> Vec<CgroupRate> {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.to_vec();
+    }
+
+    let mut merged: HashMap<String, CgroupRate> = HashMap::new();
+    for (rates, weight) in [(older, older_secs / secs), (newer, newer_secs / secs)] {
+        for rate in rates {
+            let cgroup = merged
+                .entry(rate.path.clone())
+                .or_insert_with(|| CgroupRate::idle(&rate.path));
+            cgroup.read_bytes_per_sec += rate.read_bytes_per_sec * weight;
+            cgroup.write_bytes_per_sec += rate.write_bytes_per_sec * weight;
+            cgroup.read_iops += rate.read_iops * weight;
+            cgroup.write_iops += rate.write_iops * weight;
+            cgroup.throttled |= rate.throttled;
+        }
+    }
+
+    let mut merged: Vec<CgroupRate> = merged.into_values().collect();
+    sort_busiest_first(&mut merged);
+    merged
+}
+
+fn sort_busiest_first(rates: &mut [CgroupRate]) {
+    rates.sort_by(|a, b| {
+        b.bytes_per_sec()
+            .total_cmp(&a.bytes_per_sec())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
+
+/// Walks the cgroup v2 hierarchy and turns consecutive io.stat samples into
+/// rates
+#[derive(Default)]
+pub struct CgroupSampler {
+    prev: HashMap<String, CgroupCounters>,
+    prev_at: Option<Instant>,
+}
+
+/// What one walk of the hierarchy found
+#[derive(Default)]
+struct Walk {
+    counters: HashMap<String, CgroupCounters>,
+    throttled: HashSet<String>,
+}
+
+impl CgroupSampler {
+    /// Read io.stat of every cgroup and return the rates of those that did
+    /// I/O since the previous call, or are being throttled (empty on the
+    /// first call), busiest first
+    pub fn sample(&mut self) -> Result<Vec<CgroupRate>> {
+        let root = Path::new(CGROUP_ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            bail!("cgroup v2 is not mounted at {}", CGROUP_ROOT);
+        }
+
+        let mut current = Walk::default();
+        walk(root, root, false, &mut current);
+        let now = Instant::now();
+
+        let rates = self.rates(&current, now);
+        self.prev = current.counters;
+        self.prev_at = Some(now);
+        Ok(rates)
+    }
+
+    fn rates(&self, current: &Walk, now: Instant) -> Vec<CgroupRate> {
+        let Some(prev_at) = self.prev_at else {
+            return Vec::new();
+        };
+        let secs = now.duration_since(prev_at).as_secs_f64();
+        if secs <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut rates: Vec<CgroupRate> = current
+            .counters
+            .iter()
+            .filter_map(|(path, curr)| {
+                let prev = self.prev.get(path)?;
+                let throttled = current.throttled.contains(path);
+                (curr != prev || throttled).then(|| CgroupRate {
+                    throttled,
+                    ..CgroupRate::from_delta(path, prev, curr, secs)
+                })
+            })
+            .collect();
+        sort_busiest_first(&mut rates);
+        rates
+    }
+}
+
+/// Collect the io.stat counters of every cgroup below `dir`, and which of
+/// them are throttled; `limited` tells whether io.max limits `dir`
+///
+/// The root cgroup has no io.stat of its own; cgroups whose io controller is
+/// not enabled have an empty one and are skipped.
+fn walk(dir: &Path, root: &Path, limited: bool, out: &mut Walk) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let path = entry.path();
+        // Limits apply to the whole subtree
+        let limited = limited
+            || fs::read_to_string(path.join("io.max")).is_ok_and(|content| has_limits(&content));
+        if let Ok(content) = fs::read_to_string(path.join("io.stat")) {
+            if !content.trim().is_empty() {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    let relative = relative.display().to_string();
+                    if limited && is_stalling(&path) {
+                        out.throttled.insert(relative.clone());
+                    }
+                    out.counters.insert(relative, parse_io_stat(&content));
+                }
+            }
+        }
+        walk(&path, root, limited, out);
+    }
+}
+
+/// Whether the tasks of the cgroup at `path` have been stalling on I/O
+fn is_stalling(path: &Path) -> bool {
+    fs::read_to_string(path.join("io.pressure"))
+        .ok()
+        .and_then(|content| pressure::parse(&content))
+        .is_some_and(|pressure| pressure.some_avg10 >= THROTTLE_PRESSURE)
+}
+
+/// Files holding a cgroup's weight in proportional I/O control, for the
+/// io.weight controller first, then for the BFQ scheduler
+const WEIGHT_FILES: [&str; 2] = ["io.weight", "io.bfq.weight"];
+const WEIGHT_FILES_V1: [&str; 2] = ["blkio.weight", "blkio.bfq.weight"];
+
+/// Path of a process's cgroup from the root of the hierarchy, such as
+/// `/system.slice/nginx.service`, from its /proc/[pid]/cgroup content
+///
+/// On hybrid systems this is its place in systemd's named v1 hierarchy,
+/// which mirrors the unified one there.
+pub fn parse_path(content: &str) -> Option<&str> {
+    let mut unified = None;
+    for line in content.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if controllers == "name=systemd" {
+            return Some(path);
+        }
+        if id == "0" && controllers.is_empty() {
+            unified = Some(path);
+        }
+    }
+    unified
+}
+
+/// Parse the default weight from io.weight-style content: either a bare
+/// number, or `default N` followed by per-device `MAJ:MIN N` overrides
+pub fn parse_weight(content: &str) -> Option<u32> {
+    content.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next()?, fields.next()) {
+            ("default", Some(weight)) => weight.parse().ok(),
+            (weight, None) => weight.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// Directory of the cgroup that controls the I/O weight of a process, from
+/// its /proc/[pid]/cgroup content, with the files the weight is kept in
+///
+/// A v1 blkio hierarchy takes precedence: the io controller cannot be
+/// enabled in both, and hybrid systems keep it in v1.
+fn weight_dir(content: &str) -> Option<(PathBuf, &'static [&'static str])> {
+    let mut unified = None;
+    for line in content.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let relative = path.trim_start_matches('/');
+        if controllers.split(',').any(|c| c == "blkio") {
+            let dir = Path::new(CGROUP_ROOT).join(controllers).join(relative);
+            return Some((dir, &WEIGHT_FILES_V1));
+        }
+        if id == "0" && controllers.is_empty() {
+            unified = Some(Path::new(CGROUP_ROOT).join(relative));
+        }
+    }
+    Some((unified?, &WEIGHT_FILES))
+}
+
+/// Effective I/O weight of process `pid`
+///
+/// Weights only divide I/O among the children of one cgroup: where the io
+/// controller is not enabled for the process's own cgroup, that of the
+/// nearest ancestor it is enabled for applies. `None` where no weight
+/// applies, as in the v2 root cgroup, or without weight-based control (no
+/// io.cost, no BFQ).
+pub fn read_weight(pid: i32) -> Option<u32> {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let (dir, files) = weight_dir(&content)?;
+    dir.ancestors()
+        .take_while(|dir| dir.starts_with(CGROUP_ROOT))
+        .find_map(|dir| {
+            files
+                .iter()
+                .find_map(|file| parse_weight(&fs::read_to_string(dir.join(file)).ok()?))
+        })
+}
+
+/// I/O weights of the processes currently on screen
+///
+/// Like the mount points, they are only looked up for the rows being
+/// drawn, and reused until `max_age` passes.
+#[derive(Default)]
+pub struct WeightCache {
+    entries: HashMap<i32, (Instant, Option<u32>)>,
+}
+
+impl WeightCache {
+    /// Look up the weights of `pids`, dropping those of processes that are
+    /// no longer shown; processes without a weight are left out
+    pub fn lookup(&mut self, pids: &[i32], max_age: Duration) -> HashMap<i32, u32> {
+        self.entries.retain(|pid, _| pids.contains(pid));
+        pids.iter()
+            .filter_map(|&pid| {
+                let (at, weight) = self
+                    .entries
+                    .entry(pid)
+                    .or_insert_with(|| (Instant::now(), read_weight(pid)));
+                if at.elapsed() >= max_age {
+                    *at = Instant::now();
+                    *weight = read_weight(pid);
+                }
+                Some((pid, (*weight)?))
+            })
+            .collect()
+    }
+}
+
+/// Processes (TGIDs) directly in the throttled cgroups among `cgroups`
+pub fn throttled_pids(cgroups: &[CgroupRate]) -> HashSet<i32> {
+    cgroups
+        .iter()
+        .filter(|cgroup| cgroup.throttled)
+        .filter_map(|cgroup| {
+            fs::read_to_string(
+                Path::new(CGROUP_ROOT)
+                    .join(&cgroup.path)
+                    .join("cgroup.procs"),
+            )
+            .ok()
+        })
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .collect::<Vec<i32>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_io_stat() {
+        let content = "8:0 rbytes=1048576 wbytes=4096 rios=16 wios=1 dbytes=0 dios=0\n\
+                       259:0 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=512 dios=1 cost.usage=7\n";
+        assert_eq!(
+            parse_io_stat(content),
+            CgroupCounters {
+                rbytes: 1052672,
+                wbytes: 4096,
+                rios: 17,
+                wios: 1,
+            }
+        );
+        assert_eq!(parse_io_stat(""), CgroupCounters::default());
+
+        assert!(has_limits(
+            "8:0 rbps=max wbps=1048576 riops=max wiops=max\n"
+        ));
+        assert!(!has_limits("8:0 rbps=max wbps=max riops=max wiops=max\n"));
+        assert!(!has_limits(""));
+    }
+
+    #[test]
+    fn test_merge_rates() {
+        let mut db = CgroupRate::idle("system.slice/postgresql.service");
+        db.write_bytes_per_sec = 3000.0;
+        let mut backup = CgroupRate::idle("system.slice/backup.service");
+        backup.read_bytes_per_sec = 600.0;
+        backup.throttled = true;
+
+        let merged = merge_rates(&[db], 1.0, &[backup], 2.0);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].path, "system.slice/postgresql.service");
+        assert!((merged[0].write_bytes_per_sec - 1000.0).abs() < 1e-9);
+        assert!((merged[1].read_bytes_per_sec - 400.0).abs() < 1e-9);
+        assert!(merged[1].throttled && !merged[0].throttled);
+    }
+
+    #[test]
+    fn test_parse_weight() {
+        assert_eq!(parse_weight("default 100\n8:0 200\n"), Some(100));
+        assert_eq!(parse_weight("500\n"), Some(500));
+        assert_eq!(parse_weight(""), None);
+
+        let (dir, files) = weight_dir("0::/system.slice/backup.service\n").unwrap();
+        assert_eq!(dir, Path::new("/sys/fs/cgroup/system.slice/backup.service"));
+        assert_eq!(files, WEIGHT_FILES);
+        let (dir, files) =
+            weight_dir("7:blkio:/user.slice\n1:name=systemd:/user.slice\n0::/\n").unwrap();
+        assert_eq!(dir, Path::new("/sys/fs/cgroup/blkio/user.slice"));
+        assert_eq!(files, WEIGHT_FILES_V1);
+    }
+}
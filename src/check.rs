@@ -0,0 +1,314 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::connector::TaskTracker;
+use crate::taskstats::{TaskStats, TaskStatsConnection};
+
+const PRIVILEGE_HINT: &str =
+    "run as root, or grant the capability: sudo setcap cap_net_admin+eip $(command -v iotop)";
+
+/// How well the kernel supports one of the features iotop uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works in a degraded way
+    Limited,
+    Missing,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Limited => "limited",
+            Status::Missing => "missing",
+        }
+    }
+}
+
+/// Result of probing one feature
+#[derive(Debug, Clone)]
+pub struct Capability {
+    pub name: &'static str,
+    /// iotop does not start without it
+    pub required: bool,
+    pub status: Status,
+    /// What was found, or what is lost without it
+    pub detail: String,
+    /// How to get it, unless it is there
+    pub hint: Option<&'static str>,
+}
+
+impl Capability {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            required: false,
+            status,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    fn hint(mut self, hint: &'static str) -> Self {
+        if self.status != Status::Ok {
+            self.hint = Some(hint);
+        }
+        self
+    }
+
+    fn is_fatal(&self) -> bool {
+        self.required && self.status == Status::Missing
+    }
+}
+
+/// Probe the features iotop cannot run without
+fn probe_required() -> Vec<Capability> {
+    vec![
+        file_capability("I/O accounting", "/proc/self/io", "per-task byte counters")
+            .required()
+            .hint("a kernel built with CONFIG_TASK_IO_ACCOUNTING"),
+        file_capability("VM event counters", "/proc/vmstat", "actual disk I/O")
+            .required()
+            .hint("a kernel built with CONFIG_VM_EVENT_COUNTERS"),
+    ]
+}
+
+/// Probe every feature iotop uses
+pub fn probe() -> Vec<Capability> {
+    let mut capabilities = probe_required();
+
+    let taskstats = TaskStatsConnection::new();
+    capabilities.push(
+        match &taskstats {
+            Ok(_) => Capability::new("taskstats", Status::Ok, "netlink queries allowed"),
+            Err(_) => Capability::new(
+                "taskstats",
+                Status::Limited,
+                "no CAP_NET_ADMIN: own processes only, from /proc/[pid]/io",
+            ),
+        }
+        .hint(PRIVILEGE_HINT),
+    );
+
+    capabilities.push(delay_accounting(taskstats.ok()));
+
+    capabilities.push(
+        match TaskTracker::start() {
+            Ok(_) => Capability::new("process events", Status::Ok, "proc connector"),
+            Err(_) => Capability::new(
+                "process events",
+                Status::Limited,
+                "/proc is walked at every refresh",
+            ),
+        }
+        .hint(PRIVILEGE_HINT),
+    );
+
+    capabilities.push(
+        if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+            let controllers =
+                fs::read_to_string("/sys/fs/cgroup/cgroup.controllers").unwrap_or_default();
+            if controllers.split_whitespace().any(|c| c == "io") {
+                Capability::new("cgroup v2", Status::Ok, "io controller available")
+            } else {
+                Capability::new(
+                    "cgroup v2",
+                    Status::Limited,
+                    "no io controller: no cgroup I/O nor throttling",
+                )
+            }
+        } else {
+            Capability::new(
+                "cgroup v2",
+                Status::Missing,
+                "no cgroup panel; IOWGT from cgroup v1 only",
+            )
+        }
+        .hint("mount the unified hierarchy at /sys/fs/cgroup (systemd.unified_cgroup_hierarchy=1)"),
+    );
+
+    capabilities.push(
+        file_capability(
+            "pressure stall info",
+            "/proc/pressure/io",
+            "I/O PRESSURE line",
+        )
+        .hint("a kernel built with CONFIG_PSI, booted without psi=0"),
+    );
+
+    capabilities.push(ebpf());
+    capabilities
+}
+
+fn file_capability(name: &'static str, path: &str, provides: &str) -> Capability {
+    if fs::File::open(path).is_ok() {
+        Capability::new(name, Status::Ok, format!("{} from {}", provides, path))
+    } else {
+        Capability::new(name, Status::Missing, format!("no {}", provides))
+    }
+}
+
+/// Delay accounting, known for sure from a sample when taskstats can be
+/// queried
+fn delay_accounting(taskstats: Option<TaskStatsConnection>) -> Capability {
+    let hint =
+        "sudo sysctl -w kernel.task_delayacct=1, iotop --enable-delayacct, or boot with delayacct";
+    // Off by default since Linux 5.14, and always on before if built in
+    let sysctl = fs::read_to_string("/proc/sys/kernel/task_delayacct").ok();
+    if sysctl.as_deref().map(str::trim) == Some("0") {
+        return Capability::new(
+            "delay accounting",
+            Status::Missing,
+            "kernel.task_delayacct is 0: no SWAPIN, IO or delay columns",
+        )
+        .hint(hint);
+    }
+
+    let sampled = taskstats.and_then(|mut conn| {
+        conn.get_task_stats(std::process::id() as i32)
+            .ok()
+            .flatten()
+    });
+    let version = TaskStats::kernel_version()
+        .map(|version| format!(", taskstats v{}", version))
+        .unwrap_or_default();
+    let capability = match (sampled, sysctl) {
+        (Some(_), _) if TaskStats::has_delay_acct() => Capability::new(
+            "delay accounting",
+            Status::Ok,
+            format!("enabled{}", version),
+        ),
+        (Some(_), _) => Capability::new(
+            "delay accounting",
+            Status::Missing,
+            format!("no delays reported{}", version),
+        ),
+        (None, Some(_)) => Capability::new(
+            "delay accounting",
+            Status::Limited,
+            "kernel.task_delayacct is 1, IO column only without taskstats",
+        ),
+        (None, None) => Capability::new(
+            "delay accounting",
+            Status::Limited,
+            "unknown without taskstats",
+        ),
+    };
+    capability.hint(hint)
+}
+
+fn ebpf() -> Capability {
+    let hint = "build with --features ebpf, run as root on a kernel with BTF (CONFIG_DEBUG_INFO_BTF), and mount tracefs at /sys/kernel/tracing";
+    let capability = if !cfg!(feature = "ebpf") {
+        Capability::new(
+            "eBPF tracing",
+            Status::Missing,
+            "built without the ebpf feature",
+        )
+    } else if !nix::unistd::geteuid().is_root() {
+        Capability::new("eBPF tracing", Status::Missing, "needs root")
+    } else if !Path::new("/sys/kernel/btf/vmlinux").exists() {
+        Capability::new("eBPF tracing", Status::Missing, "the kernel has no BTF")
+    } else if !Path::new("/sys/kernel/tracing/events").exists() {
+        Capability::new("eBPF tracing", Status::Missing, "tracefs is not mounted")
+    } else {
+        Capability::new(
+            "eBPF tracing",
+            Status::Ok,
+            "--trace-files, --net, --trace-devices, --trace-syncs and --latency",
+        )
+    };
+    capability.hint(hint)
+}
+
+/// Table of capabilities, followed by the hints for those not fully there
+pub fn report(capabilities: &[Capability]) -> String {
+    let mut report = format!("{:<20} {:<8} {}\n", "CAPABILITY", "STATUS", "DETAIL");
+    for capability in capabilities {
+        report.push_str(&format!(
+            "{:<20} {:<8} {}{}\n",
+            capability.name,
+            capability.status.label(),
+            capability.detail,
+            if capability.required {
+                " (required)"
+            } else {
+                ""
+            }
+        ));
+    }
+    let hints: Vec<String> = capabilities
+        .iter()
+        .filter_map(|capability| Some(format!("  {}: {}", capability.name, capability.hint?)))
+        .collect();
+    if !hints.is_empty() {
+        report.push_str("\nTo enable:\n");
+        report.push_str(&hints.join("\n"));
+        report.push('\n');
+    }
+    report
+}
+
+/// Print the capability report for `--check`, failing if iotop cannot run
+pub fn run() -> Result<()> {
+    let capabilities = probe();
+    print!("{}", report(&capabilities));
+    if capabilities.iter().any(Capability::is_fatal) {
+        bail!("iotop cannot run on this system");
+    }
+    Ok(())
+}
+
+/// Fail with the remediation hints unless iotop can run at all
+pub fn requirements() -> Result<()> {
+    let missing: Vec<String> = probe_required()
+        .into_iter()
+        .filter(Capability::is_fatal)
+        .map(|capability| {
+            format!(
+                " - {} ({})",
+                capability.name,
+                capability.hint.unwrap_or_default()
+            )
+        })
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "Could not run iotop as some of the requirements are not met:\n{}\n\
+             Run iotop --check for details.",
+            missing.join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let capabilities = [
+            Capability::new("I/O accounting", Status::Ok, "from /proc/self/io")
+                .required()
+                .hint("CONFIG_TASK_IO_ACCOUNTING"),
+            Capability::new(
+                "pressure stall info",
+                Status::Missing,
+                "no I/O PRESSURE line",
+            )
+            .hint("CONFIG_PSI"),
+        ];
+        assert!(!capabilities.iter().any(Capability::is_fatal));
+        let report = report(&capabilities);
+        assert!(report.contains("I/O accounting       ok       from /proc/self/io (required)\n"));
+        assert!(report.ends_with("To enable:\n  pressure stall info: CONFIG_PSI\n"));
+    }
+}
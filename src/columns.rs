@@ -0,0 +1,784 @@
+use anyhow::Result;
+use ratatui::layout::{Alignment, Constraint};
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::devtrace::{DeviceIo, DeviceRates};
+use crate::netio::{NetIo, NetRates};
+use crate::proc_reader::MemoryUsage;
+use crate::process::ProcessInfo;
+use crate::synctrace::SyncRates;
+use crate::taskstats::{self, TaskStats};
+use crate::ui::{format_bandwidth, format_delay, format_delay_ms, human_size};
+use crate::writeback::WritebackRates;
+
+/// Optional columns that are hidden unless enabled with `--columns` or the
+/// in-TUI column chooser
+///
+/// They are rendered between the delay accounting columns and COMMAND, in the
+/// order of `ExtraColumn::ALL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtraColumn {
+    Graph,
+    ReadChar,
+    WriteChar,
+    Cancelled,
+    Writeback,
+    MajorFaults,
+    MinorFaults,
+    IoWaits,
+    SwapIns,
+    IoAvg,
+    CpuDelay,
+    Reclaim,
+    Thrashing,
+    Compact,
+    WpCopy,
+    Cpu,
+    Mem,
+    Swap,
+    Oom,
+    Fds,
+    Age,
+    Tty,
+    Unit,
+    Container,
+    NsPid,
+    IoWeight,
+    Mount,
+    Device,
+    Fsync,
+    NetRead,
+    NetWrite,
+}
+
+impl ExtraColumn {
+    pub const ALL: [ExtraColumn; 31] = [
+        ExtraColumn::ReadChar,
+        ExtraColumn::WriteChar,
+        ExtraColumn::Cancelled,
+        ExtraColumn::Writeback,
+        ExtraColumn::MajorFaults,
+        ExtraColumn::MinorFaults,
+        ExtraColumn::IoWaits,
+        ExtraColumn::SwapIns,
+        ExtraColumn::IoAvg,
+        ExtraColumn::CpuDelay,
+        ExtraColumn::Reclaim,
+        ExtraColumn::Thrashing,
+        ExtraColumn::Compact,
+        ExtraColumn::WpCopy,
+        ExtraColumn::Cpu,
+        ExtraColumn::Mem,
+        ExtraColumn::Swap,
+        ExtraColumn::Oom,
+        ExtraColumn::Fds,
+        ExtraColumn::Age,
+        ExtraColumn::Tty,
+        ExtraColumn::Unit,
+        ExtraColumn::Container,
+        ExtraColumn::NsPid,
+        ExtraColumn::IoWeight,
+        ExtraColumn::Mount,
+        ExtraColumn::Device,
+        ExtraColumn::Fsync,
+        ExtraColumn::NetRead,
+        ExtraColumn::NetWrite,
+        ExtraColumn::Graph,
+    ];
+
+    /// Name used by `--columns`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExtraColumn::Graph => "graph",
+            ExtraColumn::ReadChar => "rchar",
+            ExtraColumn::WriteChar => "wchar",
+            ExtraColumn::Cancelled => "cancelled",
+            ExtraColumn::Writeback => "writeback",
+            ExtraColumn::MajorFaults => "majflt",
+            ExtraColumn::MinorFaults => "minflt",
+            ExtraColumn::IoWaits => "iowaits",
+            ExtraColumn::SwapIns => "swapins",
+            ExtraColumn::IoAvg => "ioavg",
+            ExtraColumn::CpuDelay => "cpudelay",
+            ExtraColumn::Reclaim => "reclaim",
+            ExtraColumn::Thrashing => "thrashing",
+            ExtraColumn::Compact => "compact",
+            ExtraColumn::WpCopy => "wpcopy",
+            ExtraColumn::Cpu => "cpu",
+            ExtraColumn::Mem => "mem",
+            ExtraColumn::Swap => "swap",
+            ExtraColumn::Oom => "oom",
+            ExtraColumn::Fds => "fds",
+            ExtraColumn::Age => "age",
+            ExtraColumn::Tty => "tty",
+            ExtraColumn::Unit => "unit",
+            ExtraColumn::Container => "container",
+            ExtraColumn::NsPid => "nspid",
+            ExtraColumn::IoWeight => "ioweight",
+            ExtraColumn::Mount => "mount",
+            ExtraColumn::Device => "device",
+            ExtraColumn::Fsync => "fsync",
+            ExtraColumn::NetRead => "netread",
+            ExtraColumn::NetWrite => "netwrite",
+        }
+    }
+
+    /// One-line description shown in the column chooser
+    pub fn description(&self) -> &'static str {
+        match self {
+            ExtraColumn::Graph => "bar of the row's share of I/O",
+            ExtraColumn::ReadChar => "bytes read by syscalls, cache hits included (rchar)",
+            ExtraColumn::WriteChar => "bytes written by syscalls, before the page cache (wchar)",
+            ExtraColumn::Cancelled => "writes dropped before reaching the disk, e.g. deleted files",
+            ExtraColumn::Writeback => "estimated share of Actual DISK WRITE (heuristic)",
+            ExtraColumn::MajorFaults => "page faults that read from disk or swap",
+            ExtraColumn::MinorFaults => "page faults served from memory",
+            ExtraColumn::IoWaits => "waits for block I/O (delay accounting)",
+            ExtraColumn::SwapIns => "waits for swap-in (delay accounting)",
+            ExtraColumn::IoAvg => "average wait per block I/O (delay accounting)",
+            ExtraColumn::CpuDelay => "time waiting for a CPU (delay accounting)",
+            ExtraColumn::Reclaim => "time stalled in memory reclaim (delay accounting)",
+            ExtraColumn::Thrashing => {
+                "time refaulting pages evicted while in use (delay accounting, Linux 4.20+)"
+            }
+            ExtraColumn::Compact => "time in memory compaction (delay accounting, Linux 5.19+)",
+            ExtraColumn::WpCopy => {
+                "time copying write-protected pages (delay accounting, Linux 6.3+)"
+            }
+            ExtraColumn::Cpu => "CPU usage (CPU time with -a)",
+            ExtraColumn::Mem => "resident memory (RSS)",
+            ExtraColumn::Swap => "memory swapped out (VmSwap)",
+            ExtraColumn::Oom => "OOM killer score (oom_score)",
+            ExtraColumn::Fds => "open file descriptors",
+            ExtraColumn::Age => "time since the process started",
+            ExtraColumn::Tty => "controlling terminal (? for none)",
+            ExtraColumn::Unit => "systemd service or scope",
+            ExtraColumn::Container => "docker, podman, Kubernetes or LXC container",
+            ExtraColumn::NsPid => "PID inside the container's PID namespace (NSpid)",
+            ExtraColumn::IoWeight => "cgroup I/O weight (io.weight or blkio.weight)",
+            ExtraColumn::Mount => "mount point of the files open for writing",
+            ExtraColumn::Device => "block device the I/O goes to (--trace-devices)",
+            ExtraColumn::Fsync => "fsync and fdatasync calls (--trace-syncs)",
+            ExtraColumn::NetRead => "bytes received from the network (--net)",
+            ExtraColumn::NetWrite => "bytes sent to the network (--net)",
+        }
+    }
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            ExtraColumn::Graph => "GRAPH:",
+            ExtraColumn::ReadChar => "RCHAR",
+            ExtraColumn::WriteChar => "WCHAR",
+            ExtraColumn::Cancelled => "CANCELLED",
+            ExtraColumn::Writeback => "WBACK",
+            ExtraColumn::MajorFaults => "MAJFLT",
+            ExtraColumn::MinorFaults => "MINFLT",
+            ExtraColumn::IoWaits => "IO WAITS",
+            ExtraColumn::SwapIns => "SWAPINS",
+            ExtraColumn::IoAvg => "IO AVG",
+            ExtraColumn::CpuDelay => "CPUDLY",
+            ExtraColumn::Reclaim => "RECLAIM",
+            ExtraColumn::Thrashing => "THRASH",
+            ExtraColumn::Compact => "COMPACT",
+            ExtraColumn::WpCopy => "WPCOPY",
+            ExtraColumn::Cpu => "CPU%",
+            ExtraColumn::Mem => "MEM",
+            ExtraColumn::Swap => "SWAP",
+            ExtraColumn::Oom => "OOM",
+            ExtraColumn::Fds => "FD",
+            ExtraColumn::Age => "AGE",
+            ExtraColumn::Tty => "TTY",
+            ExtraColumn::Unit => "UNIT",
+            ExtraColumn::Container => "CONTAINER",
+            ExtraColumn::NsPid => "NSPID",
+            ExtraColumn::IoWeight => "IOWGT",
+            ExtraColumn::Mount => "MOUNT",
+            ExtraColumn::Device => "DEVICE",
+            ExtraColumn::Fsync => "FSYNC",
+            ExtraColumn::NetRead => "NET READ",
+            ExtraColumn::NetWrite => "NET WRITE",
+        }
+    }
+
+    pub fn width(&self) -> Constraint {
+        match self {
+            ExtraColumn::Graph => Constraint::Length(GRAPH_WIDTH as u16 + 1),
+            // Wide enough for delays in milliseconds (`m`)
+            ExtraColumn::ReadChar
+            | ExtraColumn::WriteChar
+            | ExtraColumn::Cancelled
+            | ExtraColumn::Writeback
+            | ExtraColumn::IoAvg
+            | ExtraColumn::CpuDelay
+            | ExtraColumn::Reclaim
+            | ExtraColumn::Thrashing
+            | ExtraColumn::Compact
+            | ExtraColumn::WpCopy
+            | ExtraColumn::NetRead
+            | ExtraColumn::NetWrite => Constraint::Length(11),
+            ExtraColumn::Cpu
+            | ExtraColumn::Age
+            | ExtraColumn::Tty
+            | ExtraColumn::NsPid
+            | ExtraColumn::IoWeight
+            | ExtraColumn::Oom
+            | ExtraColumn::Fds => Constraint::Length(8),
+            ExtraColumn::Mem
+            | ExtraColumn::Swap
+            | ExtraColumn::MajorFaults
+            | ExtraColumn::MinorFaults
+            | ExtraColumn::IoWaits
+            | ExtraColumn::SwapIns
+            | ExtraColumn::Fsync => Constraint::Length(9),
+            ExtraColumn::Mount => Constraint::Length(14),
+            ExtraColumn::Container => Constraint::Length(20),
+            ExtraColumn::Unit => Constraint::Length(22),
+            ExtraColumn::Device => Constraint::Length(12),
+        }
+    }
+
+    pub fn alignment(&self) -> Alignment {
+        match self {
+            ExtraColumn::Graph
+            | ExtraColumn::Tty
+            | ExtraColumn::Unit
+            | ExtraColumn::Container
+            | ExtraColumn::Mount
+            | ExtraColumn::Device => Alignment::Left,
+            ExtraColumn::ReadChar
+            | ExtraColumn::WriteChar
+            | ExtraColumn::Cancelled
+            | ExtraColumn::Writeback
+            | ExtraColumn::MajorFaults
+            | ExtraColumn::MinorFaults
+            | ExtraColumn::IoWaits
+            | ExtraColumn::SwapIns
+            | ExtraColumn::IoAvg
+            | ExtraColumn::CpuDelay
+            | ExtraColumn::Reclaim
+            | ExtraColumn::Thrashing
+            | ExtraColumn::Compact
+            | ExtraColumn::WpCopy
+            | ExtraColumn::Cpu
+            | ExtraColumn::Mem
+            | ExtraColumn::Swap
+            | ExtraColumn::Oom
+            | ExtraColumn::Fds
+            | ExtraColumn::Age
+            | ExtraColumn::NsPid
+            | ExtraColumn::IoWeight
+            | ExtraColumn::Fsync
+            | ExtraColumn::NetRead
+            | ExtraColumn::NetWrite => Alignment::Right,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|column| column.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Parse a comma-separated list such as `graph,cpu`
+    pub fn parse_list(list: &str) -> Result<Vec<Self>> {
+        let mut columns = Vec::new();
+        for name in list.split(',').filter(|s| !s.trim().is_empty()) {
+            match Self::from_name(name) {
+                Some(column) if !columns.contains(&column) => columns.push(column),
+                Some(_) => {}
+                None => {
+                    let available: Vec<&str> = Self::ALL.iter().map(|c| c.name()).collect();
+                    anyhow::bail!(
+                        "Unknown column: {} (available: {})",
+                        name.trim(),
+                        available.join(", ")
+                    );
+                }
+            }
+        }
+        Ok(Self::ordered(&columns))
+    }
+
+    /// Return `columns` in display order
+    pub fn ordered(columns: &[Self]) -> Vec<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|column| columns.contains(column))
+            .collect()
+    }
+}
+
+/// Values shared by every row when formatting optional columns
+pub struct ColumnContext {
+    pub accumulated: bool,
+    /// Read plus write bytes summed over all rows in the current view
+    pub io_sum: u64,
+    /// Length of the interval, in seconds
+    pub duration: f64,
+    /// Render text instead of glyphs (accessible mode)
+    pub plain: bool,
+    /// Show delays in milliseconds instead of percent of the interval
+    pub delay_ms: bool,
+    /// Memory usage of the visible rows' processes, by PID (TGID); only
+    /// filled in when a memory column is shown
+    pub memory: HashMap<i32, MemoryUsage>,
+    /// Network I/O by PID (TGID); `None` unless it is counted (`--net`)
+    pub net: Option<NetRates>,
+    /// Block I/O by PID (TGID) and device; `None` unless it is traced
+    /// (`--trace-devices`)
+    pub device_io: Option<DeviceRates>,
+    /// Sync calls by PID (TGID); `None` unless they are counted
+    /// (`--trace-syncs`)
+    pub syncs: Option<SyncRates>,
+    /// Estimated share of Actual DISK WRITE by row ID
+    pub writeback: WritebackRates,
+    /// Mount points of the visible rows' files open for writing, busiest
+    /// first; only filled in when the MOUNT column is shown
+    pub mounts: HashMap<i32, Vec<String>>,
+    /// Mount points of the nearly full filesystems, marked in the MOUNT
+    /// column; only filled in when it is shown
+    pub full_mounts: HashSet<String>,
+    /// Container names of the visible rows' processes that run in one; only
+    /// filled in when the CONTAINER column is shown
+    pub containers: HashMap<i32, String>,
+    /// cgroup I/O weights of the visible rows' processes that have one; only
+    /// filled in when the IOWGT column is shown
+    pub io_weights: HashMap<i32, u32>,
+    /// Open descriptors of the visible rows' processes that could be
+    /// counted; only filled in when the FD column is shown
+    pub fds: HashMap<i32, usize>,
+}
+
+/// Width of the GRAPH bar in cells
+const GRAPH_WIDTH: usize = 10;
+
+/// Format the value of an optional column for one row
+pub fn column_text(column: ExtraColumn, process: &ProcessInfo, ctx: &ColumnContext) -> String {
+    let stats = if ctx.accumulated {
+        &process.stats_accum
+    } else {
+        &process.stats_delta
+    };
+
+    match column {
+        ExtraColumn::Graph => {
+            let io = stats.read_bytes.saturating_add(
+                stats
+                    .write_bytes
+                    .saturating_sub(stats.cancelled_write_bytes),
+            );
+            let share = if ctx.io_sum > 0 {
+                io as f64 / ctx.io_sum as f64
+            } else {
+                0.0
+            };
+            if ctx.plain {
+                format!("{:.0}%", share * 100.0)
+            } else {
+                io_bar(share, GRAPH_WIDTH)
+            }
+        }
+        ExtraColumn::ReadChar => format_bytes(stats.read_char, ctx.duration, ctx.accumulated),
+        ExtraColumn::WriteChar => format_bytes(stats.write_char, ctx.duration, ctx.accumulated),
+        ExtraColumn::Cancelled => {
+            format_bytes(stats.cancelled_write_bytes, ctx.duration, ctx.accumulated)
+        }
+        ExtraColumn::Writeback => {
+            let writeback = ctx.writeback.get(&process.tid).copied().unwrap_or_default();
+            if ctx.accumulated {
+                human_size(writeback.bytes as i64)
+            } else {
+                human_size(writeback.bytes_per_sec as i64) + "/s"
+            }
+        }
+        ExtraColumn::MajorFaults => format_count(stats.major_faults, ctx.duration, ctx.accumulated),
+        ExtraColumn::MinorFaults => format_count(stats.minor_faults, ctx.duration, ctx.accumulated),
+        ExtraColumn::IoWaits => format_count(stats.blkio_count, ctx.duration, ctx.accumulated),
+        ExtraColumn::SwapIns => format_count(stats.swapin_count, ctx.duration, ctx.accumulated),
+        ExtraColumn::IoAvg => format_average_delay(stats.blkio_delay_total, stats.blkio_count),
+        ExtraColumn::CpuDelay => format_delay(stats.cpu_delay_total, ctx.duration, ctx.delay_ms),
+        ExtraColumn::Reclaim => {
+            format_delay(stats.freepages_delay_total, ctx.duration, ctx.delay_ms)
+        }
+        ExtraColumn::Thrashing => format_newer_delay(
+            stats.thrashing_delay_total,
+            taskstats::THRASHING_VERSION,
+            ctx,
+        ),
+        ExtraColumn::Compact => {
+            format_newer_delay(stats.compact_delay_total, taskstats::COMPACT_VERSION, ctx)
+        }
+        ExtraColumn::WpCopy => {
+            format_newer_delay(stats.wpcopy_delay_total, taskstats::WPCOPY_VERSION, ctx)
+        }
+        ExtraColumn::Cpu => format_cpu(stats.cpu_time_total, ctx.duration, ctx.accumulated),
+        ExtraColumn::Mem => format_kb(ctx.memory.get(&process.pid).and_then(|m| m.rss_kb)),
+        ExtraColumn::Swap => format_kb(ctx.memory.get(&process.pid).and_then(|m| m.swap_kb)),
+        ExtraColumn::Oom => ctx
+            .memory
+            .get(&process.pid)
+            .and_then(|m| m.oom_score)
+            .map_or("-".to_string(), |score| score.to_string()),
+        ExtraColumn::Fds => ctx
+            .fds
+            .get(&process.pid)
+            .map_or("-".to_string(), |count| count.to_string()),
+        ExtraColumn::Age => process
+            .started
+            .and_then(|started| SystemTime::now().duration_since(started).ok())
+            .map_or("-".to_string(), |age| format_age(age.as_secs())),
+        ExtraColumn::Tty => process.tty.clone().unwrap_or_else(|| "?".to_string()),
+        ExtraColumn::Unit => process.unit.clone().unwrap_or_else(|| "-".to_string()),
+        ExtraColumn::Container => ctx
+            .containers
+            .get(&process.pid)
+            .cloned()
+            .unwrap_or_else(|| "-".to_string()),
+        ExtraColumn::NsPid => process.nspid.map_or("-".to_string(), |pid| pid.to_string()),
+        ExtraColumn::IoWeight => ctx
+            .io_weights
+            .get(&process.pid)
+            .map_or("-".to_string(), |weight| weight.to_string()),
+        ExtraColumn::Mount => format_mounts(
+            ctx.mounts.get(&process.pid).map_or(&[], |m| m),
+            &ctx.full_mounts,
+        ),
+        ExtraColumn::Device => format_devices(ctx, process.pid),
+        ExtraColumn::Fsync => format_syncs(ctx, process.pid),
+        ExtraColumn::NetRead => {
+            format_net(ctx, process.pid, |n| (n.read_bytes_per_sec, n.read_bytes))
+        }
+        ExtraColumn::NetWrite => {
+            format_net(ctx, process.pid, |n| (n.write_bytes_per_sec, n.write_bytes))
+        }
+    }
+}
+
+/// Format a process's network rate, or its total when accumulated; threads
+/// show the traffic of their whole process, as sockets are shared
+fn format_net(ctx: &ColumnContext, pid: i32, value: impl Fn(&NetIo) -> (f64, u64)) -> String {
+    let Some(net) = &ctx.net else {
+        return "-".to_string();
+    };
+    let (rate, total) = net.get(&pid).map_or((0.0, 0), value);
+    if ctx.accumulated {
+        human_size(total as i64)
+    } else {
+        human_size(rate as i64) + "/s"
+    }
+}
+
+/// Format a process's sync call rate, or its total when accumulated; threads
+/// show the calls of their whole process
+fn format_syncs(ctx: &ColumnContext, pid: i32) -> String {
+    let Some(syncs) = &ctx.syncs else {
+        return "-".to_string();
+    };
+    let sync = syncs.get(&pid).copied().unwrap_or_default();
+    if ctx.accumulated {
+        sync.calls.to_string()
+    } else if sync.calls_per_sec < 10.0 {
+        format!("{:.1}/s", sync.calls_per_sec)
+    } else {
+        format!("{:.0}/s", sync.calls_per_sec)
+    }
+}
+
+/// Format the mount point a process has the most files open for writing on,
+/// with the number of other such mount points, e.g. `/home +2`, and a `!`
+/// when one of them is nearly full
+fn format_mounts(mounts: &[String], full: &HashSet<String>) -> String {
+    let text = match mounts {
+        [] => return "-".to_string(),
+        [mount] => mount.clone(),
+        [mount, others @ ..] => format!("{} +{}", mount, others.len()),
+    };
+    if writes_to_full(mounts, full) {
+        text + " !"
+    } else {
+        text
+    }
+}
+
+/// Whether a process writes to a nearly full filesystem, going by the
+/// mount points of its files open for writing
+pub fn writes_to_full(mounts: &[String], full: &HashSet<String>) -> bool {
+    mounts.iter().any(|mount| full.contains(mount))
+}
+
+/// Format the device a process sent the most bytes to over the interval, or
+/// in total when accumulated, with the number of other devices, e.g.
+/// `sda +1`; threads show the devices of their whole process
+fn format_devices(ctx: &ColumnContext, pid: i32) -> String {
+    let Some(device_io) = &ctx.device_io else {
+        return "-".to_string();
+    };
+    let mut devices: Vec<&DeviceIo> = device_io
+        .get(&pid)
+        .into_iter()
+        .flatten()
+        .filter(|io| {
+            if ctx.accumulated {
+                io.bytes > 0
+            } else {
+                io.bytes_per_sec > 0.0
+            }
+        })
+        .collect();
+    if ctx.accumulated {
+        devices.sort_by_key(|io| std::cmp::Reverse(io.bytes));
+    }
+    match devices.as_slice() {
+        [] => "-".to_string(),
+        [io] => io.device.clone(),
+        [io, others @ ..] => format!("{} +{}", io.device, others.len()),
+    }
+}
+
+/// Format a byte count as a rate over the interval, or as a total when
+/// accumulated, like DISK READ and DISK WRITE
+fn format_bytes(bytes: u64, duration: f64, accumulated: bool) -> String {
+    if accumulated {
+        human_size(bytes as i64)
+    } else {
+        format_bandwidth(bytes, duration)
+    }
+}
+
+/// Format a delay that the kernel only reports from taskstats `version` on,
+/// or `-` when it is older or taskstats is not used
+fn format_newer_delay(delay_ns: u64, version: u16, ctx: &ColumnContext) -> String {
+    if TaskStats::kernel_has(version) {
+        format_delay(delay_ns, ctx.duration, ctx.delay_ms)
+    } else {
+        "-".to_string()
+    }
+}
+
+/// Format the average of `count` delays summing to `delay_ns`, or `-` when
+/// there were none
+fn format_average_delay(delay_ns: u64, count: u64) -> String {
+    match delay_ns.checked_div(count) {
+        Some(average) => format_delay_ms(average),
+        None => "-".to_string(),
+    }
+}
+
+/// Format an event count as a rate per second over the interval, or as a
+/// total when accumulated
+fn format_count(count: u64, duration: f64, accumulated: bool) -> String {
+    if accumulated {
+        return count.to_string();
+    }
+    let rate = if duration > 0.0 {
+        count as f64 / duration
+    } else {
+        0.0
+    };
+    if rate < 10.0 {
+        format!("{:.1}/s", rate)
+    } else {
+        format!("{:.0}/s", rate)
+    }
+}
+
+/// Format CPU time as a percentage of one CPU over the interval, or as
+/// seconds when accumulated; like top, busy multi-threaded rows can exceed 100%
+fn format_cpu(cpu_ns: u64, duration: f64, accumulated: bool) -> String {
+    let secs = cpu_ns as f64 / 1_000_000_000.0;
+    if accumulated {
+        format!("{:.2}s", secs)
+    } else if duration > 0.0 {
+        format!("{:.1}%", secs / duration * 100.0)
+    } else {
+        "0.0%".to_string()
+    }
+}
+
+/// Format a process age compactly, e.g. `20s`, `5m07s`, `3h12m` or `2d04h`
+fn format_age(secs: u64) -> String {
+    let (days, hours, minutes, seconds) =
+        (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d{:02}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m{:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Format a memory size read from /proc, or `-` for kernel threads and
+/// processes that have exited
+fn format_kb(kb: Option<u64>) -> String {
+    match kb {
+        Some(kb) => human_size(kb.saturating_mul(1024) as i64),
+        None => "-".to_string(),
+    }
+}
+
+/// Draw a horizontal bar `width` cells wide filled to `share` (0.0-1.0),
+/// using eighth-block glyphs for sub-cell precision
+pub fn io_bar(share: f64, width: usize) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    let eighths = (share.clamp(0.0, 1.0) * (width * 8) as f64).round() as usize;
+    let full = eighths / 8;
+    let mut bar = "█".repeat(full);
+    if full < width {
+        bar.push(PARTIAL[eighths % 8]);
+    }
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            ExtraColumn::parse_list("graph").unwrap(),
+            vec![ExtraColumn::Graph]
+        );
+        assert_eq!(
+            ExtraColumn::parse_list(" GRAPH ,graph,").unwrap(),
+            vec![ExtraColumn::Graph]
+        );
+        assert!(ExtraColumn::parse_list("").unwrap().is_empty());
+        assert!(ExtraColumn::parse_list("nope").is_err());
+    }
+
+    #[test]
+    fn test_format_cpu() {
+        assert_eq!(format_cpu(250_000_000, 1.0, false), "25.0%");
+        assert_eq!(format_cpu(3_000_000_000, 2.0, false), "150.0%");
+        assert_eq!(format_cpu(1_500_000_000, 2.0, true), "1.50s");
+        assert_eq!(format_cpu(1, 0.0, false), "0.0%");
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(3, 2.0, false), "1.5/s");
+        assert_eq!(format_count(12345, 1.0, false), "12345/s");
+        assert_eq!(format_count(5, 0.0, false), "0.0/s");
+        assert_eq!(format_count(12345, 2.0, true), "12345");
+    }
+
+    #[test]
+    fn test_format_average_delay() {
+        // Many short waits and one long one add up to the same total
+        assert_eq!(format_average_delay(100_000_000, 1000), "0.10 ms");
+        assert_eq!(format_average_delay(100_000_000, 1), "100.00 ms");
+        assert_eq!(format_average_delay(0, 0), "-");
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(20), "20s");
+        assert_eq!(format_age(307), "5m07s");
+        assert_eq!(format_age(3 * 3600 + 12 * 60 + 5), "3h12m");
+        assert_eq!(format_age(2 * 86400 + 4 * 3600), "2d04h");
+    }
+
+    #[test]
+    fn test_format_kb() {
+        assert_eq!(format_kb(Some(0)), "0 B");
+        assert_eq!(format_kb(Some(1536)), "1.50 M");
+        assert_eq!(format_kb(None), "-");
+    }
+
+    #[test]
+    fn test_format_mounts() {
+        let none = HashSet::new();
+        assert_eq!(format_mounts(&[], &none), "-");
+        let mounts = ["/home".to_string(), "/".to_string(), "/tmp".to_string()];
+        assert_eq!(format_mounts(&mounts[..1], &none), "/home");
+        assert_eq!(format_mounts(&mounts, &none), "/home +2");
+        let full = HashSet::from(["/tmp".to_string()]);
+        assert_eq!(format_mounts(&mounts, &full), "/home +2 !");
+        assert_eq!(format_mounts(&mounts[..1], &full), "/home");
+    }
+
+    #[test]
+    fn test_format_devices() {
+        let io = |device: &str, bytes_per_sec: f64, bytes: u64| DeviceIo {
+            device: device.to_string(),
+            bytes_per_sec,
+            bytes,
+        };
+        let mut ctx = ColumnContext {
+            accumulated: false,
+            io_sum: 0,
+            duration: 1.0,
+            plain: false,
+            delay_ms: false,
+            memory: HashMap::new(),
+            mounts: HashMap::new(),
+            full_mounts: HashSet::new(),
+            containers: HashMap::new(),
+            io_weights: HashMap::new(),
+            fds: HashMap::new(),
+            net: None,
+            device_io: None,
+            syncs: None,
+            writeback: HashMap::new(),
+        };
+        assert_eq!(format_devices(&ctx, 1), "-");
+
+        let devices = vec![io("sda", 100.0, 100), io("sdb", 0.0, 5000)];
+        ctx.device_io = Some(HashMap::from([(1, devices)]));
+        // sdb was idle this interval
+        assert_eq!(format_devices(&ctx, 1), "sda");
+        assert_eq!(format_devices(&ctx, 2), "-");
+        ctx.accumulated = true;
+        assert_eq!(format_devices(&ctx, 1), "sdb +1");
+    }
+
+    #[test]
+    fn test_format_net() {
+        let mut ctx = ColumnContext {
+            accumulated: false,
+            io_sum: 0,
+            duration: 1.0,
+            plain: false,
+            delay_ms: false,
+            memory: HashMap::new(),
+            mounts: HashMap::new(),
+            full_mounts: HashSet::new(),
+            containers: HashMap::new(),
+            io_weights: HashMap::new(),
+            fds: HashMap::new(),
+            net: None,
+            device_io: None,
+            syncs: None,
+            writeback: HashMap::new(),
+        };
+        let read = |n: &NetIo| (n.read_bytes_per_sec, n.read_bytes);
+        assert_eq!(format_net(&ctx, 1, read), "-");
+
+        let net = NetIo {
+            read_bytes_per_sec: 2048.0,
+            read_bytes: 1024 * 1024,
+            ..Default::default()
+        };
+        ctx.net = Some(HashMap::from([(1, net)]));
+        assert_eq!(format_net(&ctx, 1, read), "2.00 K/s");
+        // Processes without traffic are measured too
+        assert_eq!(format_net(&ctx, 2, read), "0 B/s");
+        ctx.accumulated = true;
+        assert_eq!(format_net(&ctx, 1, read), "1.00 M");
+    }
+
+    #[test]
+    fn test_io_bar() {
+        assert_eq!(io_bar(0.0, 4), " ");
+        assert_eq!(io_bar(1.0, 4), "████");
+        assert_eq!(io_bar(0.5, 4), "██ ");
+        assert_eq!(io_bar(0.5 + 1.0 / 32.0, 4), "██▏");
+        assert_eq!(io_bar(2.0, 4), "████");
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const NLMSG_HEADER_LEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+/// Offset of the event data union in `struct proc_event`
+const EVENT_DATA_OFFSET: usize = 16;
+
+/// Receive buffer for events, so a fork storm does not overrun it before
+/// the listener thread catches up
+const RX_BUFFER_BYTES: libc::c_int = 1024 * 1024;
+
+/// A process event from the kernel's proc connector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcEvent {
+    /// A process or thread was created
+    Fork {
+        tid: i32,
+        tgid: i32,
+    },
+    /// A process replaced its program; its threads other than the caller
+    /// exit with it
+    Exec {
+        tgid: i32,
+    },
+    Exit {
+        tid: i32,
+    },
+}
+
+/// Parse the payload of one netlink message: a `cn_msg` carrying a
+/// `proc_event`
+///
+/// Returns `None` for events that are not tracked (UID, COMM, ...).
+pub fn parse_event(payload: &[u8]) -> Option<ProcEvent> {
+    let event = payload.get(CN_MSG_LEN..)?;
+    let field = |offset: usize| -> Option<u32> {
+        let bytes = event.get(offset..offset + 4)?;
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+    };
+    let data = |index: usize| field(EVENT_DATA_OFFSET + 4 * index).map(|v| v as i32);
+
+    match field(0)? {
+        libc::PROC_EVENT_FORK => Some(ProcEvent::Fork {
+            tid: data(2)?,
+            tgid: data(3)?,
+        }),
+        libc::PROC_EVENT_EXEC => Some(ProcEvent::Exec { tgid: data(1)? }),
+        libc::PROC_EVENT_EXIT => Some(ProcEvent::Exit { tid: data(0)? }),
+        _ => None,
+    }
+}
+
+/// Every `(TGID, TID)` pair listed in /proc
+pub fn scan_tasks() -> Result<Vec<(i32, i32)>> {
+    let mut tasks = Vec::new();
+    for entry in fs::read_dir("/proc")?.flatten() {
+        let Some(tgid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+        // Gone in between, or not readable
+        let Ok(task_entries) = fs::read_dir(format!("/proc/{}/task", tgid)) else {
+            continue;
+        };
+        for task_entry in task_entries.flatten() {
+            if let Some(tid) = task_entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+                tasks.push((tgid, tid));
+            }
+        }
+    }
+    Ok(tasks)
+}
+
+#[derive(Default)]
+struct Tasks {
+    /// TGID of every live task, by TID
+    by_tid: HashMap<i32, i32>,
+    /// Processes that called exec since the last [`TaskTracker::take_execs`]
+    execs: Vec<i32>,
+    /// Events were lost; /proc has to be scanned again
+    stale: bool,
+}
+
+impl Tasks {
+    fn apply(&mut self, event: ProcEvent) {
+        match event {
+            ProcEvent::Fork { tid, tgid } => {
+                self.by_tid.insert(tid, tgid);
+            }
+            ProcEvent::Exec { tgid } => self.execs.push(tgid),
+            ProcEvent::Exit { tid } => {
+                self.by_tid.remove(&tid);
+            }
+        }
+    }
+
+    /// Rebuild the set from /proc
+    ///
+    /// Zombies are left out: their exit event was either lost or sent
+    /// before subscribing, and no event comes when they are reaped.
+    fn rescan(&mut self) -> Result<()> {
+        self.by_tid = scan_tasks()?
+            .into_iter()
+            .filter(|&(tgid, tid)| is_alive(tgid, tid))
+            .map(|(tgid, tid)| (tid, tgid))
+            .collect();
+        self.stale = false;
+        Ok(())
+    }
+}
+
+fn is_alive(tgid: i32, tid: i32) -> bool {
+    let Ok(stat) = fs::read_to_string(format!("/proc/{}/task/{}/stat", tgid, tid)) else {
+        return false;
+    };
+    // The state follows the command name, which may contain parentheses
+    let state = stat
+        .rfind(')')
+        .and_then(|end| stat[end + 1..].split_whitespace().next());
+    !matches!(state, Some("Z" | "X") | None)
+}
+
+/// Set of live tasks, kept up to date from fork, exec and exit events so
+/// /proc does not have to be walked on every refresh
+///
+/// New tasks are known from the moment they fork. If the kernel drops
+/// events because the listener fell behind, the next [`TaskTracker::tasks`]
+/// call scans /proc again.
+pub struct TaskTracker {
+    tasks: Arc<Mutex<Tasks>>,
+}
+
+impl TaskTracker {
+    pub fn start() -> Result<Self> {
+        let socket = subscribe().context("Failed to subscribe to process events")?;
+
+        // Scanning after subscribing means no task can slip in between;
+        // events for tasks already found are applied twice, harmlessly
+        let mut tasks = Tasks::default();
+        tasks.rescan()?;
+        let tasks = Arc::new(Mutex::new(tasks));
+
+        let shared = Arc::downgrade(&tasks);
+        thread::Builder::new()
+            .name("proc-connector".to_string())
+            .spawn(move || {
+                let mut buf = vec![0u8; 8192];
+                loop {
+                    let received = recv(&socket, &mut buf);
+                    // The tracker was dropped
+                    let Some(tasks) = shared.upgrade() else {
+                        break;
+                    };
+                    let Ok(mut tasks) = tasks.lock() else {
+                        break;
+                    };
+                    match received {
+                        Ok(len) => {
+                            for event in messages(&buf[..len]).filter_map(parse_event) {
+                                tasks.apply(event);
+                            }
+                        }
+                        Err(e) if e.raw_os_error() == Some(libc::ENOBUFS) => tasks.stale = true,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(_) => {
+                            tasks.stale = true;
+                            break;
+                        }
+                    }
+                }
+            })
+            .context("Failed to spawn process event listener")?;
+
+        Ok(Self { tasks })
+    }
+
+    /// Every live `(TGID, TID)` pair
+    pub fn tasks(&self) -> Result<Vec<(i32, i32)>> {
+        let mut tasks = self
+            .tasks
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Process event listener panicked"))?;
+        if tasks.stale {
+            tasks.rescan()?;
+        }
+        Ok(tasks
+            .by_tid
+            .iter()
+            .map(|(&tid, &tgid)| (tgid, tid))
+            .collect())
+    }
+
+    /// Processes that replaced their program since the previous call, so
+    /// their command line has changed
+    pub fn take_execs(&self) -> Vec<i32> {
+        self.tasks
+            .lock()
+            .map(|mut tasks| mem::take(&mut tasks.execs))
+            .unwrap_or_default()
+    }
+}
+
+/// Open a connector socket and ask the kernel for process events; needs
+/// CAP_NET_ADMIN
+fn subscribe() -> io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_CONNECTOR,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = libc::CN_IDX_PROC;
+    let result = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Best effort: the default buffer still works, it just overruns sooner
+    unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUFFORCE,
+            &RX_BUFFER_BYTES as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        );
+    }
+
+    let request = listen_request();
+    let sent = unsafe {
+        libc::send(
+            socket.as_raw_fd(),
+            request.as_ptr() as *const libc::c_void,
+            request.len(),
+            0,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+/// `nlmsghdr` + `cn_msg` + `PROC_CN_MCAST_LISTEN`
+fn listen_request() -> Vec<u8> {
+    let op = libc::PROC_CN_MCAST_LISTEN;
+    let len = NLMSG_HEADER_LEN + CN_MSG_LEN + mem::size_of_val(&op);
+
+    let mut msg = Vec::with_capacity(len);
+    msg.extend_from_slice(&(len as u32).to_ne_bytes());
+    msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+    msg.extend_from_slice(&0u16.to_ne_bytes()); // flags
+    msg.extend_from_slice(&0u32.to_ne_bytes()); // seq
+    msg.extend_from_slice(&std::process::id().to_ne_bytes());
+
+    msg.extend_from_slice(&libc::CN_IDX_PROC.to_ne_bytes());
+    msg.extend_from_slice(&libc::CN_VAL_PROC.to_ne_bytes());
+    msg.extend_from_slice(&0u32.to_ne_bytes()); // seq
+    msg.extend_from_slice(&0u32.to_ne_bytes()); // ack
+    msg.extend_from_slice(&(mem::size_of_val(&op) as u16).to_ne_bytes());
+    msg.extend_from_slice(&0u16.to_ne_bytes()); // flags
+
+    msg.extend_from_slice(&op.to_ne_bytes());
+    msg
+}
+
+fn recv(socket: &OwnedFd, buf: &mut [u8]) -> io::Result<usize> {
+    let len = unsafe {
+        libc::recv(
+            socket.as_raw_fd(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            0,
+        )
+    };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(len as usize)
+}
+
+/// Payloads of the netlink messages in a datagram
+fn messages(mut buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        let len = u32::from_ne_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+        if len < NLMSG_HEADER_LEN || len > buf.len() {
+            return None;
+        }
+        let payload = &buf[NLMSG_HEADER_LEN..len];
+        // Messages are padded to 4 bytes
+        buf = buf.get((len + 3) & !3..).unwrap_or_default();
+        Some(payload)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(what: u32, data: &[u32]) -> Vec<u8> {
+        let mut payload = vec![0u8; CN_MSG_LEN];
+        payload.extend_from_slice(&what.to_ne_bytes());
+        payload.extend_from_slice(&3u32.to_ne_bytes()); // cpu
+        payload.extend_from_slice(&123u64.to_ne_bytes()); // timestamp
+        for value in data {
+            payload.extend_from_slice(&value.to_ne_bytes());
+        }
+        payload
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let fork = event(libc::PROC_EVENT_FORK, &[100, 100, 205, 100]);
+        assert_eq!(
+            parse_event(&fork),
+            Some(ProcEvent::Fork {
+                tid: 205,
+                tgid: 100
+            })
+        );
+        let exit = event(libc::PROC_EVENT_EXIT, &[205, 100, 0, 17]);
+        assert_eq!(parse_event(&exit), Some(ProcEvent::Exit { tid: 205 }));
+        let uid = event(libc::PROC_EVENT_UID, &[205, 100, 0, 0]);
+        assert_eq!(parse_event(&uid), None);
+        assert_eq!(parse_event(&fork[..30]), None);
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut datagram = Vec::new();
+        for payload in [&[1u8, 2, 3][..], &[4u8, 5, 6, 7][..]] {
+            datagram.extend_from_slice(&((NLMSG_HEADER_LEN + payload.len()) as u32).to_ne_bytes());
+            datagram.extend_from_slice(&[0u8; NLMSG_HEADER_LEN - 4]);
+            datagram.extend_from_slice(payload);
+            datagram.resize((datagram.len() + 3) & !3, 0);
+        }
+        let payloads: Vec<&[u8]> = messages(&datagram).collect();
+        assert_eq!(payloads, vec![&[1u8, 2, 3][..], &[4u8, 5, 6, 7][..]]);
+    }
+}
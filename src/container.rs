@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+/// How long a container name is trusted before the engine is asked again;
+/// containers can be renamed, but rarely are
+const NAME_TTL: Duration = Duration::from_secs(60);
+
+/// How long to wait for a container engine, as lookups hold up drawing
+const ENGINE_TIMEOUT: Duration = Duration::from_millis(100);
+
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+const PODMAN_SOCKET: &str = "/run/podman/podman.sock";
+
+/// Container engines recognized in cgroup paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Runtime {
+    Docker,
+    Podman,
+    /// A Kubernetes pod, run by containerd or CRI-O
+    Kubernetes,
+    Lxc,
+}
+
+impl Runtime {
+    fn label(&self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+            Runtime::Kubernetes => "k8s",
+            Runtime::Lxc => "lxc",
+        }
+    }
+}
+
+/// A container as named by its cgroup: an ID, or the name itself for LXC
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContainerId {
+    pub runtime: Runtime,
+    pub id: String,
+}
+
+impl ContainerId {
+    /// What to show when the engine does not tell the name, such as
+    /// `docker:3f2a9c1b7e4d`
+    pub fn short(&self) -> String {
+        let id = if self.runtime == Runtime::Lxc {
+            &self.id
+        } else {
+            &self.id[..self.id.len().min(12)]
+        };
+        format!("{}:{}", self.runtime.label(), id)
+    }
+}
+
+/// The container a process runs in, from /proc/[pid]/cgroup content
+///
+/// Both the systemd layout (`docker-ID.scope`) and the cgroupfs one
+/// (`/docker/ID`) are recognized, in the v2 hierarchy as well as the v1
+/// ones.
+pub fn parse_cgroup(content: &str) -> Option<ContainerId> {
+    content
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .find_map(from_path)
+}
+
+/// The container of the process in the cgroup at `path`
+pub fn from_path(path: &str) -> Option<ContainerId> {
+    let container = |runtime, id: &str| {
+        Some(ContainerId {
+            runtime,
+            id: id.to_string(),
+        })
+    };
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+
+    // Innermost first, so a container nested in another shows as itself
+    for component in components.iter().rev() {
+        let name = component.strip_suffix(".scope").unwrap_or(component);
+        for (prefix, runtime) in [
+            ("docker-", Runtime::Docker),
+            ("libpod-", Runtime::Podman),
+            ("cri-containerd-", Runtime::Kubernetes),
+            ("crio-", Runtime::Kubernetes),
+        ] {
+            match name.strip_prefix(prefix) {
+                Some(id) if is_id(id) => return container(runtime, id),
+                _ => {}
+            }
+        }
+        if let Some(name) = name.strip_prefix("lxc.payload.") {
+            return container(Runtime::Lxc, name);
+        }
+    }
+
+    match components.as_slice() {
+        ["docker", id, ..] if is_id(id) => container(Runtime::Docker, id),
+        ["lxc", name, ..] => container(Runtime::Lxc, name),
+        ["kubepods", .., id] if is_id(id) => container(Runtime::Kubernetes, id),
+        _ => None,
+    }
+}
+
+/// Container IDs are 64 hex digits
+fn is_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// A string field of the response to a Docker API inspect request, which
+/// podman's compatible API answers the same way
+///
+/// Only the top-level `Id` and `Name` come before the nested objects that
+/// have one of their own, and neither needs escaping.
+fn parse_inspect_field<'a>(response: &'a str, field: &str) -> Option<&'a str> {
+    let (status, body) = response.split_once("\r\n\r\n")?;
+    if status.split_whitespace().nth(1)? != "200" {
+        return None;
+    }
+    let (_, rest) = body.split_once(&format!("\"{}\":\"", field))?;
+    let (value, _) = rest.split_once('"')?;
+    Some(value)
+}
+
+/// The container name in the response to an inspect request
+fn parse_inspect_name(response: &str) -> Option<String> {
+    let name = parse_inspect_field(response, "Name")?.trim_start_matches('/');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Ask the engine listening on `socket` about container `id`, which may
+/// also be its name
+fn inspect(socket: &str, id: &str) -> Option<String> {
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(ENGINE_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(ENGINE_TIMEOUT)).ok()?;
+    // HTTP/1.0 so that the engine closes the connection after answering
+    write!(
+        stream,
+        "GET /containers/{}/json HTTP/1.0\r\nHost: localhost\r\n\r\n",
+        id
+    )
+    .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+/// Human-readable name of a container, asking its engine where there is one
+///
+/// Kubernetes pods get the hostname kubelet gives them, which is the pod
+/// name; the CRI sockets speak gRPC, which iotop does not.
+fn query_name(container: &ContainerId, pid: i32) -> Option<String> {
+    match container.runtime {
+        Runtime::Docker => parse_inspect_name(&inspect(DOCKER_SOCKET, &container.id)?),
+        Runtime::Podman => parse_inspect_name(&inspect(PODMAN_SOCKET, &container.id)?),
+        Runtime::Kubernetes => fs::read_to_string(format!("/proc/{}/root/etc/hostname", pid))
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty()),
+        Runtime::Lxc => Some(container.id.clone()),
+    }
+}
+
+/// Container given to `--container`, by ID, ID prefix or name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerFilter {
+    given: String,
+    /// Full ID of the container of that name, if Docker or podman knows it
+    resolved: Option<String>,
+}
+
+impl ContainerFilter {
+    /// Look up the ID of the container `given` names with the engines,
+    /// which also complete ID prefixes
+    ///
+    /// LXC containers are known by their name already; Kubernetes ones can
+    /// only be given by ID.
+    pub fn resolve(given: &str) -> Self {
+        // Anything else would not be a valid request, nor a container name
+        let valid = given
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"_.-".contains(&b));
+        let sockets = if valid {
+            [DOCKER_SOCKET, PODMAN_SOCKET].as_slice()
+        } else {
+            &[]
+        };
+        let resolved = sockets.iter().find_map(|socket| {
+            let response = inspect(socket, given)?;
+            parse_inspect_field(&response, "Id")
+                .filter(|id| is_id(id))
+                .map(str::to_string)
+        });
+        Self {
+            given: given.to_string(),
+            resolved,
+        }
+    }
+
+    pub fn matches(&self, container: &ContainerId) -> bool {
+        // Shorter prefixes would match containers by chance
+        self.resolved.as_deref() == Some(&container.id)
+            || (self.given.len() >= 4 && container.id.starts_with(&self.given))
+            || (container.runtime == Runtime::Lxc && container.id == self.given)
+    }
+}
+
+/// Containers of the processes currently on screen
+///
+/// Like the mount points, they are only looked up for the rows being drawn.
+/// A process stays in its container, so that is looked up once; names are
+/// asked again once [`NAME_TTL`] passes.
+#[derive(Default)]
+pub struct ContainerCache {
+    containers: HashMap<i32, Option<ContainerId>>,
+    names: HashMap<ContainerId, (Instant, Option<String>)>,
+}
+
+impl ContainerCache {
+    /// Names of the containers `pids` run in, for those in one, dropping the
+    /// processes that are no longer shown
+    pub fn lookup(&mut self, pids: &[i32]) -> HashMap<i32, String> {
+        self.containers.retain(|pid, _| pids.contains(pid));
+        let mut found = HashMap::new();
+        for &pid in pids {
+            let container = self.containers.entry(pid).or_insert_with(|| {
+                fs::read_to_string(format!("/proc/{}/cgroup", pid))
+                    .ok()
+                    .and_then(|content| parse_cgroup(&content))
+            });
+            let Some(container) = container else {
+                continue;
+            };
+            let (at, name) = self
+                .names
+                .entry(container.clone())
+                .or_insert_with(|| (Instant::now(), query_name(container, pid)));
+            if at.elapsed() >= NAME_TTL {
+                *at = Instant::now();
+                *name = query_name(container, pid);
+            }
+            found.insert(pid, name.clone().unwrap_or_else(|| container.short()));
+        }
+        let shown: Vec<&ContainerId> = self.containers.values().flatten().collect();
+        self.names.retain(|container, _| shown.contains(&container));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f2a9c1b7e4d5a6b8c9d0e1f2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0a1b";
+
+    #[test]
+    fn test_parse_cgroup() {
+        let found = |content: &str| parse_cgroup(content).map(|c| c.short());
+        assert_eq!(
+            found(&format!("0::/system.slice/docker-{}.scope\n", ID)),
+            Some("docker:3f2a9c1b7e4d".to_string())
+        );
+        assert_eq!(
+            found(&format!(
+                "12:pids:/docker/{}\n1:name=systemd:/docker/{}\n",
+                ID, ID
+            )),
+            Some("docker:3f2a9c1b7e4d".to_string())
+        );
+        assert_eq!(
+            found(&format!(
+                "0::/machine.slice/libpod-{}.scope/container\n",
+                ID
+            )),
+            Some("podman:3f2a9c1b7e4d".to_string())
+        );
+        assert_eq!(
+            found(&format!(
+                "0::/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1234.slice/cri-containerd-{}.scope\n",
+                ID
+            )),
+            Some("k8s:3f2a9c1b7e4d".to_string())
+        );
+        assert_eq!(
+            found(&format!("0::/kubepods/besteffort/pod1234/{}\n", ID)),
+            Some("k8s:3f2a9c1b7e4d".to_string())
+        );
+        assert_eq!(
+            found("0::/lxc.payload.web/init.scope\n"),
+            Some("lxc:web".to_string())
+        );
+        // Podman's monitor process runs next to the container, not in it
+        assert_eq!(
+            found(&format!("0::/machine.slice/libpod-conmon-{}.scope\n", ID)),
+            None
+        );
+        assert_eq!(
+            found("0::/user.slice/user-1000.slice/session-2.scope\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_inspect_name() {
+        let response = "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+                        {\"Id\":\"3f2a\",\"State\":{\"Status\":\"running\"},\"Name\":\"/web\",\
+                        \"GraphDriver\":{\"Name\":\"overlay2\"}}";
+        assert_eq!(parse_inspect_name(response), Some("web".to_string()));
+        let missing = "HTTP/1.0 404 Not Found\r\n\r\n{\"message\":\"No such container\"}";
+        assert_eq!(parse_inspect_name(missing), None);
+        assert_eq!(parse_inspect_field(response, "Id"), Some("3f2a"));
+    }
+
+    #[test]
+    fn test_container_filter() {
+        let docker = from_path(&format!("/system.slice/docker-{}.scope", ID)).unwrap();
+        let lxc = from_path("/lxc.payload.web").unwrap();
+        let filter = |given: &str, resolved: Option<&str>| ContainerFilter {
+            given: given.to_string(),
+            resolved: resolved.map(str::to_string),
+        };
+        assert!(filter("3f2a9c1b", None).matches(&docker));
+        assert!(!filter("3f2", None).matches(&docker));
+        assert!(filter("web", Some(ID)).matches(&docker));
+        assert!(filter("web", None).matches(&lxc));
+        assert!(!filter("db", None).matches(&lxc));
+    }
+}
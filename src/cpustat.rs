@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+
+/// Cumulative CPU time from a `cpu` line of /proc/stat, in clock ticks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    /// Time idle while some task waited for I/O
+    pub iowait: u64,
+    /// Time in every state; guest time is already part of user time
+    pub total: u64,
+}
+
+impl CpuTimes {
+    /// Parse the fields after the name of a `cpu` line
+    fn parse(fields: &str) -> Option<Self> {
+        let values: Vec<u64> = fields
+            .split_whitespace()
+            .map(|field| field.parse().ok())
+            .collect::<Option<_>>()?;
+        // user nice system idle iowait irq softirq steal guest guest_nice
+        Some(CpuTimes {
+            iowait: *values.get(4)?,
+            total: values.iter().take(8).sum(),
+        })
+    }
+
+    /// Share of the time between `prev` and this sample spent in iowait, in
+    /// percent; `None` when no time passed
+    pub fn iowait_percent(&self, prev: &CpuTimes) -> Option<f64> {
+        let total = self.total.checked_sub(prev.total).filter(|&t| t > 0)?;
+        let iowait = self.iowait.saturating_sub(prev.iowait);
+        Some(iowait as f64 * 100.0 / total as f64)
+    }
+}
+
+/// Parse the summary `cpu` line of /proc/stat content
+pub fn parse_stat(content: &str) -> Option<CpuTimes> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("cpu "))
+        .and_then(CpuTimes::parse)
+}
+
+/// Parse the `cpuN` lines of /proc/stat content, by CPU number; offline
+/// CPUs have none
+pub fn parse_cpus(content: &str) -> HashMap<u32, CpuTimes> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (name, fields) = line.strip_prefix("cpu")?.split_once(' ')?;
+            Some((name.parse().ok()?, CpuTimes::parse(fields)?))
+        })
+        .collect()
+}
+
+/// iowait of one CPU over an interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuIowait {
+    pub cpu: u32,
+    pub percent: f64,
+}
+
+/// System and per-CPU iowait over an interval
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Iowait {
+    /// Over all CPUs, in percent of CPU time
+    pub total: Option<f64>,
+    /// Each online CPU, by CPU number
+    pub cpus: Vec<CpuIowait>,
+}
+
+/// Samples /proc/stat and turns consecutive samples into the system and
+/// per-CPU iowait percentages
+///
+/// iowait is idle time with I/O outstanding: a busy CPU hides it, so a low
+/// value does not rule out I/O stalls, but a high one means CPUs sat idle
+/// waiting for storage. A task waiting on a slow device keeps only the CPU
+/// it last ran on in iowait, which the system-wide average spreads thin on
+/// a large machine.
+#[derive(Default)]
+pub struct CpuSampler {
+    prev: Option<CpuTimes>,
+    prev_cpus: HashMap<u32, CpuTimes>,
+}
+
+impl CpuSampler {
+    /// Read /proc/stat and return the iowait since the previous call, empty
+    /// on the first call
+    pub fn sample(&mut self) -> Result<Iowait> {
+        let content = fs::read_to_string("/proc/stat")?;
+        let current = parse_stat(&content).context("No cpu line in /proc/stat")?;
+        let total = self
+            .prev
+            .replace(current)
+            .and_then(|prev| current.iowait_percent(&prev));
+
+        let current_cpus = parse_cpus(&content);
+        let mut cpus: Vec<CpuIowait> = current_cpus
+            .iter()
+            .filter_map(|(&cpu, times)| {
+                let percent = times.iowait_percent(self.prev_cpus.get(&cpu)?)?;
+                Some(CpuIowait { cpu, percent })
+            })
+            .collect();
+        cpus.sort_by_key(|c| c.cpu);
+        self.prev_cpus = current_cpus;
+
+        Ok(Iowait { total, cpus })
+    }
+}
+
+/// Combine the iowait of two consecutive intervals into that of both,
+/// weighting each by its length
+pub fn merge_rates(older: &Iowait, older_secs: f64, newer: &Iowait, newer_secs: f64) -> Iowait {
+    Iowait {
+        total: merge_iowait(older.total, older_secs, newer.total, newer_secs),
+        cpus: merge_cpus(&older.cpus, older_secs, &newer.cpus, newer_secs),
+    }
+}
+
+fn merge_iowait(
+    older: Option<f64>,
+    older_secs: f64,
+    newer: Option<f64>,
+    newer_secs: f64,
+) -> Option<f64> {
+    let secs = older_secs + newer_secs;
+    match (older, newer) {
+        (Some(older), Some(newer)) if secs > 0.0 => {
+            Some((older * older_secs + newer * newer_secs) / secs)
+        }
+        _ => newer.or(older),
+    }
+}
+
+/// CPUs only in one of the intervals keep their value
+fn merge_cpus(
+    older: &[CpuIowait],
+    older_secs: f64,
+    newer: &[CpuIowait],
+    newer_secs: f64,
+) -> Vec<CpuIowait> {
+    let mut merged = newer.to_vec();
+    for older in older {
+        match merged.iter_mut().find(|c| c.cpu == older.cpu) {
+            Some(cpu) => {
+                cpu.percent = merge_iowait(
+                    Some(older.percent),
+                    older_secs,
+                    Some(cpu.percent),
+                    newer_secs,
+                )
+                .unwrap_or(cpu.percent)
+            }
+            None => merged.push(*older),
+        }
+    }
+    merged.sort_by_key(|c| c.cpu);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iowait_percent() {
+        let before = "cpu  100 0 50 800 40 5 5 0 0 0\ncpu0 50 0 25 400 20 2 3 0 0 0\n";
+        let after = "cpu  130 0 60 880 70 5 5 0 20 0\ncpu0 65 0 30 440 35 2 3 0 10 0\n";
+        let before = parse_stat(before).unwrap();
+        let after = parse_stat(after).unwrap();
+        assert_eq!(before.iowait, 40);
+        // 30 of 150 ticks, guest time not counted twice
+        assert_eq!(after.iowait_percent(&before), Some(20.0));
+        assert_eq!(after.iowait_percent(&after), None);
+        assert!(parse_stat("intr 1 2 3\n").is_none());
+
+        assert_eq!(merge_iowait(Some(10.0), 1.0, Some(40.0), 2.0), Some(30.0));
+        assert_eq!(merge_iowait(None, 1.0, Some(40.0), 2.0), Some(40.0));
+    }
+
+    #[test]
+    fn test_parse_cpus() {
+        let before = parse_cpus(
+            "cpu  10 0 0 10 0 0 0 0 0 0
+cpu0 5 0 0 5 0 0 0 0 0 0
+cpu2 5 0 0 5 0 0 0 0 0 0
+intr 1
+",
+        );
+        let after = parse_cpus(
+            "cpu  10 0 0 20 20 0 0 0 0 0
+cpu0 5 0 0 15 0 0 0 0 0 0
+cpu2 5 0 0 5 20 0 0 0 0 0
+",
+        );
+        assert_eq!(before.len(), 2);
+        assert_eq!(after[&0].iowait_percent(&before[&0]), Some(0.0));
+        assert_eq!(after[&2].iowait_percent(&before[&2]), Some(100.0));
+
+        let cpu = |cpu, percent| CpuIowait { cpu, percent };
+        let merged = merge_cpus(&[cpu(0, 10.0), cpu(1, 50.0)], 1.0, &[cpu(0, 40.0)], 2.0);
+        assert_eq!(merged, vec![cpu(0, 30.0), cpu(1, 50.0)]);
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use std::fs;
+use tokio::signal::unix::{signal, SignalKind};
+
+const SYSCTL: &str = "/proc/sys/kernel/task_delayacct";
+
+/// Delay accounting switched on for the session with `--enable-delayacct`,
+/// and switched off again when dropped
+///
+/// Tasks only accrue delays while it is on, so the SWAPIN and IO columns
+/// start from zero for the tasks already running.
+pub struct DelayAcct;
+
+impl DelayAcct {
+    /// Turn delay accounting on, or `None` if it is on already or cannot be
+    /// turned off (kernels before 5.14 have no sysctl for it)
+    pub fn enable() -> Result<Option<Self>> {
+        let Ok(current) = fs::read_to_string(SYSCTL) else {
+            return Ok(None);
+        };
+        if current.trim() != "0" {
+            return Ok(None);
+        }
+        fs::write(SYSCTL, "1").context("Cannot set kernel.task_delayacct=1 (needs root)")?;
+        let enabled = Self;
+        enabled.restore_on_signals()?;
+        Ok(Some(enabled))
+    }
+
+    /// Switch it off again when iotop is killed rather than quit, as the
+    /// guard is not dropped then
+    fn restore_on_signals(&self) -> Result<()> {
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sighup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            let received = tokio::select! {
+                _ = sigint.recv() => libc::SIGINT,
+                _ = sigterm.recv() => libc::SIGTERM,
+                _ = sighup.recv() => libc::SIGHUP,
+            };
+            disable();
+            std::process::exit(128 + received);
+        });
+        Ok(())
+    }
+}
+
+impl Drop for DelayAcct {
+    fn drop(&mut self) {
+        disable();
+    }
+}
+
+fn disable() {
+    let _ = fs::write(SYSCTL, "0");
+}
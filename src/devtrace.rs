@@ -0,0 +1,325 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::diskstats::DeviceNames;
+
+#[cfg(feature = "ebpf")]
+use crate::bpf::DeviceProbes;
+
+#[cfg(not(feature = "ebpf"))]
+use unsupported::DeviceProbes;
+
+/// Block I/O of one process on one device
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceIo {
+    /// Name of the device, a whole disk such as `nvme0n1` or, for a logical
+    /// volume, `vg0-data`
+    pub device: String,
+    pub bytes_per_sec: f64,
+    /// Bytes submitted since tracing started
+    pub bytes: u64,
+}
+
+/// Block I/O by process ID, busiest device first
+pub type DeviceRates = HashMap<i32, Vec<DeviceIo>>;
+
+/// Running byte count of one process on one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviceTotal {
+    pub tgid: u32,
+    /// Kernel encoding of the device number: major << 20 | minor
+    pub dev: u32,
+    pub bytes: u64,
+}
+
+/// Attributes the block I/O processes submit to the devices it goes to, with
+/// an eBPF program (only with the `ebpf` cargo feature)
+///
+/// Stacked devices count at every layer: a write to a logical volume shows
+/// up on `dm-0` and again on the disk beneath it. I/O to a partition counts
+/// for its whole disk, as the bio is remapped to it before it is queued.
+pub struct DeviceTracer {
+    probes: DeviceProbes,
+    prev: HashMap<(u32, u32), u64>,
+    prev_at: Instant,
+    /// Friendly device names by kernel device number
+    names: HashMap<u32, String>,
+}
+
+impl DeviceTracer {
+    pub fn start() -> Result<Self> {
+        Ok(Self {
+            probes: DeviceProbes::attach()?,
+            prev: HashMap::new(),
+            prev_at: Instant::now(),
+            names: HashMap::new(),
+        })
+    }
+
+    /// Rates of each process on each device since the previous call, plus
+    /// totals
+    pub fn sample(&mut self) -> Result<DeviceRates> {
+        let totals = self.probes.totals()?;
+        let now = Instant::now();
+        let secs = now.duration_since(self.prev_at).as_secs_f64();
+        self.prev_at = now;
+
+        // Devices appear when hot-plugged
+        if totals.iter().any(|t| !self.names.contains_key(&t.dev)) {
+            if let Ok(content) = fs::read_to_string("/proc/diskstats") {
+                let names = parse_device_names(&content);
+                let friendly = DeviceNames::read(names.values().map(String::as_str));
+                self.names = names
+                    .iter()
+                    .map(|(&dev, name)| (dev, friendly.name(name).to_string()))
+                    .collect();
+            }
+        }
+        let rates = account(&mut self.prev, &totals, secs, |dev| {
+            self.names
+                .get(&dev)
+                .cloned()
+                .unwrap_or_else(|| format!("{}:{}", dev >> 20, dev & 0xfffff))
+        });
+
+        for total in &totals {
+            if !Path::new(&format!("/proc/{}", total.tgid)).exists() {
+                self.probes.forget(total);
+                self.prev.remove(&(total.tgid, total.dev));
+            }
+        }
+        Ok(rates)
+    }
+}
+
+/// Turn running totals into per-process rates over `secs`, against the
+/// totals seen last time (`prev`)
+fn account(
+    prev: &mut HashMap<(u32, u32), u64>,
+    totals: &[DeviceTotal],
+    secs: f64,
+    name: impl Fn(u32) -> String,
+) -> DeviceRates {
+    let mut rates = DeviceRates::new();
+    for total in totals {
+        let last = prev.insert((total.tgid, total.dev), total.bytes);
+        let bytes = total.bytes.saturating_sub(last.unwrap_or_default());
+        rates.entry(total.tgid as i32).or_default().push(DeviceIo {
+            device: name(total.dev),
+            bytes_per_sec: if secs > 0.0 { bytes as f64 / secs } else { 0.0 },
+            bytes: total.bytes,
+        });
+    }
+    for devices in rates.values_mut() {
+        busiest_first(devices);
+    }
+    rates
+}
+
+fn busiest_first(devices: &mut [DeviceIo]) {
+    devices.sort_by(|a, b| {
+        b.bytes_per_sec
+            .total_cmp(&a.bytes_per_sec)
+            .then_with(|| b.bytes.cmp(&a.bytes))
+            .then_with(|| a.device.cmp(&b.device))
+    });
+}
+
+/// Combine the device rates of two consecutive intervals into rates over
+/// both, weighting each by its length; totals come from the newer one
+pub fn merge_rates(
+    older: &DeviceRates,
+    older_secs: f64,
+    newer: &DeviceRates,
+    newer_secs: f64,
+) -> DeviceRates {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.clone();
+    }
+
+    let mut merged: HashMap<i32, HashMap<String, (f64, u64)>> = HashMap::new();
+    for (rates, weight) in [(older, older_secs / secs), (newer, newer_secs / secs)] {
+        for (&tgid, devices) in rates {
+            for io in devices {
+                let entry = merged
+                    .entry(tgid)
+                    .or_default()
+                    .entry(io.device.clone())
+                    .or_default();
+                entry.0 += io.bytes_per_sec * weight;
+                entry.1 = entry.1.max(io.bytes);
+            }
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(tgid, devices)| {
+            let mut devices: Vec<DeviceIo> = devices
+                .into_iter()
+                .map(|(device, (bytes_per_sec, bytes))| DeviceIo {
+                    device,
+                    bytes_per_sec,
+                    bytes,
+                })
+                .collect();
+            busiest_first(&mut devices);
+            (tgid, devices)
+        })
+        .collect()
+}
+
+/// Whether process `pid` sent I/O to `device` over the interval, or at all
+/// when `accumulated`
+pub fn did_io_on(rates: &DeviceRates, pid: i32, device: &str, accumulated: bool) -> bool {
+    rates.get(&pid).into_iter().flatten().any(|io| {
+        let active = if accumulated {
+            io.bytes > 0
+        } else {
+            io.bytes_per_sec > 0.0
+        };
+        active && is_device_or_partition(&io.device, device)
+    })
+}
+
+/// Whether `name` is `device`, or a partition of it such as `sda1` or
+/// `nvme0n1p2`
+fn is_device_or_partition(name: &str, device: &str) -> bool {
+    let Some(rest) = name.strip_prefix(device) else {
+        return false;
+    };
+    let number = rest.strip_prefix('p').unwrap_or(rest);
+    rest.is_empty() || (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parse the device given to `--device`, by name or path, e.g. `sda`,
+/// `/dev/nvme0n1` or `/dev/mapper/vg0-data`
+pub fn parse_device(value: &str) -> Result<String, String> {
+    let device = value.trim();
+    let device = device
+        .strip_prefix("/dev/mapper/")
+        .or_else(|| device.strip_prefix("/dev/"))
+        .unwrap_or(device);
+    if device.is_empty() {
+        return Err("empty device name".to_string());
+    }
+    Ok(device.to_string())
+}
+
+/// Device names by kernel device number from /proc/diskstats content
+fn parse_device_names(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let major: u32 = fields.next()?.parse().ok()?;
+            let minor: u32 = fields.next()?.parse().ok()?;
+            let name = fields.next()?;
+            Some((major << 20 | minor, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "ebpf"))]
+mod unsupported {
+    use super::DeviceTotal;
+    use anyhow::{bail, Result};
+
+    /// Stand-in for builds without the `ebpf` feature
+    pub struct DeviceProbes;
+
+    impl DeviceProbes {
+        pub fn attach() -> Result<Self> {
+            bail!("iotop was built without eBPF support; rebuild it with `--features ebpf`")
+        }
+
+        pub fn totals(&self) -> Result<Vec<DeviceTotal>> {
+            Ok(Vec::new())
+        }
+
+        pub fn forget(&self, _total: &DeviceTotal) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io(device: &str, bytes_per_sec: f64, bytes: u64) -> DeviceIo {
+        DeviceIo {
+            device: device.to_string(),
+            bytes_per_sec,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn test_account() {
+        let names = parse_device_names(
+            " 259       0 nvme0n1 100 0 0 0 0 0 0 0 0 0 0\n\
+             \x20 8      16 sdb 1 0 0 0 0 0 0 0 0 0 0\n",
+        );
+        assert_eq!(names[&(259 << 20)], "nvme0n1");
+        let name = |dev| names.get(&dev).cloned().unwrap_or_default();
+        let total = |tgid, dev, bytes| DeviceTotal { tgid, dev, bytes };
+
+        let mut prev = HashMap::new();
+        let rates = account(
+            &mut prev,
+            &[total(10, 259 << 20, 4096), total(10, 8 << 20 | 16, 8192)],
+            2.0,
+            name,
+        );
+        assert_eq!(
+            rates[&10],
+            vec![io("sdb", 4096.0, 8192), io("nvme0n1", 2048.0, 4096)]
+        );
+
+        // Only the growth counts; an idle device keeps its total
+        let rates = account(
+            &mut prev,
+            &[total(10, 259 << 20, 6144), total(10, 8 << 20 | 16, 8192)],
+            1.0,
+            name,
+        );
+        assert_eq!(
+            rates[&10],
+            vec![io("nvme0n1", 2048.0, 6144), io("sdb", 0.0, 8192)]
+        );
+    }
+
+    #[test]
+    fn test_did_io_on() {
+        let rates = DeviceRates::from([(1, vec![io("sda2", 100.0, 100), io("vg0-data", 0.0, 50)])]);
+        assert!(did_io_on(&rates, 1, "sda", false));
+        assert!(did_io_on(&rates, 1, "sda2", false));
+        assert!(!did_io_on(&rates, 1, "sd", false));
+        assert!(!did_io_on(&rates, 1, "vg0-data", false));
+        assert!(did_io_on(&rates, 1, "vg0-data", true));
+        assert!(!did_io_on(&rates, 2, "sda", true));
+        assert!(is_device_or_partition("nvme0n1p2", "nvme0n1"));
+        assert!(!is_device_or_partition("nvme0n1", "nvme0"));
+
+        assert_eq!(
+            parse_device("/dev/mapper/vg0-data"),
+            Ok("vg0-data".to_string())
+        );
+        assert_eq!(parse_device(" /dev/sda "), Ok("sda".to_string()));
+        assert!(parse_device("/dev/").is_err());
+    }
+
+    #[test]
+    fn test_merge_rates() {
+        let older = DeviceRates::from([(1, vec![io("sda", 100.0, 100)])]);
+        let newer = DeviceRates::from([(1, vec![io("sdb", 300.0, 300), io("sda", 0.0, 100)])]);
+        let merged = merge_rates(&older, 1.0, &newer, 1.0);
+        assert_eq!(
+            merged[&1],
+            vec![io("sdb", 150.0, 300), io("sda", 50.0, 100)]
+        );
+    }
+}
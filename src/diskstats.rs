@@ -0,0 +1,572 @@
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::mounts::{self, FsUsage};
+
+/// /proc/diskstats counts sectors in 512-byte units regardless of the device
+const SECTOR_SIZE: u64 = 512;
+
+/// Cumulative counters from one line of /proc/diskstats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskCounters {
+    pub reads_completed: u64,
+    pub sectors_read: u64,
+    /// Milliseconds spent by all reads, from submission to completion
+    pub read_ticks: u64,
+    pub writes_completed: u64,
+    pub sectors_written: u64,
+    /// Milliseconds spent by all writes
+    pub write_ticks: u64,
+    /// Milliseconds during which the device had I/O in flight
+    pub io_ticks: u64,
+    /// Milliseconds spent by all requests in flight, summed, so that its
+    /// growth over an interval is the average queue depth times its length
+    pub time_in_queue: u64,
+}
+
+impl DiskCounters {
+    /// Whether these counters can follow `prev` on the same device: a device
+    /// detached and another attached under its kernel name between two
+    /// samples starts again from zero
+    fn follows(&self, prev: &DiskCounters) -> bool {
+        self.reads_completed >= prev.reads_completed
+            && self.writes_completed >= prev.writes_completed
+            && self.io_ticks >= prev.io_ticks
+    }
+}
+
+/// Parse /proc/diskstats content into `(device name, counters)` pairs
+pub fn parse(content: &str) -> Vec<(String, DiskCounters)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 14 {
+                return None;
+            }
+            let field = |idx: usize| fields[idx].parse::<u64>().ok();
+            Some((
+                fields[2].to_string(),
+                DiskCounters {
+                    reads_completed: field(3)?,
+                    sectors_read: field(5)?,
+                    read_ticks: field(6)?,
+                    writes_completed: field(7)?,
+                    sectors_written: field(9)?,
+                    write_ticks: field(10)?,
+                    io_ticks: field(12)?,
+                    time_in_queue: field(13)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Throughput of one block device over the last interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceRate {
+    /// Friendly name, see [`DeviceNames`]
+    pub name: String,
+    /// Layers of block devices beneath this one: 0 for a disk, 1 for an md
+    /// array or logical volume on disks, 2 for a logical volume on an array
+    pub depth: usize,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Milliseconds spent by the reads completed per second, summed, so
+    /// that divided by `read_iops` it is their average latency
+    pub read_time: f64,
+    pub write_time: f64,
+    /// Share of the interval the device was busy, in percent
+    pub utilization: f64,
+    /// Average number of requests in flight
+    pub queue_depth: f64,
+    /// Filesystems mounted from the device or its partitions, at the end
+    /// of the interval
+    pub filesystems: Vec<FsUsage>,
+}
+
+impl DeviceRate {
+    fn idle(name: &str, depth: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            depth,
+            read_bytes_per_sec: 0.0,
+            write_bytes_per_sec: 0.0,
+            read_iops: 0.0,
+            write_iops: 0.0,
+            read_time: 0.0,
+            write_time: 0.0,
+            utilization: 0.0,
+            queue_depth: 0.0,
+            filesystems: Vec::new(),
+        }
+    }
+
+    fn from_delta(
+        name: &str,
+        depth: usize,
+        prev: &DiskCounters,
+        curr: &DiskCounters,
+        secs: f64,
+    ) -> Self {
+        let rate = |curr: u64, prev: u64| curr.saturating_sub(prev) as f64 / secs;
+        Self {
+            name: name.to_string(),
+            depth,
+            read_bytes_per_sec: rate(curr.sectors_read, prev.sectors_read) * SECTOR_SIZE as f64,
+            write_bytes_per_sec: rate(curr.sectors_written, prev.sectors_written)
+                * SECTOR_SIZE as f64,
+            read_iops: rate(curr.reads_completed, prev.reads_completed),
+            write_iops: rate(curr.writes_completed, prev.writes_completed),
+            read_time: rate(curr.read_ticks, prev.read_ticks),
+            write_time: rate(curr.write_ticks, prev.write_ticks),
+            utilization: (rate(curr.io_ticks, prev.io_ticks) / 1000.0 * 100.0).min(100.0),
+            queue_depth: rate(curr.time_in_queue, prev.time_in_queue) / 1000.0,
+            filesystems: Vec::new(),
+        }
+    }
+
+    /// Average time a read took, queueing included, in milliseconds; `None`
+    /// without reads
+    ///
+    /// A device that is slow per request rather than busy shows here, as
+    /// opposed to the utilization, which only grows with the load.
+    pub fn read_latency_ms(&self) -> Option<f64> {
+        (self.read_iops > 0.0).then(|| self.read_time / self.read_iops)
+    }
+
+    /// Average time a write took, in milliseconds; `None` without writes
+    pub fn write_latency_ms(&self) -> Option<f64> {
+        (self.write_iops > 0.0).then(|| self.write_time / self.write_iops)
+    }
+
+    /// Filesystems on the device that are nearly full while it is being
+    /// written to, which is when running out of space is close
+    pub fn filling_up(&self) -> impl Iterator<Item = &FsUsage> {
+        let written = self.write_bytes_per_sec > 0.0;
+        self.filesystems
+            .iter()
+            .filter(move |fs| written && fs.is_nearly_full())
+    }
+}
+
+/// Combine the device rates of two consecutive intervals into rates over
+/// both, weighting each by its length
+///
+/// Devices missing from one interval count as idle during it.
+pub fn merge_rates(
+    older: &[DeviceRate],
+    older_secs: f64,
+    newer: &[DeviceRate],
+    newer_secs: f64,
+) -> Vec<DeviceRate> {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.to_vec();
+    }
+
+    let mut merged: HashMap<String, DeviceRate> = HashMap::new();
+    for (rates, weight) in [(older, older_secs / secs), (newer, newer_secs / secs)] {
+        for rate in rates {
+            let device = merged
+                .entry(rate.name.clone())
+                .or_insert_with(|| DeviceRate::idle(&rate.name, rate.depth));
+            device.read_bytes_per_sec += rate.read_bytes_per_sec * weight;
+            device.write_bytes_per_sec += rate.write_bytes_per_sec * weight;
+            device.read_iops += rate.read_iops * weight;
+            device.write_iops += rate.write_iops * weight;
+            device.read_time += rate.read_time * weight;
+            device.write_time += rate.write_time * weight;
+            device.utilization += rate.utilization * weight;
+            device.queue_depth += rate.queue_depth * weight;
+            device.filesystems.clone_from(&rate.filesystems);
+        }
+    }
+
+    let mut merged: Vec<DeviceRate> = merged.into_values().collect();
+    sort_stacked(&mut merged);
+    merged
+}
+
+/// Sort devices by name, disks first and every layer stacked on them after
+fn sort_stacked(devices: &mut [DeviceRate]) {
+    devices.sort_by(|a, b| a.depth.cmp(&b.depth).then_with(|| a.name.cmp(&b.name)));
+}
+
+/// The busiest device, whose utilization tells whether storage is the
+/// bottleneck; a sum or an average would be diluted by idle disks
+pub fn busiest(devices: &[DeviceRate]) -> Option<&DeviceRate> {
+    devices.iter().max_by(|a, b| {
+        a.utilization
+            .total_cmp(&b.utilization)
+            .then(a.queue_depth.total_cmp(&b.queue_depth))
+    })
+}
+
+/// Friendly names and stacking of block devices, from sysfs
+///
+/// Device-mapper devices go by their mapping name, so an LVM logical volume
+/// shows as `vg0-data` rather than `dm-3`, and md arrays assembled with a
+/// name by the name mdadm links under /dev/md. Other devices keep their
+/// kernel name.
+#[derive(Debug, Default)]
+pub struct DeviceNames {
+    names: HashMap<String, String>,
+    depths: HashMap<String, usize>,
+}
+
+impl DeviceNames {
+    /// Look up the devices with these kernel names
+    pub fn read<'a>(kernel_names: impl IntoIterator<Item = &'a str>) -> Self {
+        let md_names = read_md_names();
+        let mut names = HashMap::new();
+        let mut slaves = HashMap::new();
+        for kernel_name in kernel_names {
+            let device = Path::new("/sys/block").join(kernel_name);
+            let name = fs::read_to_string(device.join("dm/name"))
+                .ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .or_else(|| md_names.get(kernel_name).cloned());
+            if let Some(name) = name {
+                names.insert(kernel_name.to_string(), name);
+            }
+            if let Ok(entries) = fs::read_dir(device.join("slaves")) {
+                let below: Vec<String> = entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect();
+                slaves.insert(kernel_name.to_string(), below);
+            }
+        }
+        Self {
+            names,
+            depths: stack_depths(&slaves),
+        }
+    }
+
+    pub fn name<'a>(&'a self, kernel_name: &'a str) -> &'a str {
+        self.names
+            .get(kernel_name)
+            .map_or(kernel_name, String::as_str)
+    }
+
+    pub fn depth(&self, kernel_name: &str) -> usize {
+        self.depths.get(kernel_name).copied().unwrap_or_default()
+    }
+}
+
+/// Array names by kernel name, from the /dev/md/NAME -> ../mdN links
+fn read_md_names() -> HashMap<String, String> {
+    let Ok(entries) = fs::read_dir("/dev/md") else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let target = fs::read_link(entry.path()).ok()?;
+            md_array_name(
+                &entry.file_name().to_string_lossy(),
+                &target.file_name()?.to_string_lossy(),
+            )
+        })
+        .collect()
+}
+
+/// `(kernel name, array name)` for a /dev/md link, unless the array has no
+/// name of its own: mdadm links unnamed arrays by number
+fn md_array_name(link: &str, target: &str) -> Option<(String, String)> {
+    if !target.starts_with("md") || link.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((target.to_string(), link.to_string()))
+}
+
+/// Layers of devices beneath each device, from the devices each one is
+/// built on (/sys/block/NAME/slaves)
+///
+/// Partitions have no entry of their own and count as their disk.
+fn stack_depths(slaves: &HashMap<String, Vec<String>>) -> HashMap<String, usize> {
+    fn depth(
+        name: &str,
+        slaves: &HashMap<String, Vec<String>>,
+        depths: &mut HashMap<String, usize>,
+        seen: usize,
+    ) -> usize {
+        if let Some(&known) = depths.get(name) {
+            return known;
+        }
+        // sysfs has no cycles, but stay safe against a racing rebuild
+        let below = match slaves.get(name) {
+            Some(below) if !below.is_empty() && seen < slaves.len() => below
+                .iter()
+                .map(|slave| depth(slave, slaves, depths, seen + 1) + 1)
+                .max()
+                .unwrap_or_default(),
+            _ => 0,
+        };
+        if slaves.contains_key(name) {
+            depths.insert(name.to_string(), below);
+        }
+        below
+    }
+
+    let mut depths = HashMap::new();
+    for name in slaves.keys() {
+        depth(name, slaves, &mut depths, 0);
+    }
+    depths
+}
+
+/// Samples /proc/diskstats and turns consecutive samples into rates
+///
+/// The whole disks are listed again from /sys/block at every sample, so
+/// devices attached mid-session (USB sticks, NVMe drives, activated volumes)
+/// show up from their second sample, and detached ones go away.
+#[derive(Default)]
+pub struct DiskSampler {
+    prev: HashMap<String, DiskCounters>,
+    prev_at: Option<Instant>,
+    /// Kernel names of the whole disks, as of the last sample
+    disks: HashSet<String>,
+    names: DeviceNames,
+}
+
+impl DiskSampler {
+    /// Read /proc/diskstats and return the rates of whole disks since the
+    /// previous call (empty on the first call)
+    ///
+    /// Partitions are skipped since their I/O is already counted in the
+    /// parent disk, as are devices that never did any I/O (unused loop and
+    /// ram devices). Each device comes with the space left on the
+    /// filesystems mounted from it.
+    pub fn sample(&mut self) -> Result<Vec<DeviceRate>> {
+        let content = fs::read_to_string("/proc/diskstats")?;
+        let now = Instant::now();
+        // Devices come and go when hot-plugged or when volumes are activated
+        // and deactivated, and a kernel name can come back as another device
+        let disks = whole_disks();
+        if disks != self.disks {
+            self.names = DeviceNames::read(disks.iter().map(String::as_str));
+            self.disks = disks;
+        }
+        let current: HashMap<String, DiskCounters> = parse(&content)
+            .into_iter()
+            .filter(|(name, counters)| {
+                *counters != DiskCounters::default() && self.disks.contains(name)
+            })
+            .collect();
+
+        let rates = self.rates(&current, now);
+        self.prev = current;
+        self.prev_at = Some(now);
+        Ok(rates)
+    }
+
+    fn rates(&self, current: &HashMap<String, DiskCounters>, now: Instant) -> Vec<DeviceRate> {
+        let Some(prev_at) = self.prev_at else {
+            return Vec::new();
+        };
+        let secs = now.duration_since(prev_at).as_secs_f64();
+        if secs <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut usage: HashMap<String, Vec<FsUsage>> = HashMap::new();
+        for fs in mounts::read_usage() {
+            usage.entry(fs.disk.clone()).or_default().push(fs);
+        }
+        let mut rates: Vec<DeviceRate> = current
+            .iter()
+            .filter_map(|(name, curr)| {
+                let prev = self.prev.get(name).filter(|prev| curr.follows(prev))?;
+                let mut rate = DeviceRate::from_delta(
+                    self.names.name(name),
+                    self.names.depth(name),
+                    prev,
+                    curr,
+                    secs,
+                );
+                rate.filesystems = usage.remove(name).unwrap_or_default();
+                Some(rate)
+            })
+            .collect();
+        sort_stacked(&mut rates);
+        rates
+    }
+}
+
+/// Kernel names of the whole disks: they have an entry in /sys/block, while
+/// partitions only appear below it
+fn whole_disks() -> HashSet<String> {
+    let Ok(entries) = fs::read_dir("/sys/block") else {
+        return HashSet::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "   8       0 sda 100 5 2048 30 50 2 4096 60 0 90 90\n\
+                       259       1 nvme0n1p1 1 0 8 0 0 0 0 0 0 0 0\n\
+                       bogus line\n";
+        let stats = parse(content);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].0, "sda");
+        assert_eq!(
+            stats[0].1,
+            DiskCounters {
+                reads_completed: 100,
+                sectors_read: 2048,
+                read_ticks: 30,
+                writes_completed: 50,
+                sectors_written: 4096,
+                write_ticks: 60,
+                io_ticks: 90,
+                time_in_queue: 90,
+            }
+        );
+        assert_eq!(stats[1].0, "nvme0n1p1");
+    }
+
+    #[test]
+    fn test_device_rate() {
+        let prev = DiskCounters {
+            reads_completed: 10,
+            sectors_read: 100,
+            read_ticks: 50,
+            writes_completed: 0,
+            sectors_written: 0,
+            write_ticks: 0,
+            io_ticks: 1000,
+            time_in_queue: 2000,
+        };
+        let curr = DiskCounters {
+            reads_completed: 30,
+            sectors_read: 2148,
+            read_ticks: 150,
+            writes_completed: 4,
+            sectors_written: 8,
+            write_ticks: 80,
+            io_ticks: 1500,
+            time_in_queue: 5000,
+        };
+        let rate = DeviceRate::from_delta("sda", 0, &prev, &curr, 2.0);
+        assert_eq!(rate.read_bytes_per_sec, 1024.0 * 512.0);
+        assert_eq!(rate.write_bytes_per_sec, 2048.0);
+        assert_eq!(rate.read_iops, 10.0);
+        assert_eq!(rate.write_iops, 2.0);
+        assert_eq!(rate.utilization, 25.0);
+        assert_eq!(rate.queue_depth, 1.5);
+        // 20 reads in 100 ms, 4 writes in 80 ms
+        assert_eq!(rate.read_latency_ms(), Some(5.0));
+        assert_eq!(rate.write_latency_ms(), Some(20.0));
+        assert_eq!(DeviceRate::idle("sda", 0).read_latency_ms(), None);
+    }
+
+    #[test]
+    fn test_follows() {
+        let prev = DiskCounters {
+            reads_completed: 500,
+            writes_completed: 200,
+            io_ticks: 3000,
+            ..Default::default()
+        };
+        let curr = DiskCounters {
+            reads_completed: 510,
+            ..prev
+        };
+        assert!(curr.follows(&prev));
+        // Another stick plugged in as sdb while the table was refreshing
+        let replaced = DiskCounters {
+            reads_completed: 40,
+            io_ticks: 120,
+            ..Default::default()
+        };
+        assert!(!replaced.follows(&prev));
+    }
+
+    #[test]
+    fn test_merge_rates() {
+        let mut sda = DeviceRate::idle("sda", 0);
+        sda.read_iops = 30.0;
+        sda.utilization = 60.0;
+        let mut sdb = DeviceRate::idle("sdb", 0);
+        sdb.write_iops = 10.0;
+
+        let merged = merge_rates(&[sda], 1.0, &[sdb], 2.0);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].name, "sda");
+        assert!((merged[0].read_iops - 10.0).abs() < 1e-9);
+        assert!((merged[0].utilization - 20.0).abs() < 1e-9);
+        assert_eq!(merged[1].name, "sdb");
+        assert!((merged[1].write_iops - 20.0 / 3.0).abs() < 1e-9);
+
+        // Latency is weighted by the requests of each interval
+        let mut slow = DeviceRate::idle("sda", 0);
+        slow.read_iops = 10.0;
+        slow.read_time = 100.0;
+        let mut fast = DeviceRate::idle("sda", 0);
+        fast.read_iops = 30.0;
+        fast.read_time = 30.0;
+        let merged = merge_rates(&[slow], 1.0, &[fast], 1.0);
+        assert!((merged[0].read_latency_ms().unwrap() - 130.0 / 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_busiest() {
+        assert_eq!(busiest(&[]), None);
+        let mut sda = DeviceRate::idle("sda", 0);
+        sda.utilization = 40.0;
+        let mut sdb = DeviceRate::idle("sdb", 0);
+        sdb.utilization = 90.0;
+        assert_eq!(busiest(&[sda, sdb]).unwrap().name, "sdb");
+    }
+
+    #[test]
+    fn test_stacking() {
+        // An LV on a RAID1 of two disks, and a plain disk
+        let slaves = HashMap::from([
+            ("sda".to_string(), vec![]),
+            ("sdb".to_string(), vec![]),
+            ("nvme0n1".to_string(), vec![]),
+            (
+                "md0".to_string(),
+                vec!["sda1".to_string(), "sdb1".to_string()],
+            ),
+            ("dm-0".to_string(), vec!["md0".to_string()]),
+        ]);
+        let depths = stack_depths(&slaves);
+        assert_eq!(depths["sda"], 0);
+        assert_eq!(depths["md0"], 1);
+        assert_eq!(depths["dm-0"], 2);
+
+        let mut devices = vec![
+            DeviceRate::idle("vg0-data", 2),
+            DeviceRate::idle("sdb", 0),
+            DeviceRate::idle("md0", 1),
+            DeviceRate::idle("nvme0n1", 0),
+        ];
+        sort_stacked(&mut devices);
+        let names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["nvme0n1", "sdb", "md0", "vg0-data"]);
+
+        assert_eq!(
+            md_array_name("data", "md127"),
+            Some(("md127".to_string(), "data".to_string()))
+        );
+        assert_eq!(md_array_name("0", "md0"), None);
+    }
+}
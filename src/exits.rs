@@ -0,0 +1,271 @@
+use anyhow::{Context, Result};
+use linux_taskstats::{Client, TaskStats as KernelTaskStats};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::taskstats::TaskStats;
+
+/// Receive buffer for exit records, so bursts of exiting tasks (a `make -j`
+/// finishing) are not dropped between two reads
+const RX_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// Consecutive receive errors after which the listener gives up
+const MAX_RECV_ERRORS: u32 = 100;
+
+/// Final statistics of a task, sent by the kernel when it exits
+#[derive(Debug, Clone)]
+pub struct ExitedTask {
+    pub tid: i32,
+    pub tgid: i32,
+    /// Parent process, to place it in a `--children` subtree
+    pub ppid: i32,
+    pub uid: u32,
+    /// Command name (`comm`), since /proc/[pid]/cmdline is gone
+    pub comm: String,
+    pub stats: TaskStats,
+    /// Byte counters as /proc/[pid]/io has them, which process rows are
+    /// sampled from: disk bytes rather than those of the syscalls
+    pub io: TaskStats,
+}
+
+impl ExitedTask {
+    fn from_kernel_stats(stats: &KernelTaskStats) -> Self {
+        let raw = stats.inner();
+        let comm: Vec<u8> = raw
+            .ac_comm
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .collect();
+        Self {
+            tid: raw.ac_pid as i32,
+            // Kernels before 5.17 do not fill in ac_tgid
+            tgid: if raw.ac_tgid != 0 {
+                raw.ac_tgid as i32
+            } else {
+                raw.ac_pid as i32
+            },
+            ppid: raw.ac_ppid as i32,
+            uid: raw.ac_uid,
+            comm: String::from_utf8_lossy(&comm).into_owned(),
+            stats: TaskStats::from_kernel_stats(stats),
+            io: TaskStats {
+                read_bytes: stats.blkio.read_bytes,
+                write_bytes: stats.blkio.write_bytes,
+                cancelled_write_bytes: stats.blkio.cancelled_write_bytes,
+                read_char: stats.io.read_bytes,
+                write_char: stats.io.write_bytes,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Collects the taskstats exit records of every CPU in a background thread
+///
+/// Tasks that start and exit between two refreshes never show up in /proc
+/// when it is walked; their exit records are the only trace of their I/O.
+pub struct ExitListener {
+    rx: mpsc::Receiver<ExitedTask>,
+}
+
+impl ExitListener {
+    pub fn start() -> Result<Self> {
+        let client = Client::open().context("Failed to open taskstats exit listener")?;
+        let cpus = fs::read_to_string("/sys/devices/system/cpu/possible")
+            .context("Failed to read /sys/devices/system/cpu/possible")?;
+        client
+            .register_cpumask(cpus.trim())
+            .context("Failed to register for taskstats exit records")?;
+        // Best effort: the default buffer still works, it just drops more
+        let _ = client.set_rx_buf_sz(RX_BUFFER_BYTES);
+
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("taskstats-exit".to_string())
+            .spawn(move || {
+                let mut errors = 0;
+                while errors < MAX_RECV_ERRORS {
+                    let Ok(records) = client.listen_registered() else {
+                        // Overruns are reported as errors; the next read resyncs
+                        errors += 1;
+                        continue;
+                    };
+                    errors = 0;
+                    // The per-task record comes first; when the last thread of
+                    // a group exits it is followed by a group total without
+                    // I/O byte counts, which is skipped
+                    if let Some(record) = records.first() {
+                        if tx.send(ExitedTask::from_kernel_stats(record)).is_err() {
+                            break;
+                        }
+                    }
+                }
+            })
+            .context("Failed to spawn taskstats exit listener")?;
+
+        Ok(Self { rx })
+    }
+
+    /// Exit records received since the previous call
+    pub fn drain(&self) -> Vec<ExitedTask> {
+        self.rx.try_iter().collect()
+    }
+}
+
+/// Byte counters of the threads and children of every process that has
+/// exit records, to account process rows sampled from /proc/[pid]/io
+///
+/// That file counts the exited threads of a process, and also every child
+/// from the moment the process waits for it. Those children have rows of
+/// their own, so their bytes are taken back out of their parent, which
+/// would otherwise show a shell or `make` doing the I/O of its jobs.
+#[derive(Debug, Default)]
+pub struct ExitLedger {
+    /// Parent and bytes of the threads whose exit records arrived, by TGID,
+    /// until the process is reaped
+    exited: HashMap<i32, (i32, TaskStats)>,
+    /// Bytes of the children each process has reaped, which go to its own
+    /// parent along with its threads' when it is reaped in turn
+    reaped: HashMap<i32, TaskStats>,
+    /// Bytes of the children reaped since the previous refresh, still to be
+    /// taken out of their parent, and whether they were already due then
+    pending: HashMap<i32, (TaskStats, bool)>,
+    /// What /proc/[pid]/io counted of the processes with exit records in
+    /// the current interval before those, by TGID
+    settled: HashMap<i32, TaskStats>,
+}
+
+impl ExitLedger {
+    /// Add the exit records of the interval, then note the processes that
+    /// were reaped since the previous call, as told by `is_gone`: zombies
+    /// are still listed in /proc
+    pub fn record(&mut self, exited: &[ExitedTask], is_gone: impl Fn(i32) -> bool) {
+        // Left over by a parent that had its children reaped automatically
+        self.pending
+            .retain(|_, (_, due)| !std::mem::replace(due, true));
+
+        self.settled.clear();
+        for task in exited {
+            if !self.settled.contains_key(&task.tgid) {
+                let mut settled = self.reaped.get(&task.tgid).copied().unwrap_or_default();
+                if let Some((_, bytes)) = self.exited.get(&task.tgid) {
+                    settled.accumulate(bytes);
+                }
+                self.settled.insert(task.tgid, settled);
+            }
+            let (ppid, bytes) = self.exited.entry(task.tgid).or_default();
+            *ppid = task.ppid;
+            bytes.accumulate(&task.io);
+        }
+
+        let gone: Vec<i32> = self
+            .exited
+            .keys()
+            .copied()
+            .filter(|&tgid| is_gone(tgid))
+            .collect();
+        for tgid in gone {
+            let Some((ppid, mut bytes)) = self.exited.remove(&tgid) else {
+                continue;
+            };
+            if let Some(children) = self.reaped.remove(&tgid) {
+                bytes.accumulate(&children);
+            }
+            self.reaped.entry(ppid).or_default().accumulate(&bytes);
+            let (pending, due) = self.pending.entry(ppid).or_default();
+            pending.accumulate(&bytes);
+            *due = false;
+        }
+        // Reaped without an exit record, or never to be: nothing inherits
+        self.reaped
+            .retain(|tgid, _| self.exited.contains_key(tgid) || !is_gone(*tgid));
+    }
+
+    /// Bytes /proc/[pid]/io had counted of process `tgid` apart from its
+    /// threads with exit records in the current interval: those of its
+    /// threads that exited before, and of the children it had reaped, as
+    /// far as they exited while iotop was running
+    pub fn settled(&self, tgid: i32) -> TaskStats {
+        self.settled.get(&tgid).copied().unwrap_or_default()
+    }
+
+    /// Take the bytes of the children process `tgid` reaped out of `change`,
+    /// the change of its counters since its previous sample, and `delta`,
+    /// the same scaled to the refresh interval
+    ///
+    /// What is left, when the process was sampled before it reaped the
+    /// children, is taken at its next sample.
+    pub fn take(&mut self, tgid: i32, change: &mut TaskStats, delta: &mut TaskStats) {
+        let Some((pending, _)) = self.pending.get_mut(&tgid) else {
+            return;
+        };
+        let left = pending.delta(change);
+        *change = change.delta(pending);
+        *delta = delta.delta(pending);
+        if left.read_bytes == 0
+            && left.write_bytes == 0
+            && left.read_char == 0
+            && left.write_char == 0
+        {
+            self.pending.remove(&tgid);
+        } else {
+            *pending = left;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit(tid: i32, tgid: i32, ppid: i32, write_bytes: u64) -> ExitedTask {
+        let io = TaskStats {
+            write_bytes,
+            ..Default::default()
+        };
+        ExitedTask {
+            tid,
+            tgid,
+            ppid,
+            uid: 0,
+            comm: "make".to_string(),
+            stats: io,
+            io,
+        }
+    }
+
+    #[test]
+    fn test_exit_ledger() {
+        let mut ledger = ExitLedger::default();
+        // A worker thread of 20 exits, then 30 and its child 31, which 30
+        // reaps before being reaped by 20
+        ledger.record(&[exit(21, 20, 1, 100), exit(31, 31, 30, 200)], |_| false);
+        assert_eq!(ledger.settled(20).write_bytes, 0);
+        ledger.record(&[exit(30, 30, 20, 300)], |tgid| tgid == 31);
+        // Reaped in the same interval, so not in 30's previous sample either
+        assert_eq!(ledger.settled(30).write_bytes, 0);
+        ledger.record(&[exit(22, 20, 1, 50)], |tgid| tgid >= 30);
+        // Its earlier thread and both processes it reaped
+        assert_eq!(ledger.settled(20).write_bytes, 100);
+
+        // 20 was sampled before reaping them, then after 400 bytes of its own
+        let mut change = TaskStats {
+            write_bytes: 900,
+            ..Default::default()
+        };
+        let mut delta = change;
+        ledger.take(20, &mut change, &mut delta);
+        assert_eq!(change.write_bytes, 400);
+        assert_eq!(delta.write_bytes, 400);
+        // Nothing left to take
+        ledger.take(20, &mut change, &mut delta);
+        assert_eq!(change.write_bytes, 400);
+
+        // Bytes 30 reaped count for 20 from now on
+        ledger.record(&[exit(20, 20, 1, 0)], |_| false);
+        assert_eq!(ledger.settled(20).write_bytes, 650);
+    }
+}
@@ -0,0 +1,480 @@
+use nix::unistd::User;
+use regex::Regex;
+
+use crate::alerts;
+use crate::ioprio::IoprioClass;
+use crate::process::ProcessInfo;
+
+/// Row filter given with `--where`, such as
+/// `user == "www-data" && write > 1M && !kthread`
+///
+/// Conditions compare a field of the row with a value and are combined with
+/// `&&`/`and`, `||`/`or`, `!`/`not` and parentheses. Rates are in bytes per
+/// second over the last interval and take K, M and G suffixes; `io` and
+/// `swapin` are the delay percentages of the IO> and SWAPIN columns.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err("empty expression".to_string());
+        }
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {}", token));
+        }
+        Ok(Self {
+            source: input.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// Whether `process` passes, with rates over an interval of `duration`
+    /// seconds
+    pub fn matches(&self, process: &ProcessInfo, duration: f64) -> bool {
+        self.expr.eval(process, duration)
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Flag(Flag),
+    Number(NumberField, Op, f64),
+    Text(TextField, Op, String),
+    Match(TextField, Regex),
+}
+
+impl Expr {
+    fn eval(&self, process: &ProcessInfo, duration: f64) -> bool {
+        match self {
+            Expr::Or(a, b) => a.eval(process, duration) || b.eval(process, duration),
+            Expr::And(a, b) => a.eval(process, duration) && b.eval(process, duration),
+            Expr::Not(expr) => !expr.eval(process, duration),
+            Expr::Flag(flag) => flag.get(process),
+            Expr::Number(field, op, value) => field
+                .get(process, duration)
+                .is_some_and(|actual| op.compare(actual.total_cmp(value))),
+            Expr::Text(field, op, value) => {
+                op.compare(field.get(process).as_str().cmp(value.as_str()))
+            }
+            Expr::Match(field, regex) => regex.is_match(&field.get(process)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    fn compare(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Op::Eq => ordering == Equal,
+            Op::Ne => ordering != Equal,
+            Op::Gt => ordering == Greater,
+            Op::Ge => ordering != Less,
+            Op::Lt => ordering == Less,
+            Op::Le => ordering != Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Flag {
+    /// Kernel threads are the only tasks in session 0
+    Kthread,
+    Blocked,
+    Active,
+}
+
+impl Flag {
+    fn get(self, process: &ProcessInfo) -> bool {
+        match self {
+            Flag::Kthread => process.sid == Some(0),
+            Flag::Blocked => process.uninterruptible,
+            Flag::Active => process.did_some_io(false),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NumberField {
+    Pid,
+    Tid,
+    Uid,
+    Session,
+    Read,
+    Write,
+    Io,
+    Swapin,
+}
+
+impl NumberField {
+    fn get(self, process: &ProcessInfo, duration: f64) -> Option<f64> {
+        let stats = &process.stats_delta;
+        let rate = |bytes: u64| {
+            if duration > 0.0 {
+                bytes as f64 / duration
+            } else {
+                0.0
+            }
+        };
+        let percent = |delay_ns: u64| {
+            if duration > 0.0 {
+                delay_ns as f64 / (duration * 1_000_000_000.0) * 100.0
+            } else {
+                0.0
+            }
+        };
+        match self {
+            NumberField::Pid => Some(process.pid as f64),
+            NumberField::Tid => Some(process.tid as f64),
+            NumberField::Uid => process.uid.map(f64::from),
+            NumberField::Session => process.sid.map(f64::from),
+            NumberField::Read => Some(rate(stats.read_bytes)),
+            NumberField::Write => Some(rate(stats.write_bytes)),
+            NumberField::Io => Some(percent(stats.blkio_delay_total)),
+            NumberField::Swapin => Some(percent(stats.swapin_delay_total)),
+        }
+    }
+
+    /// Rates take a size suffix, the rest are plain numbers
+    fn parse_value(self, value: &str) -> Result<f64, String> {
+        match self {
+            NumberField::Read | NumberField::Write => {
+                alerts::parse_rate(value).map(|rate| rate as f64)
+            }
+            _ => value
+                .parse()
+                .map_err(|_| format!("invalid number '{}'", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TextField {
+    User,
+    Command,
+    Unit,
+    Cgroup,
+    Tty,
+    Prio,
+    Class,
+}
+
+impl TextField {
+    fn get(self, process: &ProcessInfo) -> String {
+        let text = match self {
+            TextField::User => process.get_user(),
+            TextField::Command => process.get_cmdline(),
+            TextField::Unit => process.unit.as_deref().unwrap_or(""),
+            TextField::Cgroup => process.cgroup.as_deref().unwrap_or(""),
+            TextField::Tty => process.tty.as_deref().unwrap_or(""),
+            TextField::Prio => process.get_prio(),
+            TextField::Class => process.prio_class().map_or("", |class| class.as_str()),
+        };
+        text.to_string()
+    }
+}
+
+enum Field {
+    Flag(Flag),
+    Number(NumberField),
+    Text(TextField),
+}
+
+impl Field {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "kthread" => Field::Flag(Flag::Kthread),
+            "blocked" => Field::Flag(Flag::Blocked),
+            "active" => Field::Flag(Flag::Active),
+            "pid" => Field::Number(NumberField::Pid),
+            "tid" => Field::Number(NumberField::Tid),
+            "uid" => Field::Number(NumberField::Uid),
+            "session" => Field::Number(NumberField::Session),
+            "read" => Field::Number(NumberField::Read),
+            "write" => Field::Number(NumberField::Write),
+            "io" => Field::Number(NumberField::Io),
+            "swapin" => Field::Number(NumberField::Swapin),
+            "user" => Field::Text(TextField::User),
+            "command" | "comm" => Field::Text(TextField::Command),
+            "unit" => Field::Text(TextField::Unit),
+            "cgroup" => Field::Text(TextField::Cgroup),
+            "tty" => Field::Text(TextField::Tty),
+            "prio" => Field::Text(TextField::Prio),
+            "class" => Field::Text(TextField::Class),
+            _ => return Err(format!("unknown field '{}'", name)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Str(text) => write!(f, "\"{}\"", text),
+            Token::Op(op) => write!(f, "'{}'", op),
+        }
+    }
+}
+
+// Longest first, so `>=` is not read as `>`
+const OPERATORS: [&str; 13] = [
+    "&&", "||", "==", "!=", ">=", "<=", "=~", ">", "<", "!", "(", ")", "=",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| "unterminated string".to_string())?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            // A lone `=` is a common slip for `==`
+            tokens.push(Token::Op(if *op == "=" { "==" } else { op }));
+            rest = &rest[op.len()..];
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '/')))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("unexpected '{}'", c));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn take(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    /// Consume the next token if it is the operator `op` or the keyword
+    /// `word`
+    fn accept(&mut self, op: &str, word: &str) -> bool {
+        let found = match self.peek() {
+            Some(Token::Op(found)) => *found == op,
+            Some(Token::Word(found)) => found == word,
+            _ => false,
+        };
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.accept("||", "or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.accept("&&", "and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.accept("!", "not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.accept("(", "") {
+            let expr = self.or()?;
+            if !self.accept(")", "") {
+                return Err("missing ')'".to_string());
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let name = match self.take()? {
+            Token::Word(name) => name,
+            token => return Err(format!("expected a field, got {}", token)),
+        };
+        let field = Field::parse(&name)?;
+        let op = match self.peek() {
+            Some(Token::Op(op)) if !matches!(*op, "&&" | "||" | "!" | "(" | ")") => *op,
+            _ => {
+                return match field {
+                    Field::Flag(flag) => Ok(Expr::Flag(flag)),
+                    _ => Err(format!("'{}' needs a comparison", name)),
+                }
+            }
+        };
+        self.next += 1;
+        let value = match self.take()? {
+            Token::Word(value) | Token::Str(value) => value,
+            token => return Err(format!("expected a value, got {}", token)),
+        };
+
+        let order = |op: &str| match op {
+            "==" => Some(Op::Eq),
+            "!=" => Some(Op::Ne),
+            ">" => Some(Op::Gt),
+            ">=" => Some(Op::Ge),
+            "<" => Some(Op::Lt),
+            "<=" => Some(Op::Le),
+            _ => None,
+        };
+        match field {
+            Field::Flag(_) => Err(format!("'{}' cannot be compared", name)),
+            Field::Number(field) => {
+                let op = order(op).ok_or_else(|| format!("cannot match '{}' with {}", name, op))?;
+                Ok(Expr::Number(field, op, field.parse_value(&value)?))
+            }
+            Field::Text(field) if op == "=~" => {
+                let regex =
+                    Regex::new(&value).map_err(|e| format!("invalid regex '{}': {}", value, e))?;
+                Ok(Expr::Match(field, regex))
+            }
+            Field::Text(field) => {
+                let op = order(op)
+                    .filter(|op| matches!(op, Op::Eq | Op::Ne))
+                    .ok_or_else(|| format!("'{}' only takes ==, != and =~", name))?;
+                text_comparison(field, op, value)
+            }
+        }
+    }
+}
+
+/// Users are compared by UID when known, since the USER column is cut to
+/// eight characters; classes are checked against the names displayed
+fn text_comparison(field: TextField, op: Op, value: String) -> Result<Expr, String> {
+    match field {
+        TextField::User => {
+            if let Ok(Some(user)) = User::from_name(&value) {
+                return Ok(Expr::Number(
+                    NumberField::Uid,
+                    op,
+                    f64::from(user.uid.as_raw()),
+                ));
+            }
+            Ok(Expr::Text(field, op, value))
+        }
+        TextField::Class => {
+            let class = IoprioClass::parse(&value)?;
+            Ok(Expr::Text(field, op, class.as_str().to_string()))
+        }
+        _ => Ok(Expr::Text(field, op, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(tid: i32, write_bytes: u64) -> ProcessInfo {
+        let mut process = ProcessInfo::new(tid);
+        process.cmdline = Some("nginx: worker process".to_string());
+        process.uid = Some(65534);
+        process.user = Some("nobodyx".to_string());
+        process.sid = Some(tid);
+        process.stats_delta.write_bytes = write_bytes;
+        process
+    }
+
+    fn matches(input: &str, process: &ProcessInfo) -> bool {
+        Condition::parse(input).unwrap().matches(process, 2.0)
+    }
+
+    #[test]
+    fn test_matches() {
+        let busy = process(4213, 4 << 20);
+        let idle = process(4214, 0);
+        assert!(matches("write > 1M", &busy));
+        assert!(!matches("write > 1M", &idle));
+        assert!(matches("write >= 2M && pid == 4213", &busy));
+        assert!(!matches("write > 2M", &busy));
+        assert!(matches(
+            "pid == 1 or (tid == 4214 and not write > 0)",
+            &idle
+        ));
+        assert!(matches("user == \"nobodyx\" && !kthread", &busy));
+        assert!(matches("command =~ '^nginx' && user != 'root'", &busy));
+        assert!(!matches("command =~ 'apache' || uid == 0", &busy));
+        assert!(matches("!(blocked)", &busy));
+
+        let mut kernel = process(42, 0);
+        kernel.sid = Some(0);
+        assert!(matches("kthread", &kernel));
+        assert!(!matches("session > 0", &kernel));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Condition::parse("").is_err());
+        assert!(Condition::parse("size > 1").is_err());
+        assert!(Condition::parse("write").is_err());
+        assert!(Condition::parse("write > ").is_err());
+        assert!(Condition::parse("write > fast").is_err());
+        assert!(Condition::parse("user > \"a\"").is_err());
+        assert!(Condition::parse("kthread == 1").is_err());
+        assert!(Condition::parse("(write > 1").is_err());
+        assert!(Condition::parse("pid == 1 pid == 2").is_err());
+        assert!(Condition::parse("command =~ '('").is_err());
+        assert!(Condition::parse("class == \"batch\"").is_err());
+        assert_eq!(
+            Condition::parse(" pid = 1 ").unwrap().to_string(),
+            "pid = 1"
+        );
+    }
+}
@@ -0,0 +1,546 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::netio::{NetRates, NetSampler};
+
+#[cfg(feature = "ebpf")]
+use crate::bpf::FileProbes;
+
+#[cfg(not(feature = "ebpf"))]
+use unsupported::FileProbes;
+
+/// Read and write rates of one file of a process over an interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileRate {
+    /// Target of the descriptor, e.g. a path or `pipe:[1234]`
+    pub path: String,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    /// Whether the file was opened with `O_DIRECT`, bypassing the page cache
+    pub direct: bool,
+    /// Whether it is a TCP, UDP or raw socket
+    pub net: bool,
+}
+
+/// Per-file rates keyed by process ID
+pub type FileRates = HashMap<i32, Vec<FileRate>>;
+
+/// Running byte counts of one file of a process
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTotals {
+    pub tgid: u32,
+    /// Kernel encoding of the device number: major << 20 | minor
+    pub dev: u32,
+    pub ino: u64,
+    /// Last path component, for when the file is closed before it is
+    /// resolved
+    pub name: String,
+    /// Whether the file was opened with `O_DIRECT`
+    pub direct: bool,
+    /// Whether it is a TCP, UDP or raw socket
+    pub net: bool,
+    pub read: u64,
+    pub write: u64,
+}
+
+/// File as of the previous sample
+struct OpenFile {
+    path: String,
+    read: u64,
+    write: u64,
+}
+
+/// Attributes the bytes processes read and write to the files they have
+/// open, with eBPF programs (only with the `ebpf` cargo feature)
+///
+/// Counts are kept per file, by device and inode, and resolved to paths
+/// through /proc/[pid]/fd when sampled, so files opened and closed between
+/// two samples are only known by their name. Memory-mapped and asynchronous
+/// (io_uring, AIO) file I/O is not seen.
+pub struct FileTracer {
+    probes: FileProbes,
+    open: HashMap<(u32, u32, u64), OpenFile>,
+    prev_at: Instant,
+    /// Sums the socket traffic of each process, when network I/O is counted
+    net: Option<NetSampler>,
+}
+
+impl FileTracer {
+    pub fn start(count_net: bool) -> Result<Self> {
+        Ok(Self {
+            probes: FileProbes::attach()?,
+            open: HashMap::new(),
+            prev_at: Instant::now(),
+            net: count_net.then(NetSampler::default),
+        })
+    }
+
+    /// Rates of each file since the previous call, keyed by process ID,
+    /// busiest file first, and the network I/O of each process
+    pub fn sample(&mut self) -> Result<(FileRates, NetRates)> {
+        let totals = self.probes.totals()?;
+        let now = Instant::now();
+        let secs = now.duration_since(self.prev_at).as_secs_f64();
+        self.prev_at = now;
+
+        // Descriptors of each process, listed once per sample
+        let mut descriptors: HashMap<u32, Vec<OpenFd>> = HashMap::new();
+        let (rates, stale) = account(&mut self.open, totals, secs, |total| {
+            let fds = descriptors
+                .entry(total.tgid)
+                .or_insert_with(|| open_fds(total.tgid));
+            find_file(fds, total.dev, total.ino)
+        });
+        for total in &stale {
+            self.probes.forget(total);
+        }
+        let net = self
+            .net
+            .as_mut()
+            .map(|net| net.sample(&rates, secs))
+            .unwrap_or_default();
+        Ok((rates, net))
+    }
+}
+
+/// Descriptor of a process: the device and inode it refers to, and its
+/// target, e.g. a path or `pipe:[1234]`
+struct OpenFd {
+    dev: u32,
+    ino: u64,
+    path: String,
+}
+
+/// Every descriptor process `tgid` has open
+fn open_fds(tgid: u32) -> Vec<OpenFd> {
+    let Ok(entries) = fs::read_dir(format!("/proc/{}/fd", tgid)) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            // Follows the link to the open file itself, even a deleted one
+            let metadata = fs::metadata(entry.path()).ok()?;
+            let path = fs::read_link(entry.path()).ok()?;
+            Some(OpenFd {
+                dev: kernel_dev(metadata.dev()),
+                ino: metadata.ino(),
+                path: path.to_string_lossy().into_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Device number from `stat` in the kernel's internal encoding, major << 20
+/// | minor, which the programs see
+fn kernel_dev(dev: u64) -> u32 {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major << 20 | minor) as u32
+}
+
+/// Target of the descriptor open on inode `ino` of device `dev`
+///
+/// Btrfs subvolumes and overlayfs report another device to `stat` than the
+/// one the inode is on, so an inode number alone will do when no other file
+/// has it.
+fn find_file(fds: &[OpenFd], dev: u32, ino: u64) -> Option<String> {
+    if let Some(fd) = fds.iter().find(|fd| fd.dev == dev && fd.ino == ino) {
+        return Some(fd.path.clone());
+    }
+    let mut same_ino = fds.iter().filter(|fd| fd.ino == ino);
+    let first = same_ino.next()?;
+    same_ino
+        .all(|fd| fd.path == first.path)
+        .then(|| first.path.clone())
+}
+
+/// Turn file totals into per-file rates over `secs`, against the files
+/// seen last time (`open`)
+///
+/// Also returns the entries whose bytes are now fully accounted: files
+/// closed since, and those of processes that exited.
+fn account(
+    open: &mut HashMap<(u32, u32, u64), OpenFile>,
+    totals: Vec<FileTotals>,
+    secs: f64,
+    mut resolve: impl FnMut(&FileTotals) -> Option<String>,
+) -> (FileRates, Vec<FileTotals>) {
+    let mut previous = std::mem::take(open);
+    let mut by_process: HashMap<i32, HashMap<String, (u64, u64, bool, bool)>> = HashMap::new();
+    let mut stale = Vec::new();
+
+    for total in totals {
+        let key = (total.tgid, total.dev, total.ino);
+        let last = previous.remove(&key);
+        let (last_read, last_write) = last.as_ref().map_or((0, 0), |f| (f.read, f.write));
+        let read = total.read.saturating_sub(last_read);
+        let write = total.write.saturating_sub(last_write);
+
+        let path = match resolve(&total) {
+            Some(path) => {
+                open.insert(
+                    key,
+                    OpenFile {
+                        path: path.clone(),
+                        read: total.read,
+                        write: total.write,
+                    },
+                );
+                path
+            }
+            // Closed files keep the path they had, if they were seen
+            None => {
+                let path = match last {
+                    Some(last) => last.path,
+                    None if total.name.is_empty() => format!("inode {} (closed)", total.ino),
+                    None => format!("{} (closed)", total.name),
+                };
+                stale.push(total.clone());
+                path
+            }
+        };
+
+        if read > 0 || write > 0 {
+            let bytes = by_process
+                .entry(total.tgid as i32)
+                .or_default()
+                .entry(path)
+                .or_default();
+            bytes.0 += read;
+            bytes.1 += write;
+            bytes.2 |= total.direct;
+            bytes.3 |= total.net;
+        }
+    }
+
+    if secs <= 0.0 {
+        return (HashMap::new(), stale);
+    }
+    let rates = by_process
+        .into_iter()
+        .map(|(tgid, files)| {
+            let rates = files
+                .into_iter()
+                .map(|(path, (read, write, direct, net))| FileRate {
+                    path,
+                    read_bytes_per_sec: read as f64 / secs,
+                    write_bytes_per_sec: write as f64 / secs,
+                    direct,
+                    net,
+                })
+                .collect();
+            (tgid, busiest_first(rates))
+        })
+        .collect();
+    (rates, stale)
+}
+
+fn busiest_first(mut rates: Vec<FileRate>) -> Vec<FileRate> {
+    rates.sort_by(|a, b| {
+        let total = |r: &FileRate| r.read_bytes_per_sec + r.write_bytes_per_sec;
+        total(b)
+            .total_cmp(&total(a))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    rates
+}
+
+/// Combine the file rates of two consecutive intervals into rates over both,
+/// weighting each by its length
+pub fn merge_rates(
+    older: &FileRates,
+    older_secs: f64,
+    newer: &FileRates,
+    newer_secs: f64,
+) -> FileRates {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.clone();
+    }
+
+    let mut merged: HashMap<i32, HashMap<String, (f64, f64, bool, bool)>> = HashMap::new();
+    for (rates, weight) in [(older, older_secs / secs), (newer, newer_secs / secs)] {
+        for (&tgid, files) in rates {
+            for file in files {
+                let rate = merged
+                    .entry(tgid)
+                    .or_default()
+                    .entry(file.path.clone())
+                    .or_default();
+                rate.0 += file.read_bytes_per_sec * weight;
+                rate.1 += file.write_bytes_per_sec * weight;
+                rate.2 |= file.direct;
+                rate.3 |= file.net;
+            }
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(tgid, files)| {
+            let rates = files
+                .into_iter()
+                .map(|(path, (read, write, direct, net))| FileRate {
+                    path,
+                    read_bytes_per_sec: read,
+                    write_bytes_per_sec: write,
+                    direct,
+                    net,
+                })
+                .collect();
+            (tgid, busiest_first(rates))
+        })
+        .collect()
+}
+
+/// Whether process `pid` read or wrote a file under `prefix` over the
+/// interval
+pub fn did_io_under(rates: &FileRates, pid: i32, prefix: &Path) -> bool {
+    rates.get(&pid).into_iter().flatten().any(|file| {
+        (file.read_bytes_per_sec > 0.0 || file.write_bytes_per_sec > 0.0)
+            && Path::new(&file.path).starts_with(prefix)
+    })
+}
+
+/// Parse the directory given to `--path`, which has to be absolute like the
+/// paths of open files
+pub fn parse_path_prefix(value: &str) -> Result<PathBuf, String> {
+    let path = Path::new(value.trim());
+    if !path.is_absolute() {
+        return Err(format!("'{}' is not an absolute path", value.trim()));
+    }
+    Ok(path.to_path_buf())
+}
+
+/// Traced file I/O of a process split by whether it bypassed the page cache
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheSplit {
+    pub direct_read_bytes_per_sec: f64,
+    pub direct_write_bytes_per_sec: f64,
+    pub buffered_read_bytes_per_sec: f64,
+    pub buffered_write_bytes_per_sec: f64,
+}
+
+impl CacheSplit {
+    /// Split the rates of files on disk; pipes, sockets, devices such as
+    /// /dev/zero and the /proc and /sys pseudo-files are left out
+    pub fn of(files: &[FileRate]) -> Self {
+        let on_disk = |path: &str| {
+            path.starts_with('/')
+                && !["/dev/", "/proc/", "/sys/"]
+                    .iter()
+                    .any(|prefix| path.starts_with(prefix))
+        };
+        let mut split = Self::default();
+        for file in files.iter().filter(|file| on_disk(&file.path)) {
+            if file.direct {
+                split.direct_read_bytes_per_sec += file.read_bytes_per_sec;
+                split.direct_write_bytes_per_sec += file.write_bytes_per_sec;
+            } else {
+                split.buffered_read_bytes_per_sec += file.read_bytes_per_sec;
+                split.buffered_write_bytes_per_sec += file.write_bytes_per_sec;
+            }
+        }
+        split
+    }
+}
+
+#[cfg(not(feature = "ebpf"))]
+mod unsupported {
+    use super::FileTotals;
+    use anyhow::{bail, Result};
+
+    /// Stand-in for builds without the `ebpf` feature
+    pub struct FileProbes;
+
+    impl FileProbes {
+        pub fn attach() -> Result<Self> {
+            bail!("iotop was built without eBPF support; rebuild it with `--features ebpf`")
+        }
+
+        pub fn totals(&self) -> Result<Vec<FileTotals>> {
+            Ok(Vec::new())
+        }
+
+        pub fn forget(&self, _total: &FileTotals) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals(ino: u64, name: &str, read: u64, write: u64) -> FileTotals {
+        FileTotals {
+            tgid: 100,
+            dev: 8 << 20 | 1,
+            ino,
+            name: name.to_string(),
+            direct: false,
+            net: false,
+            read,
+            write,
+        }
+    }
+
+    fn rate(path: &str, read: f64, write: f64) -> FileRate {
+        FileRate {
+            path: path.to_string(),
+            read_bytes_per_sec: read,
+            write_bytes_per_sec: write,
+            direct: false,
+            net: false,
+        }
+    }
+
+    #[test]
+    fn test_account() {
+        let mut open = HashMap::new();
+        let paths = |total: &FileTotals| match total.ino {
+            11 => Some("/var/log/a".to_string()),
+            12 => Some("/data/b".to_string()),
+            _ => None,
+        };
+
+        // ino 13 was opened and closed before the sample
+        let direct = FileTotals {
+            direct: true,
+            ..totals(12, "b", 0, 1000)
+        };
+        let (rates, stale) = account(
+            &mut open,
+            vec![
+                totals(11, "a", 100, 0),
+                direct.clone(),
+                totals(13, "tmp.1", 10, 0),
+            ],
+            2.0,
+            paths,
+        );
+        assert_eq!(stale, vec![totals(13, "tmp.1", 10, 0)]);
+        assert_eq!(
+            rates[&100],
+            vec![
+                FileRate {
+                    direct: true,
+                    ..rate("/data/b", 0.0, 500.0)
+                },
+                rate("/var/log/a", 50.0, 0.0),
+                rate("tmp.1 (closed)", 5.0, 0.0),
+            ]
+        );
+
+        // Only the growth since the previous sample counts. ino 12 was
+        // closed after writing more and keeps its path; ino 14 has no name
+        let closed = FileTotals {
+            direct: true,
+            ..totals(12, "b", 0, 1200)
+        };
+        let (rates, stale) = account(
+            &mut open,
+            vec![
+                totals(11, "a", 150, 0),
+                closed.clone(),
+                totals(14, "", 0, 30),
+            ],
+            1.0,
+            |total| match total.ino {
+                11 => paths(total),
+                _ => None,
+            },
+        );
+        assert_eq!(stale, vec![closed, totals(14, "", 0, 30)]);
+        assert_eq!(
+            rates[&100],
+            vec![
+                FileRate {
+                    direct: true,
+                    ..rate("/data/b", 0.0, 200.0)
+                },
+                rate("/var/log/a", 50.0, 0.0),
+                rate("inode 14 (closed)", 0.0, 30.0),
+            ]
+        );
+        assert_eq!(open.len(), 1);
+    }
+
+    #[test]
+    fn test_find_file() {
+        let fd = |dev: u32, ino: u64, path: &str| OpenFd {
+            dev,
+            ino,
+            path: path.to_string(),
+        };
+        let fds = [
+            fd(8 << 20 | 1, 11, "/var/log/a"),
+            fd(8 << 20 | 2, 11, "/home/b"),
+            fd(0x2d, 12, "/srv/c"),
+            fd(0x2d, 12, "/srv/c"),
+            fd(0x2e, 13, "/srv/d"),
+        ];
+        assert_eq!(find_file(&fds, 8 << 20 | 2, 11).as_deref(), Some("/home/b"));
+        // Reported on another device, as on a btrfs subvolume
+        assert_eq!(find_file(&fds, 0x30, 12).as_deref(), Some("/srv/c"));
+        // Two files have that number
+        assert_eq!(find_file(&fds, 0x30, 11), None);
+        assert_eq!(find_file(&fds, 0x30, 99), None);
+
+        // 259:3 and 0:45 as `stat` encodes them
+        assert_eq!(kernel_dev(0x10303), 259 << 20 | 3);
+        assert_eq!(kernel_dev(0x2d), 45);
+    }
+
+    #[test]
+    fn test_merge_rates() {
+        let older = HashMap::from([(1, vec![rate("/a", 10.0, 0.0), rate("/b", 4.0, 0.0)])]);
+        let newer = HashMap::from([
+            (1, vec![rate("/b", 0.0, 20.0)]),
+            (2, vec![rate("/c", 2.0, 2.0)]),
+        ]);
+        let merged = merge_rates(&older, 1.0, &newer, 1.0);
+        assert_eq!(
+            merged[&1],
+            vec![rate("/b", 2.0, 10.0), rate("/a", 5.0, 0.0)]
+        );
+        assert_eq!(merged[&2], vec![rate("/c", 1.0, 1.0)]);
+    }
+
+    #[test]
+    fn test_did_io_under() {
+        let rates = FileRates::from([(
+            1,
+            vec![
+                rate("/var/lib/postgresql/16/main/base/1/1259", 8192.0, 0.0),
+                rate("/var/log/syslog", 0.0, 0.0),
+            ],
+        )]);
+        let prefix = parse_path_prefix("/var/lib/postgresql/").unwrap();
+        assert!(did_io_under(&rates, 1, &prefix));
+        assert!(!did_io_under(&rates, 1, Path::new("/var/lib/postgres")));
+        assert!(!did_io_under(&rates, 1, Path::new("/var/log")));
+        assert!(!did_io_under(&rates, 2, &prefix));
+        assert!(parse_path_prefix("var/lib").is_err());
+    }
+
+    #[test]
+    fn test_cache_split() {
+        let direct = FileRate {
+            direct: true,
+            ..rate("/data/db", 0.0, 300.0)
+        };
+        let split = CacheSplit::of(&[
+            direct,
+            rate("/var/log/a", 10.0, 20.0),
+            rate("pipe:[7]", 5.0, 0.0),
+            rate("/dev/zero", 5.0, 0.0),
+        ]);
+        assert_eq!(split.direct_write_bytes_per_sec, 300.0);
+        assert_eq!(split.buffered_read_bytes_per_sec, 10.0);
+        assert_eq!(split.buffered_write_bytes_per_sec, 20.0);
+    }
+}
@@ -0,0 +1,362 @@
+use anyhow::Result;
+use regex::Regex;
+use std::ops::RangeInclusive;
+
+use crate::container::{self, ContainerFilter};
+use crate::process::ProcessInfo;
+use crate::unit;
+
+/// Runtime PID filter applied to the rows shown in the TUI
+///
+/// Entries are separated by commas. A plain ID keeps only the row with that
+/// ID; an ID followed by `+` also keeps every thread of that process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PidFilter {
+    entries: Vec<(i32, bool)>,
+}
+
+impl PidFilter {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for entry in input.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (id, threads) = match entry.strip_suffix('+') {
+                Some(id) => (id.trim(), true),
+                None => (entry, false),
+            };
+            let id: i32 = id
+                .parse()
+                .ok()
+                .filter(|id| *id > 0)
+                .ok_or_else(|| anyhow::anyhow!("Invalid PID: {}", entry))?;
+            entries.push((id, threads));
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        self.is_empty()
+            || self
+                .entries
+                .iter()
+                .any(|&(id, threads)| process.tid == id || (threads && process.pid == id))
+    }
+}
+
+impl std::fmt::Display for PidFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(id, threads)| format!("{}{}", id, if *threads { "+" } else { "" }))
+            .collect();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+/// Runtime systemd unit filter applied to the rows shown in the TUI
+///
+/// Entries are separated by commas; a name without a unit type means a
+/// service, so `nginx` keeps the rows of `nginx.service`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnitFilter {
+    units: Vec<String>,
+}
+
+impl UnitFilter {
+    pub fn parse(input: &str) -> Self {
+        let units = input
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(unit::full_name)
+            .collect();
+        Self { units }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        self.is_empty()
+            || process
+                .unit
+                .as_ref()
+                .is_some_and(|unit| self.units.contains(unit))
+    }
+}
+
+impl std::fmt::Display for UnitFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.units.join(","))
+    }
+}
+
+/// Parse a regex matched against command lines, by `--highlight` and
+/// `--filter`
+pub fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e))
+}
+
+/// cgroup subtree given to `--cgroup`, as a path from the root of the
+/// hierarchy whose components may contain `*` and `?` wildcards
+///
+/// A cgroup matches if it or one of its ancestors does, so
+/// `/system.slice/docker-*.scope` selects every container started by
+/// Docker, and `system.slice` every system service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CgroupPattern {
+    components: Vec<String>,
+}
+
+impl CgroupPattern {
+    /// Parse a path relative to the root of the hierarchy, or under
+    /// /sys/fs/cgroup
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let path = input.trim();
+        if path.is_empty() {
+            return Err("empty cgroup path".to_string());
+        }
+        let path = path.strip_prefix("/sys/fs/cgroup").unwrap_or(path);
+        let components = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(Self { components })
+    }
+
+    /// Whether the cgroup at `path` is in the subtree
+    pub fn matches(&self, path: &str) -> bool {
+        let mut path = path.split('/').filter(|c| !c.is_empty());
+        self.components
+            .iter()
+            .all(|pattern| path.next().is_some_and(|c| wildcard_match(pattern, c)))
+    }
+}
+
+/// Match `text` against `pattern`, where `*` stands for any run of
+/// characters and `?` for any one
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // Position after the last `*`, and where the text matched from it
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            star = Some((p, t));
+        } else if let Some((after, from)) = star {
+            // Let the last `*` take one more character
+            p = after;
+            t = from + 1;
+            star = Some((after, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Filters given on the command line that decide which tasks are sampled
+/// at all, unlike the runtime filters, which only hide rows
+///
+/// They need the metadata of new tasks right away, so it is read during
+/// the refresh rather than in the background.
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    /// `--filter`: command line regex
+    pub command: Option<Regex>,
+    /// `--cgroup`: cgroup subtree
+    pub cgroup: Option<CgroupPattern>,
+    /// `--unit`: cgroup of a systemd unit
+    pub unit: Option<CgroupPattern>,
+    /// `--container`: container, found from the cgroup
+    pub container: Option<ContainerFilter>,
+    /// `--tty`: controlling terminal, e.g. `pts/3`
+    pub tty: Option<String>,
+    /// `--session`: session ID
+    pub session: Option<i32>,
+}
+
+impl TaskFilter {
+    pub fn is_empty(&self) -> bool {
+        self.command.is_none()
+            && self.cgroup.is_none()
+            && self.unit.is_none()
+            && self.container.is_none()
+            && self.tty.is_none()
+            && self.session.is_none()
+    }
+
+    /// Whether the criteria on the cgroup accept the one at `path`
+    fn matches_cgroup(&self, path: Option<&str>) -> bool {
+        [&self.cgroup, &self.unit]
+            .into_iter()
+            .flatten()
+            .all(|cgroup| path.is_some_and(|p| cgroup.matches(p)))
+            && self.container.as_ref().is_none_or(|container| {
+                path.and_then(container::from_path)
+                    .is_some_and(|id| container.matches(&id))
+            })
+    }
+
+    /// Whether to sample `process`, whose metadata is known; tasks gone
+    /// before it could be read are not
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        let cmdline = process.cmdline.as_deref();
+        self.command
+            .as_ref()
+            .is_none_or(|command| cmdline.is_some_and(|c| command.is_match(c)))
+            && self.matches_cgroup(process.cgroup.as_deref())
+            && self
+                .tty
+                .as_ref()
+                .is_none_or(|tty| process.tty.as_ref() == Some(tty))
+            && self.session.is_none_or(|sid| process.sid == Some(sid))
+    }
+}
+
+/// Parse the terminal given to `--tty`, with or without `/dev/`
+pub fn parse_tty(value: &str) -> Result<String, String> {
+    let tty = value.trim();
+    let tty = tty.strip_prefix("/dev/").unwrap_or(tty);
+    if tty.is_empty() {
+        return Err("empty terminal name".to_string());
+    }
+    Ok(tty.to_string())
+}
+
+/// Parse the UIDs given to `--uid-range`, as `FIRST-LAST`
+pub fn parse_uid_range(value: &str) -> Result<RangeInclusive<u32>, String> {
+    let (first, last) = value
+        .split_once('-')
+        .ok_or_else(|| format!("expected FIRST-LAST, got '{}'", value))?;
+    let parse = |uid: &str| {
+        uid.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid UID '{}'", uid.trim()))
+    };
+    let (first, last) = (parse(first)?, parse(last)?);
+    if first > last {
+        return Err(format!("empty UID range {}-{}", first, last));
+    }
+    Ok(first..=last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thread(pid: i32, tid: i32) -> ProcessInfo {
+        let mut process = ProcessInfo::new(pid);
+        process.tid = tid;
+        process
+    }
+
+    #[test]
+    fn test_pid_filter() {
+        let filter = PidFilter::parse(" 100, 200+ ").unwrap();
+        assert_eq!(filter.to_string(), "100,200+");
+
+        assert!(filter.matches(&thread(100, 100)));
+        assert!(!filter.matches(&thread(100, 101)));
+        assert!(filter.matches(&thread(200, 201)));
+        assert!(!filter.matches(&thread(300, 300)));
+
+        assert!(PidFilter::parse("").unwrap().matches(&thread(300, 300)));
+        assert!(PidFilter::parse("abc").is_err());
+        assert!(PidFilter::parse("-1").is_err());
+    }
+
+    #[test]
+    fn test_unit_filter() {
+        let filter = UnitFilter::parse(" nginx, session-2.scope ");
+        assert_eq!(filter.to_string(), "nginx.service,session-2.scope");
+
+        let mut process = thread(100, 100);
+        assert!(!filter.matches(&process));
+        process.unit = Some("nginx.service".to_string());
+        assert!(filter.matches(&process));
+        process.unit = Some("nginx-exporter.service".to_string());
+        assert!(!filter.matches(&process));
+        assert!(UnitFilter::parse("").matches(&process));
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        let pattern = parse_pattern("^postgres|nginx: worker").unwrap();
+        assert!(pattern.is_match("postgres: checkpointer"));
+        assert!(pattern.is_match("nginx: worker process"));
+        assert!(!pattern.is_match("/usr/sbin/nginx -g daemon off;"));
+        assert!(parse_pattern("(unclosed").is_err());
+    }
+
+    #[test]
+    fn test_cgroup_pattern() {
+        let pattern = CgroupPattern::parse("/system.slice/docker-*.scope").unwrap();
+        assert!(pattern.matches("/system.slice/docker-3f2a9c1b.scope"));
+        assert!(pattern.matches("/system.slice/docker-3f2a9c1b.scope/init"));
+        assert!(!pattern.matches("/system.slice/containerd.service"));
+        assert!(!pattern.matches("/system.slice"));
+
+        let pattern = CgroupPattern::parse("/sys/fs/cgroup/user.slice/").unwrap();
+        assert!(pattern.matches("/user.slice/user-1000.slice/session-2.scope"));
+        assert!(!pattern.matches("/system.slice/user.slice"));
+        assert!(CgroupPattern::parse("/").unwrap().matches("/"));
+        assert!(CgroupPattern::parse(" ").is_err());
+
+        assert!(wildcard_match("user-????.slice", "user-1000.slice"));
+        assert!(!wildcard_match("user-????.slice", "user-0.slice"));
+        assert!(wildcard_match("*a*b", "xaxxab"));
+        assert!(!wildcard_match("*a*b", "xaxxa"));
+    }
+
+    #[test]
+    fn test_task_filter() {
+        let filter = TaskFilter {
+            command: Some(parse_pattern("^postgres").unwrap()),
+            cgroup: Some(CgroupPattern::parse("system.slice").unwrap()),
+            unit: Some(CgroupPattern::parse("/system.slice/postgresql.service").unwrap()),
+            ..Default::default()
+        };
+        let mut process = thread(100, 100);
+        assert!(TaskFilter::default().matches(&process));
+        assert!(!filter.matches(&process));
+        process.cmdline = Some("postgres: checkpointer".to_string());
+        process.cgroup = Some("/system.slice/postgresql.service".to_string());
+        assert!(filter.matches(&process));
+        process.cgroup = Some("/user.slice".to_string());
+        assert!(!filter.matches(&process));
+
+        let filter = TaskFilter {
+            tty: Some(parse_tty("/dev/pts/3").unwrap()),
+            session: Some(4120),
+            ..Default::default()
+        };
+        process.tty = Some("pts/3".to_string());
+        assert!(!filter.matches(&process));
+        process.sid = Some(4120);
+        assert!(filter.matches(&process));
+        process.tty = Some("pts/4".to_string());
+        assert!(!filter.matches(&process));
+        assert!(parse_tty("/dev/").is_err());
+    }
+
+    #[test]
+    fn test_parse_uid_range() {
+        assert_eq!(parse_uid_range("1000-2000"), Ok(1000..=2000));
+        assert_eq!(parse_uid_range(" 0 - 0 "), Ok(0..=0));
+        assert!(parse_uid_range("1000").is_err());
+        assert!(parse_uid_range("2000-1000").is_err());
+        assert!(parse_uid_range("-1-5").is_err());
+    }
+}
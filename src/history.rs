@@ -0,0 +1,306 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::process::ProcessSnapshot;
+use crate::taskstats::TaskStats;
+
+/// Time window the displayed rates are averaged over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AverageWindow {
+    Instant,
+    Secs5,
+    Secs30,
+    Mins5,
+}
+
+impl AverageWindow {
+    pub fn next(&self) -> Self {
+        match self {
+            AverageWindow::Instant => AverageWindow::Secs5,
+            AverageWindow::Secs5 => AverageWindow::Secs30,
+            AverageWindow::Secs30 => AverageWindow::Mins5,
+            AverageWindow::Mins5 => AverageWindow::Instant,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AverageWindow::Instant => "instant",
+            AverageWindow::Secs5 => "5s",
+            AverageWindow::Secs30 => "30s",
+            AverageWindow::Mins5 => "5min",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        [
+            AverageWindow::Instant,
+            AverageWindow::Secs5,
+            AverageWindow::Secs30,
+            AverageWindow::Mins5,
+        ]
+        .into_iter()
+        .find(|window| window.label() == label)
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        match self {
+            AverageWindow::Instant => None,
+            AverageWindow::Secs5 => Some(Duration::from_secs(5)),
+            AverageWindow::Secs30 => Some(Duration::from_secs(30)),
+            AverageWindow::Mins5 => Some(Duration::from_secs(300)),
+        }
+    }
+}
+
+/// Samples older than the longest window are dropped
+const MAX_WINDOW: Duration = Duration::from_secs(300);
+
+/// One refresh interval, as seen by the whole system
+struct Sample {
+    at: Instant,
+    duration: f64,
+    total_io: (u64, u64),
+    actual_io: (u64, u64),
+}
+
+/// Per-row history of interval deltas, used to compute moving averages
+///
+/// Only intervals in which a row did something are stored; a missing sample
+/// counts as zero I/O, so a row that just appeared is averaged over the whole
+/// window rather than over its own short lifetime.
+#[derive(Default)]
+pub struct RateHistory {
+    samples: VecDeque<Sample>,
+    rows: HashMap<i32, VecDeque<(Instant, TaskStats)>>,
+}
+
+impl RateHistory {
+    /// Record the deltas of a new snapshot taken at `now`
+    pub fn record(&mut self, snapshot: &ProcessSnapshot, now: Instant) {
+        self.samples.push_back(Sample {
+            at: now,
+            duration: snapshot.duration,
+            total_io: snapshot.total_io,
+            actual_io: snapshot.actual_io,
+        });
+
+        for (id, process) in &snapshot.processes {
+            let stats = &process.stats_delta;
+            if !stats.is_all_zero()
+                || stats.cpu_time_total != 0
+                || stats.cpu_delay_total != 0
+                || stats.freepages_delay_total != 0
+            {
+                self.rows
+                    .entry(*id)
+                    .or_default()
+                    .push_back((now, process.stats_delta));
+            }
+        }
+
+        let Some(cutoff) = now.checked_sub(MAX_WINDOW) else {
+            return;
+        };
+        while self.samples.front().is_some_and(|s| s.at <= cutoff) {
+            self.samples.pop_front();
+        }
+        self.rows.retain(|_, samples| {
+            while samples.front().is_some_and(|(at, _)| *at <= cutoff) {
+                samples.pop_front();
+            }
+            !samples.is_empty()
+        });
+    }
+
+    /// Forget all samples, e.g. when row IDs change meaning
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.rows.clear();
+    }
+
+    /// Interval lengths and deltas of one row over the recorded history,
+    /// oldest first; intervals in which the row did nothing are zero
+    pub fn series(&self, id: i32) -> Vec<(f64, TaskStats)> {
+        let mut row = self.rows.get(&id).into_iter().flatten().peekable();
+        self.samples
+            .iter()
+            .map(|sample| {
+                let stats = match row.next_if(|(at, _)| *at == sample.at) {
+                    Some((_, stats)) => *stats,
+                    None => TaskStats::default(),
+                };
+                (sample.duration, stats)
+            })
+            .collect()
+    }
+
+    /// Return a copy of `snapshot` whose deltas, totals and duration cover the
+    /// last `window` of recorded samples instead of the last interval
+    pub fn average(&self, snapshot: &ProcessSnapshot, window: Duration) -> ProcessSnapshot {
+        let Some(latest) = self.samples.back().map(|s| s.at) else {
+            return snapshot.clone();
+        };
+        let in_window = |at: Instant| latest.duration_since(at) < window;
+
+        let mut averaged = snapshot.clone();
+        averaged.duration = 0.0;
+        averaged.total_io = (0, 0);
+        averaged.actual_io = (0, 0);
+        for sample in self.samples.iter().filter(|s| in_window(s.at)) {
+            averaged.duration += sample.duration;
+            averaged.total_io.0 = averaged.total_io.0.saturating_add(sample.total_io.0);
+            averaged.total_io.1 = averaged.total_io.1.saturating_add(sample.total_io.1);
+            averaged.actual_io.0 = averaged.actual_io.0.saturating_add(sample.actual_io.0);
+            averaged.actual_io.1 = averaged.actual_io.1.saturating_add(sample.actual_io.1);
+        }
+        if averaged.duration <= 0.0 {
+            return snapshot.clone();
+        }
+
+        for (id, process) in averaged.processes.iter_mut() {
+            let mut sum = TaskStats {
+                version: process.stats_delta.version,
+                ..Default::default()
+            };
+            if let Some(samples) = self.rows.get(id) {
+                for (_, stats) in samples.iter().filter(|(at, _)| in_window(*at)) {
+                    sum.accumulate(stats);
+                }
+            }
+            process.stats_delta = sum;
+        }
+
+        averaged
+    }
+}
+
+/// Ring buffer of the most recently displayed snapshots, so the user can
+/// scrub back to an interval that has already scrolled past
+pub struct SnapshotRing {
+    snapshots: VecDeque<ProcessSnapshot>,
+    capacity: usize,
+}
+
+impl SnapshotRing {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, snapshot: ProcessSnapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// The snapshot `offset` intervals before the latest one
+    pub fn get(&self, offset: usize) -> Option<&ProcessSnapshot> {
+        let idx = self.snapshots.len().checked_sub(offset + 1)?;
+        self.snapshots.get(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::ProcessInfo;
+    use std::collections::HashSet;
+
+    fn snapshot(read_bytes: u64) -> ProcessSnapshot {
+        let mut process = ProcessInfo::new(42);
+        process.stats_delta.read_bytes = read_bytes;
+        ProcessSnapshot {
+            processes: HashMap::from([(42, process)]),
+            total_io: (read_bytes, 0),
+            actual_io: (read_bytes, 0),
+            duration: 1.0,
+            devices: Vec::new(),
+            cgroups: Vec::new(),
+            throttled: HashSet::new(),
+            nfs_mounts: Vec::new(),
+            pressure: None,
+            dirty: None,
+            swap: None,
+            iowait: Default::default(),
+            load: None,
+            uninterruptible: 0,
+            files: HashMap::new(),
+            net: HashMap::new(),
+            device_io: HashMap::new(),
+            syncs: HashMap::new(),
+            latency: HashMap::new(),
+            writeback: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_average_window() {
+        let mut history = RateHistory::default();
+        let start = Instant::now();
+        for (i, read) in [1000, 0, 0, 0, 0, 0, 0, 0, 0, 3000].into_iter().enumerate() {
+            history.record(&snapshot(read), start + Duration::from_secs(i as u64));
+        }
+
+        let latest = snapshot(3000);
+        let avg = history.average(&latest, Duration::from_secs(5));
+        assert_eq!(avg.duration, 5.0);
+        assert_eq!(avg.processes[&42].stats_delta.read_bytes, 3000);
+        assert_eq!(avg.total_io, (3000, 0));
+
+        let avg = history.average(&latest, Duration::from_secs(30));
+        assert_eq!(avg.duration, 10.0);
+        assert_eq!(avg.processes[&42].stats_delta.read_bytes, 4000);
+    }
+
+    #[test]
+    fn test_series() {
+        let mut history = RateHistory::default();
+        let start = Instant::now();
+        for (i, read) in [1000, 0, 2000].into_iter().enumerate() {
+            history.record(&snapshot(read), start + Duration::from_secs(i as u64));
+        }
+
+        let reads: Vec<u64> = history
+            .series(42)
+            .iter()
+            .map(|(_, stats)| stats.read_bytes)
+            .collect();
+        assert_eq!(reads, vec![1000, 0, 2000]);
+        assert_eq!(history.series(7).len(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_ring() {
+        let mut ring = SnapshotRing::new(3);
+        for read in 1..=5 {
+            ring.push(snapshot(read));
+        }
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.get(0).unwrap().total_io.0, 5);
+        assert_eq!(ring.get(2).unwrap().total_io.0, 3);
+        assert!(ring.get(3).is_none());
+    }
+
+    #[test]
+    fn test_window_cycle() {
+        let mut window = AverageWindow::Instant;
+        for _ in 0..4 {
+            window = window.next();
+        }
+        assert_eq!(window, AverageWindow::Instant);
+        assert_eq!(AverageWindow::Instant.duration(), None);
+    }
+}
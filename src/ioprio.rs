@@ -24,7 +24,21 @@ impl IoprioClass {
         }
     }
 
-    fn as_str(&self) -> &str {
+    /// Parse the class given to `--class`, by name as displayed or by
+    /// number as given to `ionice -c`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "rt" | "1" => Ok(IoprioClass::RealTime),
+            "be" | "2" => Ok(IoprioClass::BestEffort),
+            "idle" | "3" => Ok(IoprioClass::Idle),
+            other => Err(format!(
+                "invalid I/O priority class '{}', expected rt, be or idle",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
         match self {
             IoprioClass::None => "none",
             IoprioClass::RealTime => "rt",
@@ -58,7 +72,6 @@ impl Ioprio {
         (((self.class as u32) << IOPRIO_CLASS_SHIFT) | self.data) as i32
     }
 
-    #[allow(dead_code)]
     pub fn from_string(s: &str) -> Result<Self> {
         if s == "idle" {
             return Ok(Self::new(IoprioClass::Idle, 0));
@@ -137,7 +150,6 @@ fn get_ioprio_from_sched(pid: i32) -> Result<Ioprio> {
 }
 
 // Set I/O priority for a process
-#[allow(dead_code)]
 pub fn set_ioprio(pid: i32, ioprio: Ioprio) -> Result<()> {
     let result = unsafe {
         libc::syscall(
@@ -180,6 +192,15 @@ mod tests {
         assert!(Ioprio::from_string("invalid").is_err());
     }
 
+    #[test]
+    fn test_class_parse() {
+        assert_eq!(IoprioClass::parse("idle"), Ok(IoprioClass::Idle));
+        assert_eq!(IoprioClass::parse("2"), Ok(IoprioClass::BestEffort));
+        assert!(IoprioClass::parse("none").is_err());
+        let prio = Ioprio::from_string("rt/3").unwrap();
+        assert_eq!(prio.class, IoprioClass::RealTime);
+    }
+
     #[test]
     fn test_ioprio_display() {
         let ioprio = Ioprio::new(IoprioClass::BestEffort, 4);
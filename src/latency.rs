@@ -0,0 +1,205 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(feature = "ebpf")]
+use crate::bpf::LatencyProbes;
+
+#[cfg(not(feature = "ebpf"))]
+use unsupported::LatencyProbes;
+
+/// Histogram buckets; bucket `b` holds latencies from 2^b up to 2^(b+1)
+/// microseconds, the first one also those under a microsecond and the last
+/// one everything longer
+pub const BUCKETS: usize = 32;
+
+/// Block I/O latencies in log2 microsecond buckets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub counts: [u64; BUCKETS],
+}
+
+impl Histogram {
+    /// Number of I/Os counted
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Latency below which a share `q` (0 to 1) of the I/Os completed, as
+    /// the upper bound of its bucket; `None` without any I/O
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let bucket = self.counts.iter().position(|&n| {
+            seen += n;
+            seen >= rank
+        })?;
+        Some(Duration::from_micros(2u64 << bucket))
+    }
+
+    fn add(&mut self, other: &Histogram) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+}
+
+/// Block I/O latency of one process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessLatency {
+    /// I/Os completed during the interval
+    pub interval: Histogram,
+    /// I/Os completed since tracing started
+    pub total: Histogram,
+}
+
+/// Block I/O latency by process ID
+pub type LatencyRates = HashMap<i32, ProcessLatency>;
+
+/// Running count of one process's I/Os in one bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyTotal {
+    pub tgid: u32,
+    pub bucket: usize,
+    pub count: u64,
+}
+
+/// Times the block I/O of every process from queueing to completion with an
+/// eBPF program (only with the `ebpf` cargo feature)
+///
+/// Bandwidth alone does not tell a process waiting on a slow disk from one
+/// that is simply busy. Like the DEVICE column, I/O is charged to the task
+/// submitting it, so background writeback goes to the flusher threads.
+pub struct LatencyTracer {
+    probes: LatencyProbes,
+    prev: HashMap<(u32, usize), u64>,
+}
+
+impl LatencyTracer {
+    pub fn start() -> Result<Self> {
+        Ok(Self {
+            probes: LatencyProbes::attach()?,
+            prev: HashMap::new(),
+        })
+    }
+
+    /// Latencies of each process since the previous call, plus totals
+    pub fn sample(&mut self) -> Result<LatencyRates> {
+        let totals = self.probes.totals()?;
+        let rates = account(&mut self.prev, &totals);
+        for total in &totals {
+            if !Path::new(&format!("/proc/{}", total.tgid)).exists() {
+                self.probes.forget(total);
+                self.prev.remove(&(total.tgid, total.bucket));
+            }
+        }
+        Ok(rates)
+    }
+}
+
+/// Turn running bucket counts into per-process histograms of the interval,
+/// against the counts seen last time (`prev`)
+fn account(prev: &mut HashMap<(u32, usize), u64>, totals: &[LatencyTotal]) -> LatencyRates {
+    let mut rates = LatencyRates::new();
+    for total in totals.iter().filter(|t| t.bucket < BUCKETS) {
+        let last = prev.insert((total.tgid, total.bucket), total.count);
+        let latency = rates.entry(total.tgid as i32).or_default();
+        latency.interval.counts[total.bucket] =
+            total.count.saturating_sub(last.unwrap_or_default());
+        latency.total.counts[total.bucket] = total.count;
+    }
+    rates
+}
+
+/// Combine the latencies of two consecutive intervals into those of both;
+/// totals come from the newer one
+pub fn merge_rates(older: &LatencyRates, newer: &LatencyRates) -> LatencyRates {
+    let mut merged = newer.clone();
+    for (&tgid, older) in older {
+        let latency = merged.entry(tgid).or_insert(ProcessLatency {
+            total: older.total,
+            ..Default::default()
+        });
+        latency.interval.add(&older.interval);
+    }
+    merged
+}
+
+/// A latency for display, e.g. `512 us`, `4.1 ms` or `1.1 s`
+pub fn format_latency(latency: Duration) -> String {
+    let us = latency.as_micros();
+    if us < 1000 {
+        format!("{} us", us)
+    } else if us < 1_000_000 {
+        format!("{:.1} ms", us as f64 / 1000.0)
+    } else {
+        format!("{:.1} s", latency.as_secs_f64())
+    }
+}
+
+#[cfg(not(feature = "ebpf"))]
+mod unsupported {
+    use super::LatencyTotal;
+    use anyhow::{bail, Result};
+
+    /// Stand-in for builds without the `ebpf` feature
+    pub struct LatencyProbes;
+
+    impl LatencyProbes {
+        pub fn attach() -> Result<Self> {
+            bail!("iotop was built without eBPF support; rebuild it with `--features ebpf`")
+        }
+
+        pub fn totals(&self) -> Result<Vec<LatencyTotal>> {
+            Ok(Vec::new())
+        }
+
+        pub fn forget(&self, _total: &LatencyTotal) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantile() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+        // 98 I/Os in 256-511 us, 2 in 16-32 ms
+        histogram.counts[8] = 98;
+        histogram.counts[14] = 2;
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(512)));
+        assert_eq!(histogram.quantile(0.98), Some(Duration::from_micros(512)));
+        assert_eq!(histogram.quantile(0.99), Some(Duration::from_micros(32768)));
+        assert_eq!(format_latency(Duration::from_micros(512)), "512 us");
+        assert_eq!(format_latency(Duration::from_micros(32768)), "32.8 ms");
+    }
+
+    #[test]
+    fn test_account() {
+        let total = |bucket, count| LatencyTotal {
+            tgid: 10,
+            bucket,
+            count,
+        };
+        let mut prev = HashMap::new();
+        let first = account(&mut prev, &[total(3, 5), total(7, 1)]);
+        assert_eq!(first[&10].interval.count(), 6);
+
+        // Only the growth counts
+        let second = account(&mut prev, &[total(3, 8), total(7, 1)]);
+        assert_eq!(second[&10].interval.counts[3], 3);
+        assert_eq!(second[&10].interval.count(), 3);
+        assert_eq!(second[&10].total.count(), 9);
+
+        let merged = merge_rates(&first, &second);
+        assert_eq!(merged[&10].interval.count(), 9);
+        assert_eq!(merged[&10].total, second[&10].total);
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+/// Load averages and task counts from /proc/loadavg
+///
+/// On Linux the load counts tasks in uninterruptible sleep as well as
+/// runnable ones, so a load well above the number of CPUs with idle CPUs is
+/// a sign of tasks stuck on I/O.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadAvg {
+    /// Averages over 1, 5 and 15 minutes
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+    /// Tasks runnable right now, system-wide
+    pub running: u32,
+    /// Tasks in the system
+    pub total: u32,
+}
+
+/// Parse /proc/loadavg content, e.g. `0.52 0.40 0.31 2/345 6789`
+pub fn parse(content: &str) -> Option<LoadAvg> {
+    let mut fields = content.split_whitespace();
+    let one = fields.next()?.parse().ok()?;
+    let five = fields.next()?.parse().ok()?;
+    let fifteen = fields.next()?.parse().ok()?;
+    let (running, total) = fields.next()?.split_once('/')?;
+    Some(LoadAvg {
+        one,
+        five,
+        fifteen,
+        running: running.parse().ok()?,
+        total: total.parse().ok()?,
+    })
+}
+
+/// Read the current load averages
+pub fn read() -> Result<LoadAvg> {
+    let content = fs::read_to_string("/proc/loadavg")?;
+    parse(&content).context("Unexpected format of /proc/loadavg")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("0.52 0.40 0.31 2/345 6789\n"),
+            Some(LoadAvg {
+                one: 0.52,
+                five: 0.40,
+                fifteen: 0.31,
+                running: 2,
+                total: 345,
+            })
+        );
+        assert_eq!(parse("0.52 0.40 0.31 2 6789\n"), None);
+        assert_eq!(parse(""), None);
+    }
+}
@@ -1,39 +1,117 @@
+mod actions;
+mod alerts;
+#[cfg(feature = "ebpf")]
+mod bpf;
+mod cgroup;
+mod check;
+mod columns;
+mod connector;
+mod container;
+mod cpustat;
+mod delayacct;
+mod devtrace;
+mod diskstats;
+mod exits;
+mod expr;
+mod filetrace;
+mod filter;
+mod history;
 mod ioprio;
+mod latency;
+mod loadavg;
+mod meminfo;
+mod metadata;
+mod mounts;
+mod netio;
+mod nfsstats;
+mod overhead;
+mod presets;
+mod pressure;
+mod priocache;
 mod proc_reader;
 mod process;
+mod quota;
+mod state_file;
+mod swapstats;
+mod synctrace;
 mod taskstats;
+mod theme;
 mod ui;
+mod unit;
+mod writeback;
+mod zoom;
 
+use alerts::AlertThresholds;
 use anyhow::Result;
 use argh::FromArgs;
+use columns::ExtraColumn;
+use container::ContainerFilter;
 use crossterm::event::MouseEventKind;
 use crossterm::event::{KeyCode, KeyModifiers};
+use delayacct::DelayAcct;
+use history::SnapshotRing;
 use nix::unistd::User;
+use overhead::OverheadSampler;
 use process::{ProcessList, ProcessSnapshot};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use taskstats::{TaskStats, TaskStatsConnection};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
-use ui::{Event, SortColumn, Tui, UIState};
+use ui::{Event, Prompt, PromptKind, SortColumn, Tui, UIState};
 
 // UI scroll constants
 const SCROLL_PAGE_SIZE: usize = 10;
 const SCROLL_WHEEL_SIZE: usize = 3;
-const UI_HEADER_HEIGHT: u16 = 7;
 
 /// A Rust implementation of iotop - display I/O usage of processes
-#[derive(FromArgs, Debug)]
+#[derive(FromArgs, Debug, Clone)]
 struct Args {
     /// only show processes or threads actually doing I/O
     #[argh(switch, short = 'o')]
     only: bool,
 
+    /// only show processes or threads reading and writing at least this
+    /// much per second, e.g. 100K
+    #[argh(option, default = "0", from_str_fn(alerts::parse_rate))]
+    min_io: u64,
+
+    /// only show processes or threads blocked in uninterruptible sleep (D
+    /// state), usually waiting for I/O
+    #[argh(switch)]
+    blocked: bool,
+
+    /// only show processes or threads in this I/O priority class: rt, be
+    /// or idle
+    #[argh(option, from_str_fn(ioprio::IoprioClass::parse))]
+    class: Option<ioprio::IoprioClass>,
+
+    /// only show processes or threads matching this expression, e.g.
+    /// 'user == "www-data" && write > 1M && !kthread'
+    #[argh(option, long = "where", from_str_fn(expr::Condition::parse))]
+    condition: Option<expr::Condition>,
+
     /// show processes, not all threads
     #[argh(switch, short = 'P')]
     processes: bool,
 
+    /// show a row per process group (pgid) or session (sid) instead of per
+    /// process; implies -P
+    #[argh(option, from_str_fn(process::Grouping::parse))]
+    group: Option<process::Grouping>,
+
     /// show accumulated I/O instead of bandwidth
     #[argh(switch, short = 'a')]
     accumulated: bool,
 
+    /// start accumulated totals from what each running process did since
+    /// it started (/proc/[pid]/io), not since iotop did
+    #[argh(switch)]
+    lifetime: bool,
+
     /// delay between iterations in seconds
     #[argh(option, short = 'd', default = "1.0")]
     delay: f64,
@@ -50,10 +128,19 @@ struct Args {
     #[argh(option, short = 'p')]
     pid: Vec<i32>,
 
+    /// also monitor every descendant of the processes given with -p,
+    /// including those started later
+    #[argh(switch)]
+    children: bool,
+
     /// users to monitor (username or UID, can be repeated)
     #[argh(option, short = 'u')]
     user: Vec<String>,
 
+    /// only monitor users with a UID in this range, e.g. 1000-60000
+    #[argh(option, from_str_fn(filter::parse_uid_range))]
+    uid_range: Option<std::ops::RangeInclusive<u32>>,
+
     /// add timestamp on each line (implies --batch)
     #[argh(switch, short = 't')]
     time: bool,
@@ -65,50 +152,242 @@ struct Args {
     /// use kilobytes instead of human-friendly units
     #[argh(switch, short = 'k')]
     kilobytes: bool,
+
+    /// highlight rows whose read or write rate grew by more than this factor
+    /// since the previous interval (0 disables)
+    #[argh(option, default = "ui::DEFAULT_SPIKE_FACTOR")]
+    spike_factor: f64,
+
+    /// with -P, show the threads doing more than this percentage of their
+    /// process's I/O under it (0 disables, default 50)
+    #[argh(option, default = "ui::DEFAULT_HOT_THREADS")]
+    hot_threads: f64,
+
+    /// log rows whose read rate exceeds this, e.g. 200M (bytes per second,
+    /// K/M/G suffixes allowed)
+    #[argh(option, from_str_fn(alerts::parse_rate))]
+    alert_read: Option<u64>,
+
+    /// log rows whose write rate exceeds this, e.g. 200M
+    #[argh(option, from_str_fn(alerts::parse_rate))]
+    alert_write: Option<u64>,
+
+    /// watch each user's I/O against their budget in
+    /// ~/.config/iotop/quotas.toml, marking those over it
+    #[argh(switch)]
+    quotas: bool,
+
+    /// also log users going over their budget in the alert log (implies
+    /// --quotas)
+    #[argh(switch)]
+    quota_alert: bool,
+
+    /// in interactive mode, sample this many times per delay and show rates
+    /// averaged over the last delay at each sample, so bursts are not split
+    /// across two intervals (1-10, default 1)
+    #[argh(option, default = "1")]
+    subsamples: u32,
+
+    /// number of past intervals kept for scrubbing back with [ and ]
+    #[argh(option, default = "60")]
+    rewind: usize,
+
+    /// only monitor processes whose command line matches this regex
+    #[argh(option, from_str_fn(filter::parse_pattern))]
+    filter: Option<regex::Regex>,
+
+    /// only monitor processes in this cgroup subtree, e.g.
+    /// /system.slice/docker-*.scope
+    #[argh(option, from_str_fn(filter::CgroupPattern::parse))]
+    cgroup: Option<filter::CgroupPattern>,
+
+    /// only monitor the processes of this systemd unit, e.g. nginx.service
+    #[argh(option)]
+    unit: Option<String>,
+
+    /// only monitor the processes of this container, by ID, ID prefix or
+    /// name
+    #[argh(option)]
+    container: Option<String>,
+
+    /// only monitor processes whose controlling terminal is this one, e.g.
+    /// pts/3
+    #[argh(option, from_str_fn(filter::parse_tty))]
+    tty: Option<String>,
+
+    /// only monitor the processes of the session with this ID
+    #[argh(option)]
+    session: Option<i32>,
+
+    /// leave kernel threads out
+    #[argh(switch)]
+    no_kthreads: bool,
+
+    /// apply the filters of this preset from ~/.config/iotop/presets.toml;
+    /// options given here take precedence
+    #[argh(option)]
+    preset: Option<String>,
+
+    /// highlight commands matching this regex in interactive mode
+    #[argh(option, from_str_fn(filter::parse_pattern))]
+    highlight: Option<regex::Regex>,
+
+    /// comma-separated optional columns to show (rchar, wchar, cancelled,
+    /// writeback, majflt, minflt, iowaits, swapins, ioavg, cpudelay, reclaim,
+    /// thrashing, compact, wpcopy, cpu, mem, swap, oom, fds, age, tty, unit,
+    /// container, nspid, ioweight, mount, device, fsync, netread, netwrite,
+    /// graph)
+    #[argh(option, default = "String::new()")]
+    columns: String,
+
+    /// show per-device throughput, IOPS and utilization, NFS mounts and swap
+    /// traffic (the device panel interactively, extra lines in batch mode)
+    #[argh(switch)]
+    devices: bool,
+
+    /// show how much CPU time, taskstats queries and reads iotop itself used
+    /// over each interval (in the header, or an IOTOP line in batch mode)
+    #[argh(switch)]
+    stats: bool,
+
+    /// trace which files each process reads and writes with eBPF, shown in
+    /// the zoom view (needs a build with the ebpf feature)
+    #[argh(switch)]
+    trace_files: bool,
+
+    /// only show processes reading or writing files under this directory,
+    /// e.g. /var/lib/postgresql (implies --trace-files)
+    #[argh(option, from_str_fn(filetrace::parse_path_prefix))]
+    path: Option<std::path::PathBuf>,
+
+    /// attribute block I/O to the devices it goes to with eBPF, shown in the
+    /// DEVICE column (needs a build with the ebpf feature)
+    #[argh(switch)]
+    trace_devices: bool,
+
+    /// only show processes doing I/O to this device, e.g. sda (implies
+    /// --trace-devices)
+    #[argh(option, from_str_fn(devtrace::parse_device))]
+    device: Option<String>,
+
+    /// count each process's fsync and fdatasync calls with eBPF, shown in
+    /// the FSYNC column (needs a build with the ebpf feature)
+    #[argh(switch)]
+    trace_syncs: bool,
+
+    /// count each process's TCP, UDP and raw socket traffic with eBPF, shown
+    /// in the NET READ and NET WRITE columns (needs a build with the ebpf
+    /// feature)
+    #[argh(switch)]
+    net: bool,
+
+    /// time each process's block I/O from queueing to completion with eBPF,
+    /// shown as p50/p99 latency in the zoom view (needs a build with the
+    /// ebpf feature)
+    #[argh(switch)]
+    latency: bool,
+
+    /// screen-reader friendly mode: plain text rows and spoken state changes
+    #[argh(switch)]
+    a11y: bool,
+
+    /// colors of the interactive display: dark (default), light for
+    /// terminals with a light background, or mono; saved for next time
+    #[argh(option, from_str_fn(theme::Theme::parse))]
+    theme: Option<theme::Theme>,
+
+    /// turn on kernel.task_delayacct while iotop runs if it is off, so that
+    /// the SWAPIN and IO columns are filled in (needs root)
+    #[argh(switch)]
+    enable_delayacct: bool,
+
+    /// report which kernel features iotop can use here, with hints to
+    /// enable the missing ones, and exit
+    #[argh(switch)]
+    check: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args: Args = argh::from_env();
+    let command_line: Args = argh::from_env();
+    if command_line.check {
+        return check::run();
+    }
+    let mut args = command_line.clone();
+    if let Some(name) = &command_line.preset {
+        apply_preset(&mut args, &presets::find(name)?)?;
+    }
+    if args.children && args.pid.is_empty() {
+        anyhow::bail!("--children needs the processes to start from, given with -p");
+    }
+    if !(0.0..100.0).contains(&args.hot_threads) {
+        anyhow::bail!("--hot-threads must be a percentage below 100");
+    }
+    if !(1..=10).contains(&args.subsamples) {
+        anyhow::bail!("--subsamples must be between 1 and 10");
+    }
+    if args.device.is_some() && (args.batch || args.time || args.quiet) {
+        anyhow::bail!("--device traces devices with eBPF, which only interactive mode does");
+    }
+    if args.path.is_some() && (args.batch || args.time || args.quiet) {
+        anyhow::bail!("--path traces files with eBPF, which only interactive mode does");
+    }
+    if (args.quotas || args.quota_alert) && (args.batch || args.time || args.quiet) {
+        anyhow::bail!("--quotas shows a panel, which only interactive mode has");
+    }
 
-    // Check for requirements
-    check_requirements()?;
+    check::requirements()?;
+    // Switched off again on the way out
+    let _delayacct = if args.enable_delayacct {
+        DelayAcct::enable()?
+    } else {
+        None
+    };
 
     // Resolve usernames to UIDs
-    let uids = resolve_users(&args.user)?;
-
-    // Connect to taskstats
-    let taskstats_conn = TaskStatsConnection::new()?;
+    let mut uids = resolve_users(&args.user)?;
+
+    // Connect to taskstats, or fall back to /proc/[pid]/io without privileges
+    let taskstats_conn = match TaskStatsConnection::new() {
+        Ok(conn) => conn,
+        Err(_) => {
+            if args.batch || args.time || args.quiet {
+                eprintln!("Warning: {}", ui::PROCFS_FALLBACK_NOTICE);
+            }
+            // Other users' /proc/[pid]/io is not readable anyway
+            if uids.is_empty() {
+                uids.push(nix::unistd::getuid().as_raw());
+            }
+            TaskStatsConnection::procfs()
+        }
+    };
     let mut process_list = ProcessList::new(taskstats_conn)
         .with_pids(args.pid.clone())
-        .with_uids(uids.clone());
+        .with_children(args.children)
+        .with_lifetime(args.lifetime)
+        .with_uids(uids.clone())
+        .with_uid_range(args.uid_range.clone())
+        .with_kthreads(!args.no_kthreads)
+        .with_filter(task_filter(&args)?);
 
     if args.batch || args.time || args.quiet {
+        // The interactive mode's refresh stream starts its own listeners
+        let mut process_list = process_list.with_event_listeners();
         run_batch_mode(&mut process_list, &args)?;
     } else {
-        run_interactive_mode(&mut process_list, &args).await?;
-    }
-
-    Ok(())
-}
-
-fn check_requirements() -> Result<()> {
-    // Check if /proc/self/io exists (I/O accounting)
-    if !std::path::Path::new("/proc/self/io").exists() {
-        anyhow::bail!(
-            "Could not run iotop as some of the requirements are not met:\n\
-             - Linux >= 2.6.20 with I/O accounting support \n\
-             (CONFIG_TASKSTATS, CONFIG_TASK_DELAY_ACCT, CONFIG_TASK_IO_ACCOUNTING, \n\
-             kernel.task_delayacct sysctl)"
-        );
-    }
-
-    // Check if /proc/vmstat exists (VM event counters)
-    if !std::path::Path::new("/proc/vmstat").exists() {
-        anyhow::bail!(
-            "Could not run iotop as some of the requirements are not met:\n\
-             - Linux kernel with VM event counters (CONFIG_VM_EVENT_COUNTERS)"
-        );
+        if args.trace_files || args.net || args.path.is_some() {
+            process_list = process_list.with_file_tracer(args.net)?;
+        }
+        if args.trace_devices || args.device.is_some() {
+            process_list = process_list.with_device_tracer()?;
+        }
+        if args.trace_syncs {
+            process_list = process_list.with_sync_tracer()?;
+        }
+        if args.latency {
+            process_list = process_list.with_latency_tracer()?;
+        }
+        run_interactive_mode(&mut process_list, &args, &command_line).await?;
     }
 
     Ok(())
@@ -135,42 +414,256 @@ fn resolve_users(users: &[String]) -> Result<Vec<u32>> {
     Ok(uids)
 }
 
-async fn run_interactive_mode(process_list: &mut ProcessList, args: &Args) -> Result<()> {
+/// Criteria on the tasks themselves, looking up the cgroup of `--unit` and
+/// the ID of `--container`
+fn task_filter(args: &Args) -> Result<filter::TaskFilter> {
+    let unit = match &args.unit {
+        Some(name) => {
+            let path = unit::find_cgroup(&unit::full_name(name))?;
+            Some(filter::CgroupPattern::parse(&path).map_err(anyhow::Error::msg)?)
+        }
+        None => None,
+    };
+    Ok(filter::TaskFilter {
+        command: args.filter.clone(),
+        cgroup: args.cgroup.clone(),
+        unit,
+        container: args.container.as_deref().map(ContainerFilter::resolve),
+        tty: args.tty.clone(),
+        session: args.session,
+    })
+}
+
+/// Fill in the filters `args` leaves unset from `preset`, so that options
+/// given on the command line take precedence
+fn apply_preset(args: &mut Args, preset: &presets::Preset) -> Result<()> {
+    for (key, value) in &preset.settings {
+        let invalid = |e: String| anyhow::anyhow!("Preset '{}', {}: {}", preset.name, key, e);
+        match key.as_str() {
+            "user" if args.user.is_empty() => {
+                args.user = value
+                    .split(',')
+                    .map(|user| user.trim().to_string())
+                    .filter(|user| !user.is_empty())
+                    .collect();
+            }
+            "uid_range" if args.uid_range.is_none() => {
+                args.uid_range = Some(filter::parse_uid_range(value).map_err(invalid)?);
+            }
+            "filter" if args.filter.is_none() => {
+                args.filter = Some(filter::parse_pattern(value).map_err(invalid)?);
+            }
+            "cgroup" if args.cgroup.is_none() => {
+                args.cgroup = Some(filter::CgroupPattern::parse(value).map_err(invalid)?);
+            }
+            "unit" if args.unit.is_none() => args.unit = Some(value.clone()),
+            "container" if args.container.is_none() => args.container = Some(value.clone()),
+            "tty" if args.tty.is_none() => {
+                args.tty = Some(filter::parse_tty(value).map_err(invalid)?);
+            }
+            "session" if args.session.is_none() => {
+                let sid = value
+                    .parse::<i32>()
+                    .map_err(|_| invalid(format!("invalid session ID '{}'", value)))?;
+                args.session = Some(sid);
+            }
+            "no_kthreads" => args.no_kthreads |= presets::parse_bool(value).map_err(invalid)?,
+            "only" => args.only |= presets::parse_bool(value).map_err(invalid)?,
+            "blocked" => args.blocked |= presets::parse_bool(value).map_err(invalid)?,
+            "min_io" if args.min_io == 0 => {
+                args.min_io = alerts::parse_rate(value).map_err(invalid)?;
+            }
+            "class" if args.class.is_none() => {
+                args.class = Some(ioprio::IoprioClass::parse(value).map_err(invalid)?);
+            }
+            "device" if args.device.is_none() => {
+                args.device = Some(devtrace::parse_device(value).map_err(invalid)?);
+            }
+            "path" if args.path.is_none() => {
+                args.path = Some(filetrace::parse_path_prefix(value).map_err(invalid)?);
+            }
+            "where" if args.condition.is_none() => {
+                args.condition = Some(expr::Condition::parse(value).map_err(invalid)?);
+            }
+            "user" | "uid_range" | "filter" | "cgroup" | "unit" | "container" | "tty"
+            | "session" | "min_io" | "class" | "device" | "path" | "where" => {}
+            _ => return Err(invalid("unknown setting".to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// Switch to the filters of the command line plus those of the preset
+/// called `name`, or of the command line alone for an empty name
+fn select_preset(
+    process_list: &mut ProcessList,
+    state: &mut UIState,
+    command_line: &Args,
+    name: &str,
+) -> Result<()> {
+    let mut args = command_line.clone();
+    if !name.is_empty() {
+        apply_preset(&mut args, &presets::find(name)?)?;
+    }
+    // Their tracers only start with iotop
+    if args.device.is_some() && !state.trace_devices {
+        anyhow::bail!("Filtering by device needs iotop started with --trace-devices");
+    }
+    if args.path.is_some() && !state.trace_files {
+        anyhow::bail!("Filtering by path needs iotop started with --trace-files");
+    }
+
+    let mut uids = resolve_users(&args.user)?;
+    if uids.is_empty() && state.procfs_fallback {
+        uids.push(nix::unistd::getuid().as_raw());
+    }
+    process_list.filter = task_filter(&args)?;
+    process_list.uids = uids;
+    process_list.uid_range = args.uid_range.clone();
+    process_list.kthreads = !args.no_kthreads;
+
+    state.user_filter = args.user.clone();
+    state.only_active = args.only;
+    state.blocked_only = args.blocked;
+    state.min_io = args.min_io;
+    state.prio_class = args.class;
+    state.condition = args.condition.clone();
+    state.device_filter = args.device.clone();
+    state.path_filter = args.path.clone();
+    state.preset = Some(name.to_string()).filter(|name| !name.is_empty());
+    Ok(())
+}
+
+async fn run_interactive_mode(
+    process_list: &mut ProcessList,
+    args: &Args,
+    command_line: &Args,
+) -> Result<()> {
+    // Read before taking over the terminal, so that errors are readable
+    let quotas = if args.quotas || args.quota_alert {
+        Some(quota::load()?)
+    } else {
+        None
+    };
+
     let mut tui = Tui::new()?;
     tui.enter()?;
 
     let mut state = UIState::default();
     let mut iteration = 0;
-    let has_delay_acct = TaskStats::has_delay_acct();
+    let mut has_delay_acct = TaskStats::has_delay_acct();
+    let mut overhead = args.stats.then(OverheadSampler::default);
+    if let Some(overhead) = &mut overhead {
+        overhead.sample();
+    }
 
-    // Apply command line arguments to initial state
-    state.only_active = args.only;
-    state.accumulated = args.accumulated;
-    state.show_processes = args.processes;
+    // Restore the previous session, then let command line arguments override it
+    state_file::load(&mut state);
+    state.only_active |= args.only;
+    state.blocked_only = args.blocked;
+    state.min_io = args.min_io;
+    state.prio_class = args.class;
+    state.condition = args.condition.clone();
+    state.accumulated |= args.accumulated;
+    state.show_processes |= args.processes || args.group.is_some();
+    state.grouping = args.group;
+    state.show_devices |= args.devices;
+    state.a11y = args.a11y;
+    if let Some(theme) = args.theme {
+        state.theme = theme;
+    }
+    state.spike_factor = args.spike_factor;
+    state.hot_threads = args.hot_threads;
+    state.delay = args.delay;
+    state.subsamples = args.subsamples;
+    if !args.columns.is_empty() {
+        state.extra_columns = ExtraColumn::parse_list(&args.columns)?;
+    }
+    // Shown along with the tracing options, but only saved when chosen
+    let implied = [
+        (args.net, ExtraColumn::NetRead),
+        (args.net, ExtraColumn::NetWrite),
+        (
+            args.trace_devices || args.device.is_some(),
+            ExtraColumn::Device,
+        ),
+        (args.trace_syncs, ExtraColumn::Fsync),
+    ];
+    for (enabled, column) in implied {
+        if enabled && !state.extra_columns.contains(&column) {
+            state.extra_columns.push(column);
+            state.implied_columns.push(column);
+        }
+    }
+    state.extra_columns = ExtraColumn::ordered(&state.extra_columns);
+    state.user_filter = args.user.clone();
+    state.highlight = args.highlight.clone();
+    state.trace_files = args.trace_files || args.path.is_some();
+    state.path_filter = args.path.clone();
+    state.count_net = args.net;
+    state.trace_devices = args.trace_devices || args.device.is_some();
+    state.device_filter = args.device.clone();
+    state.trace_syncs = args.trace_syncs;
+    state.trace_latency = args.latency;
+    state.procfs_fallback = process_list
+        .taskstats_conn
+        .lock()
+        .is_ok_and(|conn| conn.is_procfs());
+    state.prio_changed = process_list.prio_changed.clone();
+    state.alert_thresholds = AlertThresholds {
+        read: args.alert_read,
+        write: args.alert_write,
+    };
+    state.quotas = quotas;
+    state.quota_alerts = args.quota_alert;
 
     // Start async data stream
     let mut data_cancel_token = CancellationToken::new();
-    let mut data_stream = ProcessList::spawn_refresh_stream(
-        1.0 / args.delay,
-        state.show_processes,
-        process_list.taskstats_conn.clone(),
-        args.pid.clone(),
-        process_list.uids.clone(),
-        data_cancel_token.clone(),
+    let mut data_stream = start_refresh_stream(
+        process_list,
+        args,
+        &state,
+        &mut data_cancel_token,
+        HashMap::new(),
     );
 
-    // Store current snapshot
-    let mut current_snapshot: Option<ProcessSnapshot> = None;
+    // Snapshots shown so far, newest last; `state.rewind` indexes back from the end
+    let mut snapshots = SnapshotRing::new(args.rewind);
+    // I/O that happened while paused
+    let mut paused_backlog: Option<ProcessSnapshot> = None;
+
+    // Ctrl-Z arrives as a key in raw mode, but `kill -TSTP` still needs the
+    // terminal restored
+    let mut sigtstp = signal(SignalKind::from_raw(libc::SIGTSTP))?;
+    let mut sigcont = signal(SignalKind::from_raw(libc::SIGCONT))?;
+    // Whether iotop stopped itself and left the terminal, so that the next
+    // SIGCONT, rather than any, takes it back
+    let mut suspended = false;
 
     loop {
         // Wait for next event
         tokio::select! {
+            Some(()) = sigtstp.recv() => {
+                suspended = true;
+                tui.suspend()?;
+            }
+            // Also the only way back from `tui.suspend()`
+            Some(()) = sigcont.recv() => {
+                if std::mem::take(&mut suspended) {
+                    tui.resume()?;
+                }
+            }
             // Handle data updates from the stream
             Some(snapshot) = data_stream.recv() => {
-                current_snapshot = Some(snapshot.clone());
+                state.samples += 1;
                 // Send event to TUI event loop if not paused
                 if !state.paused {
-                    let _ = tui.event_tx.send(Event::DataUpdate(snapshot));
+                    let _ = tui.event_tx.send(Event::DataUpdate(Box::new(snapshot)));
+                } else if let Some(ref mut backlog) = paused_backlog {
+                    // Fold intervals that arrive while paused into one, shown on resume
+                    backlog.merge(snapshot);
+                } else {
+                    paused_backlog = Some(snapshot);
                 }
             }
             // Handle UI events
@@ -180,73 +673,472 @@ async fn run_interactive_mode(process_list: &mut ProcessList, args: &Args) -> Re
 
                     }
                     Event::DataUpdate(snapshot) => {
-                        render_snapshot(&mut tui, &snapshot, &mut state, has_delay_acct)?;
+                        // Only known once delays were seen in a sample
+                        has_delay_acct = TaskStats::has_delay_acct();
+                        if let Some(overhead) = &mut overhead {
+                            state.overhead = overhead.sample();
+                        }
+                        state.history.record(&snapshot, Instant::now());
+                        // Sub-samples are judged over a whole delay, as they are shown
+                        let interval = state
+                            .subsample_window()
+                            .map(|window| state.history.average(&snapshot, window));
+                        let judged = interval.as_ref().unwrap_or(&snapshot);
+                        state.track_spikes(judged);
+                        state.check_alerts(judged);
+                        state.check_quotas(judged);
+                        if let Some(zoom) = &mut state.zoom {
+                            zoom.invalidate();
+                        }
+                        snapshots.push(*snapshot);
+                        if state.rewind > 0 {
+                            // Keep showing the same interval
+                            state.rewind = (state.rewind + 1).min(snapshots.len() - 1);
+                        }
+                        if let Some(snapshot) = snapshots.get(state.rewind) {
+                            render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                        }
 
-                        // Check iteration limit
+                        // Check iteration limit, counted in delays
                         if let Some(max_iter) = args.iterations {
                             iteration += 1;
-                            if iteration >= max_iter {
+                            if iteration >= max_iter * args.subsamples as usize {
                                 break;
                             }
                         }
                     }
                     Event::Render => {
-                        if let Some(ref snapshot) = current_snapshot {
+                        if let Some(snapshot) = snapshots.get(state.rewind) {
                             render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
                         }
                     }
+                    Event::Key(key) if state.column_chooser.is_some() => {
+                        handle_column_chooser_key(&mut state, key.code);
+                    }
+                    Event::Key(key) if state.zoom.is_some() && state.prompt.is_none() => {
+                        match key.code {
+                            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                            KeyCode::Esc
+                            | KeyCode::Enter
+                            | KeyCode::Char('z')
+                            | KeyCode::Char('Z')
+                            | KeyCode::Char('q') => {
+                                state.zoom = None;
+                                state.announce("Back to the table");
+                                if let Some(snapshot) = snapshots.get(state.rewind) {
+                                    render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    Event::Key(key)
+                        if state
+                            .prompt
+                            .as_ref()
+                            .is_some_and(|prompt| prompt.kind == PromptKind::Where) =>
+                    {
+                        if handle_filter_key(&mut state, key.code) {
+                            state.scroll_offset = 0;
+                            state.selection_mode = false;
+                            state.selected_row = None;
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+                    }
+                    Event::Key(key) if state.prompt.is_some() => {
+                        let shown = snapshots.get(state.rewind);
+                        let Some(prompt) = handle_prompt_key(&mut state, key.code, shown) else {
+                            continue;
+                        };
+
+                        if prompt.kind == PromptKind::User {
+                            let users: Vec<String> = prompt
+                                .input
+                                .split(',')
+                                .map(|user| user.trim().to_string())
+                                .filter(|user| !user.is_empty())
+                                .collect();
+                            match resolve_users(&users) {
+                                Ok(uids) => {
+                                    process_list.uids = uids;
+                                    paused_backlog = None;
+                                    state.announce(format!(
+                                        "Showing {}",
+                                        if users.is_empty() {
+                                            "all users".to_string()
+                                        } else {
+                                            users.join(", ")
+                                        }
+                                    ));
+                                    state.user_filter = users;
+                                    state.scroll_offset = 0;
+                                    state.selection_mode = false;
+                                    state.selected_row = None;
+                                    data_stream = start_refresh_stream(
+                                        process_list,
+                                        args,
+                                        &state,
+                                        &mut data_cancel_token,
+                                        HashMap::new(),
+                                    );
+                                }
+                                Err(e) => state.report(e.to_string()),
+                            }
+                        }
+
+                        if prompt.kind == PromptKind::Preset {
+                            let name = prompt.input.trim();
+                            match select_preset(process_list, &mut state, command_line, name) {
+                                Ok(()) => {
+                                    paused_backlog = None;
+                                    state.announce(if name.is_empty() {
+                                        "Showing the command line's filters".to_string()
+                                    } else {
+                                        format!("Showing preset {}", name)
+                                    });
+                                    state.scroll_offset = 0;
+                                    state.selection_mode = false;
+                                    state.selected_row = None;
+                                    data_stream = start_refresh_stream(
+                                        process_list,
+                                        args,
+                                        &state,
+                                        &mut data_cancel_token,
+                                        HashMap::new(),
+                                    );
+                                }
+                                Err(e) => state.report(e.to_string()),
+                            }
+                        }
+
+                        if prompt.kind == PromptKind::Pid {
+                            match filter::PidFilter::parse(&prompt.input) {
+                                Ok(pid_filter) => {
+                                    state.announce(if pid_filter.is_empty() {
+                                        "Showing all PIDs".to_string()
+                                    } else {
+                                        format!("Showing PIDs {}", pid_filter)
+                                    });
+                                    state.pid_filter = pid_filter;
+                                    state.scroll_offset = 0;
+                                    state.selection_mode = false;
+                                    state.selected_row = None;
+                                    if let Some(snapshot) = snapshots.get(state.rewind) {
+                                        render_snapshot(
+                                            &mut tui,
+                                            snapshot,
+                                            &mut state,
+                                            has_delay_acct,
+                                        )?;
+                                    }
+                                }
+                                Err(e) => state.report(e.to_string()),
+                            }
+                        }
+
+                        if prompt.kind == PromptKind::Unit {
+                            let unit_filter = filter::UnitFilter::parse(&prompt.input);
+                            state.announce(if unit_filter.is_empty() {
+                                "Showing all units".to_string()
+                            } else {
+                                format!("Showing units {}", unit_filter)
+                            });
+                            state.unit_filter = unit_filter;
+                            state.scroll_offset = 0;
+                            state.selection_mode = false;
+                            state.selected_row = None;
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+
+                        if prompt.kind == PromptKind::Highlight {
+                            let pattern = prompt.input.trim();
+                            let highlight = if pattern.is_empty() {
+                                Ok(None)
+                            } else {
+                                filter::parse_pattern(pattern).map(Some)
+                            };
+                            match highlight {
+                                Ok(highlight) => {
+                                    state.announce(match &highlight {
+                                        Some(pattern) => format!("Highlighting {}", pattern),
+                                        None => "Highlight off".to_string(),
+                                    });
+                                    state.highlight = highlight;
+                                    if let Some(snapshot) = snapshots.get(state.rewind) {
+                                        render_snapshot(
+                                            &mut tui,
+                                            snapshot,
+                                            &mut state,
+                                            has_delay_acct,
+                                        )?;
+                                    }
+                                }
+                                Err(e) => state.report(e),
+                            }
+                        }
+                    }
                     Event::Key(key) => match key.code {
                         KeyCode::Char('q') | KeyCode::Char('Q') => break,
                         KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                        KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            suspended = true;
+                            tui.suspend()?;
+                        }
                         KeyCode::Char('o') | KeyCode::Char('O') => {
                             state.only_active = !state.only_active;
                             state.scroll_offset = 0;
                             state.selection_mode = false;
                             state.selected_row = None;
+                            state.announce(format!("Only active {}", ui::on_off(state.only_active)));
+                        }
+                        KeyCode::Char('b') | KeyCode::Char('B') => {
+                            state.blocked_only = !state.blocked_only;
+                            state.scroll_offset = 0;
+                            state.selection_mode = false;
+                            state.selected_row = None;
+                            state.announce(format!("Only blocked {}", ui::on_off(state.blocked_only)));
                         }
                         KeyCode::Char('a') | KeyCode::Char('A') => {
                             state.accumulated = !state.accumulated;
                             state.scroll_offset = 0;
                             state.selection_mode = false;
                             state.selected_row = None;
+                            state.announce(format!("Accumulated {}", ui::on_off(state.accumulated)));
                         }
                         KeyCode::Char('r') | KeyCode::Char('R') => {
                             state.sort_reverse = !state.sort_reverse;
                             state.scroll_offset = 0;
                             state.selection_mode = false;
                             state.selected_row = None;
+                            state.announce(format!("Reversed {}", ui::on_off(!state.sort_reverse)));
+                        }
+                        KeyCode::Char('m') | KeyCode::Char('M') if has_delay_acct => {
+                            state.delay_ms = !state.delay_ms;
+                            state.announce(format!(
+                                "Delays in {}",
+                                if state.delay_ms { "milliseconds" } else { "percent" }
+                            ));
+                        }
+                        KeyCode::Char('c') | KeyCode::Char('C') => {
+                            state.column_chooser = Some(0);
+                            state.announce("Column chooser open");
+                        }
+                        // While paused, Space resumes instead of tagging
+                        KeyCode::Char(' ') if state.selection_mode && !state.paused => {
+                            state.toggle_tag_selected();
+                        }
+                        KeyCode::Char('v') | KeyCode::Char('V') if state.selection_mode => {
+                            state.toggle_tag_selected();
+                        }
+                        KeyCode::Char('w') | KeyCode::Char('W') => {
+                            state.average_window = state.average_window.next();
+                            state.announce(format!(
+                                "Rates averaged over {}",
+                                state.average_window.label()
+                            ));
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+                        KeyCode::Char('l') | KeyCode::Char('L') => {
+                            state.show_alerts = !state.show_alerts;
+                            state.announce(format!(
+                                "Alert log {}, {} entries",
+                                ui::on_off(state.show_alerts),
+                                state.alerts.len()
+                            ));
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+                        KeyCode::Char('d') | KeyCode::Char('D') => {
+                            state.show_devices = !state.show_devices;
+                            state.announce(format!("Devices {}", ui::on_off(state.show_devices)));
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+                        KeyCode::Char('t') | KeyCode::Char('T') => {
+                            state.show_cgroups = !state.show_cgroups;
+                            state.announce(format!("Cgroups {}", ui::on_off(state.show_cgroups)));
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+                        KeyCode::Char('e') | KeyCode::Char('E') => {
+                            state.show_cpus = !state.show_cpus;
+                            state.announce(format!("CPU iowait {}", ui::on_off(state.show_cpus)));
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
+                        }
+                        KeyCode::Char('s') | KeyCode::Char('S') => {
+                            open_action_prompt(&mut state, PromptKind::Stop);
+                        }
+                        KeyCode::Char('g') | KeyCode::Char('G') => {
+                            let shown = snapshots.get(state.rewind);
+                            run_bulk_action(&mut state, BulkAction::Continue, shown);
+                        }
+                        KeyCode::Char('k') | KeyCode::Char('K') => {
+                            open_action_prompt(&mut state, PromptKind::Kill);
+                        }
+                        KeyCode::Char('i') | KeyCode::Char('I') => {
+                            open_action_prompt(&mut state, PromptKind::Ionice);
+                        }
+                        KeyCode::Char('n') | KeyCode::Char('N') => {
+                            open_action_prompt(&mut state, PromptKind::Nice);
                         }
                         KeyCode::Char(' ') => {
                             state.paused = !state.paused;
+                            state.announce(if state.paused { "Paused" } else { "Resumed" });
+                            if !state.paused && state.rewind > 0 {
+                                state.rewind = 0;
+                                if let Some(snapshot) = snapshots.get(state.rewind) {
+                                    render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                                }
+                            }
+                            if !state.paused {
+                                if let Some(backlog) = paused_backlog.take() {
+                                    state.report(format!(
+                                        "Showing I/O accumulated over the {:.1}s pause",
+                                        backlog.duration
+                                    ));
+                                    let _ = tui.event_tx.send(Event::DataUpdate(Box::new(backlog)));
+                                }
+                            }
+                        }
+                        KeyCode::Char('[') | KeyCode::Char(']') => {
+                            // Scrubbing only makes sense on a frozen table
+                            state.paused = true;
+                            state.rewind = if key.code == KeyCode::Char('[') {
+                                (state.rewind + 1).min(snapshots.len().saturating_sub(1))
+                            } else {
+                                state.rewind.saturating_sub(1)
+                            };
+                            state.announce(if state.rewind == 0 {
+                                "Paused at latest interval".to_string()
+                            } else {
+                                format!("Paused, {} intervals back", state.rewind)
+                            });
+                            if let Some(snapshot) = snapshots.get(state.rewind) {
+                                render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                            }
                         }
                         KeyCode::Char('p') | KeyCode::Char('P') => {
                             state.show_processes = !state.show_processes;
+                            state.tagged.clear();
+                            state.zoom = None;
+                            state.history.clear();
+                            snapshots.clear();
+                            paused_backlog = None;
+                            state.rewind = 0;
                             state.scroll_offset = 0;
                             state.selection_mode = false;
                             state.selected_row = None;
-
-                            data_cancel_token.cancel();
-                            data_cancel_token = CancellationToken::new();
-                            data_stream = ProcessList::spawn_refresh_stream(
-                                1.0 / args.delay,
-                                state.show_processes,
-                                process_list.taskstats_conn.clone(),
-                                args.pid.clone(),
-                                process_list.uids.clone(),
-                                data_cancel_token.clone(),
+                            state.announce(format!("Processes {}", ui::on_off(state.show_processes)));
+
+                            // Carry on from the rows of the other mode, with
+                            // their accumulated totals
+                            let rows = process_list
+                                .stream_rows
+                                .lock()
+                                .map(|mut rows| std::mem::take(&mut *rows))
+                                .unwrap_or_default();
+                            data_stream = start_refresh_stream(
+                                process_list,
+                                args,
+                                &state,
+                                &mut data_cancel_token,
+                                process::convert_rows(rows, state.show_processes),
                             );
                         }
+                        KeyCode::Enter | KeyCode::Char('z') | KeyCode::Char('Z') => {
+                            match state.selected_id() {
+                                Some(id) => {
+                                    state.zoom = Some(zoom::ZoomView::new(id, state.show_processes));
+                                    state.announce(format!("Zoom on {}", id));
+                                    if let Some(snapshot) = snapshots.get(state.rewind) {
+                                        render_snapshot(&mut tui, snapshot, &mut state, has_delay_acct)?;
+                                    }
+                                }
+                                None => state.report("Select a row (Up/Down) first"),
+                            }
+                        }
+                        KeyCode::Char('#') => {
+                            let mut prompt = Prompt::new(PromptKind::Pid);
+                            prompt.input = state.pid_filter.to_string();
+                            state.prompt = Some(prompt);
+                        }
+                        KeyCode::Char('f') | KeyCode::Char('F') => {
+                            let mut prompt = Prompt::new(PromptKind::Unit);
+                            prompt.input = state.unit_filter.to_string();
+                            state.prompt = Some(prompt);
+                        }
+                        KeyCode::Char('/') => {
+                            let mut prompt = Prompt::new(PromptKind::Highlight);
+                            prompt.input = state
+                                .highlight
+                                .as_ref()
+                                .map(|pattern| pattern.to_string())
+                                .unwrap_or_default();
+                            state.prompt = Some(prompt);
+                        }
+                        KeyCode::F(4) => {
+                            let mut prompt = Prompt::new(PromptKind::Where);
+                            prompt.input = state
+                                .condition
+                                .as_ref()
+                                .map(|condition| condition.to_string())
+                                .unwrap_or_default();
+                            state.condition_before_edit = state.condition.clone();
+                            state.prompt = Some(prompt);
+                        }
+                        KeyCode::Char('x') | KeyCode::Char('X') => {
+                            let mut prompt = Prompt::new(PromptKind::Preset);
+                            prompt.input = state.preset.clone().unwrap_or_default();
+                            state.prompt = Some(prompt);
+                        }
+                        KeyCode::Char('u') | KeyCode::Char('U') => {
+                            let mut prompt = Prompt::new(PromptKind::User);
+                            prompt.input = state.user_filter.join(",");
+                            state.prompt = Some(prompt);
+                        }
+                        KeyCode::Char(c @ '1'..='8') => {
+                            let columns = SortColumn::available_columns(has_delay_acct, &state.extra_columns);
+                            let index = c as usize - '1' as usize;
+                            if let Some(&column) = columns.get(index) {
+                                // Pressing the current column's number flips the order
+                                if column == state.sort_column {
+                                    state.sort_reverse = !state.sort_reverse;
+                                }
+                                state.sort_column = column;
+                                state.scroll_offset = 0;
+                                state.selection_mode = false;
+                                state.selected_row = None;
+                                state.announce(format!("Sort by {}", state.sort_column.as_str()));
+                            }
+                        }
                         KeyCode::Left => {
-                            state.sort_column = state.sort_column.cycle_backward(has_delay_acct);
+                            state.sort_column = state
+                                .sort_column
+                                .cycle_backward(has_delay_acct, &state.extra_columns);
                             state.scroll_offset = 0;
                             state.selection_mode = false;
                             state.selected_row = None;
+                            state.announce(format!("Sort by {}", state.sort_column.as_str()));
                         }
                         KeyCode::Right => {
-                            state.sort_column = state.sort_column.cycle_forward(has_delay_acct);
+                            state.sort_column = state
+                                .sort_column
+                                .cycle_forward(has_delay_acct, &state.extra_columns);
                             state.scroll_offset = 0;
                             state.selection_mode = false;
                             state.selected_row = None;
+                            state.announce(format!("Sort by {}", state.sort_column.as_str()));
                         }
                         KeyCode::Up => {
                             if !state.selection_mode {
@@ -275,9 +1167,10 @@ async fn run_interactive_mode(process_list: &mut ProcessList, args: &Args) -> Re
                                 }
                                 state.scroll_offset = 0;
                             } else {
-                                state.sort_column = SortColumn::available_columns(has_delay_acct)[0];
+                                state.sort_column = SortColumn::available_columns(has_delay_acct, &state.extra_columns)[0];
                                 state.selection_mode = false;
                                 state.selected_row = None;
+                                state.announce(format!("Sort by {}", state.sort_column.as_str()));
                             }
                         }
                         KeyCode::End => {
@@ -287,10 +1180,11 @@ async fn run_interactive_mode(process_list: &mut ProcessList, args: &Args) -> Re
                                     state.selected_row = Some(usize::MAX);
                                 }
                             } else {
-                                let columns = SortColumn::available_columns(has_delay_acct);
+                                let columns = SortColumn::available_columns(has_delay_acct, &state.extra_columns);
                                 state.sort_column = columns[columns.len() - 1];
                                 state.selection_mode = false;
                                 state.selected_row = None;
+                                state.announce(format!("Sort by {}", state.sort_column.as_str()));
                             }
                         }
                         KeyCode::PageUp => {
@@ -316,7 +1210,13 @@ async fn run_interactive_mode(process_list: &mut ProcessList, args: &Args) -> Re
                                 state.scroll_offset = state.scroll_offset.saturating_add(SCROLL_PAGE_SIZE);
                             }
                         }
+                        KeyCode::Esc if state.show_alerts => {
+                            state.show_alerts = false;
+                        }
                         KeyCode::Esc => {
+                            if !state.selection_mode {
+                                state.tagged.clear();
+                            }
                             state.selection_mode = false;
                             state.selected_row = None;
                         }
@@ -377,9 +1277,296 @@ async fn run_interactive_mode(process_list: &mut ProcessList, args: &Args) -> Re
     // Ensure terminal cleanup happens
     tui.exit()?;
 
+    if let Err(e) = state_file::save(&state) {
+        eprintln!("Warning: could not save state: {:#}", e);
+    }
+
     Ok(())
 }
 
+/// Handle a key press while the optional column chooser is open
+fn handle_column_chooser_key(state: &mut UIState, code: KeyCode) {
+    let Some(cursor) = state.column_chooser else {
+        return;
+    };
+    let last = ExtraColumn::ALL.len() - 1;
+
+    match code {
+        KeyCode::Up => state.column_chooser = Some(cursor.saturating_sub(1)),
+        KeyCode::Down => state.column_chooser = Some((cursor + 1).min(last)),
+        KeyCode::Char(' ') | KeyCode::Enter => {
+            let column = ExtraColumn::ALL[cursor];
+            state.toggle_extra_column(column);
+            let shown = state.extra_columns.contains(&column);
+            state.announce(format!("Column {} {}", column.name(), ui::on_off(shown)));
+        }
+        KeyCode::Esc | KeyCode::Char('c') | KeyCode::Char('C') | KeyCode::Char('q') => {
+            state.column_chooser = None;
+            state.announce("Column chooser closed");
+        }
+        _ => {}
+    }
+}
+
+/// Open the confirmation/value prompt for a bulk action
+fn open_action_prompt(state: &mut UIState, kind: PromptKind) {
+    if state.action_targets().is_empty() {
+        state.report("Select a row (Up/Down) or tag rows (v) first");
+        return;
+    }
+    state.prompt = Some(Prompt::new(kind));
+}
+
+/// Handle a key press while a text prompt is open
+///
+/// Bulk actions are applied directly; other prompts are returned once
+/// submitted, for the caller to act on.
+fn handle_prompt_key(
+    state: &mut UIState,
+    code: KeyCode,
+    snapshot: Option<&ProcessSnapshot>,
+) -> Option<Prompt> {
+    let prompt = state.prompt.as_mut()?;
+
+    match code {
+        KeyCode::Esc => {
+            state.prompt = None;
+            state.announce("Cancelled");
+        }
+        KeyCode::Char('n') | KeyCode::Char('N') if prompt.kind.is_confirmation() => {
+            state.prompt = None;
+            state.announce("Cancelled");
+        }
+        KeyCode::Char('y') | KeyCode::Char('Y') if prompt.kind.is_confirmation() => {
+            let kind = prompt.kind;
+            state.prompt = None;
+            apply_action(state, kind, "", snapshot);
+        }
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        }
+        KeyCode::Char(c) => prompt.input.push(c),
+        KeyCode::Enter => {
+            let prompt = state.prompt.take()?;
+            match prompt.kind {
+                PromptKind::Kill | PromptKind::Stop | PromptKind::Ionice | PromptKind::Nice => {
+                    apply_action(state, prompt.kind, &prompt.input, snapshot);
+                }
+                PromptKind::User
+                | PromptKind::Pid
+                | PromptKind::Unit
+                | PromptKind::Highlight
+                | PromptKind::Preset
+                | PromptKind::Where => return Some(prompt),
+            }
+        }
+        _ => {}
+    }
+
+    None
+}
+
+/// Edit the expression of the F4 filter editor, applying it to the table
+/// as soon as it parses; true if the rows shown may have changed
+fn handle_filter_key(state: &mut UIState, code: KeyCode) -> bool {
+    let Some(prompt) = state.prompt.as_mut() else {
+        return false;
+    };
+
+    match code {
+        KeyCode::Esc => {
+            state.prompt = None;
+            state.condition = state.condition_before_edit.take();
+            state.announce("Cancelled");
+            return true;
+        }
+        KeyCode::Enter => {
+            if let Some(error) = &prompt.error {
+                let error = format!("Invalid filter: {}", error);
+                state.report(error);
+                return false;
+            }
+            state.prompt = None;
+            state.condition_before_edit = None;
+            state.announce(match &state.condition {
+                Some(condition) => format!("Filter {}", condition),
+                None => "Filter off".to_string(),
+            });
+            return false;
+        }
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        }
+        KeyCode::Char(c) => prompt.input.push(c),
+        _ => return false,
+    }
+
+    // Keep the last valid expression applied while the input is incomplete
+    if prompt.input.trim().is_empty() {
+        prompt.error = None;
+        state.condition = None;
+    } else {
+        match expr::Condition::parse(&prompt.input) {
+            Ok(condition) => {
+                prompt.error = None;
+                state.condition = Some(condition);
+            }
+            Err(e) => {
+                prompt.error = Some(e);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Cancel the running refresh stream, if any, and start a new one with the
+/// current process mode and filters, from `rows` of that mode or from scratch
+fn start_refresh_stream(
+    process_list: &mut ProcessList,
+    args: &Args,
+    state: &UIState,
+    cancel_token: &mut CancellationToken,
+    rows: HashMap<i32, process::ProcessInfo>,
+) -> mpsc::UnboundedReceiver<ProcessSnapshot> {
+    cancel_token.cancel();
+    *cancel_token = CancellationToken::new();
+    // The old stream may still finish a refresh, so it keeps its own rows
+    process_list.stream_rows = Arc::new(Mutex::new(rows));
+    let config = process_list.refresh_config(
+        f64::from(args.subsamples) / args.delay,
+        state.show_processes,
+        state.grouping,
+    );
+    ProcessList::spawn_refresh_stream(config, cancel_token.clone())
+}
+
+/// A bulk action with its parsed argument
+#[derive(Clone, Copy)]
+enum BulkAction {
+    Kill,
+    Ionice(ioprio::Ioprio),
+    Nice(i32),
+    Stop,
+    Continue,
+}
+
+/// Parse the answer to a bulk action prompt and apply it
+fn apply_action(
+    state: &mut UIState,
+    kind: PromptKind,
+    input: &str,
+    snapshot: Option<&ProcessSnapshot>,
+) {
+    let parsed = match kind {
+        PromptKind::Kill => Ok(BulkAction::Kill),
+        PromptKind::Stop => Ok(BulkAction::Stop),
+        PromptKind::Ionice => ioprio::Ioprio::from_string(input.trim()).map(BulkAction::Ionice),
+        PromptKind::Nice => actions::parse_nice(input).map(BulkAction::Nice),
+        PromptKind::User
+        | PromptKind::Pid
+        | PromptKind::Unit
+        | PromptKind::Highlight
+        | PromptKind::Preset
+        | PromptKind::Where => return,
+    };
+    let action = match parsed {
+        Ok(action) => action,
+        Err(e) => return state.report(e.to_string()),
+    };
+    run_bulk_action(state, action, snapshot);
+}
+
+/// Apply a bulk action to the tagged rows (or the selected row)
+fn run_bulk_action(state: &mut UIState, action: BulkAction, snapshot: Option<&ProcessSnapshot>) {
+    let Some(snapshot) = snapshot else {
+        return;
+    };
+    let targets: Vec<&process::ProcessInfo> = state
+        .action_targets()
+        .iter()
+        .filter_map(|id| snapshot.processes.get(id))
+        .collect();
+    if targets.is_empty() {
+        return state.report("Select a row (Up/Down) or tag rows (v) first");
+    }
+
+    // Stopping iotop itself, or the shell and terminal it runs under, would
+    // leave no way to continue them
+    let protected = match action {
+        BulkAction::Stop => actions::own_lineage(),
+        _ => HashSet::new(),
+    };
+    let mut done = 0;
+    let mut skipped = 0;
+    let mut last_error = None;
+    for process in &targets {
+        // In process mode, priorities are per thread, so apply to every thread
+        let tids: Vec<i32> = if state.show_processes {
+            process.threads.keys().copied().collect()
+        } else {
+            vec![process.tid]
+        };
+        // Process group and session rows signal each of their processes
+        let mut pids = if process.members.is_empty() {
+            vec![process.pid]
+        } else {
+            process.members.clone()
+        };
+        pids.retain(|pid| !protected.contains(pid));
+        if pids.is_empty() {
+            skipped += 1;
+            continue;
+        }
+        let signal = |signal| {
+            pids.iter()
+                .try_for_each(|pid| actions::send_signal(*pid, signal))
+        };
+
+        let result = match action {
+            BulkAction::Kill => signal(nix::sys::signal::SIGTERM),
+            BulkAction::Ionice(prio) => tids
+                .iter()
+                .try_for_each(|tid| actions::set_ionice(*tid, prio)),
+            BulkAction::Nice(nice) => tids
+                .iter()
+                .try_for_each(|tid| actions::set_nice(*tid, nice)),
+            // Job control signals always act on the whole thread group
+            BulkAction::Stop => signal(nix::sys::signal::SIGSTOP),
+            BulkAction::Continue => signal(nix::sys::signal::SIGCONT),
+        };
+
+        match result {
+            Ok(()) => done += 1,
+            Err(e) => last_error = Some(e),
+        }
+    }
+    // The priority shown follows the nice value unless one was set
+    if done > 0 && matches!(action, BulkAction::Ionice(_) | BulkAction::Nice(_)) {
+        state.prio_changed.store(true, Ordering::Relaxed);
+    }
+
+    let verb = match action {
+        BulkAction::Kill => "Sent SIGTERM to",
+        BulkAction::Ionice(_) => "Set I/O priority of",
+        BulkAction::Nice(_) => "Set nice of",
+        BulkAction::Stop => "Sent SIGSTOP to",
+        BulkAction::Continue => "Sent SIGCONT to",
+    };
+    let mut message = match last_error {
+        None => format!("{} {} row(s)", verb, done),
+        Some(e) => format!("{} {} of {} row(s); {}", verb, done, targets.len(), e),
+    };
+    if skipped > 0 {
+        message.push_str(&format!(
+            "; skipped {} row(s) of iotop and the processes it runs under",
+            skipped
+        ));
+    }
+    state.report(message);
+    state.tagged.clear();
+}
+
 /// Prepare and render a snapshot of process data to the TUI
 fn render_snapshot(
     tui: &mut Tui,
@@ -387,18 +1574,73 @@ fn render_snapshot(
     state: &mut UIState,
     has_delay_acct: bool,
 ) -> Result<()> {
+    // Averages are anchored at the latest sample, so they only apply to the live view
+    let averaged = match state.average_window.duration().or(state.subsample_window()) {
+        Some(window) if !state.accumulated && state.rewind == 0 => {
+            Some(state.history.average(snapshot, window))
+        }
+        _ => None,
+    };
+    let snapshot = averaged.as_ref().unwrap_or(snapshot);
+
     let mut processes: Vec<&process::ProcessInfo> = snapshot.processes.values().collect();
 
     if state.only_active {
         processes.retain(|p| p.did_some_io(state.accumulated));
     }
+    if state.min_io > 0 {
+        processes.retain(|p| p.io_rate(snapshot.duration) >= state.min_io as f64);
+    }
+    if state.blocked_only {
+        processes.retain(|p| p.uninterruptible);
+    }
+    if let Some(prefix) = &state.path_filter {
+        processes.retain(|p| filetrace::did_io_under(&snapshot.files, p.pid, prefix));
+    }
+    if let Some(device) = &state.device_filter {
+        processes
+            .retain(|p| devtrace::did_io_on(&snapshot.device_io, p.pid, device, state.accumulated));
+    }
+    if let Some(class) = state.prio_class {
+        processes.retain(|p| p.prio_class() == Some(class));
+    }
+    if let Some(condition) = &state.condition {
+        processes.retain(|p| condition.matches(p, snapshot.duration));
+    }
+    processes.retain(|p| state.pid_filter.matches(p) && state.unit_filter.matches(p));
 
     sort_processes(&mut processes, state);
 
+    state.row_ids = processes.iter().map(|p| p.tid).collect();
+    state
+        .tagged
+        .retain(|id| snapshot.processes.contains_key(id));
+    if let Some(zoom) = &mut state.zoom {
+        zoom.update(snapshot, &state.history);
+    }
+    state.devices = snapshot.devices.clone();
+    state.cgroups = snapshot.cgroups.clone();
+    if let Some(budgets) = &state.quotas {
+        state.quota_usage = budgets.usage(snapshot.processes.values(), snapshot.duration);
+    }
+    state.throttled = snapshot.throttled.clone();
+    state.nfs_mounts = snapshot.nfs_mounts.clone();
+    state.net = snapshot.net.clone();
+    state.device_io = snapshot.device_io.clone();
+    state.syncs = snapshot.syncs.clone();
+    state.latency = snapshot.latency.clone();
+    state.writeback = snapshot.writeback.clone();
+    state.pressure = snapshot.pressure;
+    state.dirty = snapshot.dirty;
+    state.swap = snapshot.swap.clone();
+    state.iowait = snapshot.iowait.clone();
+    state.load = snapshot.load;
+    state.uninterruptible = snapshot.uninterruptible;
+
     let available_height = tui
         .terminal
         .size()
-        .map(|size| size.height.saturating_sub(UI_HEADER_HEIGHT) as usize)
+        .map(|size| ui::visible_rows(size.height, state))
         .unwrap_or(10);
 
     // Clamp selected_row to valid range if in selection mode
@@ -461,6 +1703,34 @@ fn sort_processes(processes: &mut Vec<&process::ProcessInfo>, state: &UIState) {
             }
             SortColumn::Swapin => stats_b.swapin_delay_total.cmp(&stats_a.swapin_delay_total),
             SortColumn::Io => stats_b.blkio_delay_total.cmp(&stats_a.blkio_delay_total),
+            SortColumn::CpuDelay => stats_b.cpu_delay_total.cmp(&stats_a.cpu_delay_total),
+            SortColumn::Reclaim => stats_b
+                .freepages_delay_total
+                .cmp(&stats_a.freepages_delay_total),
+            SortColumn::Thrashing => stats_b
+                .thrashing_delay_total
+                .cmp(&stats_a.thrashing_delay_total),
+            SortColumn::Compact => stats_b
+                .compact_delay_total
+                .cmp(&stats_a.compact_delay_total),
+            SortColumn::WpCopy => stats_b.wpcopy_delay_total.cmp(&stats_a.wpcopy_delay_total),
+            SortColumn::ReadChar => stats_b.read_char.cmp(&stats_a.read_char),
+            SortColumn::WriteChar => stats_b.write_char.cmp(&stats_a.write_char),
+            SortColumn::Cancelled => stats_b
+                .cancelled_write_bytes
+                .cmp(&stats_a.cancelled_write_bytes),
+            SortColumn::MajorFaults => stats_b.major_faults.cmp(&stats_a.major_faults),
+            SortColumn::MinorFaults => stats_b.minor_faults.cmp(&stats_a.minor_faults),
+            SortColumn::IoWaits => stats_b.blkio_count.cmp(&stats_a.blkio_count),
+            SortColumn::SwapIns => stats_b.swapin_count.cmp(&stats_a.swapin_count),
+            SortColumn::IoAvg => {
+                // Rows without waits sort as if their average were zero
+                let average =
+                    |s: &TaskStats| s.blkio_delay_total.checked_div(s.blkio_count).unwrap_or(0);
+                average(stats_b).cmp(&average(stats_a))
+            }
+            // Keeps the processes of each unit together
+            SortColumn::Unit => a.unit.cmp(&b.unit),
 
             SortColumn::Command => a.get_cmdline().cmp(b.get_cmdline()),
         };
@@ -489,6 +1759,11 @@ fn run_batch_mode(process_list: &mut ProcessList, args: &Args) -> Result<()> {
     use std::time::Duration;
 
     let mut iteration = 0;
+    // Started now, so the first interval counts the setup as well
+    let mut overhead = OverheadSampler::default();
+    if args.stats {
+        overhead.sample();
+    }
 
     loop {
         let timestamp = if args.time {
@@ -497,7 +1772,8 @@ fn run_batch_mode(process_list: &mut ProcessList, args: &Args) -> Result<()> {
             String::new()
         };
 
-        let (total, actual) = process_list.refresh_processes(args.processes)?;
+        let (total, actual) =
+            process_list.refresh_processes(args.processes || args.group.is_some())?;
 
         if !args.quiet {
             if writeln!(
@@ -525,6 +1801,64 @@ fn run_batch_mode(process_list: &mut ProcessList, args: &Args) -> Result<()> {
             }
         }
 
+        if args.devices {
+            for device in &process_list.devices {
+                if writeln!(
+                    io::stdout(),
+                    "{}DEVICE {:<9}:   {:>14} | {:>14} | {:>6.0} r/s {:>6.0} w/s | {:>5.1} % busy | {:>6} r_lat {:>6} w_lat",
+                    timestamp,
+                    device.name,
+                    ui::human_size(device.read_bytes_per_sec as i64) + "/s",
+                    ui::human_size(device.write_bytes_per_sec as i64) + "/s",
+                    device.read_iops,
+                    device.write_iops,
+                    device.utilization,
+                    ui::format_ms(device.read_latency_ms()),
+                    ui::format_ms(device.write_latency_ms())
+                )
+                .is_err()
+                {
+                    return Ok(());
+                }
+            }
+            for mount in &process_list.nfs_mounts {
+                if writeln!(
+                    io::stdout(),
+                    "{}NFS {}:   {:>14} | {:>14} | {:>6.0} r/s {:>6.0} w/s | {:>7} rtt",
+                    timestamp,
+                    mount.mount_point,
+                    ui::human_size(mount.read_bytes_per_sec as i64) + "/s",
+                    ui::human_size(mount.write_bytes_per_sec as i64) + "/s",
+                    mount.read_iops,
+                    mount.write_iops,
+                    ui::format_ms(mount.rtt_ms)
+                )
+                .is_err()
+                {
+                    return Ok(());
+                }
+            }
+            if let Some(swap) = &process_list.swap {
+                if writeln!(
+                    io::stdout(),
+                    "{}SWAP:   {:>14} in | {:>14} out",
+                    timestamp,
+                    ui::human_size(swap.in_bytes_per_sec as i64) + "/s",
+                    ui::human_size(swap.out_bytes_per_sec as i64) + "/s"
+                )
+                .is_err()
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(overhead) = overhead.sample().filter(|_| args.stats && !args.quiet) {
+            if writeln!(io::stdout(), "{}IOTOP: {}", timestamp, overhead.summary()).is_err() {
+                return Ok(());
+            }
+        }
+
         if iteration == 0 && !args.quiet {
             let has_delay = TaskStats::has_delay_acct();
             let header_prefix = if args.time { "    TIME " } else { "" };
@@ -561,11 +1895,30 @@ fn run_batch_mode(process_list: &mut ProcessList, args: &Args) -> Result<()> {
             }
         }
 
-        let mut processes: Vec<&process::ProcessInfo> = process_list.processes.values().collect();
+        let grouped = args
+            .group
+            .map(|grouping| process::group_rows(process_list.processes.clone(), grouping));
+        let mut processes: Vec<&process::ProcessInfo> = grouped
+            .as_ref()
+            .unwrap_or(&process_list.processes)
+            .values()
+            .collect();
 
         if args.only {
             processes.retain(|p| p.did_some_io(args.accumulated));
         }
+        if args.min_io > 0 {
+            processes.retain(|p| p.io_rate(process_list.duration) >= args.min_io as f64);
+        }
+        if args.blocked {
+            processes.retain(|p| p.uninterruptible);
+        }
+        if let Some(class) = args.class {
+            processes.retain(|p| p.prio_class() == Some(class));
+        }
+        if let Some(condition) = &args.condition {
+            processes.retain(|p| condition.matches(p, process_list.duration));
+        }
 
         processes.sort_by(|a, b| {
             let stats_a = if args.accumulated {
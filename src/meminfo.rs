@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+/// Page cache waiting to be written back, from /proc/meminfo
+///
+/// Writes land here first, so while `dirty_kb` grows the per-process write
+/// rates run ahead of Actual DISK WRITE.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirtyMemory {
+    /// Modified pages not yet queued for writing, in KiB
+    pub dirty_kb: u64,
+    /// Pages being written to storage right now, in KiB
+    pub writeback_kb: u64,
+}
+
+/// Parse /proc/meminfo content
+///
+/// Returns `None` unless both the `Dirty` and the `Writeback` line are
+/// present.
+pub fn parse(content: &str) -> Option<DirtyMemory> {
+    let mut dirty_kb = None;
+    let mut writeback_kb = None;
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value
+            .split_whitespace()
+            .next()
+            .and_then(|kb| kb.parse::<u64>().ok());
+        match key {
+            "Dirty" => dirty_kb = value,
+            "Writeback" => writeback_kb = value,
+            _ => {}
+        }
+    }
+    Some(DirtyMemory {
+        dirty_kb: dirty_kb?,
+        writeback_kb: writeback_kb?,
+    })
+}
+
+/// Read the current amount of dirty and writeback memory
+pub fn read() -> Result<DirtyMemory> {
+    let content = fs::read_to_string("/proc/meminfo")?;
+    parse(&content).context("Unexpected format of /proc/meminfo")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "MemTotal:       16318476 kB\n\
+                       Dirty:             52416 kB\n\
+                       Writeback:          1024 kB\n\
+                       WritebackTmp:          0 kB\n";
+        assert_eq!(
+            parse(content),
+            Some(DirtyMemory {
+                dirty_kb: 52416,
+                writeback_kb: 1024,
+            })
+        );
+        assert_eq!(parse("Dirty: 1 kB\n"), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+
+use crate::cgroup;
+use crate::proc_reader::{ProcReader, ProcessMetadata};
+use crate::unit;
+
+/// What a row shows about its task besides the counters
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub process: ProcessMetadata,
+    pub unit: Option<String>,
+    pub cgroup: Option<String>,
+}
+
+/// Read the metadata of task `tid` of process `pid`
+pub fn read(pid: i32, tid: i32) -> Option<Metadata> {
+    let process = ProcReader::new(tid).metadata_bundle(pid).ok()?;
+    let cgroups = fs::read_to_string(format!("/proc/{}/cgroup", process.pid)).unwrap_or_default();
+    Some(Metadata {
+        process,
+        unit: unit::parse_unit(&cgroups),
+        cgroup: cgroup::parse_path(&cgroups).map(str::to_string),
+    })
+}
+
+#[derive(Default)]
+struct State {
+    /// Tasks to look up, with their process
+    pending: Vec<(i32, i32)>,
+    /// Tasks pending or being looked up
+    queued: HashSet<i32>,
+    ready: HashMap<i32, Metadata>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when there are tasks to look up, or on close
+    wake: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Command lines, users and the rest of the metadata of newly seen tasks,
+/// read from /proc in a background thread
+///
+/// A refresh finding dozens of new tasks would otherwise read several files
+/// of each before sampling the next one. Their rows show up with the TID
+/// alone and get the rest one refresh later. The thread starts with the
+/// first request; if it cannot be started, requests read the metadata
+/// themselves.
+#[derive(Default)]
+pub struct MetadataQueue {
+    shared: Arc<Shared>,
+    started: OnceLock<bool>,
+}
+
+impl MetadataQueue {
+    /// Look up the metadata of task `tid` of process `pid`, unless already on
+    /// its way
+    pub fn request(&self, pid: i32, tid: i32) {
+        let started = *self.started.get_or_init(|| {
+            let shared = self.shared.clone();
+            thread::Builder::new()
+                .name("metadata".to_string())
+                .spawn(move || run(&shared))
+                .is_ok()
+        });
+
+        let mut state = self.shared.lock();
+        if !state.queued.insert(tid) {
+            return;
+        }
+        if started {
+            state.pending.push((pid, tid));
+            self.shared.wake.notify_one();
+        } else {
+            state.queued.remove(&tid);
+            if let Some(metadata) = read(pid, tid) {
+                state.ready.insert(tid, metadata);
+            }
+        }
+    }
+
+    /// Metadata looked up since the last call, by TID
+    ///
+    /// Tasks that were gone by then are left out, and can be requested
+    /// again.
+    pub fn take_ready(&self) -> HashMap<i32, Metadata> {
+        std::mem::take(&mut self.shared.lock().ready)
+    }
+}
+
+impl Drop for MetadataQueue {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.wake.notify_one();
+    }
+}
+
+/// Look up the requested tasks as they come, until the queue is dropped
+fn run(shared: &Shared) {
+    loop {
+        let mut state = shared.lock();
+        while !state.closed && state.pending.is_empty() {
+            state = shared.wake.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.closed {
+            return;
+        }
+        let pending = std::mem::take(&mut state.pending);
+        drop(state);
+
+        let found: Vec<(i32, Option<Metadata>)> = pending
+            .into_iter()
+            .map(|(pid, tid)| (tid, read(pid, tid)))
+            .collect();
+
+        let mut state = shared.lock();
+        for (tid, metadata) in found {
+            state.queued.remove(&tid);
+            if let Some(metadata) = metadata {
+                state.ready.insert(tid, metadata);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_request() {
+        let queue = MetadataQueue::default();
+        let own = std::process::id() as i32;
+        queue.request(own, own);
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let ready = loop {
+            let ready = queue.take_ready();
+            if !ready.is_empty() {
+                break ready;
+            }
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(ready[&own].process.pid, own);
+        assert!(queue.take_ready().is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Usage from which a filesystem counts as nearly full, in percent
+pub const NEARLY_FULL: f64 = 90.0;
+
+/// Mount points by mount ID, from /proc/[pid]/mountinfo content
+pub fn parse_mountinfo(content: &str) -> HashMap<u32, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let id = fields.next()?.parse().ok()?;
+            let mount_point = fields.nth(3)?;
+            Some((id, unescape(mount_point)))
+        })
+        .collect()
+}
+
+/// Undo the octal escapes of spaces and other separators in mount points, as
+/// found in mountinfo and mountstats
+pub fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            let digits = std::str::from_utf8(digits).ok()?;
+            u8::from_str_radix(digits, 8).ok()
+        });
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                out.push(byte);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Mount ID of a descriptor opened for writing, from /proc/[pid]/fdinfo/N
+/// content; `None` for read-only descriptors
+fn parse_fdinfo_writable(content: &str) -> Option<u32> {
+    let mut flags = None;
+    let mut mnt_id = None;
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key {
+            // Printed in octal
+            "flags" => flags = i32::from_str_radix(value.trim(), 8).ok(),
+            "mnt_id" => mnt_id = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    let access = flags? & libc::O_ACCMODE;
+    (access == libc::O_WRONLY || access == libc::O_RDWR)
+        .then_some(mnt_id)
+        .flatten()
+}
+
+/// Mount points of the files `pid` has open for writing, the one with the
+/// most such files first
+///
+/// Only files with a path count: pipes, sockets and the like have no mount
+/// point worth showing.
+pub fn write_mounts(pid: i32) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return Vec::new();
+    };
+    let mut mount_ids: HashMap<u32, usize> = HashMap::new();
+    for entry in entries.flatten() {
+        let is_path = fs::read_link(entry.path()).is_ok_and(|target| target.is_absolute());
+        if !is_path {
+            continue;
+        }
+        let fdinfo = format!(
+            "/proc/{}/fdinfo/{}",
+            pid,
+            entry.file_name().to_string_lossy()
+        );
+        if let Some(id) = fs::read_to_string(fdinfo)
+            .ok()
+            .and_then(|content| parse_fdinfo_writable(&content))
+        {
+            *mount_ids.entry(id).or_default() += 1;
+        }
+    }
+    if mount_ids.is_empty() {
+        return Vec::new();
+    }
+
+    let mount_points = fs::read_to_string(format!("/proc/{}/mountinfo", pid))
+        .map(|content| parse_mountinfo(&content))
+        .unwrap_or_default();
+    let mut mounts: Vec<(String, usize)> = mount_ids
+        .into_iter()
+        .filter_map(|(id, files)| Some((mount_points.get(&id)?.clone(), files)))
+        .collect();
+    mounts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    mounts.into_iter().map(|(mount, _)| mount).collect()
+}
+
+/// Space left on a filesystem mounted from a block device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsUsage {
+    pub mount_point: String,
+    /// Kernel name of the whole disk it lives on, e.g. `sda` for `/dev/sda2`
+    pub disk: String,
+    pub used_bytes: u64,
+    /// Bytes available to unprivileged users
+    pub avail_bytes: u64,
+}
+
+impl FsUsage {
+    /// Share of the space usable by unprivileged users that is taken, as
+    /// `df` computes it, so that blocks reserved for root count as full
+    pub fn used_percent(&self) -> f64 {
+        let usable = self.used_bytes.saturating_add(self.avail_bytes);
+        if usable == 0 {
+            return 0.0;
+        }
+        self.used_bytes as f64 * 100.0 / usable as f64
+    }
+
+    pub fn is_nearly_full(&self) -> bool {
+        self.used_percent() >= NEARLY_FULL
+    }
+}
+
+/// Whether a filesystem type is served by a FUSE daemon, e.g. `fuse.sshfs`
+/// or `fuseblk` for NTFS
+fn is_fuse(fstype: &str) -> bool {
+    fstype == "fuse" || fstype == "fuseblk" || fstype.starts_with("fuse.")
+}
+
+/// Mount IDs of the FUSE filesystems in /proc/[pid]/mountinfo content
+fn parse_fuse_mounts(content: &str) -> HashSet<u32> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            let id = mount.split_whitespace().next()?.parse().ok()?;
+            is_fuse(fs.split_whitespace().next()?).then_some(id)
+        })
+        .collect()
+}
+
+/// Mount ID of any descriptor, from /proc/[pid]/fdinfo/N content
+fn parse_fdinfo_mnt_id(content: &str) -> Option<u32> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("mnt_id:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Whether `pid` has files open on a FUSE filesystem
+///
+/// I/O on those is served by a userspace daemon, whose own reads and writes
+/// of the backing store show up as a separate row, so the same bytes may be
+/// counted twice in the totals.
+pub fn uses_fuse(pid: i32) -> bool {
+    let fuse_mounts = fs::read_to_string(format!("/proc/{}/mountinfo", pid))
+        .map(|content| parse_fuse_mounts(&content))
+        .unwrap_or_default();
+    if fuse_mounts.is_empty() {
+        return false;
+    }
+    let Ok(entries) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let is_path = fs::read_link(entry.path()).is_ok_and(|target| target.is_absolute());
+        is_path
+            && fs::read_to_string(format!(
+                "/proc/{}/fdinfo/{}",
+                pid,
+                entry.file_name().to_string_lossy()
+            ))
+            .ok()
+            .and_then(|content| parse_fdinfo_mnt_id(&content))
+            .is_some_and(|id| fuse_mounts.contains(&id))
+    })
+}
+
+/// `(source device, mount point)` of the filesystems mounted from block
+/// devices in /proc/[pid]/mountinfo content, the first mount point of each
+/// device only, so bind mounts are not counted twice
+fn parse_block_mounts(content: &str) -> Vec<(String, String)> {
+    let mut seen = HashSet::new();
+    content
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            let mount_point = mount.split_whitespace().nth(4)?;
+            let source = fs.split_whitespace().nth(1)?;
+            let source = unescape(source);
+            if !source.starts_with("/dev/") || !seen.insert(source.clone()) {
+                return None;
+            }
+            Some((source, unescape(mount_point)))
+        })
+        .collect()
+}
+
+/// `(used, available)` bytes of the filesystem mounted at `path`
+// The counts are narrower than u64 on some 32-bit targets
+#[allow(clippy::unnecessary_cast)]
+fn statvfs(path: &str) -> Option<(u64, u64)> {
+    let path = CString::new(Path::new(path).as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is written on success
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: statvfs succeeded
+    let stat = unsafe { stat.assume_init() };
+    let block = stat.f_frsize as u64;
+    let used = (stat.f_blocks as u64).saturating_sub(stat.f_bfree as u64);
+    Some((used * block, stat.f_bavail as u64 * block))
+}
+
+/// Kernel name of the whole disk behind a device node: partitions count as
+/// their disk, device-mapper and md devices as themselves
+fn disk_of(source: &str) -> Option<String> {
+    let node = fs::canonicalize(source).ok()?;
+    let name = node.file_name()?.to_string_lossy().into_owned();
+    let class = Path::new("/sys/class/block").join(&name);
+    if !class.join("partition").exists() {
+        return Some(name);
+    }
+    let device = fs::canonicalize(class).ok()?;
+    Some(device.parent()?.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Space left on each filesystem mounted from a block device
+///
+/// Network and virtual filesystems are left out: they have no disk in
+/// /proc/diskstats, and statfs on an unresponsive server would hang.
+pub fn read_usage() -> Vec<FsUsage> {
+    let Ok(content) = fs::read_to_string("/proc/self/mountinfo") else {
+        return Vec::new();
+    };
+    parse_block_mounts(&content)
+        .into_iter()
+        .filter_map(|(source, mount_point)| {
+            let (used_bytes, avail_bytes) = statvfs(&mount_point)?;
+            Some(FsUsage {
+                disk: disk_of(&source)?,
+                mount_point,
+                used_bytes,
+                avail_bytes,
+            })
+        })
+        .filter(|usage| usage.used_bytes + usage.avail_bytes > 0)
+        .collect()
+}
+
+/// Mount points written to by the processes currently on screen
+///
+/// Like the memory columns, they are only looked up for the rows being
+/// drawn, and reused until `max_age` passes.
+#[derive(Default)]
+pub struct MountCache {
+    entries: HashMap<i32, (Instant, Vec<String>)>,
+}
+
+impl MountCache {
+    /// Look up the write mount points of `pids`, dropping those of processes
+    /// that are no longer shown
+    pub fn lookup(&mut self, pids: &[i32], max_age: Duration) -> HashMap<i32, Vec<String>> {
+        self.entries.retain(|pid, _| pids.contains(pid));
+        pids.iter()
+            .map(|&pid| {
+                let (at, mounts) = self
+                    .entries
+                    .entry(pid)
+                    .or_insert_with(|| (Instant::now(), write_mounts(pid)));
+                if at.elapsed() >= max_age {
+                    *at = Instant::now();
+                    *mounts = write_mounts(pid);
+                }
+                (pid, mounts.clone())
+            })
+            .collect()
+    }
+}
+
+/// Processes currently on screen that have files open on FUSE filesystems,
+/// looked up like [`MountCache`]
+#[derive(Default)]
+pub struct FuseCache {
+    entries: HashMap<i32, (Instant, bool)>,
+}
+
+impl FuseCache {
+    pub fn lookup(&mut self, pids: &[i32], max_age: Duration) -> HashSet<i32> {
+        self.entries.retain(|pid, _| pids.contains(pid));
+        pids.iter()
+            .copied()
+            .filter(|&pid| {
+                let (at, fuse) = self
+                    .entries
+                    .entry(pid)
+                    .or_insert_with(|| (Instant::now(), uses_fuse(pid)));
+                if at.elapsed() >= max_age {
+                    *at = Instant::now();
+                    *fuse = uses_fuse(pid);
+                }
+                *fuse
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let content = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                       30 22 0:40 / /mnt/my\\040disk rw,noatime shared:9 - xfs /dev/sdb1 rw\n";
+        let mounts = parse_mountinfo(content);
+        assert_eq!(mounts[&22], "/");
+        assert_eq!(mounts[&30], "/mnt/my disk");
+        assert_eq!(unescape("a\\134b\\011"), "a\\b\t");
+    }
+
+    #[test]
+    fn test_parse_block_mounts() {
+        let content = "22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw\n\
+                       25 22 0:5 / /proc rw shared:2 - proc proc rw\n\
+                       30 22 8:2 /srv /mnt/my\\040srv rw shared:1 - ext4 /dev/sda2 rw\n\
+                       31 22 253:0 / /var rw - xfs /dev/mapper/vg0-var rw\n";
+        assert_eq!(
+            parse_block_mounts(content),
+            vec![
+                ("/dev/sda2".to_string(), "/".to_string()),
+                ("/dev/mapper/vg0-var".to_string(), "/var".to_string()),
+            ]
+        );
+
+        let usage = |used_bytes, avail_bytes| FsUsage {
+            mount_point: "/".to_string(),
+            disk: "sda".to_string(),
+            used_bytes,
+            avail_bytes,
+        };
+        assert_eq!(usage(90, 10).used_percent(), 90.0);
+        assert!(usage(90, 10).is_nearly_full());
+        assert!(!usage(50, 50).is_nearly_full());
+        assert_eq!(usage(0, 0).used_percent(), 0.0);
+    }
+
+    #[test]
+    fn test_parse_fuse_mounts() {
+        let content = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                       41 22 0:52 / /mnt/remote rw,nosuid - fuse.sshfs host:/srv rw\n\
+                       42 22 8:17 / /mnt/win rw - fuseblk /dev/sdb1 rw\n\
+                       43 22 0:53 / /sys/fs/fuse/connections rw - fusectl fusectl rw\n";
+        assert_eq!(parse_fuse_mounts(content), HashSet::from([41, 42]));
+        assert_eq!(
+            parse_fdinfo_mnt_id("pos:\t0\nflags:\t02\nmnt_id:\t41\n"),
+            Some(41)
+        );
+        assert_eq!(parse_fdinfo_mnt_id("pos:\t0\n"), None);
+    }
+
+    #[test]
+    fn test_parse_fdinfo_writable() {
+        let write = "pos:\t0\nflags:\t0100001\nmnt_id:\t30\nino:\t12\n";
+        let read_write = "pos:\t0\nflags:\t02100002\nmnt_id:\t22\n";
+        let read = "pos:\t0\nflags:\t0100000\nmnt_id:\t22\n";
+        assert_eq!(parse_fdinfo_writable(write), Some(30));
+        assert_eq!(parse_fdinfo_writable(read_write), Some(22));
+        assert_eq!(parse_fdinfo_writable(read), None);
+        assert_eq!(parse_fdinfo_writable("flags:\t01\n"), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::filetrace::FileRates;
+
+/// Network traffic of one process
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetIo {
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    /// Bytes received since tracing started
+    pub read_bytes: u64,
+    /// Bytes sent since tracing started
+    pub write_bytes: u64,
+}
+
+/// Network I/O by process ID
+pub type NetRates = HashMap<i32, NetIo>;
+
+/// Sums the traffic of the TCP, UDP and raw sockets among the traced files
+/// and keeps per-process totals
+///
+/// The programs flag the sockets they count in the inet protocol handlers,
+/// so sockets closed before the sample still count, and Unix and netlink
+/// sockets are left out, so local IPC such as D-Bus is not network traffic.
+#[derive(Default)]
+pub struct NetSampler {
+    totals: HashMap<i32, (u64, u64)>,
+}
+
+impl NetSampler {
+    /// Network rates over the `secs` covered by `files`, plus totals
+    pub fn sample(&mut self, files: &FileRates, secs: f64) -> NetRates {
+        let mut rates = NetRates::new();
+        for (&tgid, files) in files {
+            for file in files.iter().filter(|file| file.net) {
+                let net = rates.entry(tgid).or_default();
+                net.read_bytes_per_sec += file.read_bytes_per_sec;
+                net.write_bytes_per_sec += file.write_bytes_per_sec;
+            }
+        }
+
+        for (tgid, net) in rates.iter_mut() {
+            let total = self.totals.entry(*tgid).or_default();
+            total.0 += (net.read_bytes_per_sec * secs).round() as u64;
+            total.1 += (net.write_bytes_per_sec * secs).round() as u64;
+        }
+        self.totals
+            .retain(|tgid, _| Path::new(&format!("/proc/{}", tgid)).exists());
+        // Processes that were quiet this interval still have their totals
+        for (&tgid, &(read, write)) in &self.totals {
+            let net = rates.entry(tgid).or_default();
+            net.read_bytes = read;
+            net.write_bytes = write;
+        }
+        rates
+    }
+}
+
+/// Combine the network rates of two consecutive intervals into rates over
+/// both, weighting each by its length; totals come from the newer one
+pub fn merge_rates(
+    older: &NetRates,
+    older_secs: f64,
+    newer: &NetRates,
+    newer_secs: f64,
+) -> NetRates {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.clone();
+    }
+
+    let mut merged = newer.clone();
+    for net in merged.values_mut() {
+        net.read_bytes_per_sec *= newer_secs / secs;
+        net.write_bytes_per_sec *= newer_secs / secs;
+    }
+    for (&tgid, older) in older {
+        let net = merged.entry(tgid).or_insert(NetIo {
+            read_bytes: older.read_bytes,
+            write_bytes: older.write_bytes,
+            ..Default::default()
+        });
+        net.read_bytes_per_sec += older.read_bytes_per_sec * older_secs / secs;
+        net.write_bytes_per_sec += older.write_bytes_per_sec * older_secs / secs;
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filetrace::FileRate;
+
+    #[test]
+    fn test_sample() {
+        let file = |path: &str, read: f64, net: bool| FileRate {
+            path: path.to_string(),
+            read_bytes_per_sec: read,
+            write_bytes_per_sec: 0.0,
+            direct: false,
+            net,
+        };
+        // Totals are kept for processes that still exist
+        let tgid = std::process::id() as i32;
+        let files = FileRates::from([(
+            tgid,
+            vec![
+                file("socket:[31337]", 100.0, true),
+                file("TCP (closed)", 50.0, true),
+                file("socket:[31338]", 1000.0, false),
+                file("/var/log/syslog", 10.0, false),
+            ],
+        )]);
+        let mut sampler = NetSampler::default();
+        assert_eq!(sampler.sample(&files, 2.0)[&tgid].read_bytes, 300);
+        let rates = sampler.sample(&FileRates::new(), 1.0);
+        assert_eq!(
+            rates[&tgid],
+            NetIo {
+                read_bytes: 300,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_merge_rates() {
+        let net = |rate: f64, total: u64| NetIo {
+            read_bytes_per_sec: rate,
+            write_bytes_per_sec: 0.0,
+            read_bytes: total,
+            write_bytes: 0,
+        };
+        let older = NetRates::from([(1, net(100.0, 100)), (2, net(10.0, 10))]);
+        let newer = NetRates::from([(1, net(300.0, 400))]);
+        let merged = merge_rates(&older, 1.0, &newer, 1.0);
+        assert_eq!(merged[&1], net(200.0, 400));
+        assert_eq!(merged[&2], net(5.0, 10));
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+
+use crate::mounts::unescape;
+
+/// Cumulative counters of one NFS mount from /proc/self/mountstats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NfsCounters {
+    /// Bytes read from and written to the server, as opposed to those the
+    /// applications asked for, some of which the page cache absorbed
+    pub server_read_bytes: u64,
+    pub server_write_bytes: u64,
+    pub read_ops: u64,
+    pub write_ops: u64,
+    /// Summed round trip times of the READ and WRITE RPCs, in milliseconds
+    pub read_rtt_ms: u64,
+    pub write_rtt_ms: u64,
+}
+
+/// Parse /proc/self/mountstats into `(mount point, counters)` pairs for the
+/// NFS mounts
+///
+/// A file system mounted in several places is only listed at the first, as
+/// the counters belong to the file system rather than the mount.
+pub fn parse(content: &str) -> Vec<(String, NfsCounters)> {
+    let mut mounts: Vec<(String, NfsCounters)> = Vec::new();
+    let mut devices = Vec::new();
+    // Whether the lines that follow belong to a new NFS mount
+    let mut in_nfs = false;
+
+    for line in content.lines() {
+        if let Some(rest) = line.strip_prefix("device ") {
+            in_nfs = false;
+            let Some((device, rest)) = rest.split_once(" mounted on ") else {
+                continue;
+            };
+            let Some((mount_point, fstype)) = rest.rsplit_once(" with fstype ") else {
+                continue;
+            };
+            let fstype = fstype.split_whitespace().next().unwrap_or_default();
+            if (fstype == "nfs" || fstype == "nfs4") && !devices.contains(&device) {
+                devices.push(device);
+                mounts.push((unescape(mount_point), NfsCounters::default()));
+                in_nfs = true;
+            }
+            continue;
+        }
+        if !in_nfs {
+            continue;
+        }
+        let Some((_, counters)) = mounts.last_mut() else {
+            continue;
+        };
+        let Some((key, values)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let values: Vec<u64> = values
+            .split_whitespace()
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        let value = |idx: usize| values.get(idx).copied().unwrap_or_default();
+        match key {
+            // normal read, normal write, direct read, direct write, server
+            // read, server write, pages read, pages written
+            "bytes" => {
+                counters.server_read_bytes = value(4);
+                counters.server_write_bytes = value(5);
+            }
+            // ops, transmissions, timeouts, bytes sent, bytes received,
+            // queue time, round trip time, execution time
+            "READ" => {
+                counters.read_ops = value(0);
+                counters.read_rtt_ms = value(6);
+            }
+            "WRITE" => {
+                counters.write_ops = value(0);
+                counters.write_rtt_ms = value(6);
+            }
+            _ => {}
+        }
+    }
+    mounts
+}
+
+/// Throughput and RPC latency of one NFS mount over the last interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct NfsRate {
+    pub mount_point: String,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Average round trip time of the READ and WRITE RPCs, in milliseconds;
+    /// `None` when there were none
+    pub rtt_ms: Option<f64>,
+}
+
+impl NfsRate {
+    fn from_delta(mount_point: &str, prev: &NfsCounters, curr: &NfsCounters, secs: f64) -> Self {
+        let delta = |curr: u64, prev: u64| curr.saturating_sub(prev);
+        let ops = delta(curr.read_ops, prev.read_ops) + delta(curr.write_ops, prev.write_ops);
+        let rtt =
+            delta(curr.read_rtt_ms, prev.read_rtt_ms) + delta(curr.write_rtt_ms, prev.write_rtt_ms);
+        Self {
+            mount_point: mount_point.to_string(),
+            read_bytes_per_sec: delta(curr.server_read_bytes, prev.server_read_bytes) as f64 / secs,
+            write_bytes_per_sec: delta(curr.server_write_bytes, prev.server_write_bytes) as f64
+                / secs,
+            read_iops: delta(curr.read_ops, prev.read_ops) as f64 / secs,
+            write_iops: delta(curr.write_ops, prev.write_ops) as f64 / secs,
+            rtt_ms: (ops > 0).then(|| rtt as f64 / ops as f64),
+        }
+    }
+}
+
+/// Combine the NFS rates of two consecutive intervals into rates over both,
+/// weighting each by its length, and the latency by the number of RPCs
+///
+/// Mounts missing from one interval count as idle during it.
+pub fn merge_rates(
+    older: &[NfsRate],
+    older_secs: f64,
+    newer: &[NfsRate],
+    newer_secs: f64,
+) -> Vec<NfsRate> {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.to_vec();
+    }
+
+    // Each mount's rates along with its RPC count and summed round trips
+    let mut merged: HashMap<String, (NfsRate, f64, f64)> = HashMap::new();
+    for (rates, interval) in [(older, older_secs), (newer, newer_secs)] {
+        for rate in rates {
+            let (mount, ops, rtt) = merged.entry(rate.mount_point.clone()).or_insert_with(|| {
+                let idle = NfsRate {
+                    mount_point: rate.mount_point.clone(),
+                    read_bytes_per_sec: 0.0,
+                    write_bytes_per_sec: 0.0,
+                    read_iops: 0.0,
+                    write_iops: 0.0,
+                    rtt_ms: None,
+                };
+                (idle, 0.0, 0.0)
+            });
+            let weight = interval / secs;
+            mount.read_bytes_per_sec += rate.read_bytes_per_sec * weight;
+            mount.write_bytes_per_sec += rate.write_bytes_per_sec * weight;
+            mount.read_iops += rate.read_iops * weight;
+            mount.write_iops += rate.write_iops * weight;
+            let rate_ops = (rate.read_iops + rate.write_iops) * interval;
+            *ops += rate_ops;
+            *rtt += rate.rtt_ms.unwrap_or_default() * rate_ops;
+        }
+    }
+
+    let mut merged: Vec<NfsRate> = merged
+        .into_values()
+        .map(|(mut mount, ops, rtt)| {
+            mount.rtt_ms = (ops > 0.0).then(|| rtt / ops);
+            mount
+        })
+        .collect();
+    merged.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    merged
+}
+
+/// Samples /proc/self/mountstats and turns consecutive samples into rates
+#[derive(Default)]
+pub struct NfsSampler {
+    prev: HashMap<String, NfsCounters>,
+    prev_at: Option<Instant>,
+}
+
+impl NfsSampler {
+    /// Read /proc/self/mountstats and return the rates of the NFS mounts
+    /// since the previous call (empty on the first call)
+    pub fn sample(&mut self) -> Result<Vec<NfsRate>> {
+        let content = fs::read_to_string("/proc/self/mountstats")?;
+        let now = Instant::now();
+        let current: HashMap<String, NfsCounters> = parse(&content).into_iter().collect();
+
+        let mut rates = Vec::new();
+        if let Some(prev_at) = self.prev_at {
+            let secs = now.duration_since(prev_at).as_secs_f64();
+            if secs > 0.0 {
+                rates = current
+                    .iter()
+                    .filter_map(|(mount_point, curr)| {
+                        let prev = self.prev.get(mount_point)?;
+                        Some(NfsRate::from_delta(mount_point, prev, curr, secs))
+                    })
+                    .collect();
+                rates.sort_by(|a: &NfsRate, b| a.mount_point.cmp(&b.mount_point));
+            }
+        }
+        self.prev = current;
+        self.prev_at = Some(now);
+        Ok(rates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTSTATS: &str = "\
+device /dev/sda1 mounted on / with fstype ext4
+device srv:/export mounted on /mnt/my\\040data with fstype nfs4 statvers=1.1
+\topts:\trw,vers=4.2,rsize=1048576,wsize=1048576
+\tevents:\t0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26
+\tbytes:\t1000 2000 0 0 800 1900 0 0
+\tper-op statistics
+\t        NULL: 1 1 0 44 24 0 0 0 0
+\t        READ: 10 10 0 1200 800 5 40 50 0
+\t       WRITE: 20 20 0 3000 1600 10 100 120 0
+device srv:/export mounted on /srv/again with fstype nfs4 statvers=1.1
+\tbytes:\t1 1 1 1 1 1 1 1
+device tmpfs mounted on /tmp with fstype tmpfs
+\t        READ: 99 99 0 0 0 0 0 0 0
+";
+
+    #[test]
+    fn test_parse() {
+        let mounts = parse(MOUNTSTATS);
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].0, "/mnt/my data");
+        assert_eq!(
+            mounts[0].1,
+            NfsCounters {
+                server_read_bytes: 800,
+                server_write_bytes: 1900,
+                read_ops: 10,
+                write_ops: 20,
+                read_rtt_ms: 40,
+                write_rtt_ms: 100,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rates() {
+        let curr = parse(MOUNTSTATS)[0].1;
+        let rate = NfsRate::from_delta("/mnt", &NfsCounters::default(), &curr, 2.0);
+        assert_eq!(rate.read_bytes_per_sec, 400.0);
+        assert_eq!(rate.write_iops, 10.0);
+        assert_eq!(rate.rtt_ms, Some(140.0 / 30.0));
+        let idle = NfsRate::from_delta("/mnt", &curr, &curr, 1.0);
+        assert_eq!(idle.rtt_ms, None);
+
+        // One busy second and one idle second: half the rate, same latency
+        let merged = merge_rates(&[rate], 1.0, &[idle], 1.0);
+        assert_eq!(merged[0].write_iops, 5.0);
+        assert!((merged[0].rtt_ms.unwrap() - 140.0 / 30.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::taskstats;
+
+/// iotop's own use of the system over one interval, for `--stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Overhead {
+    /// CPU time of all of iotop's threads, as a share of one CPU
+    pub cpu_percent: f64,
+    /// Taskstats queries sent
+    pub netlink_requests: u64,
+    /// Read syscalls made, nearly all on /proc and /sys files
+    pub read_calls: u64,
+}
+
+impl Overhead {
+    pub fn summary(&self) -> String {
+        format!(
+            "{:.1}% CPU, {} netlink requests, {} reads",
+            self.cpu_percent, self.netlink_requests, self.read_calls
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Counters {
+    at: Instant,
+    cpu_time: Duration,
+    netlink_requests: u64,
+    read_calls: u64,
+}
+
+impl Counters {
+    fn read() -> Option<Self> {
+        Some(Self {
+            at: Instant::now(),
+            cpu_time: cpu_time()?,
+            netlink_requests: taskstats::netlink_requests(),
+            read_calls: parse_syscr(&fs::read_to_string("/proc/self/io").ok()?)?,
+        })
+    }
+}
+
+/// Turns consecutive readings of iotop's own counters into an [`Overhead`]
+#[derive(Debug, Default)]
+pub struct OverheadSampler {
+    prev: Option<Counters>,
+}
+
+impl OverheadSampler {
+    /// Overhead since the previous call (`None` on the first call)
+    pub fn sample(&mut self) -> Option<Overhead> {
+        let current = Counters::read()?;
+        let prev = self.prev.replace(current)?;
+        let secs = current.at.duration_since(prev.at).as_secs_f64();
+        if secs <= 0.0 {
+            return None;
+        }
+        let cpu = current.cpu_time.saturating_sub(prev.cpu_time).as_secs_f64();
+        Some(Overhead {
+            cpu_percent: cpu / secs * 100.0,
+            netlink_requests: current.netlink_requests - prev.netlink_requests,
+            read_calls: current.read_calls.saturating_sub(prev.read_calls),
+        })
+    }
+}
+
+/// CPU time used by the whole process so far
+fn cpu_time() -> Option<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut ts) } != 0 {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// Read syscalls from /proc/[pid]/io content (`syscr`)
+fn parse_syscr(content: &str) -> Option<u64> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("syscr:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_syscr() {
+        let content = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n";
+        assert_eq!(parse_syscr(content), Some(632687));
+        assert_eq!(parse_syscr("rchar: 1\n"), None);
+    }
+
+    #[test]
+    fn test_sample() {
+        let mut sampler = OverheadSampler::default();
+        assert_eq!(sampler.sample(), None);
+        std::thread::sleep(Duration::from_millis(10));
+        let overhead = sampler.sample().unwrap();
+        // Reading /proc/self/io takes a read syscall at least
+        assert!(overhead.read_calls > 0);
+        assert!(overhead.cpu_percent >= 0.0);
+    }
+}
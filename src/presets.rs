@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::state_file;
+
+/// A named combination of filters from the presets file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    /// Settings in file order, named like the command line options with
+    /// `_` for `-`, e.g. `("min_io", "1M")`
+    pub settings: Vec<(String, String)>,
+}
+
+/// Location of the presets, `$XDG_CONFIG_HOME/iotop/presets.toml` or
+/// `~/.config/iotop/presets.toml`
+pub fn path() -> Option<PathBuf> {
+    Some(state_file::config_dir()?.join("presets.toml"))
+}
+
+/// Read the presets file; none if there is no such file
+pub fn load() -> Result<Vec<Preset>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    parse(&content).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// The preset called `name`
+pub fn find(name: &str) -> Result<Preset> {
+    let presets = load()?;
+    if let Some(preset) = presets.iter().find(|preset| preset.name == name) {
+        return Ok(preset.clone());
+    }
+    let names: Vec<&str> = presets.iter().map(|preset| preset.name.as_str()).collect();
+    let location = path().map_or_else(String::new, |path| format!(" in {}", path.display()));
+    if names.is_empty() {
+        Err(anyhow!("No preset named '{}'{}", name, location))
+    } else {
+        Err(anyhow!(
+            "No preset named '{}'{} (there are {})",
+            name,
+            location,
+            names.join(", ")
+        ))
+    }
+}
+
+/// Parse presets from the small subset of TOML the file is written in:
+/// either one inline table per line, like
+/// `databases = { user = "postgres", min_io = "1M" }`, or a `[databases]`
+/// table with a `key = value` line per setting
+fn parse(content: &str) -> Result<Vec<Preset>, String> {
+    let mut presets: Vec<Preset> = Vec::new();
+    // Whether `key = value` lines go to the last preset, after a `[name]`
+    let mut in_table = false;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", index + 1, e);
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| error("missing ']'".to_string()))?;
+            presets.push(Preset {
+                name: parse_name(name).map_err(error)?,
+                settings: Vec::new(),
+            });
+            in_table = true;
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected name = value".to_string()))?;
+        let key = parse_name(key).map_err(error)?;
+        let value = value.trim();
+        if let Some(table) = value.strip_prefix('{') {
+            let table = table
+                .strip_suffix('}')
+                .ok_or_else(|| error("missing '}'".to_string()))?;
+            presets.push(Preset {
+                name: key,
+                settings: parse_inline_table(table).map_err(error)?,
+            });
+            in_table = false;
+        } else if in_table {
+            let (value, rest) = parse_value(value).map_err(error)?;
+            if !rest.trim().is_empty() {
+                return Err(error(format!("unexpected '{}'", rest.trim())));
+            }
+            if let Some(preset) = presets.last_mut() {
+                preset.settings.push((key, value));
+            }
+        } else {
+            return Err(error(format!("'{}' is not in a preset", key)));
+        }
+    }
+    Ok(presets)
+}
+
+/// A preset or setting name: letters, digits, `_` and `-`
+pub fn parse_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!("invalid name '{}'", name));
+    }
+    Ok(name.to_string())
+}
+
+/// The `key = value` pairs between the braces of an inline table
+fn parse_inline_table(mut table: &str) -> Result<Vec<(String, String)>, String> {
+    let mut settings = Vec::new();
+    while !table.trim().is_empty() {
+        let (key, rest) = table
+            .split_once('=')
+            .ok_or_else(|| format!("expected key = value in '{}'", table.trim()))?;
+        let (value, rest) = parse_value(rest)?;
+        settings.push((parse_name(key)?, value));
+        let rest = rest.trim_start();
+        table = match rest.strip_prefix(',') {
+            Some(rest) => rest,
+            None if rest.is_empty() => rest,
+            None => return Err(format!("expected ',' before '{}'", rest)),
+        };
+    }
+    Ok(settings)
+}
+
+/// Parse a value at the start of `input`, and return it with what follows
+///
+/// Strings are quoted, with `\"` and `\\` escapes, so they may contain
+/// commas; numbers and `true`/`false` are bare.
+pub fn parse_value(input: &str) -> Result<(String, &str), String> {
+    let input = input.trim_start();
+    let Some(quoted) = input.strip_prefix('"') else {
+        let end = input.find([',', '}']).unwrap_or(input.len());
+        let value = input[..end].trim();
+        if value.is_empty() {
+            return Err("missing value".to_string());
+        }
+        return Ok((value.to_string(), &input[end..]));
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &quoted[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                _ => return Err("only \\\" and \\\\ can be escaped".to_string()),
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+/// Parse a `true`/`false` setting
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected true or false, got '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let presets = parse(
+            "# Filters for --preset\n\
+             databases = { user = \"postgres,mysql\", min_io = \"1M\" }\n\
+             \n\
+             [backup]\n\
+             class = \"idle\"\n\
+             blocked = true\n\
+             filter = \"^(rsync|tar) \\\"x\\\\\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            presets,
+            vec![
+                Preset {
+                    name: "databases".to_string(),
+                    settings: settings(&[("user", "postgres,mysql"), ("min_io", "1M")]),
+                },
+                Preset {
+                    name: "backup".to_string(),
+                    settings: settings(&[
+                        ("class", "idle"),
+                        ("blocked", "true"),
+                        ("filter", "^(rsync|tar) \"x\\"),
+                    ]),
+                },
+            ]
+        );
+        assert_eq!(parse("empty = {}").unwrap()[0].settings, Vec::new());
+        assert_eq!(
+            parse("web = { session = 4120, only = true, }").unwrap()[0].settings,
+            settings(&[("session", "4120"), ("only", "true")])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("user = \"postgres\"").unwrap_err(),
+            "line 1: 'user' is not in a preset"
+        );
+        assert!(parse("db = { user = \"postgres\"").is_err());
+        assert!(parse("db = { user = \"postgres }").is_err());
+        assert!(parse("db = { user = \"a\" \"b\" }").is_err());
+        assert!(parse("db = { user }").is_err());
+        assert!(parse("[my db]").is_err());
+        assert!(parse_bool("yes").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+/// I/O pressure stall information from /proc/pressure/io
+///
+/// Values are the share of wall time, in percent, during which some (or
+/// all) non-idle tasks were stalled on I/O, averaged by the kernel over the
+/// last 10 and 60 seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IoPressure {
+    pub some_avg10: f64,
+    pub some_avg60: f64,
+    pub full_avg10: f64,
+    pub full_avg60: f64,
+}
+
+/// Parse /proc/pressure/io content
+///
+/// Returns `None` unless both the `some` and the `full` line are present.
+pub fn parse(content: &str) -> Option<IoPressure> {
+    let mut some = None;
+    let mut full = None;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next();
+        let mut avg10 = None;
+        let mut avg60 = None;
+        for (key, value) in fields.filter_map(|field| field.split_once('=')) {
+            match key {
+                "avg10" => avg10 = value.parse::<f64>().ok(),
+                "avg60" => avg60 = value.parse::<f64>().ok(),
+                _ => {}
+            }
+        }
+        let averages = avg10.zip(avg60);
+        match kind {
+            Some("some") => some = averages,
+            Some("full") => full = averages,
+            _ => {}
+        }
+    }
+
+    let ((some_avg10, some_avg60), (full_avg10, full_avg60)) = some.zip(full)?;
+    Some(IoPressure {
+        some_avg10,
+        some_avg60,
+        full_avg10,
+        full_avg60,
+    })
+}
+
+/// Read the current I/O pressure; fails on kernels built without PSI or
+/// booted with `psi=0`
+pub fn read() -> Result<IoPressure> {
+    let content = fs::read_to_string("/proc/pressure/io")?;
+    parse(&content).context("Unexpected format of /proc/pressure/io")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "some avg10=12.50 avg60=3.10 avg300=0.80 total=123456\n\
+                       full avg10=4.00 avg60=1.25 avg300=0.30 total=65432\n";
+        assert_eq!(
+            parse(content),
+            Some(IoPressure {
+                some_avg10: 12.5,
+                some_avg60: 3.1,
+                full_avg10: 4.0,
+                full_avg60: 1.25,
+            })
+        );
+        assert_eq!(
+            parse("some avg10=1.00 avg60=1.00 avg300=1.00 total=1\n"),
+            None
+        );
+        assert_eq!(parse(""), None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ioprio;
+
+/// How often the I/O priority of a known task is read again, so that
+/// `ionice` and `renice` run from elsewhere show up
+const PRIO_REFRESH: Duration = Duration::from_secs(5);
+
+/// How long the first lookup waits for the first pass, so that the first
+/// screen shows priorities
+const FIRST_PASS_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct State {
+    /// Tasks to look up, as of the last lookup
+    tids: Vec<i32>,
+    prios: HashMap<i32, String>,
+    last_pass: Option<Instant>,
+    reread: bool,
+    closed: bool,
+}
+
+impl State {
+    fn pass_due(&self) -> bool {
+        self.reread
+            || self.last_pass.is_none_or(|at| at.elapsed() >= PRIO_REFRESH)
+            || self.tids.iter().any(|tid| !self.prios.contains_key(tid))
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when there are tasks to look up, or on close
+    wake: Condvar,
+    /// Signalled after each pass
+    done: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// I/O priorities of the listed tasks, read with `ioprio_get` in a
+/// background thread
+///
+/// Each pass reads the priority of every task listed by the last lookup,
+/// once new tasks show up, after [`PRIO_REFRESH`] or when asked to, so the
+/// refresh itself makes no priority syscalls. Priorities are thus up to one
+/// refresh late. The thread starts with the first lookup; if it cannot be
+/// started, lookups read the priorities themselves.
+#[derive(Default)]
+pub struct PrioCache {
+    shared: Arc<Shared>,
+    started: OnceLock<bool>,
+}
+
+impl PrioCache {
+    /// Priorities of `tids` as of the last pass, and ask for a pass if some
+    /// are unknown; with `reread`, the next pass reads them all again
+    pub fn lookup(&self, tids: Vec<i32>, reread: bool) -> HashMap<i32, String> {
+        // Listed before the thread starts, for its first pass to see them
+        {
+            let mut state = self.shared.lock();
+            state.tids = tids;
+            state.reread |= reread;
+        }
+        let started = *self.started.get_or_init(|| {
+            let shared = self.shared.clone();
+            thread::Builder::new()
+                .name("ioprio".to_string())
+                .spawn(move || run(&shared))
+                .is_ok()
+        });
+
+        let mut state = self.shared.lock();
+        if !started {
+            read_all(&mut state);
+        } else if state.last_pass.is_none() {
+            self.shared.wake.notify_one();
+            state = self
+                .shared
+                .done
+                .wait_timeout_while(state, FIRST_PASS_WAIT, |s| s.last_pass.is_none())
+                .map_or_else(|e| e.into_inner().0, |(state, _)| state);
+        } else if state.pass_due() {
+            self.shared.wake.notify_one();
+        }
+
+        state
+            .tids
+            .iter()
+            .filter_map(|tid| Some((*tid, state.prios.get(tid)?.clone())))
+            .collect()
+    }
+}
+
+impl Drop for PrioCache {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.wake.notify_one();
+    }
+}
+
+/// Look up the priorities of the listed tasks whenever a pass is due, until
+/// the cache is dropped
+fn run(shared: &Shared) {
+    loop {
+        let mut state = shared.lock();
+        while !state.closed && !state.pass_due() {
+            let wait = state.last_pass.map_or(Duration::ZERO, |at| {
+                PRIO_REFRESH.saturating_sub(at.elapsed())
+            });
+            state = shared
+                .wake
+                .wait_timeout(state, wait)
+                .map_or_else(|e| e.into_inner().0, |(state, _)| state);
+        }
+        if state.closed {
+            return;
+        }
+        state.reread = false;
+        let tids = state.tids.clone();
+        drop(state);
+
+        let prios = read(&tids);
+
+        let mut state = shared.lock();
+        state.prios = prios;
+        state.last_pass = Some(Instant::now());
+        drop(state);
+        shared.done.notify_all();
+    }
+}
+
+fn read(tids: &[i32]) -> HashMap<i32, String> {
+    tids.iter()
+        .map(|&tid| (tid, ioprio::get_ioprio_string(tid)))
+        .collect()
+}
+
+/// Pass run by the lookup itself, without the thread
+fn read_all(state: &mut State) {
+    if state.pass_due() {
+        state.prios = read(&state.tids);
+        state.last_pass = Some(Instant::now());
+        state.reread = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let cache = PrioCache::default();
+        let own = std::process::id() as i32;
+        // The first lookup waits for the first pass
+        let prios = cache.lookup(vec![own], false);
+        assert_eq!(prios[&own], ioprio::get_ioprio_string(own));
+
+        // A task seen for the first time is only known after the next pass
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let tid = child.id() as i32;
+        let prios = cache.lookup(vec![own, tid], false);
+        assert!(prios.contains_key(&own));
+        let deadline = Instant::now() + FIRST_PASS_WAIT;
+        while !cache.lookup(vec![own, tid], false).contains_key(&tid) {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -3,15 +3,15 @@ use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Cache Time-To-Live policy for different data types
 #[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
 enum CacheTTL {
     /// Never expire - for static data like UID, TGID, cmdline
     Static,
-    /// Expire after duration - for semi-dynamic data like priority
+    /// Expire after duration - for semi-dynamic data like memory usage
     Refresh(Duration),
 }
 
@@ -78,6 +78,9 @@ pub struct ProcStatus {
     pub name: String,
     pub tgid: i32,
     pub pid: i32,
+    /// PID in the innermost PID namespace, when that is not the one of
+    /// /proc (last field of NSpid)
+    pub nspid: Option<i32>,
 }
 
 impl ProcStatus {
@@ -86,6 +89,7 @@ impl ProcStatus {
         let mut name = String::new();
         let mut tgid = 0;
         let mut pid = 0;
+        let mut nspid = None;
 
         for line in content.lines() {
             if let Some((key, value)) = line.split_once(':') {
@@ -93,6 +97,12 @@ impl ProcStatus {
                     "Name" => name = value.trim().to_string(),
                     "Tgid" => tgid = value.split_whitespace().next()?.parse().ok()?,
                     "Pid" => pid = value.split_whitespace().next()?.parse().ok()?,
+                    "NSpid" => {
+                        let pids: Vec<&str> = value.split_whitespace().collect();
+                        if pids.len() > 1 {
+                            nspid = pids.last()?.parse().ok();
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -102,7 +112,264 @@ impl ProcStatus {
             return None;
         }
 
-        Some(ProcStatus { name, tgid, pid })
+        Some(ProcStatus {
+            name,
+            tgid,
+            pid,
+            nspid,
+        })
+    }
+}
+
+/// Parsed /proc/[pid]/stat data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcStat {
+    /// Scheduling state, e.g. `R`, `S` or `D` for uninterruptible sleep
+    /// (field 3)
+    pub state: char,
+    /// Parent process (field 4)
+    pub ppid: i32,
+    /// Process group and session (fields 5 and 6)
+    pub pgrp: i32,
+    pub session: i32,
+    /// Device number of the controlling terminal, 0 for none (field 7)
+    pub tty_nr: u64,
+    /// Kernel flags of the task, `PF_*` (field 9)
+    pub flags: u64,
+    /// Start time, in clock ticks after boot (field 22)
+    pub start_ticks: u64,
+    /// Page faults served without and with a read from disk (fields 10
+    /// and 12)
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    /// CPU time in user and kernel mode, in clock ticks (fields 14 and 15);
+    /// for a whole process, that of every thread it has had
+    pub utime: u64,
+    pub stime: u64,
+    /// Time spent waiting for block I/O, in clock ticks (field 42,
+    /// `delayacct_blkio_ticks`); 0 without delay accounting
+    pub blkio_ticks: u64,
+}
+
+impl ProcStat {
+    /// Parse from /proc/[pid]/stat content
+    pub fn parse(content: &str) -> Option<Self> {
+        // The command name may contain spaces and parentheses, so fields are
+        // counted from the last ')'; the first one after it is field 3
+        let (_, rest) = content.rsplit_once(')')?;
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+
+        Some(ProcStat {
+            state: fields.first()?.chars().next()?,
+            ppid: fields.get(1)?.parse().ok()?,
+            pgrp: fields.get(2)?.parse().ok()?,
+            session: fields.get(3)?.parse().ok()?,
+            tty_nr: field(7)?,
+            flags: field(9)?,
+            start_ticks: field(22)?,
+            minor_faults: field(10)?,
+            major_faults: field(12)?,
+            utime: field(14)?,
+            stime: field(15)?,
+            blkio_ticks: field(42).unwrap_or_default(),
+        })
+    }
+}
+
+/// Scheduling state of thread `tid`, e.g. `D` for uninterruptible sleep,
+/// where tasks wait for disk I/O, or `T` when stopped by a signal; `None`
+/// if it is gone
+pub fn thread_state(tid: i32) -> Option<char> {
+    let content = fs::read_to_string(format!("/proc/{0}/task/{0}/stat", tid)).ok()?;
+    ProcStat::parse(&content).map(|stat| stat.state)
+}
+
+/// `PF_KTHREAD`, the flag of kernel threads
+const PF_KTHREAD: u64 = 0x0020_0000;
+
+/// PID of `kthreadd`, the parent of every other kernel thread
+pub const KTHREADD: i32 = 2;
+
+/// Whether task `tid` is a kernel thread; `false` if it is gone
+pub fn is_kernel_thread(tid: i32) -> bool {
+    fs::read_to_string(format!("/proc/{}/stat", tid))
+        .ok()
+        .and_then(|content| ProcStat::parse(&content))
+        .is_some_and(|stat| stat.flags & PF_KTHREAD != 0)
+}
+
+/// Parent of process `pid`, or `None` if it is gone
+pub fn parent(pid: i32) -> Option<i32> {
+    let content = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    ProcStat::parse(&content).map(|stat| stat.ppid)
+}
+
+/// Process of task `tid`, or `None` if it is gone
+pub fn tgid(tid: i32) -> Option<i32> {
+    let content = fs::read_to_string(format!("/proc/{}/status", tid)).ok()?;
+    ProcStatus::parse(&content).map(|status| status.tgid)
+}
+
+/// Name of thread `tid` of process `pid`, or `?` if it is gone
+pub fn thread_name(pid: i32, tid: i32) -> String {
+    fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, tid))
+        .map(|name| name.trim_end().to_string())
+        .unwrap_or_else(|_| "?".to_string())
+}
+
+/// Name of a terminal from its device number, like ps's TTY column, or
+/// `None` if the process has no controlling terminal
+pub fn tty_name(tty_nr: u64) -> Option<String> {
+    if tty_nr == 0 {
+        return None;
+    }
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+    Some(match major {
+        // Unix98 pseudo-terminals span several majors
+        136..=143 => format!("pts/{}", (major - 136) * 256 + minor),
+        4 if minor < 64 => format!("tty{}", minor),
+        4 => format!("ttyS{}", minor - 64),
+        _ => format!("{}:{}", major, minor),
+    })
+}
+
+/// System boot time, from the `btime` line of /proc/stat
+fn boot_time() -> Option<SystemTime> {
+    static BOOT_TIME: OnceLock<Option<SystemTime>> = OnceLock::new();
+    *BOOT_TIME.get_or_init(|| {
+        let content = fs::read_to_string("/proc/stat").ok()?;
+        let secs = content
+            .lines()
+            .find_map(|line| line.strip_prefix("btime "))?
+            .trim()
+            .parse()
+            .ok()?;
+        Some(UNIX_EPOCH + Duration::from_secs(secs))
+    })
+}
+
+/// Convert a duration in clock ticks, as in /proc/[pid]/stat, to
+/// nanoseconds
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_sec <= 0 {
+        return 0;
+    }
+    ticks.saturating_mul(1_000_000_000) / ticks_per_sec as u64
+}
+
+/// Convert a start time in clock ticks after boot to wall-clock time
+fn start_time(start_ticks: u64) -> Option<SystemTime> {
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_sec <= 0 {
+        return None;
+    }
+    Some(boot_time()? + Duration::from_secs_f64(start_ticks as f64 / ticks_per_sec as f64))
+}
+
+/// Read a `kB` field such as `VmSwap` from /proc/[pid]/status content
+///
+/// Kernel threads have no memory fields, so this returns `None` for them.
+fn parse_status_kb(content: &str, field: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() != field {
+            return None;
+        }
+        value.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// Read the resident set size, in pages, from /proc/[pid]/statm content
+fn parse_statm_resident(content: &str) -> Option<u64> {
+    content.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn page_size_kb() -> u64 {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size > 0 {
+        page_size as u64 / 1024
+    } else {
+        4
+    }
+}
+
+/// Memory usage of a process, in kilobytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub rss_kb: Option<u64>,
+    pub swap_kb: Option<u64>,
+    /// Badness the OOM killer gives the process, 0 to 1000 and higher with
+    /// a positive oom_score_adj
+    pub oom_score: Option<u32>,
+}
+
+/// Memory usage of the processes currently on screen
+///
+/// Memory is only needed for the rows being drawn, so it is read here on
+/// demand instead of on the refresh path, and reused until `max_age` passes.
+#[derive(Default)]
+pub struct MemoryCache {
+    readers: HashMap<i32, ProcReader>,
+}
+
+impl MemoryCache {
+    /// Look up the memory usage of `pids`, dropping cached readers of
+    /// processes that are no longer shown
+    pub fn lookup(&mut self, pids: &[i32], max_age: Duration) -> HashMap<i32, MemoryUsage> {
+        self.readers.retain(|pid, _| pids.contains(pid));
+        pids.iter()
+            .map(|&pid| {
+                let reader = self
+                    .readers
+                    .entry(pid)
+                    .or_insert_with(|| ProcReader::new(pid));
+                (pid, reader.memory(max_age))
+            })
+            .collect()
+    }
+}
+
+/// Shortest time between two counts of a process's descriptors, which can
+/// number in the hundreds of thousands
+const FD_REFRESH: Duration = Duration::from_secs(2);
+
+/// Number of file descriptors process `pid` has open, or `None` if its
+/// descriptor table cannot be read (another user's process without root)
+pub fn count_fds(pid: i32) -> Option<usize> {
+    Some(fs::read_dir(format!("/proc/{}/fd", pid)).ok()?.count())
+}
+
+/// Open descriptor counts of the processes currently on screen
+///
+/// Like memory, they are only counted for the rows being drawn, and reused
+/// until `max_age`, but at least [`FD_REFRESH`], passes.
+#[derive(Default)]
+pub struct FdCache {
+    entries: HashMap<i32, (Instant, Option<usize>)>,
+}
+
+impl FdCache {
+    /// Count the descriptors of `pids`, dropping the counts of processes
+    /// that are no longer shown; unreadable ones are left out
+    pub fn lookup(&mut self, pids: &[i32], max_age: Duration) -> HashMap<i32, usize> {
+        let max_age = max_age.max(FD_REFRESH);
+        self.entries.retain(|pid, _| pids.contains(pid));
+        pids.iter()
+            .filter_map(|&pid| {
+                let (at, count) = self
+                    .entries
+                    .entry(pid)
+                    .or_insert_with(|| (Instant::now(), count_fds(pid)));
+                if at.elapsed() >= max_age {
+                    *at = Instant::now();
+                    *count = count_fds(pid);
+                }
+                Some((pid, (*count)?))
+            })
+            .collect()
     }
 }
 
@@ -113,7 +380,15 @@ pub struct ProcessMetadata {
     pub tid: i32,
     pub uid: u32,
     pub cmdline: String,
-    pub priority_str: String,
+    /// When the process (TGID) started
+    pub started: Option<SystemTime>,
+    /// Controlling terminal, e.g. `pts/3`
+    pub tty: Option<String>,
+    /// Process group and session of the process
+    pub pgid: Option<i32>,
+    pub sid: Option<i32>,
+    /// PID (or TID) inside the task's PID namespace, if it is in one
+    pub nspid: Option<i32>,
 }
 
 /// High-level reader for /proc/[tid] data
@@ -138,14 +413,41 @@ impl ProcReader {
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Failed to parse status"))
     }
 
+    /// Read and parse /proc/[pid]/stat
+    fn stat(&mut self, pid: i32) -> io::Result<ProcStat> {
+        let path = format!("/proc/{}/stat", pid);
+        let content = self.cache.read(path, CacheTTL::Static)?;
+        ProcStat::parse(&content)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Failed to parse stat"))
+    }
+
     /// Read /proc/[tid]/cmdline
     fn cmdline(&mut self, pid: i32) -> io::Result<String> {
         let path = format!("/proc/{}/cmdline", pid);
         self.cache.read(path, CacheTTL::Static)
     }
 
+    /// Read the memory usage of this task's process
+    fn memory(&mut self, max_age: Duration) -> MemoryUsage {
+        let path = format!("/proc/{}/statm", self.tid);
+        let statm = self.cache.read(path, CacheTTL::Refresh(max_age)).ok();
+        let path = format!("/proc/{}/status", self.tid);
+        let status = self.cache.read(path, CacheTTL::Refresh(max_age)).ok();
+        let path = format!("/proc/{}/oom_score", self.tid);
+        let oom_score = self.cache.read(path, CacheTTL::Refresh(max_age)).ok();
+        MemoryUsage {
+            // Kernel threads report all zeroes in statm
+            rss_kb: statm
+                .and_then(|s| parse_statm_resident(&s))
+                .filter(|pages| *pages > 0)
+                .map(|pages| pages * page_size_kb()),
+            swap_kb: status.and_then(|s| parse_status_kb(&s, "VmSwap")),
+            oom_score: oom_score.and_then(|s| s.trim().parse().ok()),
+        }
+    }
+
     /// Get UID efficiently via filesystem metadata (no parsing needed)
-    fn uid_fast(&self) -> io::Result<u32> {
+    pub fn uid_fast(&self) -> io::Result<u32> {
         let path = format!("/proc/{}", self.tid);
         let metadata = fs::metadata(&path)?;
 
@@ -174,19 +476,25 @@ impl ProcReader {
         let tgid = status.tgid;
         let tid = status.pid;
 
-        // Get priority from ioprio syscall
-        let priority_str = super::ioprio::get_ioprio_string(tid);
-
         // Get cmdline (use TGID for main process cmdline)
         let cmdline_content = self.cmdline(pid)?;
         let cmdline = Self::parse_cmdline(&cmdline_content, pid, tid, &status.name, tgid)?;
 
+        // Get start time and terminal of the whole process, not of this thread
+        let stat = self.stat(tgid).ok();
+        let started = stat.and_then(|stat| start_time(stat.start_ticks));
+        let tty = stat.and_then(|stat| tty_name(stat.tty_nr));
+
         Ok(ProcessMetadata {
             pid: tgid,
             tid,
             uid,
             cmdline,
-            priority_str,
+            started,
+            tty,
+            pgid: stat.map(|stat| stat.pgrp),
+            sid: stat.map(|stat| stat.session),
+            nspid: status.nspid,
         })
     }
 
@@ -269,6 +577,16 @@ mod tests {
         assert_eq!(result.unwrap(), result2.unwrap());
     }
 
+    #[test]
+    fn test_count_fds() {
+        let own = std::process::id() as i32;
+        // stdin, stdout and stderr at least
+        assert!(count_fds(own).is_some_and(|count| count >= 3));
+        assert_eq!(count_fds(-1), None);
+        let counts = FdCache::default().lookup(&[own, -1], Duration::ZERO);
+        assert!(counts.contains_key(&own) && !counts.contains_key(&-1));
+    }
+
     #[test]
     fn test_parse_status() {
         let content = "Name:\ttest\nTgid:\t1234\nPid:\t1234\nPPid:\t1\n";
@@ -278,6 +596,61 @@ mod tests {
         assert_eq!(status.name, "test");
         assert_eq!(status.tgid, 1234);
         assert_eq!(status.pid, 1234);
+        assert_eq!(status.nspid, None);
+
+        let content = "Name:\tnginx\nTgid:\t5678\nPid:\t5678\nNSpid:\t5678\t1\n";
+        assert_eq!(ProcStatus::parse(content).unwrap().nspid, Some(1));
+    }
+
+    #[test]
+    fn test_parse_stat() {
+        let content = "1234 (my (odd) cmd) S 1 1234 1234 34816 1234 4194304 150 0 2 0 \
+                       10 5 0 0 20 0 1 0 987654 12345678 456 18446744073709551615\n";
+        let stat = ProcStat::parse(content).unwrap();
+        assert_eq!((stat.state, stat.ppid), ('S', 1));
+        assert_eq!((stat.pgrp, stat.session), (1234, 1234));
+        assert_eq!(stat.tty_nr, 34816);
+        assert_eq!(stat.flags & PF_KTHREAD, 0);
+        assert_eq!(stat.start_ticks, 987654);
+        assert_eq!((stat.minor_faults, stat.major_faults), (150, 2));
+        assert_eq!((stat.utime, stat.stime), (10, 5));
+        assert_eq!(stat.blkio_ticks, 0);
+        let content = "99 (dd) D 1 99 99 0 -1 4194560 91 0 0 0 3 250 0 0 20 0 1 0 5000 \
+                       4616192 256 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 2 0 0 \
+                       37 0 0\n";
+        let stat = ProcStat::parse(content).unwrap();
+        assert_eq!((stat.state, stat.blkio_ticks), ('D', 37));
+        let content = "2 (kthreadd) S 0 0 0 0 -1 2129984 0 0 0 0 0 0 0 0 20 0 1 0 3 0 0 \
+                       18446744073709551615\n";
+        assert_ne!(ProcStat::parse(content).unwrap().flags & PF_KTHREAD, 0);
+        assert!(ProcStat::parse("1234 (truncated) S 1").is_none());
+    }
+
+    #[test]
+    fn test_tty_name() {
+        assert_eq!(tty_name(0), None);
+        assert_eq!(tty_name(34816), Some("pts/0".to_string()));
+        assert_eq!(tty_name((136 << 8) | 5), Some("pts/5".to_string()));
+        assert_eq!(tty_name((4 << 8) | 1), Some("tty1".to_string()));
+        assert_eq!(tty_name((4 << 8) | 65), Some("ttyS1".to_string()));
+    }
+
+    #[test]
+    fn test_parse_status_kb() {
+        let content = "Name:\tpostgres\nVmRSS:\t  20480 kB\nVmSwap:\t    1536 kB\n";
+        assert_eq!(parse_status_kb(content, "VmSwap"), Some(1536));
+        assert_eq!(parse_status_kb(content, "VmRSS"), Some(20480));
+        // Kernel threads have no memory fields
+        assert_eq!(parse_status_kb("Name:\tkworker/0:1\n", "VmSwap"), None);
+    }
+
+    #[test]
+    fn test_parse_statm_resident() {
+        assert_eq!(
+            parse_statm_resident("10240 2560 512 100 0 4096 0\n"),
+            Some(2560)
+        );
+        assert_eq!(parse_statm_resident(""), None);
     }
 
     #[test]
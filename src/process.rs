@@ -1,26 +1,60 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use nix::unistd::{Uid, User};
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 use tokio::sync::mpsc;
 use tokio::task;
 use tokio::time::{interval, Duration};
 use tokio_util::sync::CancellationToken;
 
-use crate::proc_reader::ProcReader;
-use crate::taskstats::{TaskStats, TaskStatsConnection};
+use crate::cgroup::{self, CgroupRate, CgroupSampler};
+use crate::connector::{self, TaskTracker};
+use crate::cpustat::{self, CpuSampler, Iowait};
+use crate::devtrace::{self, DeviceRates, DeviceTracer};
+use crate::diskstats::{self, DeviceRate, DiskSampler};
+use crate::exits::{ExitLedger, ExitListener, ExitedTask};
+use crate::filetrace::{self, FileRates, FileTracer};
+use crate::filter::TaskFilter;
+use crate::ioprio::{Ioprio, IoprioClass};
+use crate::latency::{self, LatencyRates, LatencyTracer};
+use crate::loadavg::{self, LoadAvg};
+use crate::meminfo::{self, DirtyMemory};
+use crate::metadata::{self, Metadata, MetadataQueue};
+use crate::netio::{self, NetRates};
+use crate::nfsstats::{self, NfsRate, NfsSampler};
+use crate::pressure::{self, IoPressure};
+use crate::priocache::PrioCache;
+use crate::proc_reader::{self, ProcReader};
+use crate::swapstats::{self, SwapCounters, SwapRate, SwapSampler};
+use crate::synctrace::{self, SyncRates, SyncTracer};
+use crate::taskstats::{self, TaskStats, TaskStatsConnection};
+use crate::writeback::{self, Writeback, WritebackEstimator, WritebackRates};
 
 /// Information about a single thread
 ///
 /// Tracks I/O statistics and deltas for an individual thread (TID).
 #[derive(Debug, Clone)]
 pub struct ThreadInfo {
-    #[allow(dead_code)]
     pub tid: i32,
     pub stats_total: Option<TaskStats>,
+    /// Change over the last interval, normalized to the refresh interval so
+    /// that dividing by it gives the thread's own rates
     pub stats_delta: TaskStats,
+    /// Actual change of the counters since the previous sample, which
+    /// accumulated totals add up
+    pub stats_change: TaskStats,
+    /// When the counters were last fetched; refreshing thousands of tasks
+    /// takes a while, so this drifts from the refresh timestamp
+    sampled_at: Option<Instant>,
+    expiring: bool, // Removed at the next refresh unless sampled again
+    /// Finished from its exit record
+    exited: bool,
 }
 
 impl ThreadInfo {
@@ -29,30 +63,102 @@ impl ThreadInfo {
             tid,
             stats_total: None,
             stats_delta: TaskStats::default(),
+            stats_change: TaskStats::default(),
+            sampled_at: None,
+            expiring: false,
+            exited: false,
+        }
+    }
+
+    /// A thread that started since the previous refresh, so all of its I/O
+    /// falls in the current interval
+    pub fn started(tid: i32) -> Self {
+        Self {
+            stats_total: Some(TaskStats::default()),
+            ..Self::new(tid)
         }
     }
 
+    /// Apply the final stats of an exited thread
+    fn finish(&mut self, stats: TaskStats) {
+        self.update_stats(stats);
+        self.expiring = true;
+        self.exited = true;
+    }
+
+    /// Drop the counters of a thread that was not sampled, so that the next
+    /// sample starts over instead of covering the time in between
+    fn forget(&mut self) {
+        self.stats_total = None;
+        self.sampled_at = None;
+    }
+
     pub fn update_stats(&mut self, stats: TaskStats) {
         if let Some(ref total) = self.stats_total {
-            self.stats_delta = stats.delta(total);
+            self.stats_change = stats.delta(total);
+            self.stats_delta = self.stats_change;
         }
         self.stats_total = Some(stats);
     }
+
+    /// Apply stats fetched just now, scaling the delta from the time since
+    /// the thread's previous sample to the `interval` of the refresh, in
+    /// seconds
+    ///
+    /// Without this, a thread sampled late in one pass and early in the next
+    /// would show its I/O over less time than it took.
+    pub fn sample(&mut self, stats: TaskStats, interval: f64) {
+        let now = Instant::now();
+        let elapsed = self
+            .sampled_at
+            .replace(now)
+            .map(|at| now.duration_since(at).as_secs_f64());
+        self.update_stats(stats);
+        if let Some(elapsed) = elapsed.filter(|&elapsed| elapsed > 0.0 && interval > 0.0) {
+            self.stats_delta = self.stats_change.scaled(interval / elapsed);
+        }
+    }
 }
 
 /// Information about a process or thread group
 ///
 /// In thread mode (default), each ProcessInfo represents a single thread.
-/// In process mode (-P flag), each ProcessInfo aggregates stats from all threads in a process.
+/// In process mode (-P flag), each ProcessInfo is sampled for the whole thread group at
+/// once, including the threads that exited in between.
 #[derive(Debug, Clone)]
 pub struct ProcessInfo {
     pub pid: i32, // Parent process ID (TGID)
     pub tid: i32, // Thread ID (this specific thread)
     pub uid: Option<u32>,
     pub user: Option<String>,
+    /// I/O priority, once read by the [`PrioCache`]
     pub prio: Option<String>,
     pub cmdline: Option<String>, // Cached cmdline
+    pub started: Option<SystemTime>,
+    pub tty: Option<String>,
+    /// systemd unit of the process, e.g. `nginx.service`
+    pub unit: Option<String>,
+    /// cgroup of the process, e.g. `/system.slice/nginx.service`
+    pub cgroup: Option<String>,
+    /// Process group and session of the process
+    pub pgid: Option<i32>,
+    pub sid: Option<i32>,
+    /// PID (TID in thread mode) inside a container's PID namespace
+    pub nspid: Option<i32>,
+    /// PIDs of the processes a process group or session row stands for;
+    /// empty for other rows
+    pub members: Vec<i32>,
+    /// Whether the thread, or any thread of the process, was in
+    /// uninterruptible sleep (D state) when last sampled
+    pub uninterruptible: bool,
+    /// Whether the task was stopped by a signal (T state) when last
+    /// sampled; for process group and session rows, every process
+    pub stopped: bool,
     pub threads: HashMap<i32, ThreadInfo>,
+    /// Counters of the whole thread group in process mode, which the row
+    /// shows: bytes from /proc/[pid]/io without the children it reaped,
+    /// delays as aggregated by the kernel
+    pub group: Option<ThreadInfo>,
     pub stats_delta: TaskStats,
     pub stats_accum: TaskStats,
     #[allow(dead_code)]
@@ -69,7 +175,18 @@ impl ProcessInfo {
             user: None,
             prio: None,
             cmdline: None,
+            started: None,
+            tty: None,
+            unit: None,
+            cgroup: None,
+            pgid: None,
+            sid: None,
+            nspid: None,
+            members: Vec::new(),
+            uninterruptible: false,
+            stopped: false,
             threads: HashMap::new(),
+            group: None,
             stats_delta: TaskStats::default(),
             stats_accum: TaskStats::default(),
             stats_accum_timestamp: Instant::now(),
@@ -77,6 +194,56 @@ impl ProcessInfo {
         }
     }
 
+    /// Row for a task only known from its exit record, as /proc no longer
+    /// has its metadata
+    fn from_exited(task: &ExitedTask, tid: i32) -> Self {
+        let mut process = Self::new(task.tgid);
+        process.tid = tid;
+        process.uid = Some(task.uid);
+        process.user = Some(process.compute_user());
+        process.cmdline = Some(task.comm.clone());
+        process.metadata_initialized = true;
+        process
+    }
+
+    /// Take what a task gets from its parent `ppid` when it starts: its
+    /// cgroup, terminal and session, for a task that exited before they
+    /// could be read
+    fn inherit(&mut self, ppid: i32) {
+        let Some(parent) = metadata::read(ppid, ppid) else {
+            return;
+        };
+        self.cgroup = parent.cgroup;
+        self.unit = parent.unit;
+        self.tty = parent.process.tty;
+        self.pgid = parent.process.pgid;
+        self.sid = parent.process.sid;
+    }
+
+    fn apply_metadata(&mut self, metadata: Metadata) {
+        let Metadata {
+            process,
+            unit,
+            cgroup,
+        } = metadata;
+        self.pid = process.pid;
+        self.tid = process.tid;
+        self.uid = Some(process.uid);
+        self.cmdline = Some(process.cmdline);
+        self.started = process.started;
+        self.tty = process.tty;
+        self.unit = unit;
+        self.cgroup = cgroup;
+        self.pgid = process.pgid;
+        self.sid = process.sid;
+        self.nspid = process.nspid;
+
+        // Compute and cache user string from UID
+        self.user = Some(self.compute_user());
+
+        self.metadata_initialized = true;
+    }
+
     pub fn get_user(&self) -> &str {
         if let Some(ref user) = self.user {
             return user;
@@ -110,8 +277,14 @@ impl ProcessInfo {
             return prio;
         }
 
-        // Cache miss - shouldn't happen often, return fallback
-        "be/4"
+        // Not read yet: tasks first seen in this refresh
+        "?"
+    }
+
+    /// I/O priority class, once the priority was read
+    pub fn prio_class(&self) -> Option<IoprioClass> {
+        let prio = Ioprio::from_string(self.prio.as_deref()?).ok()?;
+        Some(prio.class)
     }
 
     pub fn get_cmdline(&self) -> &str {
@@ -120,39 +293,271 @@ impl ProcessInfo {
             return cmdline;
         }
 
-        // Not cached - return fallback
-        "{no such process}"
+        // Not read yet: tasks first seen in this refresh, or already gone
+        "?"
     }
 
     pub fn did_some_io(&self, accumulated: bool) -> bool {
         if accumulated {
             !self.stats_accum.is_all_zero()
         } else {
-            self.threads.values().any(|t| !t.stats_delta.is_all_zero())
+            !self.stats_delta.is_all_zero()
         }
     }
 
+    /// Read and write rate in bytes per second over an interval of
+    /// `duration` seconds, without the writes cancelled by truncation
+    pub fn io_rate(&self, duration: f64) -> f64 {
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        let stats = &self.stats_delta;
+        let bytes = stats.read_bytes.saturating_add(
+            stats
+                .write_bytes
+                .saturating_sub(stats.cancelled_write_bytes),
+        );
+        bytes as f64 / duration
+    }
+
+    /// Threads that did more than `share` (0 to 1) of the process's disk I/O
+    /// over the last interval, busiest first with their share; none for a
+    /// single-threaded process
+    pub fn hot_threads(&self, share: f64) -> Vec<(&ThreadInfo, f64)> {
+        if self.threads.len() < 2 {
+            return Vec::new();
+        }
+        let io = |thread: &ThreadInfo| {
+            (thread.stats_delta.read_bytes + thread.stats_delta.write_bytes) as f64
+        };
+        let total: f64 = self.threads.values().map(io).sum();
+        if total == 0.0 {
+            return Vec::new();
+        }
+        let mut hot: Vec<(&ThreadInfo, f64)> = self
+            .threads
+            .values()
+            .map(|thread| (thread, io(thread) / total))
+            .filter(|&(_, thread_share)| thread_share > share)
+            .collect();
+        hot.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.tid.cmp(&b.0.tid)));
+        hot
+    }
+
     pub fn update_stats(&mut self) -> bool {
         let mut stats_delta = TaskStats::default();
-        let num_threads = self.threads.len();
+        let mut stats_change = TaskStats::default();
 
-        if num_threads == 0 {
+        if self.threads.is_empty() {
             return false;
         }
 
-        for thread in self.threads.values() {
-            stats_delta.accumulate(&thread.stats_delta);
+        if let Some(group) = &self.group {
+            stats_delta = group.stats_delta;
+            stats_change = group.stats_change;
+        } else {
+            for thread in self.threads.values() {
+                stats_delta.accumulate(&thread.stats_delta);
+                stats_change.accumulate(&thread.stats_change);
+            }
         }
 
-        // Average delay stats
-        stats_delta.blkio_delay_total /= num_threads as u64;
-        stats_delta.swapin_delay_total /= num_threads as u64;
-
         self.stats_delta = stats_delta;
-        self.stats_accum.accumulate(&self.stats_delta);
+        self.stats_accum.accumulate(&stats_change);
 
         true
     }
+
+    /// Start the accumulated totals from the I/O the task did before it was
+    /// first sampled, from /proc: per thread in thread mode, for the whole
+    /// process otherwise
+    fn seed_lifetime(&mut self, show_processes: bool) {
+        let lifetime = if show_processes {
+            taskstats::read_process_io(self.pid)
+        } else {
+            taskstats::read_thread_io(self.tid)
+        };
+        if let Some(lifetime) = lifetime {
+            self.stats_accum.accumulate(&lifetime);
+        }
+    }
+
+    fn clear_delta(&mut self) {
+        self.stats_delta = TaskStats::default();
+        for thread in self.threads.values_mut().chain(&mut self.group) {
+            thread.stats_delta = TaskStats::default();
+            thread.stats_change = TaskStats::default();
+        }
+    }
+}
+
+/// Larger units than single processes that process mode can show as rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    /// Process groups (PGID): shells put each job, such as a pipeline, in
+    /// one of its own
+    ProcessGroup,
+    /// Sessions (SID), such as everything started from one terminal
+    Session,
+}
+
+impl Grouping {
+    /// Parse a `--group` argument
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "pgid" | "job" => Ok(Grouping::ProcessGroup),
+            "sid" | "session" => Ok(Grouping::Session),
+            _ => Err(format!(
+                "Unknown grouping: {} (expected pgid or sid)",
+                value
+            )),
+        }
+    }
+
+    /// Name of the row ID, e.g. in the zoom view
+    pub fn label(&self) -> &'static str {
+        match self {
+            Grouping::ProcessGroup => "PGID",
+            Grouping::Session => "SID",
+        }
+    }
+
+    fn id(&self, process: &ProcessInfo) -> Option<i32> {
+        match self {
+            Grouping::ProcessGroup => process.pgid,
+            Grouping::Session => process.sid,
+        }
+    }
+}
+
+/// The processes descending from `roots`, roots included, given the parent
+/// of each process
+pub fn subtree(roots: &[i32], parents: &HashMap<i32, i32>) -> HashSet<i32> {
+    let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+    for (&tgid, &ppid) in parents {
+        children.entry(ppid).or_default().push(tgid);
+    }
+    let mut found = HashSet::new();
+    let mut queue = roots.to_vec();
+    while let Some(tgid) = queue.pop() {
+        if found.insert(tgid) {
+            queue.extend(children.get(&tgid).into_iter().flatten());
+        }
+    }
+    found
+}
+
+/// Fold process rows into one row per process group or session
+///
+/// A row takes its ID from the group and its metadata from the group
+/// leader, or from its lowest PID while the leader is gone or filtered out,
+/// and sums the I/O of its members. The command line tells how many other
+/// processes it holds. Processes whose group is not known yet keep a row of
+/// their own.
+pub fn group_rows(
+    processes: HashMap<i32, ProcessInfo>,
+    grouping: Grouping,
+) -> HashMap<i32, ProcessInfo> {
+    let mut rows: Vec<ProcessInfo> = processes.into_values().collect();
+    rows.sort_by_key(|process| (grouping.id(process) != Some(process.pid), process.pid));
+
+    let mut groups: HashMap<i32, ProcessInfo> = HashMap::new();
+    for process in rows {
+        let id = grouping.id(&process).unwrap_or(process.pid);
+        match groups.get_mut(&id) {
+            Some(group) => {
+                group.members.push(process.pid);
+                group.stats_delta.accumulate(&process.stats_delta);
+                group.stats_accum.accumulate(&process.stats_accum);
+                group.threads.extend(process.threads);
+                group.uninterruptible |= process.uninterruptible;
+                group.stopped &= process.stopped;
+            }
+            None => {
+                let mut group = process;
+                group.members = vec![group.pid];
+                group.pid = id;
+                group.tid = id;
+                groups.insert(id, group);
+            }
+        }
+    }
+
+    for group in groups.values_mut() {
+        if group.members.len() > 1 {
+            let others = group.members.len() - 1;
+            group.cmdline = Some(format!("{} (+{})", group.get_cmdline(), others));
+        }
+    }
+    groups
+}
+
+/// Turn the rows of one mode into those of the other, `show_processes`
+/// giving the new one, keeping the counters of each thread and the
+/// accumulated totals so that the next refresh carries on from them
+///
+/// Processes take the metadata of their main thread and the totals of all
+/// their threads, and start sampling their thread group over. Totals are
+/// not kept per thread within a process, so splitting one gives them all to
+/// its main thread, where thread mode also adds those of exited threads.
+/// Its threads start sampling over, as process mode only samples the thread
+/// group as a whole. Rows whose task changed have their metadata read again.
+pub fn convert_rows(
+    rows: HashMap<i32, ProcessInfo>,
+    show_processes: bool,
+) -> HashMap<i32, ProcessInfo> {
+    let mut converted: HashMap<i32, ProcessInfo> = HashMap::new();
+    if show_processes {
+        let mut rows: Vec<ProcessInfo> = rows.into_values().collect();
+        rows.sort_by_key(|row| (row.tid != row.pid, row.tid));
+        for row in rows {
+            match converted.get_mut(&row.pid) {
+                Some(process) => {
+                    process.threads.extend(row.threads);
+                    process.stats_accum.accumulate(&row.stats_accum);
+                }
+                None => {
+                    let mut process = row;
+                    process.group = Some(ThreadInfo::new(process.pid));
+                    if process.tid != process.pid {
+                        process.tid = process.pid;
+                        process.metadata_initialized = false;
+                    }
+                    converted.insert(process.pid, process);
+                }
+            }
+        }
+        return converted;
+    }
+
+    for mut process in rows.into_values() {
+        let threads = std::mem::take(&mut process.threads);
+        // The group's delays are counted per thread from now on
+        process.group = None;
+        let main = if threads.contains_key(&process.pid) {
+            Some(process.pid)
+        } else {
+            threads.keys().min().copied()
+        };
+        for (tid, thread) in threads {
+            let mut row = process.clone();
+            row.tid = tid;
+            let thread = ThreadInfo {
+                expiring: thread.expiring,
+                exited: thread.exited,
+                ..ThreadInfo::new(tid)
+            };
+            row.threads = HashMap::from([(tid, thread)]);
+            if Some(tid) != main {
+                row.stats_accum = TaskStats::default();
+            }
+            if tid != process.pid {
+                row.metadata_initialized = false;
+            }
+            converted.insert(tid, row);
+        }
+    }
+    converted
 }
 
 /// A snapshot of process I/O statistics at a point in time
@@ -164,6 +569,176 @@ pub struct ProcessSnapshot {
     pub total_io: (u64, u64),
     pub actual_io: (u64, u64),
     pub duration: f64,
+    /// Per-device rates over the same interval, from /proc/diskstats
+    pub devices: Vec<DeviceRate>,
+    /// Per-cgroup rates over the same interval, from cgroup v2 io.stat
+    pub cgroups: Vec<CgroupRate>,
+    /// Processes (TGIDs) in the cgroups being throttled
+    pub throttled: HashSet<i32>,
+    /// Per-NFS-mount rates over the same interval, from /proc/self/mountstats
+    pub nfs_mounts: Vec<NfsRate>,
+    /// I/O pressure at the end of the interval, when the kernel provides it
+    pub pressure: Option<IoPressure>,
+    /// Dirty and writeback memory at the end of the interval
+    pub dirty: Option<DirtyMemory>,
+    /// Swap traffic over the same interval, while a swap area is in use
+    pub swap: Option<SwapRate>,
+    /// Share of CPU time spent in iowait over the same interval, overall and
+    /// by CPU
+    pub iowait: Iowait,
+    /// Load averages at the end of the interval
+    pub load: Option<LoadAvg>,
+    /// Monitored tasks found in uninterruptible sleep (D state)
+    pub uninterruptible: usize,
+    /// Per-file rates by process ID, when files are traced
+    pub files: FileRates,
+    /// Network I/O by process ID, when it is counted
+    pub net: NetRates,
+    /// Block I/O by process ID and device, when it is traced
+    pub device_io: DeviceRates,
+    /// Sync calls by process ID, when they are counted
+    pub syncs: SyncRates,
+    /// Block I/O latency by process ID, when it is traced
+    pub latency: LatencyRates,
+    /// Estimated share of Actual DISK WRITE by row ID
+    pub writeback: WritebackRates,
+}
+
+impl ProcessSnapshot {
+    /// Show process groups or sessions as rows instead of processes
+    ///
+    /// Rates by process ID from the tracers stay as they are, so the columns
+    /// built on them show the row's first process.
+    pub fn group(mut self, grouping: Grouping) -> Self {
+        self.processes = group_rows(std::mem::take(&mut self.processes), grouping);
+        self.writeback = self
+            .processes
+            .iter()
+            .map(|(&id, group)| {
+                let mut writeback = Writeback::default();
+                for member in group
+                    .members
+                    .iter()
+                    .filter_map(|pid| self.writeback.get(pid))
+                {
+                    writeback.bytes_per_sec += member.bytes_per_sec;
+                    writeback.bytes += member.bytes;
+                }
+                (id, writeback)
+            })
+            .collect();
+        self
+    }
+
+    /// Fold a newer snapshot into this one, as if both covered a single interval
+    ///
+    /// Rows that disappeared in the newer snapshot are kept so their I/O is
+    /// not lost.
+    pub fn merge(&mut self, newer: ProcessSnapshot) {
+        let mut processes = newer.processes;
+        for (id, older) in self.processes.drain() {
+            match processes.get_mut(&id) {
+                Some(process) => {
+                    let mut delta = older.stats_delta;
+                    delta.accumulate(&process.stats_delta);
+                    process.stats_delta = delta;
+                    // The busy threads look at these
+                    for (tid, older) in older.threads {
+                        match process.threads.get_mut(&tid) {
+                            Some(thread) => {
+                                let mut delta = older.stats_delta;
+                                delta.accumulate(&thread.stats_delta);
+                                thread.stats_delta = delta;
+                                let mut change = older.stats_change;
+                                change.accumulate(&thread.stats_change);
+                                thread.stats_change = change;
+                            }
+                            None => {
+                                process.threads.insert(tid, older);
+                            }
+                        }
+                    }
+                }
+                None => {
+                    processes.insert(id, older);
+                }
+            }
+        }
+
+        self.processes = processes;
+        self.devices =
+            diskstats::merge_rates(&self.devices, self.duration, &newer.devices, newer.duration);
+        self.cgroups =
+            cgroup::merge_rates(&self.cgroups, self.duration, &newer.cgroups, newer.duration);
+        self.throttled.extend(newer.throttled);
+        self.nfs_mounts = nfsstats::merge_rates(
+            &self.nfs_mounts,
+            self.duration,
+            &newer.nfs_mounts,
+            newer.duration,
+        );
+        self.files =
+            filetrace::merge_rates(&self.files, self.duration, &newer.files, newer.duration);
+        self.net = netio::merge_rates(&self.net, self.duration, &newer.net, newer.duration);
+        self.device_io = devtrace::merge_rates(
+            &self.device_io,
+            self.duration,
+            &newer.device_io,
+            newer.duration,
+        );
+        self.syncs =
+            synctrace::merge_rates(&self.syncs, self.duration, &newer.syncs, newer.duration);
+        self.latency = latency::merge_rates(&self.latency, &newer.latency);
+        self.writeback = writeback::merge_rates(
+            &self.writeback,
+            self.duration,
+            &newer.writeback,
+            newer.duration,
+        );
+        // Already averaged by the kernel, so the newest reading wins
+        self.pressure = newer.pressure;
+        self.dirty = newer.dirty;
+        self.swap = swapstats::merge_rates(
+            self.swap.as_ref(),
+            self.duration,
+            newer.swap.as_ref(),
+            newer.duration,
+        );
+        self.iowait =
+            cpustat::merge_rates(&self.iowait, self.duration, &newer.iowait, newer.duration);
+        self.load = newer.load;
+        self.uninterruptible = newer.uninterruptible;
+        self.total_io.0 = self.total_io.0.saturating_add(newer.total_io.0);
+        self.total_io.1 = self.total_io.1.saturating_add(newer.total_io.1);
+        self.actual_io.0 = self.actual_io.0.saturating_add(newer.actual_io.0);
+        self.actual_io.1 = self.actual_io.1.saturating_add(newer.actual_io.1);
+        self.duration += newer.duration;
+    }
+}
+
+/// What a refresh stream samples and how often, with the handles it shares
+/// with the [`ProcessList`] it was configured from
+pub struct RefreshConfig {
+    /// Refreshes per second
+    update_rate: f64,
+    show_processes: bool,
+    grouping: Option<Grouping>,
+    taskstats_conn: Arc<Mutex<TaskStatsConnection>>,
+    pids: Vec<i32>,
+    children: bool,
+    lifetime: bool,
+    uids: Vec<u32>,
+    uid_range: Option<RangeInclusive<u32>>,
+    kthreads: bool,
+    filter: TaskFilter,
+    file_tracer: Option<Arc<Mutex<FileTracer>>>,
+    device_tracer: Option<Arc<Mutex<DeviceTracer>>>,
+    sync_tracer: Option<Arc<Mutex<SyncTracer>>>,
+    latency_tracer: Option<Arc<Mutex<LatencyTracer>>>,
+    prio_cache: Arc<PrioCache>,
+    prio_changed: Arc<AtomicBool>,
+    metadata: Arc<MetadataQueue>,
+    rows: Arc<Mutex<HashMap<i32, ProcessInfo>>>,
 }
 
 /// Manages collection and tracking of process I/O statistics
@@ -178,7 +753,73 @@ pub struct ProcessList {
     pub prev_pgpgin: Option<u64>,
     pub prev_pgpgout: Option<u64>,
     pub pids: Vec<i32>,
+    /// Whether `pids` also selects the descendants of their processes
+    pub children: bool,
+    /// Whether accumulated totals of the tasks found by the first refresh
+    /// include what they did before it
+    pub lifetime: bool,
+    /// Parent of every process seen, for `children`; kept after a process
+    /// is reparented so orphans stay in the subtree
+    pub parents: HashMap<i32, i32>,
+    /// The processes of `pids` and their descendants, for `children`
+    pub subtree: HashSet<i32>,
     pub uids: Vec<u32>,
+    /// UIDs the tasks must also have, on top of `uids`
+    pub uid_range: Option<RangeInclusive<u32>>,
+    /// Whether kernel threads are sampled
+    pub kthreads: bool,
+    /// Only the tasks it matches are sampled
+    pub filter: TaskFilter,
+    /// Rows `uids`, `uid_range`, `kthreads` or `filter` did not match, so that they are
+    /// not looked at again at every refresh
+    pub excluded: HashSet<i32>,
+    pub disks: DiskSampler,
+    pub devices: Vec<DeviceRate>,
+    pub cgroup_sampler: CgroupSampler,
+    pub cgroups: Vec<CgroupRate>,
+    pub throttled: HashSet<i32>,
+    pub nfs_sampler: NfsSampler,
+    pub nfs_mounts: Vec<NfsRate>,
+    pub pressure: Option<IoPressure>,
+    pub dirty: Option<DirtyMemory>,
+    pub swap_sampler: SwapSampler,
+    pub swap: Option<SwapRate>,
+    pub cpu_sampler: CpuSampler,
+    pub iowait: Iowait,
+    pub load: Option<LoadAvg>,
+    pub uninterruptible: usize,
+    /// Source of exit records; without it short-lived tasks go unnoticed
+    pub exits: Option<ExitListener>,
+    /// Exited threads and reaped children of the processes, for the rows
+    /// of process mode
+    pub exit_ledger: ExitLedger,
+    /// Live task set; without it /proc is walked on every refresh
+    pub tracker: Option<TaskTracker>,
+    /// Shared with every refresh stream, as attaching it is costly
+    pub file_tracer: Option<Arc<Mutex<FileTracer>>>,
+    pub files: FileRates,
+    pub net: NetRates,
+    /// Shared with every refresh stream, like the file tracer
+    pub device_tracer: Option<Arc<Mutex<DeviceTracer>>>,
+    pub device_io: DeviceRates,
+    /// Shared with every refresh stream, like the file tracer
+    pub sync_tracer: Option<Arc<Mutex<SyncTracer>>>,
+    pub syncs: SyncRates,
+    /// Shared with every refresh stream, like the file tracer
+    pub latency_tracer: Option<Arc<Mutex<LatencyTracer>>>,
+    pub latency: LatencyRates,
+    pub writeback_estimator: WritebackEstimator,
+    pub writeback: WritebackRates,
+    /// Shared with every refresh stream, to keep priorities across them
+    pub prio_cache: Arc<PrioCache>,
+    /// Set once priorities were changed from iotop, to read them all again
+    /// at the next pass of the [`PrioCache`] instead of waiting for it
+    pub prio_changed: Arc<AtomicBool>,
+    /// Shared with every refresh stream, like the priority cache
+    pub metadata: Arc<MetadataQueue>,
+    /// Rows of the running refresh stream as of its last refresh, which
+    /// the next stream can start from
+    pub stream_rows: Arc<Mutex<HashMap<i32, ProcessInfo>>>,
 }
 
 impl ProcessList {
@@ -191,37 +832,220 @@ impl ProcessList {
             prev_pgpgin: None,
             prev_pgpgout: None,
             pids: Vec::new(),
+            children: false,
+            lifetime: false,
+            parents: HashMap::new(),
+            subtree: HashSet::new(),
             uids: Vec::new(),
+            uid_range: None,
+            kthreads: true,
+            filter: TaskFilter::default(),
+            excluded: HashSet::new(),
+            disks: DiskSampler::default(),
+            devices: Vec::new(),
+            cgroup_sampler: CgroupSampler::default(),
+            cgroups: Vec::new(),
+            throttled: HashSet::new(),
+            nfs_sampler: NfsSampler::default(),
+            nfs_mounts: Vec::new(),
+            pressure: None,
+            dirty: None,
+            swap_sampler: SwapSampler::default(),
+            swap: None,
+            cpu_sampler: CpuSampler::default(),
+            iowait: Iowait::default(),
+            load: None,
+            uninterruptible: 0,
+            exits: None,
+            exit_ledger: ExitLedger::default(),
+            tracker: None,
+            file_tracer: None,
+            files: HashMap::new(),
+            net: HashMap::new(),
+            device_tracer: None,
+            device_io: HashMap::new(),
+            sync_tracer: None,
+            syncs: HashMap::new(),
+            latency_tracer: None,
+            latency: HashMap::new(),
+            writeback_estimator: WritebackEstimator::default(),
+            writeback: HashMap::new(),
+            prio_cache: Arc::new(PrioCache::default()),
+            prio_changed: Arc::new(AtomicBool::new(false)),
+            metadata: Arc::new(MetadataQueue::default()),
+            stream_rows: Arc::default(),
         }
     }
 
+    /// Listen for exit records and process events, unless running on /proc
+    /// alone
+    ///
+    /// Events pile up until the next refresh, so this is only for lists
+    /// that are refreshed.
+    pub fn with_event_listeners(mut self) -> Self {
+        (self.exits, self.tracker) = Self::start_event_listeners(&self.taskstats_conn);
+        self
+    }
+
+    fn start_event_listeners(
+        taskstats_conn: &Arc<Mutex<TaskStatsConnection>>,
+    ) -> (Option<ExitListener>, Option<TaskTracker>) {
+        // Both need CAP_NET_ADMIN, like taskstats
+        let procfs = taskstats_conn.lock().map_or(true, |conn| conn.is_procfs());
+        if procfs {
+            return (None, None);
+        }
+        (ExitListener::start().ok(), TaskTracker::start().ok())
+    }
+
+    /// Attribute I/O to files with eBPF, and sum each process's network
+    /// traffic if `count_net`, failing if that is not possible
+    pub fn with_file_tracer(mut self, count_net: bool) -> Result<Self> {
+        let tracer = FileTracer::start(count_net).context("Cannot trace files")?;
+        self.file_tracer = Some(Arc::new(Mutex::new(tracer)));
+        Ok(self)
+    }
+
+    /// Attribute block I/O to devices with eBPF, failing if that is not
+    /// possible
+    pub fn with_device_tracer(mut self) -> Result<Self> {
+        let tracer = DeviceTracer::start().context("Cannot trace block devices")?;
+        self.device_tracer = Some(Arc::new(Mutex::new(tracer)));
+        Ok(self)
+    }
+
+    /// Count sync calls with eBPF, failing if that is not possible
+    pub fn with_sync_tracer(mut self) -> Result<Self> {
+        let tracer = SyncTracer::start().context("Cannot count sync calls")?;
+        self.sync_tracer = Some(Arc::new(Mutex::new(tracer)));
+        Ok(self)
+    }
+
+    /// Time block I/O with eBPF, failing if that is not possible
+    pub fn with_latency_tracer(mut self) -> Result<Self> {
+        let tracer = LatencyTracer::start().context("Cannot trace block I/O latency")?;
+        self.latency_tracer = Some(Arc::new(Mutex::new(tracer)));
+        Ok(self)
+    }
+
     pub fn with_pids(mut self, pids: Vec<i32>) -> Self {
         self.pids = pids;
         self
     }
 
+    /// Also monitor the descendants of the processes given with `with_pids`
+    pub fn with_children(mut self, children: bool) -> Self {
+        self.children = children;
+        self
+    }
+
+    /// Start the accumulated totals of the tasks already running from their
+    /// lifetime I/O
+    pub fn with_lifetime(mut self, lifetime: bool) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
     pub fn with_uids(mut self, uids: Vec<u32>) -> Self {
         self.uids = uids;
         self
     }
 
-    pub fn spawn_refresh_stream(
+    /// Only monitor the tasks of the users in `range`, such as those of
+    /// people rather than system accounts
+    pub fn with_uid_range(mut self, range: Option<RangeInclusive<u32>>) -> Self {
+        self.uid_range = range;
+        self
+    }
+
+    /// Leave kernel threads out, without even making rows for them
+    pub fn with_kthreads(mut self, kthreads: bool) -> Self {
+        self.kthreads = kthreads;
+        self
+    }
+
+    /// Only sample the tasks `filter` matches
+    pub fn with_filter(mut self, filter: TaskFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Configuration of a refresh stream sampling the tasks this list does,
+    /// `update_rate` times a second, and continuing from its `stream_rows`
+    pub fn refresh_config(
+        &self,
         update_rate: f64,
         show_processes: bool,
-        taskstats_conn: Arc<Mutex<TaskStatsConnection>>,
-        pids: Vec<i32>,
-        uids: Vec<u32>,
+        grouping: Option<Grouping>,
+    ) -> RefreshConfig {
+        RefreshConfig {
+            update_rate,
+            show_processes,
+            grouping,
+            taskstats_conn: self.taskstats_conn.clone(),
+            pids: self.pids.clone(),
+            children: self.children,
+            lifetime: self.lifetime,
+            uids: self.uids.clone(),
+            uid_range: self.uid_range.clone(),
+            kthreads: self.kthreads,
+            filter: self.filter.clone(),
+            file_tracer: self.file_tracer.clone(),
+            device_tracer: self.device_tracer.clone(),
+            sync_tracer: self.sync_tracer.clone(),
+            latency_tracer: self.latency_tracer.clone(),
+            prio_cache: self.prio_cache.clone(),
+            prio_changed: self.prio_changed.clone(),
+            metadata: self.metadata.clone(),
+            rows: self.stream_rows.clone(),
+        }
+    }
+
+    pub fn spawn_refresh_stream(
+        config: RefreshConfig,
         cancellation_token: CancellationToken,
     ) -> mpsc::UnboundedReceiver<ProcessSnapshot> {
+        let RefreshConfig {
+            update_rate,
+            show_processes,
+            grouping,
+            taskstats_conn,
+            pids,
+            children,
+            lifetime,
+            uids,
+            uid_range,
+            kthreads,
+            filter,
+            file_tracer,
+            device_tracer,
+            sync_tracer,
+            latency_tracer,
+            prio_cache,
+            prio_changed,
+            metadata,
+            rows,
+        } = config;
         let (tx, rx) = mpsc::unbounded_channel();
+        // Rows carried over from the other mode already count their lifetime
+        let lifetime = lifetime && rows.lock().is_ok_and(|rows| rows.is_empty());
 
         task::spawn(async move {
             let mut tick_interval = interval(Duration::from_secs_f64(1.0 / update_rate));
-            let mut processes: HashMap<i32, ProcessInfo> = HashMap::new();
             let mut timestamp = Instant::now();
             let mut duration = 0.0;
             let mut prev_pgpgin: Option<u64> = None;
             let mut prev_pgpgout: Option<u64> = None;
+            let mut disks = DiskSampler::default();
+            let mut cgroup_sampler = CgroupSampler::default();
+            let mut nfs_sampler = NfsSampler::default();
+            let mut swap_sampler = SwapSampler::default();
+            let mut cpu_sampler = CpuSampler::default();
+            let mut writeback_estimator = WritebackEstimator::default();
+            let mut parents = HashMap::new();
+            let mut excluded = HashSet::new();
+            let mut exit_ledger = ExitLedger::default();
+            let (mut exits, mut tracker) = Self::start_event_listeners(&taskstats_conn);
 
             loop {
                 tokio::select! {
@@ -231,41 +1055,141 @@ impl ProcessList {
                     _ = tick_interval.tick() => {
                         // Refresh process data in blocking task to avoid blocking async runtime
                         let taskstats_conn_clone = taskstats_conn.clone();
-                        let processes_clone = processes.clone();
+                        let rows_clone = rows.clone();
                         let pids_clone = pids.clone();
                         let uids_clone = uids.clone();
+                        let uid_range_clone = uid_range.clone();
+                        let filter_clone = filter.clone();
+                        let disks_taken = std::mem::take(&mut disks);
+                        let cgroup_sampler_taken = std::mem::take(&mut cgroup_sampler);
+                        let nfs_sampler_taken = std::mem::take(&mut nfs_sampler);
+                        let swap_sampler_taken = std::mem::take(&mut swap_sampler);
+                        let cpu_sampler_taken = std::mem::take(&mut cpu_sampler);
+                        let writeback_estimator_taken = std::mem::take(&mut writeback_estimator);
+                        let parents_taken = std::mem::take(&mut parents);
+                        let excluded_taken = std::mem::take(&mut excluded);
+                        let exits_taken = exits.take();
+                        let exit_ledger_taken = std::mem::take(&mut exit_ledger);
+                        let tracker_taken = tracker.take();
+                        let file_tracer_clone = file_tracer.clone();
+                        let device_tracer_clone = device_tracer.clone();
+                        let sync_tracer_clone = sync_tracer.clone();
+                        let latency_tracer_clone = latency_tracer.clone();
+                        let prio_cache_clone = prio_cache.clone();
+                        let prio_changed_clone = prio_changed.clone();
+                        let metadata_clone = metadata.clone();
 
                         let result = task::spawn_blocking(move || {
+                            // Held through the refresh, so the rows taken when the
+                            // mode is switched are never those of a refresh in progress
+                            let mut rows = rows_clone
+                                .lock()
+                                .map_err(|_| anyhow::anyhow!("Refresh stream rows poisoned"))?;
                             let mut temp_list = ProcessList {
-                                processes: processes_clone,
+                                processes: std::mem::take(&mut *rows),
                                 taskstats_conn: taskstats_conn_clone,
                                 timestamp,
                                 duration,
                                 prev_pgpgin,
                                 prev_pgpgout,
                                 pids: pids_clone,
+                                children,
+                                lifetime,
+                                parents: parents_taken,
+                                subtree: HashSet::new(),
                                 uids: uids_clone,
+                                uid_range: uid_range_clone,
+                                kthreads,
+                                filter: filter_clone,
+                                excluded: excluded_taken,
+                                disks: disks_taken,
+                                devices: Vec::new(),
+                                cgroup_sampler: cgroup_sampler_taken,
+                                cgroups: Vec::new(),
+                                throttled: HashSet::new(),
+                                nfs_sampler: nfs_sampler_taken,
+                                nfs_mounts: Vec::new(),
+                                pressure: None,
+                                dirty: None,
+                                swap_sampler: swap_sampler_taken,
+                                swap: None,
+                                cpu_sampler: cpu_sampler_taken,
+                                iowait: Iowait::default(),
+                                load: None,
+                                uninterruptible: 0,
+                                exits: exits_taken,
+                                exit_ledger: exit_ledger_taken,
+                                tracker: tracker_taken,
+                                file_tracer: file_tracer_clone,
+                                files: HashMap::new(),
+                                net: HashMap::new(),
+                                device_tracer: device_tracer_clone,
+                                device_io: HashMap::new(),
+                                sync_tracer: sync_tracer_clone,
+                                syncs: HashMap::new(),
+                                latency_tracer: latency_tracer_clone,
+                                latency: HashMap::new(),
+                                writeback_estimator: writeback_estimator_taken,
+                                writeback: HashMap::new(),
+                                prio_cache: prio_cache_clone,
+                                prio_changed: prio_changed_clone,
+                                metadata: metadata_clone,
+                                stream_rows: Arc::default(),
                             };
 
-                            let io_stats = temp_list.refresh_processes(show_processes)?;
-                            Ok::<_, anyhow::Error>((temp_list, io_stats))
+                            let io_stats = temp_list.refresh_processes(show_processes);
+                            // The only copy of the rows per refresh: one is kept for
+                            // the next refresh, the other goes out in the snapshot
+                            rows.clone_from(&temp_list.processes);
+                            Ok::<_, anyhow::Error>((temp_list, io_stats?))
                         }).await;
 
                         match result {
                             Ok(Ok((updated_list, (total_io, actual_io)))) => {
                                 // Update our state
-                                processes = updated_list.processes;
+                                let processes = updated_list.processes;
                                 timestamp = updated_list.timestamp;
                                 duration = updated_list.duration;
                                 prev_pgpgin = updated_list.prev_pgpgin;
                                 prev_pgpgout = updated_list.prev_pgpgout;
+                                disks = updated_list.disks;
+                                cgroup_sampler = updated_list.cgroup_sampler;
+                                nfs_sampler = updated_list.nfs_sampler;
+                                swap_sampler = updated_list.swap_sampler;
+                                cpu_sampler = updated_list.cpu_sampler;
+                                writeback_estimator = updated_list.writeback_estimator;
+                                parents = updated_list.parents;
+                                excluded = updated_list.excluded;
+                                exits = updated_list.exits;
+                                exit_ledger = updated_list.exit_ledger;
+                                tracker = updated_list.tracker;
 
                                 // Send snapshot
                                 let snapshot = ProcessSnapshot {
-                                    processes: processes.clone(),
+                                    processes,
                                     total_io,
                                     actual_io,
                                     duration,
+                                    devices: updated_list.devices,
+                                    cgroups: updated_list.cgroups,
+                                    throttled: updated_list.throttled,
+                                    nfs_mounts: updated_list.nfs_mounts,
+                                    pressure: updated_list.pressure,
+                                    dirty: updated_list.dirty,
+                                    swap: updated_list.swap,
+                                    iowait: updated_list.iowait,
+                                    load: updated_list.load,
+                                    uninterruptible: updated_list.uninterruptible,
+                                    files: updated_list.files,
+                                    net: updated_list.net,
+                                    device_io: updated_list.device_io,
+                                    syncs: updated_list.syncs,
+                                    latency: updated_list.latency,
+                                    writeback: updated_list.writeback,
+                                };
+                                let snapshot = match grouping.filter(|_| show_processes) {
+                                    Some(grouping) => snapshot.group(grouping),
+                                    None => snapshot,
                                 };
 
                                 if tx.send(snapshot).is_err() {
@@ -286,7 +1210,9 @@ impl ProcessList {
         rx
     }
 
-    fn read_vmstat(&self) -> Result<(u64, u64)> {
+    /// Read the disk and swap traffic counters of /proc/vmstat: pgpgin and
+    /// pgpgout in bytes, and pswpin/pswpout
+    fn read_vmstat(&self) -> Result<(u64, u64, Option<SwapCounters>)> {
         let content = fs::read_to_string("/proc/vmstat")?;
         let mut pgpgin = 0u64;
         let mut pgpgout = 0u64;
@@ -303,39 +1229,39 @@ impl ProcessList {
         }
 
         // Convert from pages to bytes (assuming 4KB pages)
-        Ok((pgpgin * 4096, pgpgout * 4096))
+        Ok((
+            pgpgin * 4096,
+            pgpgout * 4096,
+            swapstats::parse_vmstat(&content),
+        ))
     }
 
-    fn update_process_metadata(process: &mut ProcessInfo, pid_for_status: i32) {
-        // Only update metadata once when process is first seen
+    /// Fill in the metadata of a row seen for the first time, or again after
+    /// an exec: right away on the first refresh, so that the first screen is
+    /// complete, and from the background queue afterwards, so that new
+    /// tasks do not hold up sampling the others
+    fn update_process_metadata(process: &mut ProcessInfo, queue: &MetadataQueue, inline: bool) {
         if process.metadata_initialized {
             return;
         }
 
-        // Use ProcReader with local cache - cache only benefits within this single call
-        // (multiple threads reading same parent /proc files)
-        let mut reader = ProcReader::new(pid_for_status);
-        if let Ok(metadata) = reader.metadata_bundle(process.pid) {
-            process.pid = metadata.pid;
-            process.tid = metadata.tid;
-            process.uid = Some(metadata.uid);
-            process.cmdline = Some(metadata.cmdline);
-            process.prio = Some(metadata.priority_str);
-
-            // Compute and cache user string from UID
-            process.user = Some(process.compute_user());
-
-            process.metadata_initialized = true;
+        if inline {
+            if let Some(metadata) = metadata::read(process.pid, process.tid) {
+                process.apply_metadata(metadata);
+            }
+            return;
         }
+        queue.request(process.pid, process.tid);
     }
 
     fn collect_thread_stats(
         thread: &mut ThreadInfo,
         taskstats_conn: &Arc<Mutex<TaskStatsConnection>>,
+        interval: f64,
     ) -> (u64, u64) {
         if let Ok(mut conn) = taskstats_conn.lock() {
             if let Ok(Some(stats)) = conn.get_task_stats(thread.tid) {
-                thread.update_stats(stats);
+                thread.sample(stats, interval);
                 let delta = &thread.stats_delta;
                 return (delta.read_bytes, delta.write_bytes);
             }
@@ -343,24 +1269,105 @@ impl ProcessList {
         (0, 0)
     }
 
-    fn should_monitor(&self, id: i32) -> bool {
+    fn collect_thread_io(thread: &mut ThreadInfo, interval: f64) {
+        match taskstats::read_thread_io(thread.tid) {
+            Some(stats) => thread.sample(stats, interval),
+            None => thread.forget(),
+        }
+    }
+
+    /// Sample a whole thread group, returning whether it could be
+    fn collect_group_stats(
+        group: &mut ThreadInfo,
+        taskstats_conn: &Arc<Mutex<TaskStatsConnection>>,
+        interval: f64,
+    ) -> bool {
+        if let Ok(mut conn) = taskstats_conn.lock() {
+            if let Ok(Some(stats)) = conn.get_tgid_stats(group.tid) {
+                group.sample(stats, interval);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether `-p` selects the task `id` (TID, or TGID in process mode) of
+    /// process `tgid`
+    fn should_monitor(&self, tgid: i32, id: i32) -> bool {
         if self.pids.is_empty() {
             true
         } else {
-            self.pids.contains(&id)
+            self.pids.contains(&id) || self.subtree.contains(&tgid)
         }
     }
 
-    fn should_monitor_uid(&self, process: &ProcessInfo) -> bool {
-        if self.uids.is_empty() {
-            true
-        } else if let Some(uid) = process.uid {
-            self.uids.contains(&uid)
-        } else {
-            // If we don't know the UID yet, allow it for now
-            // It will be filtered on next refresh after metadata is loaded
-            true
+    /// Find the processes in the subtrees of `pids` among `tgids`, the live
+    /// processes, and those that just `exited`
+    ///
+    /// Short-lived processes are only known from their exit records, which
+    /// carry their parent, so a compiler run by a shell run by `make` is
+    /// still placed under `make`.
+    fn update_subtree(&mut self, tgids: impl Iterator<Item = i32>, exited: &[ExitedTask]) {
+        let live: HashSet<i32> = tgids.collect();
+        self.parents.retain(|tgid, _| live.contains(tgid));
+        for &tgid in &live {
+            if let Entry::Vacant(entry) = self.parents.entry(tgid) {
+                if let Some(ppid) = proc_reader::parent(tgid) {
+                    entry.insert(ppid);
+                }
+            }
+        }
+
+        let mut parents = self.parents.clone();
+        for task in exited {
+            parents.entry(task.tgid).or_insert(task.ppid);
+        }
+        self.subtree = subtree(&self.pids, &parents);
+    }
+
+    /// Whether `uids` and `uid_range` let tasks of user `uid` be sampled
+    fn wants_uid(&self, uid: u32) -> bool {
+        (self.uids.is_empty() || self.uids.contains(&uid))
+            && self
+                .uid_range
+                .as_ref()
+                .is_none_or(|range| range.contains(&uid))
+    }
+
+    /// Whether `-u` and `--uid-range` let the task `id` (TID, or TGID in
+    /// process mode) be sampled, checked before a row is made for it so
+    /// that the tasks of other users cost no more than a stat; those are
+    /// kept in `excluded`
+    fn admit_uid(&mut self, id: i32) -> bool {
+        if self.uids.is_empty() && self.uid_range.is_none() {
+            return true;
+        }
+        // Not the UID cached in the row, which is only read once
+        let Ok(uid) = ProcReader::new(id).uid_fast() else {
+            // Already gone
+            return false;
+        };
+        if self.wants_uid(uid) {
+            return true;
+        }
+        // A row already there may have changed users by running a setuid
+        // program
+        self.processes.remove(&id);
+        self.excluded.insert(id);
+        false
+    }
+
+    /// Whether `kthreads` lets the task `id` be sampled, checked like
+    /// [`Self::admit_uid`] before a row is made for it
+    fn admit_kthread(&mut self, id: i32) -> bool {
+        if self.kthreads || self.processes.contains_key(&id) {
+            return true;
         }
+        if proc_reader::is_kernel_thread(id) {
+            self.excluded.insert(id);
+            return false;
+        }
+        true
     }
 
     pub fn refresh_processes(&mut self, show_processes: bool) -> Result<((u64, u64), (u64, u64))> {
@@ -371,8 +1378,21 @@ impl ProcessList {
         let mut total_read = 0u64;
         let mut total_write = 0u64;
 
+        let reread_prio = self.prio_changed.swap(false, Ordering::Relaxed);
+
+        // Threads first seen after the initial refresh started in between
+        let initial = self.prev_pgpgin.is_none();
+        let new_thread = move |tid| {
+            if initial {
+                ThreadInfo::new(tid)
+            } else {
+                ThreadInfo::started(tid)
+            }
+        };
+
         // Read vmstat for actual disk I/O
-        let (current_pgpgin, current_pgpgout) = self.read_vmstat().unwrap_or((0, 0));
+        let (current_pgpgin, current_pgpgout, swap_counters) =
+            self.read_vmstat().unwrap_or((0, 0, None));
         let actual_read = self
             .prev_pgpgin
             .map_or(0, |prev| current_pgpgin.saturating_sub(prev));
@@ -381,151 +1401,866 @@ impl ProcessList {
             .map_or(0, |prev| current_pgpgout.saturating_sub(prev));
         self.prev_pgpgin = Some(current_pgpgin);
         self.prev_pgpgout = Some(current_pgpgout);
+        self.devices = self.disks.sample().unwrap_or_default();
+        self.cgroups = self.cgroup_sampler.sample().unwrap_or_default();
+        self.throttled = cgroup::throttled_pids(&self.cgroups);
+        self.nfs_mounts = self.nfs_sampler.sample().unwrap_or_default();
+        self.pressure = pressure::read().ok();
+        self.dirty = meminfo::read().ok();
+        self.swap = self.swap_sampler.sample(swap_counters).ok().flatten();
+        self.iowait = self.cpu_sampler.sample().unwrap_or_default();
+        self.load = loadavg::read().ok();
+        (self.files, self.net) = self
+            .file_tracer
+            .as_ref()
+            .and_then(|tracer| tracer.lock().ok()?.sample().ok())
+            .unwrap_or_default();
+        self.device_io = self
+            .device_tracer
+            .as_ref()
+            .and_then(|tracer| tracer.lock().ok()?.sample().ok())
+            .unwrap_or_default();
+        self.syncs = self
+            .sync_tracer
+            .as_ref()
+            .and_then(|tracer| tracer.lock().ok()?.sample().ok())
+            .unwrap_or_default();
+        self.latency = self
+            .latency_tracer
+            .as_ref()
+            .and_then(|tracer| tracer.lock().ok()?.sample().ok())
+            .unwrap_or_default();
+
+        // Only threads sampled or finished below get a delta this time
+        for process in self.processes.values_mut() {
+            process.clear_delta();
+            process.uninterruptible = false;
+            process.stopped = false;
+        }
+        self.uninterruptible = 0;
+
+        // Threads that exited since the last refresh, group leaders first so
+        // rows made from them are named after the process
+        let mut exited = self
+            .exits
+            .as_ref()
+            .map(|exits| exits.drain())
+            .unwrap_or_default();
+        exited.sort_by_key(|task| task.tid != task.tgid);
+        let exited_tids: HashSet<i32> = exited.iter().map(|task| task.tid).collect();
+        // Threads sampled or finished in this interval
+        let mut seen: HashSet<i32> = HashSet::new();
+        // Processes whose thread group was sampled in this interval
+        let mut sampled: HashSet<i32> = HashSet::new();
+        if show_processes {
+            self.exit_ledger.record(&exited, |tgid| {
+                !Path::new(&format!("/proc/{}", tgid)).exists()
+            });
+        }
+
+        // Metadata of the rows queued at previous refreshes
+        for (tid, metadata) in self.metadata.take_ready() {
+            if let Some(process) = self.processes.get_mut(&tid) {
+                process.apply_metadata(metadata);
+            }
+        }
+        // The task filter decides whether to sample new tasks at all
+        let inline = initial || !self.filter.is_empty();
+
+        // Processes that replaced their program have a new command line
+        for tgid in self.tracker.iter().flat_map(|tracker| tracker.take_execs()) {
+            if let Some(process) = self.processes.get_mut(&tgid) {
+                process.metadata_initialized = false;
+            }
+            self.excluded.remove(&tgid);
+        }
 
         // When show_processes=true: List TGIDs, aggregate all threads per process
         // When show_processes=false (default): List all TIDs individually
         if show_processes {
             // Process mode (-P flag): Aggregate threads by TGID
-            let tgids: Vec<i32> = if !self.pids.is_empty() {
+            let mut threads_by_tgid: HashMap<i32, Vec<i32>> = HashMap::new();
+            if let Some(tracker) = &self.tracker {
+                for (tgid, tid) in tracker.tasks()? {
+                    threads_by_tgid.entry(tgid).or_default().push(tid);
+                }
+            }
+
+            let tgids: Vec<i32> = if !self.pids.is_empty() && !self.children {
                 // If PIDs specified, only monitor those
                 self.pids.clone()
             } else {
-                // Otherwise, scan all processes
-                fs::read_dir("/proc")?
-                    .flatten()
-                    .filter_map(|entry| {
-                        entry
-                            .file_name()
-                            .into_string()
-                            .ok()
-                            .and_then(|s| s.parse::<i32>().ok())
-                    })
-                    .collect()
+                let all: Vec<i32> = if self.tracker.is_some() {
+                    threads_by_tgid.keys().copied().collect()
+                } else {
+                    // Otherwise, scan all processes
+                    fs::read_dir("/proc")?
+                        .flatten()
+                        .filter_map(|entry| {
+                            entry
+                                .file_name()
+                                .into_string()
+                                .ok()
+                                .and_then(|s| s.parse::<i32>().ok())
+                        })
+                        .collect()
+                };
+                if self.pids.is_empty() {
+                    all
+                } else {
+                    self.update_subtree(all.iter().copied(), &exited);
+                    all.into_iter()
+                        .filter(|tgid| self.subtree.contains(tgid))
+                        .collect()
+                }
             };
 
-            for tgid in &tgids {
+            let live: HashSet<i32> = tgids.iter().copied().collect();
+            self.excluded.retain(|tgid| live.contains(tgid));
+            let mut tgids_to_process = Vec::new();
+            for &tgid in &tgids {
+                if self.excluded.contains(&tgid)
+                    || !self.admit_uid(tgid)
+                    || !self.admit_kthread(tgid)
+                {
+                    continue;
+                }
                 // Get or create process entry
                 let process = self
                     .processes
-                    .entry(*tgid)
-                    .or_insert_with(|| ProcessInfo::new(*tgid));
-                process.tid = *tgid;
-
-                // Update metadata first so we can check UID
-                Self::update_process_metadata(process, *tgid);
+                    .entry(tgid)
+                    .or_insert_with(|| ProcessInfo::new(tgid));
+                process.tid = tgid;
+
+                Self::update_process_metadata(process, &self.metadata, inline);
+                if self.filter.matches(process) {
+                    tgids_to_process.push(tgid);
+                } else {
+                    self.processes.remove(&tgid);
+                    self.excluded.insert(tgid);
+                }
             }
 
-            // Now filter by UID after metadata is loaded
-            let tgids_to_process: Vec<i32> = self
-                .processes
-                .iter()
-                .filter(|(tgid, process)| tgids.contains(tgid) && self.should_monitor_uid(process))
-                .map(|(tgid, _)| *tgid)
-                .collect();
-
             for tgid in tgids_to_process {
                 let process = self.processes.get_mut(&tgid).unwrap();
 
-                // Get all threads for this process
-                let task_dir = format!("/proc/{}/task", tgid);
-                let tids: Vec<i32> = fs::read_dir(task_dir)
-                    .ok()
-                    .map(|entries| {
-                        entries
-                            .flatten()
-                            .filter_map(|e| {
-                                e.file_name()
-                                    .into_string()
-                                    .ok()
-                                    .and_then(|s| s.parse::<i32>().ok())
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_else(|| vec![tgid]);
+                // One query for the whole group, which also covers threads
+                // that exited in between
+                let group = process.group.get_or_insert_with(|| new_thread(tgid));
+                let mut busy = false;
+                if Self::collect_group_stats(group, &self.taskstats_conn, self.duration) {
+                    self.exit_ledger
+                        .take(tgid, &mut group.stats_change, &mut group.stats_delta);
+                    sampled.insert(tgid);
+                    total_read += group.stats_delta.read_bytes;
+                    total_write += group.stats_delta.write_bytes;
+                    busy = group.stats_delta.read_bytes > 0 || group.stats_delta.write_bytes > 0;
+                }
 
+                let tids = threads_by_tgid
+                    .remove(&tgid)
+                    .unwrap_or_else(|| thread_ids(tgid));
+                // Bytes per thread only to find the hot threads of a process
+                // doing I/O
+                let per_thread = busy && tids.len() > 1;
                 for tid in tids {
+                    // Zombie, accounted from its exit record
+                    if exited_tids.contains(&tid) {
+                        continue;
+                    }
                     let thread = process
                         .threads
                         .entry(tid)
-                        .or_insert_with(|| ThreadInfo::new(tid));
-
-                    let (read, write) = Self::collect_thread_stats(thread, &self.taskstats_conn);
-                    total_read += read;
-                    total_write += write;
+                        .or_insert_with(|| new_thread(tid));
+                    thread.expiring = false;
+                    seen.insert(tid);
+                    if per_thread {
+                        Self::collect_thread_io(thread, self.duration);
+                    } else {
+                        thread.forget();
+                    }
+                    match proc_reader::thread_state(tid) {
+                        Some('D') => {
+                            process.uninterruptible = true;
+                            self.uninterruptible += 1;
+                        }
+                        // SIGSTOP stops every thread of the group
+                        Some('T') => process.stopped = true,
+                        _ => {}
+                    }
                 }
-
-                process.update_stats();
             }
         } else {
             // Thread mode (default): Each thread is a separate entry
-            for entry in fs::read_dir("/proc")?.flatten() {
-                if let Ok(file_name) = entry.file_name().into_string() {
-                    if let Ok(tgid) = file_name.parse::<i32>() {
-                        // For each TGID, enumerate all its threads
-                        let task_dir = format!("/proc/{}/task", tgid);
-                        if let Ok(task_entries) = fs::read_dir(task_dir) {
-                            for task_entry in task_entries.flatten() {
-                                if let Ok(tid_name) = task_entry.file_name().into_string() {
-                                    if let Ok(tid) = tid_name.parse::<i32>() {
-                                        // Filter by TID if PIDs specified
-                                        if !self.should_monitor(tid) {
-                                            continue;
-                                        }
-
-                                        // First, check if entry exists and update metadata
-                                        let should_skip = {
-                                            let process = self
-                                                .processes
-                                                .entry(tid)
-                                                .or_insert_with(|| ProcessInfo::new(tgid));
-                                            process.tid = tid;
-
-                                            // Update metadata first to get UID
-                                            Self::update_process_metadata(process, tid);
-
-                                            // Check if we should filter by UID
-                                            if self.uids.is_empty() {
-                                                false
-                                            } else if let Some(uid) = process.uid {
-                                                !self.uids.contains(&uid)
-                                            } else {
-                                                false
-                                            }
-                                        };
-
-                                        if should_skip {
-                                            continue;
-                                        }
-
-                                        // Now get mutable reference again for thread processing
-                                        let process = self.processes.get_mut(&tid).unwrap();
-
-                                        // Add just this one thread
-                                        let thread = process
-                                            .threads
-                                            .entry(tid)
-                                            .or_insert_with(|| ThreadInfo::new(tid));
-
-                                        let (read, write) = Self::collect_thread_stats(
-                                            thread,
-                                            &self.taskstats_conn,
-                                        );
-                                        total_read += read;
-                                        total_write += write;
-
-                                        process.update_stats();
-                                    }
-                                }
-                            }
+            let tasks = match &self.tracker {
+                Some(tracker) => tracker.tasks()?,
+                // Only the tasks `-p` selects, without walking /proc
+                None if !self.pids.is_empty() && !self.children => self
+                    .pids
+                    .iter()
+                    .filter_map(|&tid| Some((proc_reader::tgid(tid)?, tid)))
+                    .collect(),
+                None => connector::scan_tasks()?,
+            };
+            if self.children && !self.pids.is_empty() {
+                self.update_subtree(tasks.iter().map(|&(tgid, _)| tgid), &exited);
+            }
+            if !self.excluded.is_empty() {
+                let live: HashSet<i32> = tasks.iter().map(|&(_, tid)| tid).collect();
+                self.excluded.retain(|tid| live.contains(tid));
+            }
+            for (tgid, tid) in tasks {
+                // Filter by TID if PIDs specified
+                if !self.should_monitor(tgid, tid) || self.excluded.contains(&tid) {
+                    continue;
+                }
+                // Zombie, accounted from its exit record
+                if exited_tids.contains(&tid) || !self.admit_uid(tid) || !self.admit_kthread(tid) {
+                    continue;
+                }
+
+                let process = self
+                    .processes
+                    .entry(tid)
+                    .or_insert_with(|| ProcessInfo::new(tgid));
+                process.tid = tid;
+
+                Self::update_process_metadata(process, &self.metadata, inline);
+                if !self.filter.matches(process) {
+                    self.processes.remove(&tid);
+                    self.excluded.insert(tid);
+                    continue;
+                }
+
+                // Now get mutable reference again for thread processing
+                let process = self.processes.get_mut(&tid).unwrap();
+
+                // Add just this one thread
+                let thread = process
+                    .threads
+                    .entry(tid)
+                    .or_insert_with(|| new_thread(tid));
+                thread.expiring = false;
+                seen.insert(tid);
+
+                let (read, write) =
+                    Self::collect_thread_stats(thread, &self.taskstats_conn, self.duration);
+                total_read += read;
+                total_write += write;
+                let state = proc_reader::thread_state(tid);
+                process.uninterruptible = state == Some('D');
+                process.stopped = state == Some('T');
+                self.uninterruptible += usize::from(process.uninterruptible);
+            }
+        }
+
+        let (read, write) =
+            self.apply_exits(&exited, show_processes, &sampled, new_thread, &mut seen);
+        total_read += read;
+        total_write += write;
+
+        if !show_processes {
+            self.fold_exited_threads(&seen);
+        }
+
+        // Threads that vanished without an exit record are kept, idle, for
+        // one more refresh in case the record is still on its way. Rows go
+        // with their last thread.
+        self.processes.retain(|_, process| {
+            process.threads.retain(|tid, thread| {
+                if seen.contains(tid) {
+                    return true;
+                }
+                let keep = !thread.expiring;
+                thread.expiring = true;
+                keep
+            });
+            process.update_stats()
+        });
+        if initial && self.lifetime {
+            for process in self.processes.values_mut() {
+                process.seed_lifetime(show_processes);
+            }
+        }
+
+        let tids = self.processes.values().map(|process| process.tid).collect();
+        let prios = self.prio_cache.lookup(tids, reread_prio);
+        for process in self.processes.values_mut() {
+            if let Some(prio) = prios.get(&process.tid) {
+                process.prio = Some(prio.clone());
+            }
+        }
+
+        let dirtied: HashMap<i32, u64> = self
+            .processes
+            .iter()
+            .map(|(&id, process)| {
+                let stats = &process.stats_delta;
+                (
+                    id,
+                    stats
+                        .write_bytes
+                        .saturating_sub(stats.cancelled_write_bytes),
+                )
+            })
+            .collect();
+        let limit = self
+            .dirty
+            .map(|dirty| (dirty.dirty_kb + dirty.writeback_kb) * 1024);
+        self.writeback =
+            self.writeback_estimator
+                .estimate(&dirtied, actual_write, limit, self.duration);
+
+        Ok(((total_read, total_write), (actual_read, actual_write)))
+    }
+
+    /// Account for threads that exited since the previous refresh, using
+    /// their final statistics
+    ///
+    /// This closes the sampled threads and adds those that started and
+    /// exited between two refreshes, with a row of their own if their
+    /// process was never seen. In process mode, only the processes whose
+    /// thread group was not `sampled`, as it is gone, get their counters
+    /// from the records.
+    fn apply_exits(
+        &mut self,
+        exited: &[ExitedTask],
+        show_processes: bool,
+        sampled: &HashSet<i32>,
+        new_thread: impl Fn(i32) -> ThreadInfo,
+        seen: &mut HashSet<i32>,
+    ) -> (u64, u64) {
+        let mut total = (0, 0);
+        // Stats and /proc/[pid]/io bytes of the records of each process
+        let mut finished: HashMap<i32, (TaskStats, TaskStats)> = HashMap::new();
+        for task in exited {
+            let id = if show_processes { task.tgid } else { task.tid };
+            if !self.should_monitor(task.tgid, id)
+                || !self.wants_uid(task.uid)
+                // Kernel threads are all started by kthreadd
+                || (!self.kthreads && task.ppid == proc_reader::KTHREADD)
+                || self.excluded.contains(&id)
+            {
+                continue;
+            }
+            let process = match self.processes.entry(id) {
+                // Rows already there matched
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let mut process = ProcessInfo::from_exited(task, id);
+                    if !self.filter.is_empty() {
+                        process.inherit(task.ppid);
+                        if !self.filter.matches(&process) {
+                            continue;
                         }
                     }
+                    entry.insert(process)
+                }
+            };
+            let thread = process
+                .threads
+                .entry(task.tid)
+                .or_insert_with(|| new_thread(task.tid));
+            seen.insert(task.tid);
+            if show_processes {
+                // Counted by the row's thread group
+                thread.expiring = true;
+                thread.exited = true;
+                if !sampled.contains(&task.tgid) {
+                    let (stats, io) = finished.entry(task.tgid).or_default();
+                    stats.accumulate(&task.stats);
+                    io.accumulate(&task.io);
                 }
+                continue;
             }
+            thread.finish(task.stats);
+
+            total.0 += thread.stats_delta.read_bytes;
+            total.1 += thread.stats_delta.write_bytes;
+        }
+
+        for (tgid, (stats, io)) in finished {
+            let Some(process) = self.processes.get_mut(&tgid) else {
+                continue;
+            };
+            let group = process.group.get_or_insert_with(|| new_thread(tgid));
+            let (base, bytes) = match group.stats_total.filter(|_| group.sampled_at.is_some()) {
+                // Reaped before it could be sampled again: /proc/[pid]/io
+                // would have ended at what it counted apart from these
+                // threads, plus their bytes. Their delays since are unknown.
+                Some(last) => {
+                    let mut bytes = self.exit_ledger.settled(tgid);
+                    bytes.accumulate(&io);
+                    (last, bytes)
+                }
+                // Never sampled: its threads' records are all there is
+                None => (stats, io),
+            };
+            group.finish(TaskStats {
+                read_bytes: bytes.read_bytes,
+                write_bytes: bytes.write_bytes,
+                cancelled_write_bytes: bytes.cancelled_write_bytes,
+                read_char: bytes.read_char,
+                write_char: bytes.write_char,
+                ..base
+            });
+
+            total.0 += group.stats_delta.read_bytes;
+            total.1 += group.stats_delta.write_bytes;
         }
+        total
+    }
 
-        // Remove processes that no longer exist
-        self.processes.retain(|_, p| !p.threads.is_empty());
+    /// In thread mode, add the accumulated I/O of the rows of exited worker
+    /// threads, which go at this refresh, to the row of their process's main
+    /// thread, so `-a` keeps counting it
+    ///
+    /// In process mode the I/O of exited threads is already part of their
+    /// process's row.
+    fn fold_exited_threads(&mut self, seen: &HashSet<i32>) {
+        let folded: Vec<(i32, TaskStats)> = self
+            .processes
+            .values()
+            .filter(|row| row.tid != row.pid && !seen.contains(&row.tid))
+            .filter(|row| row.threads.get(&row.tid).is_some_and(|t| t.exited))
+            .map(|row| (row.pid, row.stats_accum))
+            .collect();
+        for (pid, accum) in folded {
+            if let Some(leader) = self.processes.get_mut(&pid) {
+                leader.stats_accum.accumulate(&accum);
+            }
+        }
+    }
+}
 
-        Ok(((total_read, total_write), (actual_read, actual_write)))
+/// TIDs of a process, or just the TGID if its task list is unreadable
+fn thread_ids(tgid: i32) -> Vec<i32> {
+    fs::read_dir(format!("/proc/{}/task", tgid))
+        .ok()
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_else(|| vec![tgid])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn snapshot(rows: &[(i32, u64)]) -> ProcessSnapshot {
+        let processes = rows
+            .iter()
+            .map(|&(pid, read_bytes)| {
+                let mut process = ProcessInfo::new(pid);
+                process.stats_delta.read_bytes = read_bytes;
+                (pid, process)
+            })
+            .collect();
+        let total: u64 = rows.iter().map(|(_, read)| read).sum();
+        ProcessSnapshot {
+            processes,
+            total_io: (total, 0),
+            actual_io: (total, 0),
+            duration: 1.0,
+            devices: Vec::new(),
+            cgroups: Vec::new(),
+            throttled: HashSet::new(),
+            nfs_mounts: Vec::new(),
+            pressure: None,
+            dirty: None,
+            swap: None,
+            iowait: Default::default(),
+            load: None,
+            uninterruptible: 0,
+            files: HashMap::new(),
+            net: HashMap::new(),
+            device_io: HashMap::new(),
+            syncs: HashMap::new(),
+            latency: HashMap::new(),
+            writeback: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_merge() {
+        let mut merged = snapshot(&[(1, 100), (2, 50)]);
+        merged.merge(snapshot(&[(1, 10), (3, 5)]));
+
+        assert_eq!(merged.duration, 2.0);
+        assert_eq!(merged.total_io, (165, 0));
+        assert_eq!(merged.processes[&1].stats_delta.read_bytes, 110);
+        // Exited during the interval, but its I/O is kept
+        assert_eq!(merged.processes[&2].stats_delta.read_bytes, 50);
+        assert_eq!(merged.processes[&3].stats_delta.read_bytes, 5);
+    }
+
+    #[test]
+    fn test_snapshot_merge_keeps_active() {
+        let with_thread = |read_bytes| {
+            let mut snapshot = snapshot(&[(1, read_bytes)]);
+            let process = snapshot.processes.get_mut(&1).unwrap();
+            let mut thread = ThreadInfo::new(1);
+            thread.stats_delta.read_bytes = read_bytes;
+            process.threads.insert(1, thread);
+            snapshot
+        };
+        // Busy in the first interval of a pause, idle in the second
+        let mut merged = with_thread(100);
+        merged.merge(with_thread(0));
+
+        let process = &merged.processes[&1];
+        assert_eq!(process.threads[&1].stats_delta.read_bytes, 100);
+        assert!(process.did_some_io(false));
+    }
+
+    #[test]
+    fn test_group_rows() {
+        // A pipeline started from a shell: `tar | gzip` in group 20
+        let mut processes = snapshot(&[(10, 1), (20, 100), (21, 50), (30, 7)]).processes;
+        for (pid, pgid, cmdline) in [(10, 10, "bash"), (20, 20, "tar"), (21, 20, "gzip")] {
+            let process = processes.get_mut(&pid).unwrap();
+            process.pgid = Some(pgid);
+            process.cmdline = Some(cmdline.to_string());
+        }
+
+        let groups = group_rows(processes, Grouping::ProcessGroup);
+        assert_eq!(groups.len(), 3);
+        let job = &groups[&20];
+        assert_eq!(job.stats_delta.read_bytes, 150);
+        assert_eq!(job.members, vec![20, 21]);
+        assert_eq!(job.get_cmdline(), "tar (+1)");
+        assert_eq!(groups[&10].get_cmdline(), "bash");
+        // Group not known yet
+        assert_eq!(groups[&30].members, vec![30]);
+    }
+
+    #[test]
+    fn test_convert_rows() {
+        // Process 10 with threads 10 and 11, and a process 20 whose main
+        // thread is gone
+        let mut rows = HashMap::new();
+        for (pid, tid, write_bytes) in [(10, 10, 100), (10, 11, 50), (20, 21, 7)] {
+            let mut row = ProcessInfo::new(pid);
+            row.tid = tid;
+            row.cmdline = Some(format!("task {}", tid));
+            row.metadata_initialized = true;
+            row.stats_accum.write_bytes = write_bytes;
+            let mut thread = ThreadInfo::new(tid);
+            thread.stats_total = Some(TaskStats {
+                write_bytes,
+                ..Default::default()
+            });
+            row.threads.insert(tid, thread);
+            rows.insert(tid, row);
+        }
+
+        let processes = convert_rows(rows, true);
+        assert_eq!(processes.len(), 2);
+        let process = &processes[&10];
+        assert_eq!(process.stats_accum.write_bytes, 150);
+        assert_eq!(process.get_cmdline(), "task 10");
+        assert!(process.metadata_initialized);
+        assert_eq!(process.threads[&11].stats_total.unwrap().write_bytes, 50);
+        assert_eq!(processes[&20].tid, 20);
+        assert!(!processes[&20].metadata_initialized);
+
+        let threads = convert_rows(processes, false);
+        assert_eq!(threads.len(), 3);
+        assert_eq!(threads[&10].stats_accum.write_bytes, 150);
+        assert_eq!(threads[&11].stats_accum.write_bytes, 0);
+        assert_eq!(threads[&11].pid, 10);
+        assert_eq!(threads[&11].threads.len(), 1);
+        assert!(threads[&11].threads[&11].stats_total.is_none());
+        assert!(!threads[&11].metadata_initialized);
+        assert_eq!(threads[&21].stats_accum.write_bytes, 7);
+    }
+
+    #[test]
+    fn test_subtree() {
+        // make (10) -> sh (11) -> cc (12), and an unrelated 20 -> 21
+        let parents = HashMap::from([(10, 1), (11, 10), (12, 11), (20, 1), (21, 20)]);
+        assert_eq!(subtree(&[10], &parents), HashSet::from([10, 11, 12]));
+        assert_eq!(subtree(&[11, 21], &parents), HashSet::from([11, 12, 21]));
+    }
+
+    #[test]
+    fn test_sample_normalizes_interval() {
+        let mut thread = ThreadInfo::new(10);
+        thread.sample(TaskStats::default(), 1.0);
+        // Sampled half a second ago, at the end of the previous pass
+        thread.sampled_at = Some(Instant::now() - Duration::from_millis(500));
+        thread.sample(
+            TaskStats {
+                read_bytes: 1000,
+                ..Default::default()
+            },
+            1.0,
+        );
+        assert_eq!(thread.stats_change.read_bytes, 1000);
+        assert!((1900..=2000).contains(&thread.stats_delta.read_bytes));
+
+        let mut process = ProcessInfo::new(10);
+        process.threads.insert(10, thread);
+        process.update_stats();
+        assert_eq!(process.stats_accum.read_bytes, 1000);
+    }
+
+    #[test]
+    fn test_io_rate() {
+        let mut process = ProcessInfo::new(10);
+        process.stats_delta.read_bytes = 100 << 10;
+        process.stats_delta.write_bytes = 150 << 10;
+        process.stats_delta.cancelled_write_bytes = 50 << 10;
+        assert_eq!(process.io_rate(2.0), f64::from(100 << 10));
+        assert_eq!(process.io_rate(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_prio_class() {
+        let mut process = ProcessInfo::new(10);
+        assert_eq!(process.prio_class(), None);
+        process.prio = Some("be/4".to_string());
+        assert_eq!(process.prio_class(), Some(IoprioClass::BestEffort));
+        process.prio = Some("idle".to_string());
+        assert_eq!(process.prio_class(), Some(IoprioClass::Idle));
+        process.prio = Some("?err".to_string());
+        assert_eq!(process.prio_class(), None);
+    }
+
+    #[test]
+    fn test_hot_threads() {
+        let mut process = ProcessInfo::new(10);
+        for (tid, write_bytes) in [(10, 0), (11, 700), (12, 200), (13, 100)] {
+            let mut thread = ThreadInfo::new(tid);
+            thread.stats_delta.write_bytes = write_bytes;
+            process.threads.insert(tid, thread);
+        }
+
+        let hot: Vec<(i32, f64)> = process
+            .hot_threads(0.15)
+            .into_iter()
+            .map(|(thread, share)| (thread.tid, share))
+            .collect();
+        assert_eq!(hot, vec![(11, 0.7), (12, 0.2)]);
+        assert!(process.hot_threads(0.7).is_empty());
+
+        process.threads.retain(|&tid, _| tid == 11);
+        assert!(process.hot_threads(0.15).is_empty());
+    }
+
+    #[test]
+    fn test_seed_lifetime() {
+        // The test binary has read at least itself and its libraries
+        let mut process = ProcessInfo::new(std::process::id() as i32);
+        process.seed_lifetime(true);
+        assert!(process.stats_accum.read_char > 0);
+    }
+
+    #[test]
+    fn test_apply_exits() {
+        let mut list = ProcessList::new(TaskStatsConnection::procfs());
+        let mut sampled = ProcessInfo::new(10);
+        let mut thread = ThreadInfo::new(10);
+        thread.update_stats(TaskStats {
+            write_bytes: 1000,
+            ..Default::default()
+        });
+        sampled.threads.insert(10, thread);
+        list.processes.insert(10, sampled);
+
+        let exit = |tid, write_bytes| ExitedTask {
+            tid,
+            tgid: tid,
+            ppid: 1,
+            uid: 0,
+            comm: "dd".to_string(),
+            stats: TaskStats {
+                write_bytes,
+                ..Default::default()
+            },
+            io: TaskStats::default(),
+        };
+        let mut seen = HashSet::new();
+        let total = list.apply_exits(
+            &[exit(10, 1500), exit(11, 700)],
+            false,
+            &HashSet::new(),
+            ThreadInfo::started,
+            &mut seen,
+        );
+
+        assert_eq!(total, (0, 1200));
+        assert_eq!(seen, HashSet::from([10, 11]));
+        // Last interval of a sampled thread
+        assert_eq!(
+            list.processes[&10].threads[&10].stats_delta.write_bytes,
+            500
+        );
+        // Started and exited between two refreshes
+        assert_eq!(
+            list.processes[&11].threads[&11].stats_delta.write_bytes,
+            700
+        );
+        assert_eq!(list.processes[&11].get_cmdline(), "dd");
+    }
+
+    #[test]
+    fn test_apply_exits_processes() {
+        let mut list = ProcessList::new(TaskStatsConnection::procfs());
+        let exit = |tid, tgid, write_bytes| {
+            let io = TaskStats {
+                write_bytes,
+                ..Default::default()
+            };
+            ExitedTask {
+                tid,
+                tgid,
+                ppid: 1,
+                uid: 0,
+                comm: "make".to_string(),
+                stats: io,
+                io,
+            }
+        };
+        // A worker of 10 exits while 10 is still sampled, at 1000 bytes
+        list.exit_ledger.record(&[exit(11, 10, 300)], |_| false);
+        for tgid in [10, 20] {
+            let mut process = ProcessInfo::new(tgid);
+            let mut group = ThreadInfo::new(tgid);
+            group.sample(
+                TaskStats {
+                    write_bytes: 1000,
+                    ..Default::default()
+                },
+                1.0,
+            );
+            process.group = Some(group);
+            process.threads.insert(tgid, ThreadInfo::new(tgid));
+            list.processes.insert(tgid, process);
+        }
+
+        // 10 is reaped before the next sample, 20 was sampled, 30 is new
+        let exited = [exit(10, 10, 900), exit(21, 20, 50), exit(30, 30, 400)];
+        list.exit_ledger.record(&exited, |_| false);
+        let mut seen = HashSet::new();
+        let total = list.apply_exits(
+            &exited,
+            true,
+            &HashSet::from([20]),
+            ThreadInfo::started,
+            &mut seen,
+        );
+
+        assert_eq!(total, (0, 600));
+        assert_eq!(seen, HashSet::from([10, 21, 30]));
+        let group = |tgid: i32| list.processes[&tgid].group.as_ref().unwrap();
+        assert_eq!(group(10).stats_delta.write_bytes, 200);
+        assert_eq!(group(20).stats_delta.write_bytes, 0);
+        assert_eq!(group(30).stats_delta.write_bytes, 400);
+        assert!(list.processes[&20].threads[&21].exited);
+    }
+
+    #[test]
+    fn test_apply_exits_filtered() {
+        let filter = TaskFilter {
+            command: Some(regex::Regex::new(r"^dd\b").unwrap()),
+            ..Default::default()
+        };
+        let mut list = ProcessList::new(TaskStatsConnection::procfs()).with_filter(filter);
+        list.excluded.insert(12);
+        let exit = |tid, comm: &str| ExitedTask {
+            tid,
+            tgid: tid,
+            ppid: 1,
+            uid: 0,
+            comm: comm.to_string(),
+            stats: TaskStats::default(),
+            io: TaskStats::default(),
+        };
+        let mut seen = HashSet::new();
+        list.apply_exits(
+            &[exit(11, "dd"), exit(12, "dd"), exit(13, "cp")],
+            false,
+            &HashSet::new(),
+            ThreadInfo::started,
+            &mut seen,
+        );
+        assert_eq!(seen, HashSet::from([11]));
+    }
+
+    #[test]
+    fn test_admit_uid() {
+        let own = std::process::id() as i32;
+        let uid = nix::unistd::getuid().as_raw();
+        let mut list = ProcessList::new(TaskStatsConnection::procfs()).with_uids(vec![uid]);
+        assert!(list.admit_uid(own));
+
+        list.uids = vec![uid + 1];
+        assert!(!list.admit_uid(own));
+        assert!(list.excluded.contains(&own));
+        list.uids.clear();
+        list.excluded.clear();
+        list.uid_range = Some(uid..=uid);
+        assert!(list.admit_uid(own));
+        list.uid_range = Some(uid + 1..=u32::MAX);
+        assert!(!list.admit_uid(own));
+        // A row cached under another user is dropped once the task is not
+        // theirs
+        list.excluded.clear();
+        list.uid_range = None;
+        list.uids = vec![uid + 1];
+        let mut process = ProcessInfo::new(own);
+        process.uid = Some(uid + 1);
+        list.processes.insert(own, process);
+        assert!(!list.admit_uid(own));
+        assert!(!list.processes.contains_key(&own));
+        // Gone, so not remembered
+        assert!(!list.admit_uid(i32::MAX));
+        assert!(!list.excluded.contains(&i32::MAX));
+    }
+
+    #[test]
+    fn test_fold_exited_threads() {
+        let mut list = ProcessList::new(TaskStatsConnection::procfs());
+        for tid in [10, 11] {
+            let mut row = ProcessInfo::new(10);
+            row.tid = tid;
+            row.threads.insert(tid, ThreadInfo::started(tid));
+            list.processes.insert(tid, row);
+        }
+        let worker = ExitedTask {
+            tid: 11,
+            tgid: 10,
+            ppid: 1,
+            uid: 0,
+            comm: "worker".to_string(),
+            stats: TaskStats {
+                read_bytes: 300,
+                ..Default::default()
+            },
+            io: TaskStats::default(),
+        };
+        let mut seen = HashSet::from([10]);
+        list.apply_exits(
+            &[worker],
+            false,
+            &HashSet::new(),
+            ThreadInfo::started,
+            &mut seen,
+        );
+        for row in list.processes.values_mut() {
+            row.update_stats();
+        }
+
+        // Its row is still shown in the interval it exited in
+        list.fold_exited_threads(&seen);
+        assert_eq!(list.processes[&10].stats_accum.read_bytes, 0);
+
+        list.fold_exited_threads(&HashSet::from([10]));
+        assert_eq!(list.processes[&10].stats_accum.read_bytes, 300);
     }
 }
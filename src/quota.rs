@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Context, Result};
+use nix::unistd::User;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::alerts;
+use crate::presets;
+use crate::process::ProcessInfo;
+use crate::state_file;
+
+/// Disk bandwidth each user may use, in bytes read and written per second
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Budgets {
+    /// Budget of the users without one of their own
+    pub default: Option<u64>,
+    pub users: HashMap<u32, u64>,
+}
+
+impl Budgets {
+    pub fn get(&self, uid: u32) -> Option<u64> {
+        self.users.get(&uid).copied().or(self.default)
+    }
+
+    /// I/O of each user with rows in `processes` over an interval of
+    /// `duration` seconds, users over budget first, then busiest first
+    pub fn usage<'a>(
+        &self,
+        processes: impl IntoIterator<Item = &'a ProcessInfo>,
+        duration: f64,
+    ) -> Vec<UserUsage> {
+        let mut users: HashMap<u32, UserUsage> = HashMap::new();
+        for process in processes {
+            let Some(uid) = process.uid else {
+                continue;
+            };
+            let usage = users.entry(uid).or_insert_with(|| UserUsage {
+                uid,
+                user: process.get_user().to_string(),
+                rate: 0,
+                budget: self.get(uid),
+            });
+            usage.rate += process.io_rate(duration) as u64;
+        }
+
+        let mut usage: Vec<UserUsage> = users.into_values().filter(|u| u.rate > 0).collect();
+        usage.sort_by(|a, b| {
+            b.is_over()
+                .cmp(&a.is_over())
+                .then(b.rate.cmp(&a.rate))
+                .then(a.uid.cmp(&b.uid))
+        });
+        usage
+    }
+}
+
+/// A user's disk I/O over the last interval against their budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserUsage {
+    pub uid: u32,
+    pub user: String,
+    /// Bytes read and written per second
+    pub rate: u64,
+    pub budget: Option<u64>,
+}
+
+impl UserUsage {
+    pub fn is_over(&self) -> bool {
+        self.budget.is_some_and(|budget| self.rate > budget)
+    }
+
+    /// Share of the budget used, in percent
+    pub fn percent(&self) -> Option<f64> {
+        let budget = self.budget.filter(|&budget| budget > 0)?;
+        Some(self.rate as f64 / budget as f64 * 100.0)
+    }
+}
+
+/// Location of the budgets, `$XDG_CONFIG_HOME/iotop/quotas.toml` or
+/// `~/.config/iotop/quotas.toml`
+pub fn path() -> Option<PathBuf> {
+    Some(state_file::config_dir()?.join("quotas.toml"))
+}
+
+/// Read the budgets file, which `--quotas` needs
+pub fn load() -> Result<Budgets> {
+    let path = path().ok_or_else(|| anyhow!("No configuration directory for quotas.toml"))?;
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read the quotas in {}", path.display()))?;
+    parse(&content).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// Parse budgets from `user = "rate"` lines, with users given by name or
+/// UID and `default` for everyone else, e.g. `alice = "200M"`
+fn parse(content: &str) -> Result<Budgets, String> {
+    let mut budgets = Budgets::default();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", index + 1, e);
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected user = rate".to_string()))?;
+        let key = presets::parse_name(key).map_err(error)?;
+        let (value, rest) = presets::parse_value(value).map_err(error)?;
+        if !rest.trim().is_empty() {
+            return Err(error(format!("unexpected '{}'", rest.trim())));
+        }
+        let budget = alerts::parse_rate(&value).map_err(error)?;
+
+        if key == "default" {
+            budgets.default = Some(budget);
+            continue;
+        }
+        let uid = match key.parse::<u32>() {
+            Ok(uid) => uid,
+            Err(_) => match User::from_name(&key) {
+                Ok(Some(user)) => user.uid.as_raw(),
+                _ => return Err(error(format!("unknown user '{}'", key))),
+            },
+        };
+        budgets.users.insert(uid, budget);
+    }
+    Ok(budgets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(tid: i32, uid: u32, write_bytes: u64) -> ProcessInfo {
+        let mut process = ProcessInfo::new(tid);
+        process.uid = Some(uid);
+        process.user = Some(uid.to_string());
+        process.stats_delta.write_bytes = write_bytes;
+        process
+    }
+
+    #[test]
+    fn test_parse() {
+        let budgets = parse(
+            "# Read plus write per second\n\
+             default = \"50M\"\n\
+             root = \"1G\"\n\
+             1000 = 200M\n",
+        )
+        .unwrap();
+        assert_eq!(budgets.default, Some(50 << 20));
+        assert_eq!(budgets.get(0), Some(1 << 30));
+        assert_eq!(budgets.get(1000), Some(200 << 20));
+        assert_eq!(budgets.get(1001), Some(50 << 20));
+
+        assert!(parse("nosuchuser-iotop = \"1M\"").is_err());
+        assert!(parse("1000 = \"fast\"").is_err());
+        assert!(parse("1000").is_err());
+    }
+
+    #[test]
+    fn test_usage() {
+        let budgets = parse("default = \"10M\"\n1000 = \"40M\"").unwrap();
+        let processes = [
+            process(10, 1000, 30 << 20),
+            process(11, 1000, 20 << 20),
+            process(12, 1001, 12 << 20),
+            process(13, 1002, 80 << 20),
+            process(14, 1003, 0),
+        ];
+        let usage = budgets.usage(&processes, 2.0);
+        let rows: Vec<(u32, u64, bool)> = usage
+            .iter()
+            .map(|u| (u.uid, u.rate >> 20, u.is_over()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![(1002, 40, true), (1000, 25, false), (1001, 6, false)]
+        );
+        assert_eq!(usage[0].percent(), Some(400.0));
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::columns::ExtraColumn;
+use crate::history::AverageWindow;
+use crate::theme::Theme;
+use crate::ui::{SortColumn, UIState};
+
+/// Directory of iotop's files, `$XDG_CONFIG_HOME/iotop` or `~/.config/iotop`
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("iotop"))
+}
+
+/// Location of the saved TUI state, `$XDG_CONFIG_HOME/iotop/iotoprc` or
+/// `~/.config/iotop/iotoprc`
+pub fn path() -> Option<PathBuf> {
+    Some(config_dir()?.join("iotoprc"))
+}
+
+/// Restore the state saved by a previous session, if any
+pub fn load(state: &mut UIState) {
+    if let Some(content) = path().and_then(|path| fs::read_to_string(path).ok()) {
+        apply(state, &content);
+    }
+}
+
+/// Save the persistent parts of `state` for the next session
+pub fn save(state: &UIState) -> Result<()> {
+    let path = path().context("Neither XDG_CONFIG_HOME nor HOME is set")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, serialize(state))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Format as `key=value` lines, like htoprc
+fn serialize(state: &UIState) -> String {
+    let columns: Vec<&str> = state
+        .extra_columns
+        .iter()
+        .filter(|column| !state.implied_columns.contains(column))
+        .map(|c| c.name())
+        .collect();
+    let settings = [
+        ("sort_column", state.sort_column.as_str().to_string()),
+        ("sort_reverse", state.sort_reverse.to_string()),
+        ("only_active", state.only_active.to_string()),
+        ("accumulated", state.accumulated.to_string()),
+        ("show_processes", state.show_processes.to_string()),
+        ("delay_ms", state.delay_ms.to_string()),
+        ("average_window", state.average_window.label().to_string()),
+        ("show_devices", state.show_devices.to_string()),
+        ("show_cgroups", state.show_cgroups.to_string()),
+        ("show_cpus", state.show_cpus.to_string()),
+        ("theme", state.theme.name().to_string()),
+        ("columns", columns.join(",")),
+    ];
+
+    let mut content = String::from("# iotop state, rewritten on exit\n");
+    for (key, value) in settings {
+        content.push_str(&format!("{}={}\n", key, value));
+    }
+    content
+}
+
+/// Apply saved settings to `state`
+///
+/// Unknown keys and unparsable values are skipped, so files written by older
+/// or newer versions still load.
+fn apply(state: &mut UIState, content: &str) {
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let flag = value.parse::<bool>().ok();
+
+        match key.trim() {
+            "sort_column" => {
+                if let Some(column) = SortColumn::from_name(value) {
+                    state.sort_column = column;
+                }
+            }
+            "sort_reverse" => state.sort_reverse = flag.unwrap_or(state.sort_reverse),
+            "only_active" => state.only_active = flag.unwrap_or(state.only_active),
+            "accumulated" => state.accumulated = flag.unwrap_or(state.accumulated),
+            "show_processes" => state.show_processes = flag.unwrap_or(state.show_processes),
+            "delay_ms" => state.delay_ms = flag.unwrap_or(state.delay_ms),
+            "average_window" => {
+                if let Some(window) = AverageWindow::from_label(value) {
+                    state.average_window = window;
+                }
+            }
+            "show_devices" => state.show_devices = flag.unwrap_or(state.show_devices),
+            "show_cgroups" => state.show_cgroups = flag.unwrap_or(state.show_cgroups),
+            "show_cpus" => state.show_cpus = flag.unwrap_or(state.show_cpus),
+            "theme" => {
+                if let Some(theme) = Theme::from_name(value) {
+                    state.theme = theme;
+                }
+            }
+            "columns" => {
+                if let Ok(columns) = ExtraColumn::parse_list(value) {
+                    state.extra_columns = columns;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut state = UIState::default();
+        state.sort_column = SortColumn::Write;
+        state.sort_reverse = false;
+        state.only_active = true;
+        state.average_window = AverageWindow::Secs30;
+        state.theme = Theme::Light;
+        // Added by --net for this session only
+        state.extra_columns = vec![ExtraColumn::NetRead, ExtraColumn::Graph];
+        state.implied_columns = vec![ExtraColumn::NetRead];
+
+        let mut restored = UIState::default();
+        apply(&mut restored, &serialize(&state));
+        assert_eq!(restored.sort_column, SortColumn::Write);
+        assert!(!restored.sort_reverse);
+        assert!(restored.only_active);
+        assert!(!restored.accumulated);
+        assert_eq!(restored.average_window, AverageWindow::Secs30);
+        assert_eq!(restored.theme, Theme::Light);
+        assert_eq!(restored.extra_columns, vec![ExtraColumn::Graph]);
+    }
+
+    #[test]
+    fn test_apply_ignores_bad_lines() {
+        let mut state = UIState::default();
+        apply(
+            &mut state,
+            "# comment\nsort_column=nope\nonly_active=maybe\ntheme=sepia\ncolumns=graph\n",
+        );
+        assert_eq!(state.sort_column, SortColumn::Pid);
+        assert!(!state.only_active);
+        assert_eq!(state.theme, Theme::Dark);
+        assert_eq!(state.extra_columns, vec![ExtraColumn::Graph]);
+    }
+}
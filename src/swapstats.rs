@@ -0,0 +1,270 @@
+use anyhow::Result;
+use std::fs;
+use std::time::Instant;
+
+/// Cumulative swap counters from /proc/vmstat, in pages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwapCounters {
+    /// Pages read from and written to swap devices, zram included
+    pub swap_in: u64,
+    pub swap_out: u64,
+    /// Pages zswap decompressed and compressed instead; `None` on kernels
+    /// without the counters
+    pub zswap_in: Option<u64>,
+    pub zswap_out: Option<u64>,
+}
+
+/// Parse /proc/vmstat content
+///
+/// Returns `None` unless both the `pswpin` and the `pswpout` line are
+/// present.
+pub fn parse_vmstat(content: &str) -> Option<SwapCounters> {
+    let mut swap_in = None;
+    let mut swap_out = None;
+    let mut zswap_in = None;
+    let mut zswap_out = None;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(key), Some(value)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let value = value.parse().ok();
+        match key {
+            "pswpin" => swap_in = value,
+            "pswpout" => swap_out = value,
+            "zswpin" => zswap_in = value,
+            "zswpout" => zswap_out = value,
+            _ => {}
+        }
+    }
+    Some(SwapCounters {
+        swap_in: swap_in?,
+        swap_out: swap_out?,
+        zswap_in,
+        zswap_out,
+    })
+}
+
+/// Swap areas in use, from /proc/swaps content
+pub fn parse_swaps(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|file| file.replace("\\040", " "))
+        .collect()
+}
+
+/// Memory used by a zram swap device, from /sys/block/zramN/mm_stat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZramUsage {
+    pub name: String,
+    /// Uncompressed size of the data stored, in bytes
+    pub data_bytes: u64,
+    /// Memory taken to store it, allocator overhead included, in bytes
+    pub memory_bytes: u64,
+}
+
+impl ZramUsage {
+    /// How many times smaller the data is in memory; `None` while empty
+    pub fn ratio(&self) -> Option<f64> {
+        (self.memory_bytes > 0).then(|| self.data_bytes as f64 / self.memory_bytes as f64)
+    }
+}
+
+/// Parse the content of a zram device's mm_stat
+///
+/// The fields are the original data size, the compressed data size and the
+/// total memory used, followed by others iotop ignores.
+pub fn parse_mm_stat(name: &str, content: &str) -> Option<ZramUsage> {
+    let fields: Vec<u64> = content
+        .split_whitespace()
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+    Some(ZramUsage {
+        name: name.to_string(),
+        data_bytes: *fields.first()?,
+        memory_bytes: *fields.get(2)?,
+    })
+}
+
+/// Swap traffic over the last interval
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SwapRate {
+    pub in_bytes_per_sec: f64,
+    pub out_bytes_per_sec: f64,
+    /// Pages served from and stored in zswap's compressed pool, which never
+    /// reach the swap device; `None` when the kernel does not count them
+    pub zswap_in_bytes_per_sec: Option<f64>,
+    pub zswap_out_bytes_per_sec: Option<f64>,
+    /// zram swap devices at the end of the interval
+    pub zram: Vec<ZramUsage>,
+}
+
+impl SwapRate {
+    fn from_delta(
+        prev: &SwapCounters,
+        curr: &SwapCounters,
+        secs: f64,
+        page_size: u64,
+        zram: Vec<ZramUsage>,
+    ) -> Self {
+        let rate =
+            |curr: u64, prev: u64| curr.saturating_sub(prev) as f64 * page_size as f64 / secs;
+        let zswap = |curr: Option<u64>, prev: Option<u64>| Some(rate(curr?, prev?));
+        Self {
+            in_bytes_per_sec: rate(curr.swap_in, prev.swap_in),
+            out_bytes_per_sec: rate(curr.swap_out, prev.swap_out),
+            zswap_in_bytes_per_sec: zswap(curr.zswap_in, prev.zswap_in),
+            zswap_out_bytes_per_sec: zswap(curr.zswap_out, prev.zswap_out),
+            zram,
+        }
+    }
+}
+
+/// Combine the swap rates of two consecutive intervals into rates over both,
+/// weighting each by its length; zram usage comes from the newer one
+pub fn merge_rates(
+    older: Option<&SwapRate>,
+    older_secs: f64,
+    newer: Option<&SwapRate>,
+    newer_secs: f64,
+) -> Option<SwapRate> {
+    let secs = older_secs + newer_secs;
+    let (Some(older), Some(newer)) = (older, newer) else {
+        return newer.cloned();
+    };
+    if secs <= 0.0 {
+        return Some(newer.clone());
+    }
+    let weigh = |older: f64, newer: f64| (older * older_secs + newer * newer_secs) / secs;
+    let weigh_zswap = |older: Option<f64>, newer: Option<f64>| Some(weigh(older?, newer?));
+    Some(SwapRate {
+        in_bytes_per_sec: weigh(older.in_bytes_per_sec, newer.in_bytes_per_sec),
+        out_bytes_per_sec: weigh(older.out_bytes_per_sec, newer.out_bytes_per_sec),
+        zswap_in_bytes_per_sec: weigh_zswap(
+            older.zswap_in_bytes_per_sec,
+            newer.zswap_in_bytes_per_sec,
+        ),
+        zswap_out_bytes_per_sec: weigh_zswap(
+            older.zswap_out_bytes_per_sec,
+            newer.zswap_out_bytes_per_sec,
+        ),
+        zram: newer.zram.clone(),
+    })
+}
+
+/// Turns consecutive samples of the swap counters of /proc/vmstat into rates
+#[derive(Default)]
+pub struct SwapSampler {
+    prev: Option<(Instant, SwapCounters)>,
+}
+
+impl SwapSampler {
+    /// Return the swap rates since the previous call, given the counters
+    /// just read from /proc/vmstat
+    ///
+    /// Returns `None` on the first call and while no swap area is in use.
+    pub fn sample(&mut self, current: Option<SwapCounters>) -> Result<Option<SwapRate>> {
+        let now = Instant::now();
+        let Some(current) = current else {
+            return Ok(None);
+        };
+        let prev = self.prev.replace((now, current));
+
+        let swaps = parse_swaps(&fs::read_to_string("/proc/swaps")?);
+        let Some((prev_at, prev)) = prev.filter(|_| !swaps.is_empty()) else {
+            return Ok(None);
+        };
+        let secs = now.duration_since(prev_at).as_secs_f64();
+        if secs <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(SwapRate::from_delta(
+            &prev,
+            &current,
+            secs,
+            page_size(),
+            zram_usage(&swaps),
+        )))
+    }
+}
+
+/// Memory used by the zram devices among the swap areas
+fn zram_usage(swaps: &[String]) -> Vec<ZramUsage> {
+    swaps
+        .iter()
+        .filter_map(|file| {
+            let name = file.strip_prefix("/dev/")?;
+            if !name.starts_with("zram") {
+                return None;
+            }
+            let content = fs::read_to_string(format!("/sys/block/{}/mm_stat", name)).ok()?;
+            parse_mm_stat(name, &content)
+        })
+        .collect()
+}
+
+fn page_size() -> u64 {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size > 0 {
+        page_size as u64
+    } else {
+        4096
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let vmstat = "pgpgin 100\npswpin 10\npswpout 30\nzswpin 4\nzswpout 8\n";
+        let counters = parse_vmstat(vmstat).unwrap();
+        assert_eq!(counters.swap_out, 30);
+        assert_eq!(counters.zswap_in, Some(4));
+        assert_eq!(
+            parse_vmstat("pswpin 1\npswpout 2\n").unwrap().zswap_out,
+            None
+        );
+        assert_eq!(parse_vmstat("pgpgin 1\n"), None);
+
+        let swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                     /dev/zram0                              partition\t4194300\t\t1024\t\t100\n\
+                     /swap\\040file                          file\t\t1048572\t\t0\t\t-2\n";
+        assert_eq!(parse_swaps(swaps), vec!["/dev/zram0", "/swap file"]);
+
+        let zram = parse_mm_stat("zram0", "  8192000  2048000  2457600 0  2457600 12 0 0 0\n");
+        assert_eq!(zram.as_ref().map(|z| z.memory_bytes), Some(2457600));
+        assert_eq!(zram.and_then(|z| z.ratio()), Some(8192000.0 / 2457600.0));
+    }
+
+    #[test]
+    fn test_rates() {
+        let prev = SwapCounters {
+            swap_in: 0,
+            swap_out: 0,
+            zswap_in: Some(0),
+            zswap_out: None,
+        };
+        let curr = SwapCounters {
+            swap_in: 4,
+            swap_out: 8,
+            zswap_in: Some(2),
+            zswap_out: None,
+        };
+        let rate = SwapRate::from_delta(&prev, &curr, 2.0, 4096, Vec::new());
+        assert_eq!(rate.in_bytes_per_sec, 8192.0);
+        assert_eq!(rate.out_bytes_per_sec, 16384.0);
+        assert_eq!(rate.zswap_in_bytes_per_sec, Some(4096.0));
+        assert_eq!(rate.zswap_out_bytes_per_sec, None);
+
+        // One busy second and one idle second: half the rate
+        let idle = SwapRate::from_delta(&curr, &curr, 1.0, 4096, Vec::new());
+        let merged = merge_rates(Some(&rate), 1.0, Some(&idle), 1.0).unwrap();
+        assert_eq!(merged.out_bytes_per_sec, 8192.0);
+        assert_eq!(merged.zswap_in_bytes_per_sec, Some(2048.0));
+        assert_eq!(merge_rates(Some(&rate), 1.0, None, 1.0), None);
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+#[cfg(feature = "ebpf")]
+use crate::bpf::SyncProbes;
+
+#[cfg(not(feature = "ebpf"))]
+use unsupported::SyncProbes;
+
+/// `fsync` and `fdatasync` calls of one process
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SyncIo {
+    pub calls_per_sec: f64,
+    /// Calls made since tracing started
+    pub calls: u64,
+}
+
+/// Sync calls by process ID
+pub type SyncRates = HashMap<i32, SyncIo>;
+
+/// Running count of one process's sync calls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncTotal {
+    pub tgid: u32,
+    pub calls: u64,
+}
+
+/// Counts the `fsync` and `fdatasync` calls of every process with an eBPF
+/// program (only with the `ebpf` cargo feature)
+///
+/// A database syncing after every commit can keep a disk busy flushing
+/// caches while moving few bytes, which the byte counters hide.
+pub struct SyncTracer {
+    probes: SyncProbes,
+    prev: HashMap<u32, u64>,
+    prev_at: Instant,
+}
+
+impl SyncTracer {
+    pub fn start() -> Result<Self> {
+        Ok(Self {
+            probes: SyncProbes::attach()?,
+            prev: HashMap::new(),
+            prev_at: Instant::now(),
+        })
+    }
+
+    /// Call rates of each process since the previous call, plus totals
+    pub fn sample(&mut self) -> Result<SyncRates> {
+        let totals = self.probes.totals()?;
+        let now = Instant::now();
+        let secs = now.duration_since(self.prev_at).as_secs_f64();
+        self.prev_at = now;
+
+        let rates = account(&mut self.prev, &totals, secs);
+        for total in &totals {
+            if !Path::new(&format!("/proc/{}", total.tgid)).exists() {
+                self.probes.forget(total);
+                self.prev.remove(&total.tgid);
+            }
+        }
+        Ok(rates)
+    }
+}
+
+/// Turn running totals into per-process rates over `secs`, against the
+/// totals seen last time (`prev`)
+fn account(prev: &mut HashMap<u32, u64>, totals: &[SyncTotal], secs: f64) -> SyncRates {
+    totals
+        .iter()
+        .map(|total| {
+            let last = prev.insert(total.tgid, total.calls);
+            let calls = total.calls.saturating_sub(last.unwrap_or_default());
+            let sync = SyncIo {
+                calls_per_sec: if secs > 0.0 { calls as f64 / secs } else { 0.0 },
+                calls: total.calls,
+            };
+            (total.tgid as i32, sync)
+        })
+        .collect()
+}
+
+/// Combine the sync rates of two consecutive intervals into rates over both,
+/// weighting each by its length; totals come from the newer one
+pub fn merge_rates(
+    older: &SyncRates,
+    older_secs: f64,
+    newer: &SyncRates,
+    newer_secs: f64,
+) -> SyncRates {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.clone();
+    }
+
+    let mut merged = newer.clone();
+    for sync in merged.values_mut() {
+        sync.calls_per_sec *= newer_secs / secs;
+    }
+    for (&tgid, older) in older {
+        let sync = merged.entry(tgid).or_insert(SyncIo {
+            calls: older.calls,
+            ..Default::default()
+        });
+        sync.calls_per_sec += older.calls_per_sec * older_secs / secs;
+    }
+    merged
+}
+
+#[cfg(not(feature = "ebpf"))]
+mod unsupported {
+    use super::SyncTotal;
+    use anyhow::{bail, Result};
+
+    /// Stand-in for builds without the `ebpf` feature
+    pub struct SyncProbes;
+
+    impl SyncProbes {
+        pub fn attach() -> Result<Self> {
+            bail!("iotop was built without eBPF support; rebuild it with `--features ebpf`")
+        }
+
+        pub fn totals(&self) -> Result<Vec<SyncTotal>> {
+            Ok(Vec::new())
+        }
+
+        pub fn forget(&self, _total: &SyncTotal) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account() {
+        let total = |tgid, calls| SyncTotal { tgid, calls };
+        let mut prev = HashMap::new();
+        account(&mut prev, &[total(10, 40)], 1.0);
+
+        let rates = account(&mut prev, &[total(10, 100), total(11, 3)], 2.0);
+        assert_eq!(
+            rates[&10],
+            SyncIo {
+                calls_per_sec: 30.0,
+                calls: 100
+            }
+        );
+        assert_eq!(rates[&11].calls_per_sec, 1.5);
+
+        let idle = account(&mut prev, &[total(10, 100)], 1.0);
+        let merged = merge_rates(&rates, 2.0, &idle, 2.0);
+        assert_eq!(merged[&10].calls_per_sec, 15.0);
+        assert_eq!(merged[&10].calls, 100);
+        assert_eq!(merged[&11].calls, 3);
+    }
+}
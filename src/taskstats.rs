@@ -1,16 +1,80 @@
 use anyhow::{Context, Result};
 use linux_taskstats::{Client, TaskStats as KernelTaskStats};
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+
+use crate::proc_reader::{self, ProcStat};
 
 // Our TaskStats structure that contains the fields we care about
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct TaskStats {
+    /// Version of the kernel's `struct taskstats`; 0 when read from /proc
     pub version: u16,
     pub blkio_delay_total: u64,
     pub swapin_delay_total: u64,
+    /// Number of waits for block I/O and swap-in that make up the totals
+    pub blkio_count: u64,
+    pub swapin_count: u64,
     pub read_bytes: u64,
     pub write_bytes: u64,
     pub cancelled_write_bytes: u64,
+    /// Bytes passed through read and write syscalls (rchar/wchar),
+    /// including reads served from the page cache
+    pub read_char: u64,
+    pub write_char: u64,
+    /// User plus system CPU time, in nanoseconds
+    pub cpu_time_total: u64,
+    /// Time spent runnable but waiting for a CPU, in nanoseconds
+    pub cpu_delay_total: u64,
+    /// Time spent in direct memory reclaim waiting for free pages, in
+    /// nanoseconds
+    pub freepages_delay_total: u64,
+    /// Page faults served from memory
+    pub minor_faults: u64,
+    /// Page faults that had to read from disk or swap
+    pub major_faults: u64,
+    /// Time spent refaulting pages evicted while still in use, in
+    /// nanoseconds (version 9, Linux 4.20)
+    pub thrashing_delay_total: u64,
+    /// Time spent in direct memory compaction, in nanoseconds (version 11,
+    /// Linux 5.19)
+    pub compact_delay_total: u64,
+    /// Time spent copying write-protected pages, in nanoseconds (version 13,
+    /// Linux 6.3)
+    pub wpcopy_delay_total: u64,
+}
+
+/// First `struct taskstats` versions with the thrashing, compaction and
+/// write-protect copy delays
+pub const THRASHING_VERSION: u16 = 9;
+pub const COMPACT_VERSION: u16 = 11;
+pub const WPCOPY_VERSION: u16 = 13;
+
+/// Byte offsets of those delays in `struct taskstats`, which the kernel only
+/// ever extends at the end
+///
+/// Reading them by offset rather than through the generated bindings keeps
+/// the fields available when iotop is built against older kernel headers
+/// than it runs on, as far as the bindings' buffer reaches.
+const THRASHING_DELAY_OFFSET: usize = 336;
+const COMPACT_DELAY_OFFSET: usize = 360;
+const WPCOPY_DELAY_OFFSET: usize = 408;
+
+/// Version of `struct taskstats` reported by the kernel, 0 until known
+static KERNEL_VERSION: AtomicU16 = AtomicU16::new(0);
+
+/// Taskstats queries sent since start, to show iotop's own overhead
+static NETLINK_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of taskstats queries sent to the kernel so far
+pub fn netlink_requests() -> u64 {
+    NETLINK_REQUESTS.load(Ordering::Relaxed)
+}
+
+/// Count the query that got `reply`
+fn counted<T>(reply: T) -> T {
+    NETLINK_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    reply
 }
 
 // Global flag to detect if CONFIG_TASK_DELAY_ACCT is enabled
@@ -21,6 +85,18 @@ impl TaskStats {
         HAS_DELAY_ACCT.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    /// Version of the kernel's `struct taskstats`, once a query succeeded;
+    /// `None` when reading from /proc
+    pub fn kernel_version() -> Option<u16> {
+        Some(KERNEL_VERSION.load(Ordering::Relaxed)).filter(|&version| version != 0)
+    }
+
+    /// Whether the kernel's `struct taskstats` has the fields added in
+    /// `version`
+    pub fn kernel_has(version: u16) -> bool {
+        Self::kernel_version().is_some_and(|kernel| kernel >= version)
+    }
+
     pub fn from_kernel_stats(stats: &KernelTaskStats) -> Self {
         let blkio_delay = stats.delays.blkio.delay_total.as_nanos() as u64;
         let swapin_delay = stats.delays.swapin.delay_total.as_nanos() as u64;
@@ -30,13 +106,42 @@ impl TaskStats {
             HAS_DELAY_ACCT.store(true, std::sync::atomic::Ordering::Relaxed);
         }
 
+        let inner = stats.inner();
+        let version = inner.version;
+        KERNEL_VERSION.store(version, Ordering::Relaxed);
+        // SAFETY: a plain C struct, viewed as bytes for as long as `stats`
+        // is borrowed
+        let raw = unsafe {
+            std::slice::from_raw_parts(
+                (inner as *const linux_taskstats::taskstats).cast::<u8>(),
+                std::mem::size_of_val(inner),
+            )
+        };
+
         Self {
-            version: 0,
+            version,
             blkio_delay_total: blkio_delay,
             swapin_delay_total: swapin_delay,
+            blkio_count: stats.delays.blkio.count,
+            swapin_count: stats.delays.swapin.count,
             read_bytes: stats.io.read_bytes,
             write_bytes: stats.io.write_bytes,
             cancelled_write_bytes: stats.blkio.cancelled_write_bytes,
+            read_char: stats.io.read_bytes,
+            write_char: stats.io.write_bytes,
+            cpu_time_total: (stats.cpu.utime_total + stats.cpu.stime_total).as_nanos() as u64,
+            cpu_delay_total: stats.delays.cpu.delay_total.as_nanos() as u64,
+            freepages_delay_total: stats.delays.freepages.delay_total.as_nanos() as u64,
+            minor_faults: stats.memory.minor_faults,
+            major_faults: stats.memory.major_faults,
+            thrashing_delay_total: delay_at(
+                raw,
+                version,
+                THRASHING_VERSION,
+                THRASHING_DELAY_OFFSET,
+            ),
+            compact_delay_total: delay_at(raw, version, COMPACT_VERSION, COMPACT_DELAY_OFFSET),
+            wpcopy_delay_total: delay_at(raw, version, WPCOPY_VERSION, WPCOPY_DELAY_OFFSET),
         }
     }
 
@@ -57,11 +162,57 @@ impl TaskStats {
             swapin_delay_total: self
                 .swapin_delay_total
                 .saturating_sub(other.swapin_delay_total),
+            blkio_count: self.blkio_count.saturating_sub(other.blkio_count),
+            swapin_count: self.swapin_count.saturating_sub(other.swapin_count),
             read_bytes: self.read_bytes.saturating_sub(other.read_bytes),
             write_bytes: self.write_bytes.saturating_sub(other.write_bytes),
             cancelled_write_bytes: self
                 .cancelled_write_bytes
                 .saturating_sub(other.cancelled_write_bytes),
+            read_char: self.read_char.saturating_sub(other.read_char),
+            write_char: self.write_char.saturating_sub(other.write_char),
+            cpu_time_total: self.cpu_time_total.saturating_sub(other.cpu_time_total),
+            cpu_delay_total: self.cpu_delay_total.saturating_sub(other.cpu_delay_total),
+            freepages_delay_total: self
+                .freepages_delay_total
+                .saturating_sub(other.freepages_delay_total),
+            minor_faults: self.minor_faults.saturating_sub(other.minor_faults),
+            major_faults: self.major_faults.saturating_sub(other.major_faults),
+            thrashing_delay_total: self
+                .thrashing_delay_total
+                .saturating_sub(other.thrashing_delay_total),
+            compact_delay_total: self
+                .compact_delay_total
+                .saturating_sub(other.compact_delay_total),
+            wpcopy_delay_total: self
+                .wpcopy_delay_total
+                .saturating_sub(other.wpcopy_delay_total),
+        }
+    }
+
+    /// A delta with every counter multiplied by `factor`, rounded, to turn
+    /// the change over one interval into the change over another
+    pub fn scaled(&self, factor: f64) -> TaskStats {
+        let scale = |value: u64| (value as f64 * factor).round() as u64;
+        TaskStats {
+            version: self.version,
+            blkio_delay_total: scale(self.blkio_delay_total),
+            swapin_delay_total: scale(self.swapin_delay_total),
+            blkio_count: scale(self.blkio_count),
+            swapin_count: scale(self.swapin_count),
+            read_bytes: scale(self.read_bytes),
+            write_bytes: scale(self.write_bytes),
+            cancelled_write_bytes: scale(self.cancelled_write_bytes),
+            read_char: scale(self.read_char),
+            write_char: scale(self.write_char),
+            cpu_time_total: scale(self.cpu_time_total),
+            cpu_delay_total: scale(self.cpu_delay_total),
+            freepages_delay_total: scale(self.freepages_delay_total),
+            minor_faults: scale(self.minor_faults),
+            major_faults: scale(self.major_faults),
+            thrashing_delay_total: scale(self.thrashing_delay_total),
+            compact_delay_total: scale(self.compact_delay_total),
+            wpcopy_delay_total: scale(self.wpcopy_delay_total),
         }
     }
 
@@ -72,35 +223,288 @@ impl TaskStats {
         self.swapin_delay_total = self
             .swapin_delay_total
             .saturating_add(delta.swapin_delay_total);
+        self.blkio_count = self.blkio_count.saturating_add(delta.blkio_count);
+        self.swapin_count = self.swapin_count.saturating_add(delta.swapin_count);
         self.read_bytes = self.read_bytes.saturating_add(delta.read_bytes);
         self.write_bytes = self.write_bytes.saturating_add(delta.write_bytes);
         self.cancelled_write_bytes = self
             .cancelled_write_bytes
             .saturating_add(delta.cancelled_write_bytes);
+        self.read_char = self.read_char.saturating_add(delta.read_char);
+        self.write_char = self.write_char.saturating_add(delta.write_char);
+        self.cpu_time_total = self.cpu_time_total.saturating_add(delta.cpu_time_total);
+        self.cpu_delay_total = self.cpu_delay_total.saturating_add(delta.cpu_delay_total);
+        self.freepages_delay_total = self
+            .freepages_delay_total
+            .saturating_add(delta.freepages_delay_total);
+        self.minor_faults = self.minor_faults.saturating_add(delta.minor_faults);
+        self.major_faults = self.major_faults.saturating_add(delta.major_faults);
+        self.thrashing_delay_total = self
+            .thrashing_delay_total
+            .saturating_add(delta.thrashing_delay_total);
+        self.compact_delay_total = self
+            .compact_delay_total
+            .saturating_add(delta.compact_delay_total);
+        self.wpcopy_delay_total = self
+            .wpcopy_delay_total
+            .saturating_add(delta.wpcopy_delay_total);
     }
 }
 
+/// Delay counter at `offset` of a raw `struct taskstats` of `version`, or 0
+/// when that version predates the field or the buffer does not reach it
+fn delay_at(raw: &[u8], version: u16, since: u16, offset: usize) -> u64 {
+    if version < since {
+        return 0;
+    }
+    raw.get(offset..offset + 8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u64::from_ne_bytes)
+}
+
+/// Parse /proc/[pid]/io into the fields it shares with taskstats
+pub fn parse_proc_io(content: &str) -> Option<TaskStats> {
+    let mut read_bytes = None;
+    let mut write_bytes = None;
+    let mut cancelled_write_bytes = None;
+    let mut read_char = 0;
+    let mut write_char = 0;
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().parse::<u64>().ok();
+        match key {
+            "read_bytes" => read_bytes = value,
+            "write_bytes" => write_bytes = value,
+            "cancelled_write_bytes" => cancelled_write_bytes = value,
+            "rchar" => read_char = value.unwrap_or_default(),
+            "wchar" => write_char = value.unwrap_or_default(),
+            _ => {}
+        }
+    }
+    Some(TaskStats {
+        read_bytes: read_bytes?,
+        write_bytes: write_bytes?,
+        cancelled_write_bytes: cancelled_write_bytes?,
+        read_char,
+        write_char,
+        ..Default::default()
+    })
+}
+
+enum Source {
+    Netlink(Client),
+    /// Degraded mode without CAP_NET_ADMIN: byte counters from
+    /// /proc/[pid]/io, which is only readable for the user's own processes
+    Procfs,
+}
+
+const PRIVILEGE_HINT: &str = "This program requires root privileges or CAP_NET_ADMIN capability.\n\
+                              Try running with: sudo iotop";
+
 pub struct TaskStatsConnection {
-    client: Client,
+    source: Source,
 }
 
 impl TaskStatsConnection {
     pub fn new() -> Result<Self> {
-        let client = Client::open().context(
-            "Failed to create taskstats client.\n\
-             This program requires root privileges or CAP_NET_ADMIN capability.\n\
-             Try running with: sudo iotop",
-        )?;
-        Ok(Self { client })
+        let client = Client::open()
+            .with_context(|| format!("Failed to create taskstats client.\n{}", PRIVILEGE_HINT))?;
+        // Opening the socket works unprivileged, only queries are refused
+        client
+            .pid_stats(std::process::id())
+            .with_context(|| format!("Failed to query taskstats.\n{}", PRIVILEGE_HINT))?;
+        Ok(Self {
+            source: Source::Netlink(client),
+        })
+    }
+
+    /// Read I/O counters from /proc/[pid]/io instead of taskstats
+    ///
+    /// Only processes of the current user can be read, and delay accounting
+    /// and CPU time are not available.
+    pub fn procfs() -> Self {
+        Self {
+            source: Source::Procfs,
+        }
+    }
+
+    pub fn is_procfs(&self) -> bool {
+        matches!(self.source, Source::Procfs)
     }
 
     pub fn get_task_stats(&mut self, pid: i32) -> Result<Option<TaskStats>> {
-        match self.client.pid_stats(pid as u32) {
-            Ok(stats) => Ok(Some(TaskStats::from_kernel_stats(&stats))),
-            Err(_) => {
-                // Process not found or access denied - just return None
-                Ok(None)
-            }
+        match &self.source {
+            Source::Netlink(client) => match counted(client.pid_stats(pid as u32)) {
+                Ok(stats) => Ok(Some(TaskStats::from_kernel_stats(&stats))),
+                Err(_) => {
+                    // Process not found or access denied - just return None
+                    Ok(None)
+                }
+            },
+            Source::Procfs => Ok(read_thread_io(pid).map(|mut stats| {
+                if let Some(stat) = read_stat(&format!("/proc/{0}/task/{0}/stat", pid)) {
+                    (stats.minor_faults, stats.major_faults) =
+                        (stat.minor_faults, stat.major_faults);
+                    stats.blkio_delay_total = blkio_delay(&stat);
+                }
+                stats
+            })),
         }
     }
+
+    /// Counters of a whole thread group, including threads that have exited
+    ///
+    /// The kernel's TGID command only sums the delays: it leaves the I/O
+    /// byte counters, CPU time and page faults at zero. The bytes come from
+    /// /proc/[tgid]/io, which also counts the children the process has
+    /// reaped; CPU time and faults from /proc/[tgid]/stat, whose utime and
+    /// stime add up those of every thread. From /proc alone the delays are
+    /// only the block I/O delay of the live threads.
+    pub fn get_tgid_stats(&mut self, tgid: i32) -> Result<Option<TaskStats>> {
+        let stats = match &self.source {
+            Source::Netlink(client) => match counted(client.tgid_stats(tgid as u32)) {
+                Ok(stats) => TaskStats::from_kernel_stats(&stats),
+                Err(_) => return Ok(None),
+            },
+            Source::Procfs => TaskStats {
+                blkio_delay_total: std::fs::read_dir(format!("/proc/{}/task", tgid))
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter_map(|entry| read_stat(&entry.path().join("stat").to_string_lossy()))
+                    .map(|stat| blkio_delay(&stat))
+                    .sum(),
+                ..Default::default()
+            },
+        };
+        let (Some(io), Some(stat)) = (
+            read_process_io(tgid),
+            read_stat(&format!("/proc/{}/stat", tgid)),
+        ) else {
+            return Ok(None);
+        };
+        let stats = TaskStats {
+            read_bytes: io.read_bytes,
+            write_bytes: io.write_bytes,
+            cancelled_write_bytes: io.cancelled_write_bytes,
+            read_char: io.read_char,
+            write_char: io.write_char,
+            ..stats
+        };
+        Ok(Some(with_process_stat(stats, &stat)))
+    }
+}
+
+/// Byte counters of a single thread from /proc
+///
+/// /proc/[pid]/io covers the whole thread group plus the children it has
+/// reaped, so the per-thread file below task/ is read for threads. It is
+/// reachable through any TID, although only TGIDs are listed in /proc.
+pub fn read_thread_io(tid: i32) -> Option<TaskStats> {
+    std::fs::read_to_string(format!("/proc/{0}/task/{0}/io", tid))
+        .ok()
+        .and_then(|content| parse_proc_io(&content))
+}
+
+/// Byte counters of a whole process from /proc/[pid]/io: every thread it
+/// ever had, plus the children it has waited for
+pub fn read_process_io(pid: i32) -> Option<TaskStats> {
+    std::fs::read_to_string(format!("/proc/{}/io", pid))
+        .ok()
+        .and_then(|content| parse_proc_io(&content))
+}
+
+/// Thread group stats completed with the CPU time and page faults of the
+/// whole process, from its /proc/[tgid]/stat
+fn with_process_stat(stats: TaskStats, stat: &ProcStat) -> TaskStats {
+    TaskStats {
+        cpu_time_total: proc_reader::ticks_to_ns(stat.utime + stat.stime),
+        minor_faults: stat.minor_faults,
+        major_faults: stat.major_faults,
+        ..stats
+    }
+}
+
+/// Parse a /proc stat file
+fn read_stat(path: &str) -> Option<ProcStat> {
+    ProcStat::parse(&std::fs::read_to_string(path).ok()?)
+}
+
+/// Block I/O delay in nanoseconds from `delayacct_blkio_ticks`, for when
+/// taskstats cannot be used
+///
+/// The kernel fills the field from the same delay accounting, but /proc is
+/// readable without CAP_NET_ADMIN. The resolution is one clock tick, so the
+/// IO column is coarser than with taskstats.
+fn blkio_delay(stat: &ProcStat) -> u64 {
+    let delay = proc_reader::ticks_to_ns(stat.blkio_ticks);
+    if delay != 0 {
+        HAS_DELAY_ACCT.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+    delay
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_at() {
+        let mut raw = vec![0u8; WPCOPY_DELAY_OFFSET];
+        raw[COMPACT_DELAY_OFFSET..COMPACT_DELAY_OFFSET + 8].copy_from_slice(&1234u64.to_ne_bytes());
+        assert_eq!(
+            delay_at(&raw, 11, COMPACT_VERSION, COMPACT_DELAY_OFFSET),
+            1234
+        );
+        // Left over from an older kernel's layout
+        assert_eq!(delay_at(&raw, 10, COMPACT_VERSION, COMPACT_DELAY_OFFSET), 0);
+        // Past the end of the bindings' buffer
+        assert_eq!(delay_at(&raw, 13, WPCOPY_VERSION, WPCOPY_DELAY_OFFSET), 0);
+    }
+
+    #[test]
+    fn test_scaled() {
+        let delta = TaskStats {
+            read_bytes: 1000,
+            blkio_delay_total: 3,
+            ..Default::default()
+        };
+        let scaled = delta.scaled(1.25);
+        assert_eq!(scaled.read_bytes, 1250);
+        assert_eq!(scaled.blkio_delay_total, 4);
+        assert_eq!(scaled.write_bytes, 0);
+    }
+
+    #[test]
+    fn test_parse_proc_io() {
+        let content = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\n\
+                       read_bytes: 4096\nwrite_bytes: 323932160\ncancelled_write_bytes: 8192\n";
+        let stats = parse_proc_io(content).unwrap();
+        assert_eq!(stats.read_bytes, 4096);
+        assert_eq!(stats.write_bytes, 323932160);
+        assert_eq!(stats.cancelled_write_bytes, 8192);
+        assert_eq!(stats.read_char, 323934931);
+        assert_eq!(stats.write_char, 323929600);
+        assert_eq!(stats.blkio_delay_total, 0);
+        assert!(parse_proc_io("rchar: 1\n").is_none());
+    }
+
+    #[test]
+    fn test_with_process_stat() {
+        let content = "4242 (postgres: wal) S 1 4242 4242 0 -1 4194560 1500 0 12 0 \
+                       300 100 0 0 20 0 8 0 987654 0 0 18446744073709551615 1 1 0 0 0 0 0 \
+                       0 0 0 0 0 17 3 0 0 25 0 0";
+        let stat = ProcStat::parse(content).unwrap();
+        let group = TaskStats {
+            blkio_delay_total: 5000,
+            ..Default::default()
+        };
+        let stats = with_process_stat(group, &stat);
+        assert_eq!(stats.cpu_time_total, proc_reader::ticks_to_ns(400));
+        assert_eq!((stats.minor_faults, stats.major_faults), (1500, 12));
+        // The delays summed by the kernel are kept
+        assert_eq!(stats.blkio_delay_total, 5000);
+    }
 }
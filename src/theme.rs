@@ -0,0 +1,121 @@
+use ratatui::buffer::Buffer;
+use ratatui::style::{Color, Modifier};
+
+/// Colors of the interactive display
+///
+/// The display is drawn in the dark theme's colors and recolored as a whole
+/// for the others, so that no widget needs to know about themes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// Light text on a dark background
+    #[default]
+    Dark,
+    /// Dark text, for terminals with a light background
+    Light,
+    /// The terminal's own colors, with reverse video for highlighted rows
+    Mono,
+}
+
+impl Theme {
+    const ALL: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::Mono];
+
+    /// Parse the `--theme` option
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Self::from_name(value.trim())
+            .ok_or_else(|| format!("Unknown theme '{}': use dark, light or mono", value))
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|theme| theme.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Mono => "mono",
+        }
+    }
+
+    /// Recolor a frame drawn in the dark theme's colors
+    pub fn apply(&self, buffer: &mut Buffer) {
+        for cell in &mut buffer.content {
+            match self {
+                Theme::Dark => return,
+                Theme::Light => {
+                    cell.fg = light(cell.fg, false);
+                    cell.bg = light(cell.bg, true);
+                }
+                Theme::Mono => {
+                    // The selection and spikes are only told by their background
+                    if !matches!(cell.bg, Color::Reset | Color::Black) {
+                        cell.modifier |= Modifier::REVERSED;
+                    }
+                    cell.fg = Color::Reset;
+                    cell.bg = Color::Reset;
+                }
+            }
+        }
+    }
+}
+
+/// Counterpart of a dark theme color on a light background
+fn light(color: Color, background: bool) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+        Color::Gray => Color::DarkGray,
+        Color::DarkGray => Color::Gray,
+        // Backgrounds mostly white, text darker, keeping the hue
+        Color::Rgb(r, g, b) => {
+            let shade = |c: u8| {
+                if background {
+                    ((u16::from(c) + 3 * 255) / 4) as u8
+                } else {
+                    (u16::from(c) * 3 / 5) as u8
+                }
+            };
+            Color::Rgb(shade(r), shade(g), shade(b))
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::layout::Rect;
+    use ratatui::style::Style;
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(Theme::parse(" light "), Ok(Theme::Light));
+        assert!(Theme::parse("sepia").is_err());
+
+        let area = Rect::new(0, 0, 2, 1);
+        let mut buffer = Buffer::empty(area);
+        buffer.set_style(
+            Rect::new(0, 0, 1, 1),
+            Style::new().fg(Color::White).bg(Color::Black),
+        );
+        buffer.set_style(
+            Rect::new(1, 0, 1, 1),
+            Style::new().bg(Color::Rgb(60, 60, 60)),
+        );
+        let drawn = buffer.clone();
+
+        Theme::Dark.apply(&mut buffer);
+        assert_eq!(buffer, drawn);
+
+        Theme::Light.apply(&mut buffer);
+        assert_eq!(buffer.content[0].fg, Color::Black);
+        assert_eq!(buffer.content[0].bg, Color::White);
+        assert_eq!(buffer.content[1].bg, Color::Rgb(206, 206, 206));
+
+        let mut buffer = drawn;
+        Theme::Mono.apply(&mut buffer);
+        assert_eq!(buffer.content[0].fg, Color::Reset);
+        assert!(!buffer.content[0].modifier.contains(Modifier::REVERSED));
+        assert!(buffer.content[1].modifier.contains(Modifier::REVERSED));
+    }
+}
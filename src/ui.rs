@@ -15,14 +15,18 @@ use ratatui::{
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span, Text},
     widgets::{
-        Block, BorderType, Borders, Cell, Paragraph, Row, Scrollbar, ScrollbarOrientation,
-        ScrollbarState, Table, TableState,
+        Block, BorderType, Borders, Cell, Clear, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Sparkline, Table, TableState,
     },
     Frame, Terminal,
 };
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Stdout};
 use std::ops::{Deref, DerefMut};
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::{
     sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
     task::JoinHandle,
@@ -30,7 +34,36 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::process::ProcessInfo;
+use crate::alerts::{Alert, AlertLog, AlertThresholds};
+use crate::cgroup::{CgroupRate, WeightCache};
+use crate::columns::{self, column_text, io_bar, ColumnContext, ExtraColumn};
+use crate::container::ContainerCache;
+use crate::cpustat::{CpuIowait, Iowait};
+use crate::devtrace::DeviceRates;
+use crate::diskstats::{self, DeviceRate};
+use crate::expr::Condition;
+use crate::filetrace::{CacheSplit, FileRate};
+use crate::filter::{PidFilter, UnitFilter};
+use crate::history::{AverageWindow, RateHistory};
+use crate::ioprio::IoprioClass;
+use crate::latency::{format_latency, Histogram, LatencyRates};
+use crate::loadavg::LoadAvg;
+use crate::meminfo::DirtyMemory;
+use crate::mounts::{FsUsage, FuseCache, MountCache};
+use crate::netio::NetRates;
+use crate::nfsstats::NfsRate;
+use crate::overhead::Overhead;
+use crate::pressure::IoPressure;
+use crate::proc_reader::{self, FdCache, MemoryCache};
+use crate::process::{Grouping, ProcessInfo, ProcessSnapshot};
+use crate::quota::{Budgets, UserUsage};
+use crate::swapstats::SwapRate;
+use crate::synctrace::SyncRates;
+use crate::taskstats::TaskStats;
+use crate::theme::Theme;
+use crate::writeback::WritebackRates;
+use crate::zoom::{IntervalRates, OpenFile, ThreadRow, ZoomView};
+use regex::Regex;
 
 #[derive(Debug, Clone)]
 pub enum Event {
@@ -45,7 +78,7 @@ pub enum Event {
     Mouse(MouseEvent),
     #[allow(dead_code)]
     Resize(u16, u16),
-    DataUpdate(crate::process::ProcessSnapshot),
+    DataUpdate(Box<ProcessSnapshot>),
 }
 
 pub struct Tui {
@@ -67,11 +100,25 @@ pub enum SortColumn {
     Write,
     Swapin,
     Io,
+    CpuDelay,
+    Reclaim,
+    Thrashing,
+    Compact,
+    WpCopy,
+    ReadChar,
+    WriteChar,
+    Cancelled,
+    MajorFaults,
+    MinorFaults,
+    IoWaits,
+    SwapIns,
+    IoAvg,
+    Unit,
     Command,
 }
 
 impl SortColumn {
-    fn as_str(&self) -> &str {
+    pub fn as_str(&self) -> &str {
         match self {
             SortColumn::Pid => "tid",
             SortColumn::Prio => "prio",
@@ -80,39 +127,103 @@ impl SortColumn {
             SortColumn::Write => "write",
             SortColumn::Swapin => "swapin",
             SortColumn::Io => "io",
+            SortColumn::CpuDelay => "cpudelay",
+            SortColumn::Reclaim => "reclaim",
+            SortColumn::Thrashing => "thrashing",
+            SortColumn::Compact => "compact",
+            SortColumn::WpCopy => "wpcopy",
+            SortColumn::ReadChar => "rchar",
+            SortColumn::WriteChar => "wchar",
+            SortColumn::Cancelled => "cancelled",
+            SortColumn::MajorFaults => "majflt",
+            SortColumn::MinorFaults => "minflt",
+            SortColumn::IoWaits => "iowaits",
+            SortColumn::SwapIns => "swapins",
+            SortColumn::IoAvg => "ioavg",
+            SortColumn::Unit => "unit",
             SortColumn::Command => "command",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            SortColumn::Pid,
+            SortColumn::Prio,
+            SortColumn::User,
+            SortColumn::Read,
+            SortColumn::Write,
+            SortColumn::Swapin,
+            SortColumn::Io,
+            SortColumn::CpuDelay,
+            SortColumn::Reclaim,
+            SortColumn::Thrashing,
+            SortColumn::Compact,
+            SortColumn::WpCopy,
+            SortColumn::ReadChar,
+            SortColumn::WriteChar,
+            SortColumn::Cancelled,
+            SortColumn::MajorFaults,
+            SortColumn::MinorFaults,
+            SortColumn::IoWaits,
+            SortColumn::SwapIns,
+            SortColumn::IoAvg,
+            SortColumn::Unit,
+            SortColumn::Command,
+        ]
+        .into_iter()
+        .find(|column| column.as_str() == name)
+    }
 }
 
 impl SortColumn {
-    /// Get all available columns based on whether delay accounting is available
-    pub fn available_columns(has_delay_acct: bool) -> Vec<SortColumn> {
+    /// Sort column of an optional column, for those that can be sorted on
+    fn for_extra(column: ExtraColumn, has_delay_acct: bool) -> Option<Self> {
+        match column {
+            ExtraColumn::ReadChar => Some(SortColumn::ReadChar),
+            ExtraColumn::WriteChar => Some(SortColumn::WriteChar),
+            ExtraColumn::Cancelled => Some(SortColumn::Cancelled),
+            ExtraColumn::MajorFaults => Some(SortColumn::MajorFaults),
+            ExtraColumn::MinorFaults => Some(SortColumn::MinorFaults),
+            ExtraColumn::IoWaits if has_delay_acct => Some(SortColumn::IoWaits),
+            ExtraColumn::SwapIns if has_delay_acct => Some(SortColumn::SwapIns),
+            ExtraColumn::IoAvg if has_delay_acct => Some(SortColumn::IoAvg),
+            ExtraColumn::CpuDelay if has_delay_acct => Some(SortColumn::CpuDelay),
+            ExtraColumn::Reclaim if has_delay_acct => Some(SortColumn::Reclaim),
+            ExtraColumn::Thrashing if has_delay_acct => Some(SortColumn::Thrashing),
+            ExtraColumn::Compact if has_delay_acct => Some(SortColumn::Compact),
+            ExtraColumn::WpCopy if has_delay_acct => Some(SortColumn::WpCopy),
+            ExtraColumn::Unit => Some(SortColumn::Unit),
+            _ => None,
+        }
+    }
+
+    /// Get all available columns based on whether delay accounting is
+    /// available and which optional columns are shown, in display order
+    pub fn available_columns(
+        has_delay_acct: bool,
+        extra_columns: &[ExtraColumn],
+    ) -> Vec<SortColumn> {
+        let mut columns = vec![
+            SortColumn::Pid,
+            SortColumn::Prio,
+            SortColumn::User,
+            SortColumn::Read,
+            SortColumn::Write,
+        ];
         if has_delay_acct {
-            vec![
-                SortColumn::Pid,
-                SortColumn::Prio,
-                SortColumn::User,
-                SortColumn::Read,
-                SortColumn::Write,
-                SortColumn::Swapin,
-                SortColumn::Io,
-                SortColumn::Command,
-            ]
-        } else {
-            vec![
-                SortColumn::Pid,
-                SortColumn::Prio,
-                SortColumn::User,
-                SortColumn::Read,
-                SortColumn::Write,
-                SortColumn::Command,
-            ]
-        }
-    }
-
-    pub fn cycle_forward(&self, has_delay_acct: bool) -> Self {
-        let columns = Self::available_columns(has_delay_acct);
+            columns.extend([SortColumn::Swapin, SortColumn::Io]);
+        }
+        columns.extend(
+            extra_columns
+                .iter()
+                .filter_map(|c| Self::for_extra(*c, has_delay_acct)),
+        );
+        columns.push(SortColumn::Command);
+        columns
+    }
+
+    pub fn cycle_forward(&self, has_delay_acct: bool, extra_columns: &[ExtraColumn]) -> Self {
+        let columns = Self::available_columns(has_delay_acct, extra_columns);
         let current_idx = columns.iter().position(|c| c == self);
 
         match current_idx {
@@ -124,8 +235,8 @@ impl SortColumn {
         }
     }
 
-    pub fn cycle_backward(&self, has_delay_acct: bool) -> Self {
-        let columns = Self::available_columns(has_delay_acct);
+    pub fn cycle_backward(&self, has_delay_acct: bool, extra_columns: &[ExtraColumn]) -> Self {
+        let columns = Self::available_columns(has_delay_acct, extra_columns);
         let current_idx = columns.iter().position(|c| c == self);
 
         match current_idx {
@@ -138,32 +249,655 @@ impl SortColumn {
     }
 }
 
+/// Explains what is missing when taskstats could not be used
+pub const PROCFS_FALLBACK_NOTICE: &str =
+    "Limited mode without CAP_NET_ADMIN: only your own processes, read from /proc/[pid]/io, \
+     without delay accounting";
+
+/// Default growth factor between two intervals that counts as a spike
+pub const DEFAULT_SPIKE_FACTOR: f64 = 4.0;
+/// Default `--hot-threads` percentage
+pub const DEFAULT_HOT_THREADS: f64 = 50.0;
+
+/// Rates below this are never used as a spike baseline, so a thread going
+/// from idle to a few KB/s isn't flagged
+const SPIKE_MIN_RATE: f64 = 64.0 * 1024.0;
+
+/// Number of data refreshes a spiking row stays highlighted
+const SPIKE_HIGHLIGHT_FRAMES: u8 = 2;
+
+fn is_spike(previous_rate: f64, current_rate: f64, factor: f64) -> bool {
+    current_rate > factor * previous_rate.max(SPIKE_MIN_RATE)
+}
+
+/// What the text typed into a prompt will be used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    Kill,
+    Stop,
+    Ionice,
+    Nice,
+    User,
+    Pid,
+    Unit,
+    Highlight,
+    Preset,
+    Where,
+}
+
+impl PromptKind {
+    /// Whether the prompt is answered with y or n rather than typed text
+    pub fn is_confirmation(&self) -> bool {
+        matches!(self, PromptKind::Kill | PromptKind::Stop)
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            PromptKind::Kill => " Kill ",
+            PromptKind::Stop => " Stop ",
+            PromptKind::Ionice => " I/O priority ",
+            PromptKind::Nice => " Nice ",
+            PromptKind::User => " User filter ",
+            PromptKind::Pid => " PID filter ",
+            PromptKind::Unit => " Unit filter ",
+            PromptKind::Highlight => " Highlight ",
+            PromptKind::Preset => " Filter preset ",
+            PromptKind::Where => " Filter ",
+        }
+    }
+
+    fn question(&self, targets: usize) -> String {
+        match self {
+            PromptKind::Kill => format!("Send SIGTERM to {} process(es)? (y/n)", targets),
+            PromptKind::Stop => format!("Send SIGSTOP to {} process(es)? (y/n)", targets),
+            PromptKind::Ionice => format!(
+                "I/O priority for {} row(s) (rt/0-7, be/0-7, idle):",
+                targets
+            ),
+            PromptKind::Nice => format!("Nice value for {} row(s) (-20..19):", targets),
+            PromptKind::User => {
+                "Show only these users (names or UIDs, comma-separated; empty for all):".to_string()
+            }
+            PromptKind::Pid => {
+                "Show only these PIDs (comma-separated, 1234+ adds its threads; empty for all):"
+                    .to_string()
+            }
+            PromptKind::Unit => {
+                "Show only these systemd units (nginx for nginx.service, comma-separated; empty for all):"
+                    .to_string()
+            }
+            PromptKind::Highlight => {
+                "Highlight commands matching this regex (empty for none):".to_string()
+            }
+            PromptKind::Preset => {
+                "Apply the filters of this preset (empty for the command line's):".to_string()
+            }
+            PromptKind::Where => "Filter".to_string(),
+        }
+    }
+}
+
+/// A single-line text prompt shown as a popup
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+    /// Why the input is not valid yet, for prompts checked as they are typed
+    pub error: Option<String>,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind) -> Self {
+        Self {
+            kind,
+            input: String::new(),
+            error: None,
+        }
+    }
+}
+
 pub struct UIState {
     pub only_active: bool,
+    /// Only rows in uninterruptible sleep are shown (`b`)
+    pub blocked_only: bool,
+    /// Rows doing less I/O per second are hidden (`--min-io`)
+    pub min_io: u64,
+    /// Only rows in this I/O priority class are shown (`--class`)
+    pub prio_class: Option<IoprioClass>,
+    /// Only rows matching this expression are shown (`--where`, F4)
+    pub condition: Option<Condition>,
+    /// The condition when the F4 editor was opened, restored by Esc
+    pub condition_before_edit: Option<Condition>,
     pub accumulated: bool,
     pub sort_column: SortColumn,
     pub sort_reverse: bool,
     pub paused: bool,
     pub show_processes: bool,
+    /// Process groups or sessions shown as rows in process mode (`--group`)
+    pub grouping: Option<Grouping>,
     pub scroll_offset: usize,
     pub selection_mode: bool,
     pub selected_row: Option<usize>,
     pub table_state: TableState,
+    pub a11y: bool,
+    pub announcement: Option<String>,
+    pub delay_ms: bool,
+    pub theme: Theme,
+    pub extra_columns: Vec<ExtraColumn>,
+    /// Columns added by `--net`, `--trace-devices` and `--trace-syncs`
+    /// rather than chosen, which are not saved
+    pub implied_columns: Vec<ExtraColumn>,
+    /// Cursor position in the column chooser popup, if it is open
+    pub column_chooser: Option<usize>,
+    pub prompt: Option<Prompt>,
+    /// Users (as typed) the table is restricted to; empty for all users
+    pub user_filter: Vec<String>,
+    pub pid_filter: PidFilter,
+    pub unit_filter: UnitFilter,
+    /// Commands matching this are shown in a distinct color
+    pub highlight: Option<Regex>,
+    /// Only processes doing I/O to this device are shown (`--device`)
+    pub device_filter: Option<String>,
+    /// Only processes doing I/O to files under this directory are shown
+    /// (`--path`)
+    pub path_filter: Option<PathBuf>,
+    /// Preset whose filters apply on top of the command line's
+    pub preset: Option<String>,
+    /// Rows tagged for bulk actions, by TID (PID in process mode)
+    pub tagged: HashSet<i32>,
+    /// Processes (TGIDs) in cgroups that io.max is throttling
+    pub throttled: HashSet<i32>,
+    /// Processes (TGIDs) on screen doing I/O with files open on FUSE
+    /// filesystems, as of the last render
+    pub fuse_users: HashSet<i32>,
+    /// IDs of the rows in display order, as of the last render
+    pub row_ids: Vec<i32>,
+    /// Result of the last action, shown under the table
+    pub status: Option<String>,
+    pub spike_factor: f64,
+    /// Percentage of its process's I/O above which a thread is shown under
+    /// it in process mode (0 disables)
+    pub hot_threads: f64,
+    /// When monitoring started
+    pub started: Instant,
+    /// Number of samples received from the refresh stream
+    pub samples: u64,
+    /// Configured delay between displayed intervals, in seconds
+    pub delay: f64,
+    /// iotop's own use of the system over the last sample, with `--stats`
+    pub overhead: Option<Overhead>,
+    /// Samples taken per delay; above 1 each one is shown averaged with
+    /// those of the last delay
+    pub subsamples: u32,
+    /// How many intervals back from the latest one the table shows (while paused)
+    pub rewind: usize,
+    /// Window the displayed rates are averaged over (ignored with `-a`)
+    pub average_window: AverageWindow,
+    pub history: RateHistory,
+    pub alert_thresholds: AlertThresholds,
+    pub alerts: AlertLog,
+    /// Whether the alert log popup is open
+    pub show_alerts: bool,
+    /// Whether the per-device panel is shown next to the table
+    pub show_devices: bool,
+    /// Per-device rates over the last interval
+    pub devices: Vec<DeviceRate>,
+    /// Per-NFS-mount rates over the last interval, shown with the devices
+    pub nfs_mounts: Vec<NfsRate>,
+    /// Whether the per-cgroup panel is shown below the table
+    pub show_cgroups: bool,
+    /// Per-cgroup rates over the last interval, busiest first
+    pub cgroups: Vec<CgroupRate>,
+    /// Per-user budgets watched below the table (`--quotas`)
+    pub quotas: Option<Budgets>,
+    /// Per-user I/O over the last interval against the budgets
+    pub quota_usage: Vec<UserUsage>,
+    /// Whether users going over budget are logged like `--alert-write`
+    pub quota_alerts: bool,
+    /// Whether the per-CPU iowait panel is shown next to the table
+    pub show_cpus: bool,
+    /// Whether I/O comes from /proc/[pid]/io because taskstats was refused
+    pub procfs_fallback: bool,
+    /// Shared with the refresh streams, to have priorities read again after
+    /// an ionice or renice from the UI
+    pub prio_changed: Arc<AtomicBool>,
+    /// Whether files are traced with eBPF (`--trace-files`), for the zoom view
+    pub trace_files: bool,
+    /// Whether network I/O is counted with eBPF (`--net`)
+    pub count_net: bool,
+    /// Network I/O by process over the last interval, when counted
+    pub net: NetRates,
+    /// Whether block I/O is traced to devices with eBPF (`--trace-devices`)
+    pub trace_devices: bool,
+    /// Block I/O by process and device over the last interval, when traced
+    pub device_io: DeviceRates,
+    /// Whether sync calls are counted with eBPF (`--trace-syncs`)
+    pub trace_syncs: bool,
+    /// Sync calls by process over the last interval, when counted
+    pub syncs: SyncRates,
+    /// Whether block I/O latency is traced with eBPF (`--latency`)
+    pub trace_latency: bool,
+    /// Block I/O latency by process, when traced
+    pub latency: LatencyRates,
+    /// Estimated share of Actual DISK WRITE by row over the last interval
+    pub writeback: WritebackRates,
+    /// I/O pressure from /proc/pressure/io; `None` without PSI support
+    pub pressure: Option<IoPressure>,
+    /// Dirty and writeback memory from /proc/meminfo
+    pub dirty: Option<DirtyMemory>,
+    /// Swap traffic from /proc/vmstat; `None` while no swap area is in use
+    pub swap: Option<SwapRate>,
+    /// System and per-CPU iowait from /proc/stat, in percent of CPU time
+    pub iowait: Iowait,
+    /// Load averages from /proc/loadavg
+    pub load: Option<LoadAvg>,
+    /// Monitored tasks in uninterruptible sleep at the last refresh
+    pub uninterruptible: usize,
+    /// Memory of the rows on screen, for the memory columns
+    memory: MemoryCache,
+    mounts: MountCache,
+    fuse: FuseCache,
+    containers: ContainerCache,
+    io_weights: WeightCache,
+    fds: FdCache,
+    /// Full-screen dashboard of one row, when open
+    pub zoom: Option<ZoomView>,
+    spike_rates: HashMap<i32, (f64, f64)>,
+    spikes: HashMap<i32, u8>,
 }
 
 impl Default for UIState {
     fn default() -> Self {
         Self {
             only_active: false,
+            blocked_only: false,
+            min_io: 0,
+            prio_class: None,
+            condition: None,
+            condition_before_edit: None,
             accumulated: false,
             sort_column: SortColumn::Pid,
             sort_reverse: true,
             paused: false,
             show_processes: false,
+            grouping: None,
             scroll_offset: 0,
             selection_mode: false,
             selected_row: None,
             table_state: TableState::default(),
+            a11y: false,
+            announcement: None,
+            delay_ms: false,
+            theme: Theme::default(),
+            extra_columns: Vec::new(),
+            implied_columns: Vec::new(),
+            column_chooser: None,
+            prompt: None,
+            user_filter: Vec::new(),
+            pid_filter: PidFilter::default(),
+            unit_filter: UnitFilter::default(),
+            highlight: None,
+            device_filter: None,
+            path_filter: None,
+            preset: None,
+            tagged: HashSet::new(),
+            throttled: HashSet::new(),
+            fuse_users: HashSet::new(),
+            row_ids: Vec::new(),
+            status: None,
+            spike_factor: DEFAULT_SPIKE_FACTOR,
+            hot_threads: DEFAULT_HOT_THREADS,
+            started: Instant::now(),
+            samples: 0,
+            delay: 1.0,
+            overhead: None,
+            subsamples: 1,
+            rewind: 0,
+            average_window: AverageWindow::Instant,
+            history: RateHistory::default(),
+            alert_thresholds: AlertThresholds::default(),
+            alerts: AlertLog::default(),
+            show_alerts: false,
+            show_devices: false,
+            devices: Vec::new(),
+            nfs_mounts: Vec::new(),
+            show_cgroups: false,
+            show_cpus: false,
+            cgroups: Vec::new(),
+            quotas: None,
+            quota_usage: Vec::new(),
+            quota_alerts: false,
+            procfs_fallback: false,
+            prio_changed: Arc::new(AtomicBool::new(false)),
+            trace_files: false,
+            count_net: false,
+            net: HashMap::new(),
+            trace_devices: false,
+            device_io: HashMap::new(),
+            trace_syncs: false,
+            syncs: HashMap::new(),
+            trace_latency: false,
+            latency: HashMap::new(),
+            writeback: HashMap::new(),
+            pressure: None,
+            dirty: None,
+            swap: None,
+            iowait: Iowait::default(),
+            load: None,
+            uninterruptible: 0,
+            memory: MemoryCache::default(),
+            mounts: MountCache::default(),
+            fuse: FuseCache::default(),
+            containers: ContainerCache::default(),
+            io_weights: WeightCache::default(),
+            fds: FdCache::default(),
+            zoom: None,
+            spike_rates: HashMap::new(),
+            spikes: HashMap::new(),
+        }
+    }
+}
+
+impl UIState {
+    /// Record a plain-text description of the last state change.
+    ///
+    /// Only rendered in accessible mode, where it gives screen readers a
+    /// stable line to read back after each key press.
+    pub fn announce(&mut self, message: impl Into<String>) {
+        self.announcement = Some(message.into());
+    }
+
+    /// Compare the rates in a new snapshot with the previous one and flag rows
+    /// whose read or write rate jumped by more than `spike_factor`.
+    ///
+    /// Flags decay after `SPIKE_HIGHLIGHT_FRAMES` data refreshes.
+    pub fn track_spikes(&mut self, snapshot: &ProcessSnapshot) {
+        if self.spike_factor <= 0.0 || snapshot.duration <= 0.0 {
+            return;
+        }
+
+        self.spikes.retain(|_, frames| {
+            *frames -= 1;
+            *frames > 0
+        });
+
+        let mut rates = HashMap::with_capacity(snapshot.processes.len());
+        for (id, process) in &snapshot.processes {
+            let stats = &process.stats_delta;
+            let read_rate = stats.read_bytes as f64 / snapshot.duration;
+            let write_rate = stats
+                .write_bytes
+                .saturating_sub(stats.cancelled_write_bytes) as f64
+                / snapshot.duration;
+
+            if let Some(&(prev_read, prev_write)) = self.spike_rates.get(id) {
+                if is_spike(prev_read, read_rate, self.spike_factor)
+                    || is_spike(prev_write, write_rate, self.spike_factor)
+                {
+                    self.spikes.insert(*id, SPIKE_HIGHLIGHT_FRAMES);
+                }
+            }
+            rates.insert(*id, (read_rate, write_rate));
+        }
+        self.spike_rates = rates;
+    }
+
+    /// Log rows whose rate crossed an alert threshold and show the latest
+    /// one in the status line
+    pub fn check_alerts(&mut self, snapshot: &ProcessSnapshot) {
+        let alerts = self
+            .alerts
+            .check(snapshot, &self.alert_thresholds, self.show_processes);
+        self.report_alerts(&alerts);
+    }
+
+    /// Log the users going over their `--quotas` budget, with
+    /// `--quota-alert`
+    pub fn check_quotas(&mut self, snapshot: &ProcessSnapshot) {
+        let Some(budgets) = self.quotas.as_ref().filter(|_| self.quota_alerts) else {
+            return;
+        };
+        let usage = budgets.usage(snapshot.processes.values(), snapshot.duration);
+        let alerts = self.alerts.check_quotas(&usage);
+        self.report_alerts(&alerts);
+    }
+
+    fn report_alerts(&mut self, alerts: &[Alert]) {
+        if let Some(last) = alerts.last() {
+            if alerts.len() > 1 {
+                self.report(format!(
+                    "{} (+{} more, l for log)",
+                    last.message,
+                    alerts.len() - 1
+                ));
+            } else {
+                self.report(last.message.clone());
+            }
+        }
+    }
+
+    /// Share of its process's I/O, from 0 to 1, above which a thread is
+    /// shown under it; `None` unless the rows are processes showing rates
+    pub fn hot_thread_share(&self) -> Option<f64> {
+        (self.show_processes
+            && self.grouping.is_none()
+            && !self.accumulated
+            && self.hot_threads > 0.0)
+            .then_some(self.hot_threads / 100.0)
+    }
+
+    /// Window covering the samples of the last delay when sub-sampling
+    pub fn subsample_window(&self) -> Option<Duration> {
+        // A little short of the delay, so that timing jitter never lets
+        // one sample too many in
+        (self.subsamples > 1)
+            .then(|| Duration::from_secs_f64(self.delay * (1.0 - 0.5 / f64::from(self.subsamples))))
+    }
+
+    /// Elapsed time, sample count, delay and the kernel's taskstats version,
+    /// e.g. `up 1:02:03, 3723 samples every 1s, taskstats v13`
+    pub fn session_summary(&self) -> String {
+        let mut summary = format!(
+            "up {}, {} samples every {}s",
+            format_elapsed(self.started.elapsed().as_secs()),
+            self.samples,
+            self.delay
+        );
+        if let Some(version) = TaskStats::kernel_version() {
+            summary.push_str(&format!(", taskstats v{}", version));
+        }
+        if let Some(overhead) = &self.overhead {
+            summary.push_str(&format!("; iotop used {}", overhead.summary()));
+        }
+        summary
+    }
+
+    /// COMMAND text, marked when the process is stopped by a signal, its
+    /// cgroup is being throttled, its user is over their I/O budget, its
+    /// I/O may go through a FUSE daemon or it is in uninterruptible sleep
+    pub fn command_text(&self, process: &ProcessInfo) -> String {
+        let mut text = String::new();
+        if process.stopped {
+            text.push_str("[stopped] ");
+        }
+        let over_quota = self
+            .quota_usage
+            .iter()
+            .any(|usage| usage.is_over() && Some(usage.uid) == process.uid);
+        if over_quota {
+            text.push_str("[over quota] ");
+        }
+        if self.throttled.contains(&process.pid) {
+            text.push_str("[throttled] ");
+        }
+        if self.fuse_users.contains(&process.pid) {
+            text.push_str("[fuse] ");
+        }
+        if process.uninterruptible {
+            text.push_str("[D] ");
+        }
+        text.push_str(process.get_cmdline());
+        text
+    }
+
+    pub fn user_filter_label(&self) -> String {
+        if self.user_filter.is_empty() {
+            "all users".to_string()
+        } else {
+            self.user_filter.join(",")
+        }
+    }
+
+    pub fn is_highlighted(&self, process: &ProcessInfo) -> bool {
+        self.highlight
+            .as_ref()
+            .is_some_and(|pattern| pattern.is_match(process.get_cmdline()))
+    }
+
+    pub fn is_spiking(&self, id: i32) -> bool {
+        self.spikes.contains_key(&id)
+    }
+
+    /// Report the outcome of an action in the status line (and announce it)
+    pub fn report(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        self.announce(message.clone());
+        self.status = Some(message);
+    }
+
+    /// ID of the row under the cursor in selection mode
+    pub fn selected_id(&self) -> Option<i32> {
+        if !self.selection_mode {
+            return None;
+        }
+        self.selected_row
+            .and_then(|row| self.row_ids.get(row))
+            .copied()
+    }
+
+    /// Tag or untag the row under the cursor
+    pub fn toggle_tag_selected(&mut self) {
+        if let Some(id) = self.selected_id() {
+            if !self.tagged.remove(&id) {
+                self.tagged.insert(id);
+            }
+            self.announce(format!("{} tagged", self.tagged.len()));
+        }
+    }
+
+    /// Rows an action applies to: all tagged rows, or the selected one
+    pub fn action_targets(&self) -> Vec<i32> {
+        if self.tagged.is_empty() {
+            self.selected_id().into_iter().collect()
+        } else {
+            let mut ids: Vec<i32> = self.tagged.iter().copied().collect();
+            ids.sort_unstable();
+            ids
+        }
+    }
+
+    /// Show or hide an optional column, keeping display order stable
+    pub fn toggle_extra_column(&mut self, column: ExtraColumn) {
+        // Chosen from now on
+        self.implied_columns.retain(|c| *c != column);
+        if let Some(pos) = self.extra_columns.iter().position(|c| *c == column) {
+            self.extra_columns.remove(pos);
+        } else {
+            self.extra_columns.push(column);
+            self.extra_columns = ExtraColumn::ordered(&self.extra_columns);
+        }
+    }
+
+    /// Values shared by the optional columns; memory, mount points,
+    /// containers, I/O weights and descriptor counts are only looked up for
+    /// the `visible` rows, as is FUSE use for the COMMAND marker
+    fn column_context(
+        &mut self,
+        processes: &[&ProcessInfo],
+        visible: &[&ProcessInfo],
+        duration: f64,
+    ) -> ColumnContext {
+        let io_sum = processes
+            .iter()
+            .map(|p| {
+                let stats = if self.accumulated {
+                    &p.stats_accum
+                } else {
+                    &p.stats_delta
+                };
+                stats.read_bytes.saturating_add(
+                    stats
+                        .write_bytes
+                        .saturating_sub(stats.cancelled_write_bytes),
+                )
+            })
+            .fold(0u64, u64::saturating_add);
+
+        let pids: Vec<i32> = visible.iter().map(|p| p.pid).collect();
+        let max_age = Duration::from_secs_f64(self.delay);
+        let active: Vec<i32> = visible
+            .iter()
+            .filter(|p| p.did_some_io(self.accumulated))
+            .map(|p| p.pid)
+            .collect();
+        self.fuse_users = self.fuse.lookup(&active, max_age);
+        let memory = if self
+            .extra_columns
+            .iter()
+            .any(|c| matches!(c, ExtraColumn::Mem | ExtraColumn::Swap | ExtraColumn::Oom))
+        {
+            self.memory.lookup(&pids, max_age)
+        } else {
+            HashMap::new()
+        };
+        let (mounts, full_mounts) = if self.extra_columns.contains(&ExtraColumn::Mount) {
+            let full_mounts = self
+                .devices
+                .iter()
+                .flat_map(|device| &device.filesystems)
+                .filter(|fs| fs.is_nearly_full())
+                .map(|fs| fs.mount_point.clone())
+                .collect();
+            (self.mounts.lookup(&pids, max_age), full_mounts)
+        } else {
+            (HashMap::new(), HashSet::new())
+        };
+        let containers = if self.extra_columns.contains(&ExtraColumn::Container) {
+            self.containers.lookup(&pids)
+        } else {
+            HashMap::new()
+        };
+        let io_weights = if self.extra_columns.contains(&ExtraColumn::IoWeight) {
+            self.io_weights.lookup(&pids, max_age)
+        } else {
+            HashMap::new()
+        };
+        let fds = if self.extra_columns.contains(&ExtraColumn::Fds) {
+            self.fds.lookup(&pids, max_age)
+        } else {
+            HashMap::new()
+        };
+
+        ColumnContext {
+            accumulated: self.accumulated,
+            io_sum,
+            duration,
+            plain: self.a11y,
+            delay_ms: self.delay_ms,
+            memory,
+            mounts,
+            full_mounts,
+            containers,
+            io_weights,
+            fds,
+            net: self.count_net.then(|| self.net.clone()),
+            device_io: self.trace_devices.then(|| self.device_io.clone()),
+            syncs: self.trace_syncs.then(|| self.syncs.clone()),
+            writeback: if self.extra_columns.contains(&ExtraColumn::Writeback) {
+                self.writeback.clone()
+            } else {
+                HashMap::new()
+            },
         }
     }
 }
@@ -278,6 +1012,26 @@ impl Tui {
         Ok(())
     }
 
+    /// Restore the terminal and stop the process, as Ctrl-Z would outside raw
+    /// mode; returns once the shell continues us with `fg`, leaving the
+    /// terminal to [`Self::resume`] when the SIGCONT that did it is handled
+    pub fn suspend(&mut self) -> Result<()> {
+        self.exit()?;
+        // SIGTSTP is handled by iotop itself, so stop with the uncatchable SIGSTOP
+        nix::sys::signal::raise(nix::sys::signal::Signal::SIGSTOP)?;
+        Ok(())
+    }
+
+    /// Re-enter raw mode and the alternate screen after being continued, and
+    /// force a full redraw since the shell has drawn over the screen
+    pub fn resume(&mut self) -> Result<()> {
+        // The shell may have reset the terminal modes while we were stopped
+        self.exit()?;
+        self.enter()?;
+        self.terminal.clear()?;
+        Ok(())
+    }
+
     pub fn cancel(&self) {
         self.cancellation_token.cancel();
     }
@@ -305,6 +1059,7 @@ impl Tui {
                 state,
                 has_delay_acct,
             );
+            state.theme.apply(f.buffer_mut());
         })?;
         Ok(())
     }
@@ -313,25 +1068,1571 @@ impl Tui {
 impl Deref for Tui {
     type Target = Terminal<CrosstermBackend<Stdout>>;
 
-    fn deref(&self) -> &Self::Target {
-        &self.terminal
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for Tui {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = self.exit();
+    }
+}
+
+fn render_ui(
+    f: &mut Frame,
+    processes: &[&ProcessInfo],
+    total_io: (u64, u64),
+    actual_io: (u64, u64),
+    duration: f64,
+    state: &mut UIState,
+    has_delay_acct: bool,
+) {
+    let size = f.area();
+
+    if state.zoom.is_some() {
+        render_zoom(f, size, state, has_delay_acct);
+        render_alert_log(f, size, state);
+        render_prompt(f, size, state);
+        return;
+    }
+
+    if state.a11y {
+        render_a11y(
+            f,
+            size,
+            processes,
+            total_io,
+            actual_io,
+            duration,
+            state,
+            has_delay_acct,
+        );
+        render_alert_log(f, size, state);
+        render_column_chooser(f, size, state);
+        render_prompt(f, size, state);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(HEADER_HEIGHT), // Header with time and I/O stats
+            Constraint::Min(5),                // Process table
+        ])
+        .split(size);
+
+    render_header(f, chunks[0], total_io, actual_io, duration, state);
+
+    let table_area = if state.show_cgroups {
+        let panes = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(5), Constraint::Length(CGROUP_PANEL_HEIGHT)])
+            .split(chunks[1]);
+        render_cgroup_panel(f, panes[1], &state.cgroups);
+        panes[0]
+    } else {
+        chunks[1]
+    };
+
+    let table_area = if state.quotas.is_some() {
+        let panes = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(5), Constraint::Length(QUOTA_PANEL_HEIGHT)])
+            .split(table_area);
+        render_quota_panel(f, panes[1], &state.quota_usage);
+        panes[0]
+    } else {
+        table_area
+    };
+
+    let table_area = if state.show_devices {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(40), Constraint::Length(DEVICE_PANEL_WIDTH)])
+            .split(table_area);
+        render_device_panel(
+            f,
+            panes[1],
+            &state.devices,
+            &state.nfs_mounts,
+            state.swap.as_ref(),
+        );
+        panes[0]
+    } else {
+        table_area
+    };
+
+    let table_area = if state.show_cpus {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(40), Constraint::Length(CPU_PANEL_WIDTH)])
+            .split(table_area);
+        render_cpu_panel(f, panes[1], &state.iowait.cpus);
+        panes[0]
+    } else {
+        table_area
+    };
+
+    render_process_table(
+        f,
+        table_area,
+        processes,
+        total_io,
+        duration,
+        state,
+        has_delay_acct,
+    );
+
+    render_alert_log(f, size, state);
+    render_column_chooser(f, size, state);
+    render_prompt(f, size, state);
+}
+
+const DEVICE_PANEL_WIDTH: u16 = 75;
+
+/// Nearly full filesystems that are being written to
+const FULL_STYLE: Style = Style::new().fg(Color::Red).add_modifier(Modifier::BOLD);
+
+/// Draw per-device throughput, IOPS and latency next to the process table, followed
+/// by the nearly full filesystems, NFS mounts and swap traffic if there are any
+fn render_device_panel(
+    f: &mut Frame,
+    area: Rect,
+    devices: &[DeviceRate],
+    nfs_mounts: &[NfsRate],
+    swap: Option<&SwapRate>,
+) {
+    let header = Row::new(
+        [
+            "DEVICE", "READ", "WRITE", "R/s", "W/s", "R LAT", "W LAT", "UTIL",
+        ]
+        .map(|h| {
+            Cell::from(Text::from(h).alignment(if h == "DEVICE" {
+                Alignment::Left
+            } else {
+                Alignment::Right
+            }))
+        }),
+    )
+    .style(Style::default().bold());
+
+    let rows = devices.iter().map(|device| {
+        let busy = device.read_bytes_per_sec + device.write_bytes_per_sec > 0.0;
+        let name = if device.filling_up().next().is_some() {
+            Cell::from(format!("{} !", device.name)).style(FULL_STYLE)
+        } else {
+            Cell::from(device.name.clone())
+        };
+        Row::new([
+            name,
+            Cell::from(
+                Text::from(human_size(device.read_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            ),
+            Cell::from(
+                Text::from(human_size(device.write_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            ),
+            Cell::from(Text::from(format!("{:.0}", device.read_iops)).alignment(Alignment::Right)),
+            Cell::from(Text::from(format!("{:.0}", device.write_iops)).alignment(Alignment::Right)),
+            Cell::from(Text::from(format_ms(device.read_latency_ms())).alignment(Alignment::Right)),
+            Cell::from(
+                Text::from(format_ms(device.write_latency_ms())).alignment(Alignment::Right),
+            ),
+            Cell::from(
+                Text::from(format!("{:.0}%", device.utilization)).alignment(Alignment::Right),
+            ),
+        ])
+        .style(if busy {
+            Style::default().fg(Color::White)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        })
+    });
+
+    // Filesystems past NEARLY_FULL, in red while their device is written to
+    let nearly_full: Vec<(&DeviceRate, &FsUsage)> = devices
+        .iter()
+        .flat_map(|device| device.filesystems.iter().map(move |fs| (device, fs)))
+        .filter(|(_, fs)| fs.is_nearly_full())
+        .collect();
+    let fs_header = (!nearly_full.is_empty()).then(|| {
+        Row::new(["FILESYSTEM", "FREE", "", "", "", "", "", "USED"].map(|h| {
+            Cell::from(Text::from(h).alignment(if h == "FILESYSTEM" {
+                Alignment::Left
+            } else {
+                Alignment::Right
+            }))
+        }))
+        .style(Style::default().bold())
+    });
+    let fs_rows = nearly_full.into_iter().map(|(device, fs)| {
+        let filling_up = device.write_bytes_per_sec > 0.0;
+        Row::new([
+            Cell::from(fs.mount_point.clone()),
+            Cell::from(Text::from(human_size(fs.avail_bytes as i64)).alignment(Alignment::Right)),
+            Cell::from(""),
+            Cell::from(""),
+            Cell::from(""),
+            Cell::from(""),
+            Cell::from(""),
+            Cell::from(
+                Text::from(format!("{:.0}%", fs.used_percent())).alignment(Alignment::Right),
+            ),
+        ])
+        .style(if filling_up {
+            FULL_STYLE
+        } else {
+            Style::default().fg(Color::Yellow)
+        })
+    });
+
+    // NFS mounts have no utilization; their column shows the RPC round trip
+    let nfs_header = (!nfs_mounts.is_empty()).then(|| {
+        Row::new(
+            ["NFS MOUNT", "READ", "WRITE", "R/s", "W/s", "", "", "RTT"].map(|h| {
+                Cell::from(Text::from(h).alignment(if h == "NFS MOUNT" {
+                    Alignment::Left
+                } else {
+                    Alignment::Right
+                }))
+            }),
+        )
+        .style(Style::default().bold())
+    });
+    let nfs_rows = nfs_mounts.iter().map(|mount| {
+        let busy = mount.read_bytes_per_sec + mount.write_bytes_per_sec > 0.0;
+        Row::new([
+            Cell::from(mount.mount_point.clone()),
+            Cell::from(
+                Text::from(human_size(mount.read_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            ),
+            Cell::from(
+                Text::from(human_size(mount.write_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            ),
+            Cell::from(Text::from(format!("{:.0}", mount.read_iops)).alignment(Alignment::Right)),
+            Cell::from(Text::from(format!("{:.0}", mount.write_iops)).alignment(Alignment::Right)),
+            Cell::from(""),
+            Cell::from(""),
+            Cell::from(Text::from(format_ms(mount.rtt_ms)).alignment(Alignment::Right)),
+        ])
+        .style(if busy {
+            Style::default().fg(Color::White)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        })
+    });
+
+    // Swap counts pages, not requests, so only the byte rates are shown
+    let mut swap_rows = Vec::new();
+    if let Some(swap) = swap {
+        let sub_header = |headings: [&'static str; 8]| {
+            Row::new(headings.map(|h| {
+                Cell::from(Text::from(h).alignment(if h == headings[0] {
+                    Alignment::Left
+                } else {
+                    Alignment::Right
+                }))
+            }))
+            .style(Style::default().bold())
+        };
+        let rate_row = |name: &str, in_rate: f64, out_rate: f64| {
+            Row::new([
+                Cell::from(name.to_string()),
+                Cell::from(
+                    Text::from(human_size(in_rate as i64) + "/s").alignment(Alignment::Right),
+                ),
+                Cell::from(
+                    Text::from(human_size(out_rate as i64) + "/s").alignment(Alignment::Right),
+                ),
+            ])
+            .style(if in_rate + out_rate > 0.0 {
+                Style::default().fg(Color::White)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            })
+        };
+        swap_rows.push(sub_header(["SWAP", "IN", "OUT", "", "", "", "", ""]));
+        swap_rows.push(rate_row(
+            "devices",
+            swap.in_bytes_per_sec,
+            swap.out_bytes_per_sec,
+        ));
+        if let (Some(zswap_in), Some(zswap_out)) =
+            (swap.zswap_in_bytes_per_sec, swap.zswap_out_bytes_per_sec)
+        {
+            swap_rows.push(rate_row("zswap", zswap_in, zswap_out));
+        }
+        if !swap.zram.is_empty() {
+            swap_rows.push(sub_header([
+                "ZRAM", "DATA", "MEMORY", "", "", "", "", "RATIO",
+            ]));
+        }
+        for zram in &swap.zram {
+            swap_rows.push(
+                Row::new([
+                    Cell::from(zram.name.clone()),
+                    Cell::from(
+                        Text::from(human_size(zram.data_bytes as i64)).alignment(Alignment::Right),
+                    ),
+                    Cell::from(
+                        Text::from(human_size(zram.memory_bytes as i64))
+                            .alignment(Alignment::Right),
+                    ),
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from(Text::from(format_ratio(zram.ratio())).alignment(Alignment::Right)),
+                ])
+                .style(Style::default().fg(Color::White)),
+            );
+        }
+    }
+    let rows = rows
+        .chain(fs_header)
+        .chain(fs_rows)
+        .chain(nfs_header)
+        .chain(nfs_rows)
+        .chain(swap_rows);
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(8),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(6),
+            Constraint::Length(7),
+            Constraint::Length(7),
+            Constraint::Length(5),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title_top(create_toggle_title('d', "evices", true))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Gray))
+            .bg(Color::Black),
+    );
+    f.render_widget(table, area);
+}
+
+/// One line summarizing every device, for accessible mode
+fn device_summary(
+    devices: &[DeviceRate],
+    nfs_mounts: &[NfsRate],
+    swap: Option<&SwapRate>,
+) -> String {
+    if devices.is_empty() {
+        return "Devices: waiting for the next interval".to_string();
+    }
+    let parts: Vec<String> = devices
+        .iter()
+        .map(|d| {
+            format!(
+                "{} read {}/s write {}/s, {:.0} reads/s {:.0} writes/s, read latency {} write latency {}, {:.0}% busy",
+                d.name,
+                human_size(d.read_bytes_per_sec as i64),
+                human_size(d.write_bytes_per_sec as i64),
+                d.read_iops,
+                d.write_iops,
+                format_ms(d.read_latency_ms()),
+                format_ms(d.write_latency_ms()),
+                d.utilization
+            )
+        })
+        .collect();
+    let mut summary = format!("Devices: {}", parts.join("; "));
+    for device in devices {
+        for fs in device.filesystems.iter().filter(|fs| fs.is_nearly_full()) {
+            summary.push_str(&format!(
+                "; {} {:.0}% full, {} free{}",
+                fs.mount_point,
+                fs.used_percent(),
+                human_size(fs.avail_bytes as i64),
+                if device.write_bytes_per_sec > 0.0 {
+                    ", being written to"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+    for mount in nfs_mounts {
+        summary.push_str(&format!(
+            "; NFS {} read {}/s write {}/s, {:.0} reads/s {:.0} writes/s, round trip {}",
+            mount.mount_point,
+            human_size(mount.read_bytes_per_sec as i64),
+            human_size(mount.write_bytes_per_sec as i64),
+            mount.read_iops,
+            mount.write_iops,
+            format_ms(mount.rtt_ms)
+        ));
+    }
+    if let Some(swap) = swap {
+        summary.push_str(&format!(
+            "; swap in {}/s out {}/s",
+            human_size(swap.in_bytes_per_sec as i64),
+            human_size(swap.out_bytes_per_sec as i64)
+        ));
+        if let (Some(zswap_in), Some(zswap_out)) =
+            (swap.zswap_in_bytes_per_sec, swap.zswap_out_bytes_per_sec)
+        {
+            summary.push_str(&format!(
+                ", zswap in {}/s out {}/s",
+                human_size(zswap_in as i64),
+                human_size(zswap_out as i64)
+            ));
+        }
+        for zram in &swap.zram {
+            summary.push_str(&format!(
+                "; {} holds {} in {} of memory, ratio {}",
+                zram.name,
+                human_size(zram.data_bytes as i64),
+                human_size(zram.memory_bytes as i64),
+                format_ratio(zram.ratio())
+            ));
+        }
+    }
+    summary
+}
+
+/// Format the compression ratio of a zram device, `-` while it is empty
+fn format_ratio(ratio: Option<f64>) -> String {
+    ratio.map_or("-".to_string(), |ratio| format!("{:.1}x", ratio))
+}
+
+/// Format an average time in milliseconds, such as the RPC round trip of an
+/// NFS mount, `-` when there was nothing to time
+pub fn format_ms(ms: Option<f64>) -> String {
+    match ms {
+        Some(ms) if ms < 1.0 => format!("{:.2}ms", ms),
+        Some(ms) if ms < 10.0 => format!("{:.1}ms", ms),
+        Some(ms) => format!("{:.0}ms", ms),
+        None => "-".to_string(),
+    }
+}
+
+const CPU_PANEL_WIDTH: u16 = 27;
+
+/// Draw the iowait of each CPU next to the process table
+///
+/// A task blocked on a slow device leaves only the CPU it ran on idle in
+/// iowait, which the system-wide figure in the header averages away.
+fn render_cpu_panel(f: &mut Frame, area: Rect, cpus: &[CpuIowait]) {
+    let header = Row::new([
+        Cell::from("CPU"),
+        Cell::from(Text::from("IOWAIT").alignment(Alignment::Right)),
+        Cell::from(""),
+    ])
+    .style(Style::default().bold());
+
+    let rows = cpus.iter().map(|cpu| {
+        Row::new([
+            Cell::from(format!("cpu{}", cpu.cpu)),
+            Cell::from(Text::from(format!("{:.1}%", cpu.percent)).alignment(Alignment::Right)),
+            Cell::from(io_bar(cpu.percent / 100.0, 8)),
+        ])
+        .style(if cpu.percent > 0.0 {
+            pressure_style(cpu.percent)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        })
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Length(7),
+            Constraint::Length(8),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title_top(create_value_title('e', "CPU iowait".to_string(), true))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Gray))
+            .bg(Color::Black),
+    );
+    f.render_widget(table, area);
+}
+
+/// CPUs with iowait, worst first, for accessible mode
+fn cpu_summary(cpus: &[CpuIowait]) -> String {
+    let mut waiting: Vec<&CpuIowait> = cpus.iter().filter(|c| c.percent >= 0.05).collect();
+    if waiting.is_empty() {
+        return "CPU iowait: none".to_string();
+    }
+    waiting.sort_by(|a, b| b.percent.total_cmp(&a.percent));
+    let parts: Vec<String> = waiting
+        .iter()
+        .take(8)
+        .map(|c| format!("cpu{} {:.1}%", c.cpu, c.percent))
+        .collect();
+    format!("CPU iowait: {}", parts.join(", "))
+}
+
+/// Height of the cgroup panel, borders and header included
+const CGROUP_PANEL_HEIGHT: u16 = 10;
+
+/// Draw the busiest cgroups below the process table
+fn render_cgroup_panel(f: &mut Frame, area: Rect, cgroups: &[CgroupRate]) {
+    let header = Row::new(["CGROUP", "READ", "WRITE", "R/s", "W/s"].map(|h| {
+        Cell::from(Text::from(h).alignment(if h == "CGROUP" {
+            Alignment::Left
+        } else {
+            Alignment::Right
+        }))
+    }))
+    .style(Style::default().bold());
+
+    let rows = cgroups.iter().map(|cgroup| {
+        let path = if cgroup.throttled {
+            format!("{} [throttled]", cgroup.path)
+        } else {
+            cgroup.path.clone()
+        };
+        Row::new([
+            Cell::from(path),
+            Cell::from(
+                Text::from(human_size(cgroup.read_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            ),
+            Cell::from(
+                Text::from(human_size(cgroup.write_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            ),
+            Cell::from(Text::from(format!("{:.0}", cgroup.read_iops)).alignment(Alignment::Right)),
+            Cell::from(Text::from(format!("{:.0}", cgroup.write_iops)).alignment(Alignment::Right)),
+        ])
+        .style(Style::default().fg(Color::White))
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(20),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(6),
+            Constraint::Length(6),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title_top(create_value_title('t', "cgroups".to_string(), true))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Gray))
+            .bg(Color::Black),
+    );
+    f.render_widget(table, area);
+}
+
+/// Height of the quota panel, borders and header included
+const QUOTA_PANEL_HEIGHT: u16 = 8;
+
+/// Draw each user's I/O against their budget below the process table,
+/// users over budget first in red
+fn render_quota_panel(f: &mut Frame, area: Rect, usage: &[UserUsage]) {
+    let header = Row::new(["USER", "UID", "I/O", "BUDGET", "USED"].map(|h| {
+        Cell::from(Text::from(h).alignment(if h == "USER" {
+            Alignment::Left
+        } else {
+            Alignment::Right
+        }))
+    }))
+    .style(Style::default().bold());
+
+    let rows = usage.iter().map(|user| {
+        let budget = user.budget.map_or_else(
+            || "-".to_string(),
+            |budget| human_size(budget as i64) + "/s",
+        );
+        let used = user
+            .percent()
+            .map_or_else(|| "-".to_string(), |percent| format!("{:.0}%", percent));
+        Row::new([
+            Cell::from(user.user.clone()),
+            Cell::from(Text::from(user.uid.to_string()).alignment(Alignment::Right)),
+            Cell::from(Text::from(human_size(user.rate as i64) + "/s").alignment(Alignment::Right)),
+            Cell::from(Text::from(budget).alignment(Alignment::Right)),
+            Cell::from(Text::from(used).alignment(Alignment::Right)),
+        ])
+        .style(if user.is_over() {
+            FULL_STYLE
+        } else {
+            Style::default().fg(Color::White)
+        })
+    });
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Min(12),
+            Constraint::Length(8),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(6),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title_top(" Quotas ")
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Gray))
+            .bg(Color::Black),
+    );
+    f.render_widget(table, area);
+}
+
+/// One line listing the users over their I/O budget, for accessible mode
+fn quota_summary(usage: &[UserUsage]) -> String {
+    let over: Vec<String> = usage
+        .iter()
+        .filter(|user| user.is_over())
+        .map(|user| {
+            format!(
+                "{} {}/s of {}/s",
+                user.user,
+                human_size(user.rate as i64),
+                human_size(user.budget.unwrap_or_default() as i64)
+            )
+        })
+        .collect();
+    if over.is_empty() {
+        return "Quotas: all users within budget".to_string();
+    }
+    format!("Quotas: over budget {}", over.join("; "))
+}
+
+/// One line summarizing the busiest cgroups, for accessible mode
+fn cgroup_summary(cgroups: &[CgroupRate]) -> String {
+    if cgroups.is_empty() {
+        return "Cgroups: no I/O".to_string();
+    }
+    let parts: Vec<String> = cgroups
+        .iter()
+        .take(5)
+        .map(|c| {
+            format!(
+                "{} read {}/s write {}/s{}",
+                c.path,
+                human_size(c.read_bytes_per_sec as i64),
+                human_size(c.write_bytes_per_sec as i64),
+                if c.throttled { " throttled" } else { "" }
+            )
+        })
+        .collect();
+    format!("Cgroups: {}", parts.join("; "))
+}
+
+/// p50 and p99 of a latency histogram with its I/O count, e.g.
+/// `p50 512 us, p99 32.8 ms over 100 I/Os`
+fn latency_summary(histogram: &Histogram) -> String {
+    match (histogram.quantile(0.5), histogram.quantile(0.99)) {
+        (Some(p50), Some(p99)) => format!(
+            "p50 {}, p99 {} over {} I/Os",
+            format_latency(p50),
+            format_latency(p99),
+            histogram.count()
+        ),
+        _ => "no I/O".to_string(),
+    }
+}
+
+/// Height of each history chart in the zoom dashboard, borders included
+const ZOOM_CHART_HEIGHT: u16 = 6;
+
+/// Height of the traced files table in the zoom dashboard, header included
+const ZOOM_FILES_HEIGHT: u16 = 8;
+
+/// Height of the open files table in the zoom dashboard, header included
+const ZOOM_OPEN_FILES_HEIGHT: u16 = 8;
+
+/// Draw the full-screen dashboard of the zoomed row
+fn render_zoom(f: &mut Frame, area: Rect, state: &mut UIState, has_delay_acct: bool) {
+    let Some(zoom) = &state.zoom else {
+        return;
+    };
+    let max_age = Duration::from_secs_f64(state.delay);
+    let memory = zoom.process.as_ref().and_then(|process| {
+        state
+            .memory
+            .lookup(&[process.pid], max_age)
+            .remove(&process.pid)
+    });
+    if let Some(process) = &zoom.process {
+        state.fuse_users = state.fuse.lookup(&[process.pid], max_age);
+    }
+
+    let label = match state.grouping {
+        Some(grouping) if state.show_processes => grouping.label(),
+        _ if state.show_processes => "PID",
+        _ => "TID",
+    };
+    let current = zoom.current();
+    let mut info = Vec::new();
+    match &zoom.process {
+        Some(process) => {
+            info.push(format!("Command: {}", state.command_text(process)));
+            info.push(format!(
+                "PID {}, TID {}, user {}, priority {}, {} thread(s)",
+                process.pid,
+                process.tid,
+                process.get_user(),
+                process.get_prio(),
+                zoom.threads.len()
+            ));
+            if process.members.len() > 1 {
+                let pids: Vec<String> = process.members.iter().map(|pid| pid.to_string()).collect();
+                info.push(format!("Processes: {}", pids.join(", ")));
+            }
+            let mut now = format!(
+                "Now: read {}/s, write {}/s",
+                human_size(current.read as i64),
+                human_size(current.write as i64)
+            );
+            if has_delay_acct {
+                now.push_str(&format!(
+                    ", IO delay {:.2} %, swapin delay {:.2} %",
+                    current.io_delay, current.swapin_delay
+                ));
+            }
+            info.push(now);
+            let format_kb =
+                |kb: Option<u64>| kb.map_or("-".to_string(), |kb| human_size((kb * 1024) as i64));
+            info.push(format!(
+                "Total: read {}, written {}; memory: RSS {}, swap {}",
+                human_size(process.stats_accum.read_bytes as i64),
+                human_size(process.stats_accum.write_bytes as i64),
+                format_kb(memory.and_then(|m| m.rss_kb)),
+                format_kb(memory.and_then(|m| m.swap_kb))
+            ));
+            if state.fuse_users.contains(&process.pid) {
+                info.push(
+                    "Files open on a FUSE filesystem: its daemon's I/O for them shows up as \
+                     another row, and may be counted twice in the totals"
+                        .to_string(),
+                );
+            }
+            if state.trace_latency {
+                let latency = state.latency.get(&process.pid).copied().unwrap_or_default();
+                info.push(format!(
+                    "Block I/O latency: {}; since start {}",
+                    latency_summary(&latency.interval),
+                    latency_summary(&latency.total)
+                ));
+            }
+            if state.trace_files {
+                // Buffered writes reach the disk later through writeback and
+                // buffered reads may hit the page cache, which is where Total
+                // and Actual part ways
+                let split = CacheSplit::of(&zoom.files);
+                let rate = |bytes: f64| human_size(bytes as i64) + "/s";
+                info.push(format!(
+                    "File I/O: direct read {}, write {}; through the page cache read {}, write {}",
+                    rate(split.direct_read_bytes_per_sec),
+                    rate(split.direct_write_bytes_per_sec),
+                    rate(split.buffered_read_bytes_per_sec),
+                    rate(split.buffered_write_bytes_per_sec)
+                ));
+            }
+        }
+        None => info.push(format!("{} {} has exited", label, zoom.id)),
+    }
+
+    let read_title = history_title("Read", zoom, |r| r.read, |v| human_size(v as i64) + "/s");
+    let write_title = history_title("Write", zoom, |r| r.write, |v| human_size(v as i64) + "/s");
+    let delay_title = history_title("IO delay", zoom, |r| r.io_delay, |v| format!("{:.2} %", v));
+
+    if state.a11y {
+        let mut lines: Vec<Line> = vec![Line::from(format!(
+            "Zoom on {} {}, Escape or z to go back",
+            label, zoom.id
+        ))];
+        lines.extend(info.into_iter().map(Line::from));
+        lines.push(Line::from(read_title));
+        lines.push(Line::from(write_title));
+        if has_delay_acct {
+            lines.push(Line::from(delay_title));
+        }
+        if state.trace_files {
+            if zoom.files.is_empty() {
+                lines.push(Line::from("No file reads or writes traced"));
+            }
+            for file in &zoom.files {
+                lines.push(Line::from(format!(
+                    "File {}: read {}/s, write {}/s",
+                    file_label(file),
+                    human_size(file.read_bytes_per_sec as i64),
+                    human_size(file.write_bytes_per_sec as i64)
+                )));
+            }
+        }
+        for file in &zoom.open_files {
+            lines.push(Line::from(open_file_summary(file)));
+        }
+        for thread in &zoom.threads {
+            lines.push(Line::from(thread_summary(thread, has_delay_acct)));
+        }
+        f.render_widget(Paragraph::new(lines), area);
+        return;
+    }
+
+    let block = Block::default()
+        .title_top(create_value_title(
+            'z',
+            format!("{} {}", label, zoom.id),
+            true,
+        ))
+        .title_bottom(
+            Line::from(vec![
+                Span::raw("┘"),
+                Span::styled(
+                    "Esc/z: back to the table",
+                    Style::default().fg(COLOR_HIGHLIGHT),
+                ),
+                Span::raw("└"),
+            ])
+            .left_aligned(),
+        )
+        .bg(Color::Black)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(Color::Gray));
+    let inner = block.inner(area);
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+
+    let mut constraints = vec![
+        Constraint::Length(info.len() as u16),
+        Constraint::Length(ZOOM_CHART_HEIGHT),
+        Constraint::Length(ZOOM_CHART_HEIGHT),
+    ];
+    if has_delay_acct {
+        constraints.push(Constraint::Length(ZOOM_CHART_HEIGHT));
+    }
+    if state.trace_files {
+        constraints.push(Constraint::Length(ZOOM_FILES_HEIGHT));
+    }
+    let show_open_files = !zoom.open_files.is_empty();
+    if show_open_files {
+        constraints.push(Constraint::Length(ZOOM_OPEN_FILES_HEIGHT));
+    }
+    constraints.push(Constraint::Min(3));
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(inner);
+
+    let info: Vec<Line> = info.into_iter().map(Line::from).collect();
+    f.render_widget(Paragraph::new(info), chunks[0]);
+
+    let read: Vec<u64> = zoom.history.iter().map(|r| r.read as u64).collect();
+    render_history_chart(f, chunks[1], read_title, &read, None, COLOR_READ);
+    let write: Vec<u64> = zoom.history.iter().map(|r| r.write as u64).collect();
+    render_history_chart(f, chunks[2], write_title, &write, None, COLOR_WRITE);
+    if has_delay_acct {
+        // Hundredths of a percent, on a fixed 0-100 % scale
+        let delay: Vec<u64> = zoom
+            .history
+            .iter()
+            .map(|r| (r.io_delay * 100.0) as u64)
+            .collect();
+        render_history_chart(f, chunks[3], delay_title, &delay, Some(10_000), COLOR_IO);
+    }
+    if state.trace_files {
+        let index = chunks.len() - 2 - usize::from(show_open_files);
+        render_files_table(f, chunks[index], &zoom.files);
+    }
+    if show_open_files {
+        render_open_files_table(f, chunks[chunks.len() - 2], &zoom.open_files);
+    }
+
+    let mut header = vec!["TID", "NAME", "READ", "WRITE"];
+    if has_delay_acct {
+        header.push("IO");
+    }
+    let header = Row::new(header.into_iter().map(|h| {
+        Cell::from(Text::from(h).alignment(if h == "NAME" {
+            Alignment::Left
+        } else {
+            Alignment::Right
+        }))
+    }))
+    .style(Style::default().bold());
+    let rows = zoom.threads.iter().map(|thread| {
+        let rate = |value: fn(&IntervalRates) -> String| {
+            thread.rates.as_ref().map_or("-".to_string(), value)
+        };
+        let mut cells = vec![
+            Cell::from(Text::from(thread.tid.to_string()).alignment(Alignment::Right)),
+            Cell::from(thread.name.clone()),
+            Cell::from(
+                Text::from(rate(|r| human_size(r.read as i64) + "/s")).alignment(Alignment::Right),
+            )
+            .style(Style::default().fg(COLOR_READ)),
+            Cell::from(
+                Text::from(rate(|r| human_size(r.write as i64) + "/s")).alignment(Alignment::Right),
+            )
+            .style(Style::default().fg(COLOR_WRITE)),
+        ];
+        if has_delay_acct {
+            cells.push(
+                Cell::from(
+                    Text::from(rate(|r| format!("{:.2} %", r.io_delay)))
+                        .alignment(Alignment::Right),
+                )
+                .style(Style::default().fg(COLOR_IO)),
+            );
+        }
+        Row::new(cells)
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Min(16),
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Length(9),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(if zoom.process_mode {
+                " Threads (per-thread rates in thread mode, p) "
+            } else {
+                " Threads "
+            })
+            .borders(Borders::TOP)
+            .border_style(Style::default().fg(Color::Gray)),
+    );
+    f.render_widget(table, chunks[chunks.len() - 1]);
+}
+
+/// A traced file's path, marked when opened with `O_DIRECT`
+fn file_label(file: &FileRate) -> String {
+    if file.direct {
+        format!("{} [direct]", file.path)
+    } else {
+        file.path.clone()
+    }
+}
+
+/// Files the zoomed process read or wrote in the interval, busiest first
+fn render_files_table(f: &mut Frame, area: Rect, files: &[FileRate]) {
+    let header = Row::new(["READ", "WRITE", "FILE"].into_iter().map(|h| {
+        Cell::from(Text::from(h).alignment(if h == "FILE" {
+            Alignment::Left
+        } else {
+            Alignment::Right
+        }))
+    }))
+    .style(Style::default().bold());
+    let rows = files.iter().map(|file| {
+        Row::new(vec![
+            Cell::from(
+                Text::from(human_size(file.read_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            )
+            .style(Style::default().fg(COLOR_READ)),
+            Cell::from(
+                Text::from(human_size(file.write_bytes_per_sec as i64) + "/s")
+                    .alignment(Alignment::Right),
+            )
+            .style(Style::default().fg(COLOR_WRITE)),
+            Cell::from(file_label(file)),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(11),
+            Constraint::Length(11),
+            Constraint::Min(16),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(" Files (traced with eBPF) ")
+            .borders(Borders::TOP)
+            .border_style(Style::default().fg(Color::Gray)),
+    );
+    f.render_widget(table, area);
+}
+
+fn render_open_files_table(f: &mut Frame, area: Rect, files: &[OpenFile]) {
+    let header = Row::new(
+        ["FD", "OFFSET", "SIZE", "DONE", "PROGRESS", "FILE"].map(|h| {
+            Cell::from(Text::from(h).alignment(if h == "FILE" {
+                Alignment::Left
+            } else {
+                Alignment::Right
+            }))
+        }),
+    )
+    .style(Style::default().bold());
+    let rows = files.iter().map(|file| {
+        let right = |text: String| Cell::from(Text::from(text).alignment(Alignment::Right));
+        let moving = file.progress.is_some_and(|progress| progress > 0.0);
+        Row::new(vec![
+            right(file.fd.to_string()),
+            right(human_size(file.pos as i64)),
+            right(
+                file.size
+                    .map_or("-".to_string(), |size| human_size(size as i64)),
+            ),
+            right(
+                file.percent()
+                    .map_or("-".to_string(), |p| format!("{:.1} %", p)),
+            ),
+            right(
+                file.progress
+                    .map_or("-".to_string(), |rate| human_size(rate as i64) + "/s"),
+            ),
+            Cell::from(file.path.clone()),
+        ])
+        .style(if moving {
+            Style::default().fg(COLOR_HIGHLIGHT)
+        } else {
+            Style::default()
+        })
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(5),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(11),
+            Constraint::Min(16),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .title(" Open files (offsets from /proc/[pid]/fdinfo) ")
+            .borders(Borders::TOP)
+            .border_style(Style::default().fg(Color::Gray)),
+    );
+    f.render_widget(table, area);
+}
+
+/// Chart title with the current and peak value of one zoom history series
+fn history_title(
+    name: &str,
+    zoom: &ZoomView,
+    value: impl Fn(&IntervalRates) -> f64,
+    format: impl Fn(f64) -> String,
+) -> String {
+    let peak = zoom.history.iter().map(&value).fold(0.0, f64::max);
+    format!(
+        "{}: now {}, peak {} over {} intervals",
+        name,
+        format(value(&zoom.current())),
+        format(peak),
+        zoom.history.len()
+    )
+}
+
+/// Draw the most recent values of a series that fit in `area`
+fn render_history_chart(
+    f: &mut Frame,
+    area: Rect,
+    title: String,
+    data: &[u64],
+    max: Option<u64>,
+    color: Color,
+) {
+    let width = area.width.saturating_sub(2) as usize;
+    let data = &data[data.len().saturating_sub(width)..];
+    let mut sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .title(format!(" {} ", title))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(Color::Gray)),
+        )
+        .data(data)
+        .style(Style::default().fg(color));
+    if let Some(max) = max {
+        sparkline = sparkline.max(max);
+    }
+    f.render_widget(sparkline, area);
+}
+
+/// One line per thread of the zoomed process, for accessible mode
+fn thread_summary(thread: &ThreadRow, has_delay_acct: bool) -> String {
+    let Some(rates) = thread.rates else {
+        return format!("Thread {} {}", thread.tid, thread.name);
+    };
+    let mut line = format!(
+        "Thread {} {}: read {}/s, write {}/s",
+        thread.tid,
+        thread.name,
+        human_size(rates.read as i64),
+        human_size(rates.write as i64)
+    );
+    if has_delay_acct {
+        line.push_str(&format!(", IO delay {:.2} %", rates.io_delay));
+    }
+    line
+}
+
+/// One line per open file of the zoomed process, for accessible mode
+fn open_file_summary(file: &OpenFile) -> String {
+    let mut line = format!(
+        "Open file {} (fd {}): offset {}",
+        file.path,
+        file.fd,
+        human_size(file.pos as i64)
+    );
+    if let Some(size) = file.size {
+        line.push_str(&format!(" of {}", human_size(size as i64)));
+    }
+    if let Some(percent) = file.percent() {
+        line.push_str(&format!(" ({:.1} %)", percent));
+    }
+    if let Some(progress) = file.progress {
+        line.push_str(&format!(", moving {}/s", human_size(progress as i64)));
+    }
+    line
+}
+
+/// Number of alerts shown in the alert log popup
+const ALERT_LOG_LINES: usize = 15;
+
+/// Draw the alert log, newest first, when it is open
+fn render_alert_log(f: &mut Frame, area: Rect, state: &UIState) {
+    if !state.show_alerts {
+        return;
+    }
+
+    let mut lines = vec![Line::from(if state.alert_thresholds.is_enabled() {
+        format!("Alerts: {} logged, l or Esc to close", state.alerts.len())
+    } else {
+        "Alerts: no thresholds set (--alert-read, --alert-write)".to_string()
+    })];
+    lines.extend(
+        state
+            .alerts
+            .entries()
+            .take(ALERT_LOG_LINES)
+            .map(|alert| Line::from(format!("{} {}", alert.time, alert.message))),
+    );
+
+    render_popup(f, area, " Alerts ", lines, state.a11y);
+}
+
+/// Draw the optional column chooser on top of the table when it is open
+fn render_column_chooser(f: &mut Frame, area: Rect, state: &UIState) {
+    let Some(cursor) = state.column_chooser else {
+        return;
+    };
+
+    let mut lines = vec![Line::from(
+        "Columns: Up/Down to move, Space to toggle, Esc to close",
+    )];
+    for (idx, column) in ExtraColumn::ALL.iter().enumerate() {
+        let checked = if state.extra_columns.contains(column) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let text = format!(
+            "{} {} {:<8} {}",
+            if idx == cursor { ">" } else { " " },
+            checked,
+            column.name(),
+            column.description()
+        );
+        if idx == cursor && !state.a11y {
+            lines.push(Line::from(text).bold().fg(COLOR_HIGHLIGHT));
+        } else {
+            lines.push(Line::from(text));
+        }
+    }
+
+    render_popup(f, area, " Columns ", lines, state.a11y);
+}
+
+/// Draw the text prompt on top of the table when it is open
+fn render_prompt(f: &mut Frame, area: Rect, state: &UIState) {
+    let Some(ref prompt) = state.prompt else {
+        return;
+    };
+    if prompt.kind == PromptKind::Where {
+        render_filter_bar(f, area, prompt);
+        return;
+    }
+
+    let lines = vec![
+        Line::from(prompt.kind.question(state.action_targets().len())),
+        Line::from(format!("> {}_", prompt.input)),
+        Line::from("Enter to confirm, Esc to cancel"),
+    ];
+    render_popup(f, area, prompt.kind.title(), lines, state.a11y);
+}
+
+/// Draw the F4 filter editor on the bottom line, so that the rows it keeps
+/// stay visible while it is typed
+fn render_filter_bar(f: &mut Frame, area: Rect, prompt: &Prompt) {
+    if area.height == 0 {
+        return;
+    }
+    let bar = Rect {
+        y: area.y + area.height - 1,
+        height: 1,
+        ..area
+    };
+    let mut spans = vec![
+        Span::styled(
+            format!("{}: ", prompt.kind.question(0)),
+            Style::default().fg(COLOR_HIGHLIGHT).bold(),
+        ),
+        Span::raw(format!("{}_", prompt.input)),
+    ];
+    if let Some(error) = &prompt.error {
+        spans.push(Span::styled(
+            format!("  {}", error),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    f.render_widget(Clear, bar);
+    f.render_widget(Paragraph::new(Line::from(spans)), bar);
+}
+
+/// Draw `lines` in a box centered in `area`, without a border in
+/// accessible mode
+fn render_popup(f: &mut Frame, area: Rect, title: &str, lines: Vec<Line>, plain: bool) {
+    let width = lines
+        .iter()
+        .map(|l| l.width() as u16)
+        .max()
+        .unwrap_or(0)
+        .max(title.len() as u16)
+        .saturating_add(4)
+        .min(area.width);
+    let height = (lines.len() as u16).saturating_add(2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    f.render_widget(Clear, popup);
+    let mut paragraph = Paragraph::new(lines);
+    if !plain {
+        paragraph = paragraph.block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(COLOR_HIGHLIGHT))
+                .bg(Color::Black)
+                .title(title.to_string()),
+        );
+    }
+    f.render_widget(paragraph, popup);
+}
+
+fn render_header(
+    f: &mut Frame,
+    area: Rect,
+    total_io: (u64, u64),
+    actual_io: (u64, u64),
+    duration: f64,
+    state: &UIState,
+) {
+    let total_read_str = format_bandwidth(total_io.0, duration);
+    let total_write_str = format_bandwidth(total_io.1, duration);
+    let actual_read_str = format_bandwidth(actual_io.0, duration);
+    let actual_write_str = format_bandwidth(actual_io.1, duration);
+
+    let mut actual_line = vec![
+        Span::styled("Actual DISK READ: ", Style::default().fg(Color::White)),
+        Span::styled(
+            format!("{:>11}", actual_read_str),
+            Style::default().fg(Color::White),
+        ),
+        Span::raw("  │  "),
+        Span::styled("Actual DISK WRITE: ", Style::default().fg(Color::White)),
+        Span::styled(
+            format!("{:>11}", actual_write_str),
+            Style::default().fg(Color::White),
+        ),
+    ];
+    if let Some(dirty) = state.dirty {
+        let size = |kb: u64| format!("{:>9}", human_size(kb.saturating_mul(1024) as i64));
+        actual_line.extend([
+            Span::raw("  │  "),
+            Span::styled("Dirty: ", Style::default().fg(Color::White)),
+            Span::styled(size(dirty.dirty_kb), Style::default().fg(Color::White)),
+            Span::raw("  "),
+            Span::styled("Writeback: ", Style::default().fg(Color::White)),
+            Span::styled(size(dirty.writeback_kb), Style::default().fg(Color::White)),
+        ]);
+    }
+    if let Some(swap) = &state.swap {
+        let rate = |bytes_per_sec: f64| format!("{:>11}", human_size(bytes_per_sec as i64) + "/s");
+        actual_line.extend([
+            Span::raw("  │  "),
+            Span::styled("Swap in: ", Style::default().fg(Color::White)),
+            Span::styled(
+                rate(swap.in_bytes_per_sec),
+                Style::default().fg(Color::White),
+            ),
+            Span::raw("  "),
+            Span::styled("out: ", Style::default().fg(Color::White)),
+            Span::styled(
+                rate(swap.out_bytes_per_sec),
+                Style::default().fg(Color::White),
+            ),
+        ]);
+    }
+
+    let mut total_line = vec![
+        Span::styled("Total DISK READ: ", Style::default().fg(Color::White)),
+        Span::styled(
+            format!("{:>12}", total_read_str),
+            Style::default().fg(Color::White),
+        ),
+        Span::raw("  │  "),
+        Span::styled("Total DISK WRITE: ", Style::default().fg(Color::White)),
+        Span::styled(
+            format!("{:>12}", total_write_str),
+            Style::default().fg(Color::White),
+        ),
+    ];
+    if let Some(device) = diskstats::busiest(&state.devices) {
+        total_line.extend([
+            Span::raw("  │  "),
+            Span::styled("Busiest: ", Style::default().fg(Color::White)),
+            Span::styled(
+                format!("{:>4.0}%", device.utilization),
+                busy_style(device.utilization),
+            ),
+            Span::styled(
+                format!(" {}", device.name),
+                Style::default().fg(Color::White),
+            ),
+            Span::styled("  queue ", Style::default().fg(Color::White)),
+            Span::styled(
+                format!("{:.1}", device.queue_depth),
+                Style::default().fg(Color::White),
+            ),
+        ]);
+    }
+
+    let mut system_line = pressure_line(state.pressure, state.iowait.total);
+    system_line
+        .spans
+        .extend(load_spans(state.load, state.uninterruptible));
+    let text = vec![Line::from(total_line), Line::from(actual_line), system_line];
+
+    let mut block = Block::default()
+        .title_top(
+            Line::from(vec![
+                Span::raw("┐"),
+                Span::styled(
+                    format!("{}", chrono::Local::now().format("%H:%M:%S")),
+                    Style::default().fg(Color::White).bold(),
+                ),
+                Span::raw("┌"),
+            ])
+            .centered(),
+        )
+        .title_top(
+            Line::from(vec![
+                Span::raw("┐"),
+                Span::styled(state.session_summary(), Style::default().fg(Color::White)),
+                Span::raw("┌"),
+            ])
+            .right_aligned(),
+        )
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(Color::Gray))
+        .bg(Color::Black)
+        .title(" iotop - I/O Monitor ");
+    if state.procfs_fallback {
+        block = block.title_bottom(
+            Line::from(format!(" {} ", PROCFS_FALLBACK_NOTICE))
+                .style(Style::default().fg(Color::Yellow).bold()),
+        );
+    }
+
+    let paragraph = Paragraph::new(text).block(block);
+    f.render_widget(paragraph, area);
+}
+
+/// Stall percentages above these are shown in yellow and red
+const PRESSURE_WARN: f64 = 10.0;
+const PRESSURE_CRIT: f64 = 50.0;
+
+fn pressure_style(percent: f64) -> Style {
+    if percent >= PRESSURE_CRIT {
+        Style::default().fg(Color::Red).bold()
+    } else if percent >= PRESSURE_WARN {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    }
+}
+
+/// Device utilization from which the busiest disk is shown in yellow and red
+const BUSY_WARN: f64 = 80.0;
+const BUSY_CRIT: f64 = 95.0;
+
+fn busy_style(percent: f64) -> Style {
+    if percent >= BUSY_CRIT {
+        Style::default().fg(Color::Red).bold()
+    } else if percent >= BUSY_WARN {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    }
+}
+
+/// Header line with the share of time tasks stalled on I/O, and the share
+/// of CPU time idle in iowait
+fn pressure_line(pressure: Option<IoPressure>, iowait: Option<f64>) -> Line<'static> {
+    let value = |percent: f64| Span::styled(format!("{:>6.2}%", percent), pressure_style(percent));
+    let iowait = iowait.map(|percent| {
+        [
+            Span::raw("  │  "),
+            Span::styled("iowait: ", Style::default().fg(Color::White)),
+            value(percent),
+        ]
+    });
+    let Some(p) = pressure else {
+        let mut spans = vec![Span::styled(
+            "I/O PRESSURE: unavailable",
+            Style::default().fg(Color::DarkGray),
+        )];
+        spans.extend(iowait.into_iter().flatten());
+        return Line::from(spans);
+    };
+    let mut spans = vec![
+        Span::styled("I/O PRESSURE some: ", Style::default().fg(Color::White)),
+        value(p.some_avg10),
+        Span::raw(" "),
+        value(p.some_avg60),
+        Span::raw("  │  "),
+        Span::styled("full: ", Style::default().fg(Color::White)),
+        value(p.full_avg10),
+        Span::raw(" "),
+        value(p.full_avg60),
+        Span::styled("  (avg10 avg60)", Style::default().fg(Color::DarkGray)),
+    ];
+    spans.extend(iowait.into_iter().flatten());
+    Line::from(spans)
+}
+
+/// Header spans with the load averages, the tasks runnable system-wide and
+/// the monitored tasks in D state
+fn load_spans(load: Option<LoadAvg>, uninterruptible: usize) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    if let Some(load) = load {
+        spans.extend([
+            Span::raw("  │  "),
+            Span::styled("load: ", Style::default().fg(Color::White)),
+            Span::styled(
+                format!("{:.2} {:.2} {:.2}", load.one, load.five, load.fifteen),
+                Style::default().fg(Color::White),
+            ),
+        ]);
+    }
+    spans.extend([
+        Span::raw("  │  "),
+        Span::styled("tasks: ", Style::default().fg(Color::White)),
+    ]);
+    if let Some(load) = load {
+        spans.push(Span::styled(
+            format!("{} running, ", load.running),
+            Style::default().fg(Color::White),
+        ));
+    }
+    let blocked_style = if uninterruptible > 0 {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    spans.push(Span::styled(
+        format!("{} in D", uninterruptible),
+        blocked_style,
+    ));
+    spans
+}
+
+/// Load and task counts for accessible mode, spelled out
+fn load_summary(load: Option<LoadAvg>, uninterruptible: usize) -> String {
+    let blocked = format!("{} tasks in uninterruptible sleep", uninterruptible);
+    match load {
+        Some(load) => format!(
+            "Load {:.2}, {:.2}, {:.2}; {} tasks running, {}",
+            load.one, load.five, load.fifteen, load.running, blocked
+        ),
+        None => blocked,
     }
 }
 
-impl DerefMut for Tui {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.terminal
+/// Pressure and iowait for accessible mode, spelled out
+fn pressure_summary(pressure: Option<IoPressure>, iowait: Option<f64>) -> String {
+    let pressure = match pressure {
+        Some(p) => format!(
+            "I/O pressure: some {:.2}% over 10s, {:.2}% over 60s; full {:.2}% over 10s, {:.2}% over 60s",
+            p.some_avg10, p.some_avg60, p.full_avg10, p.full_avg60
+        ),
+        None => "I/O pressure unavailable".to_string(),
+    };
+    match iowait {
+        Some(percent) => format!("{}; CPU iowait {:.2}%", pressure, percent),
+        None => pressure,
     }
 }
 
-impl Drop for Tui {
-    fn drop(&mut self) {
-        let _ = self.exit();
+pub fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
     }
 }
 
-fn render_ui(
+/// Render the whole UI as plain, linear text for screen readers.
+///
+/// No borders, box-drawing titles or scrollbars are drawn; every process is a
+/// single line of text and all toggles are spelled out in words.
+#[allow(clippy::too_many_arguments)]
+fn render_a11y(
     f: &mut Frame,
+    area: Rect,
     processes: &[&ProcessInfo],
     total_io: (u64, u64),
     actual_io: (u64, u64),
@@ -339,82 +2640,334 @@ fn render_ui(
     state: &mut UIState,
     has_delay_acct: bool,
 ) {
-    let size = f.area();
+    let available_height = visible_rows(area.height, state);
+    let total_processes = processes.len();
 
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(4), // Header with time and I/O stats
-            Constraint::Min(5),    // Process table
-        ])
-        .split(size);
+    let max_scroll = total_processes.saturating_sub(available_height);
+    state.scroll_offset = state.scroll_offset.min(max_scroll);
+    let end = (state.scroll_offset + available_height).min(total_processes);
+
+    let mut status = vec![
+        format!("Sort by {}", state.sort_column.as_str()),
+        format!("reversed {}", on_off(!state.sort_reverse)),
+        format!("accumulated {}", on_off(state.accumulated)),
+        format!("only active {}", on_off(state.only_active)),
+        format!("only blocked {}", on_off(state.blocked_only)),
+        format!("average {}", state.average_window.label()),
+        format!("user {}", state.user_filter_label()),
+        format!(
+            "PIDs {}",
+            if state.pid_filter.is_empty() {
+                "all".to_string()
+            } else {
+                state.pid_filter.to_string()
+            }
+        ),
+        format!(
+            "units {}",
+            if state.unit_filter.is_empty() {
+                "all".to_string()
+            } else {
+                state.unit_filter.to_string()
+            }
+        ),
+        format!("processes {}", on_off(state.show_processes)),
+    ];
+    if let Some(grouping) = state.grouping.filter(|_| state.show_processes) {
+        status.push(format!("rows by {}", grouping.label()));
+    }
+    if let Some(pattern) = &state.highlight {
+        status.push(format!("highlighting {}", pattern));
+    }
+    if let Some(preset) = &state.preset {
+        status.push(format!("preset {}", preset));
+    }
+    if let Some(condition) = &state.condition {
+        status.push(format!("filter {}", condition));
+    }
+    if let Some(device) = &state.device_filter {
+        status.push(format!("device {}", device));
+    }
+    if let Some(prefix) = &state.path_filter {
+        status.push(format!("files under {}", prefix.display()));
+    }
+    if state.rewind > 0 {
+        status.push(format!("{} intervals back", state.rewind));
+    }
+    if has_delay_acct {
+        status.push(format!(
+            "delays in {}",
+            if state.delay_ms {
+                "milliseconds"
+            } else {
+                "percent"
+            }
+        ));
+    }
+    status.push(format!(
+        "rows {} to {} of {}",
+        if total_processes > 0 {
+            state.scroll_offset + 1
+        } else {
+            0
+        },
+        end,
+        total_processes
+    ));
+
+    let mut lines = vec![
+        Line::from(format!(
+            "iotop {}, {}{}{}",
+            chrono::Local::now().format("%H:%M:%S"),
+            state.session_summary(),
+            if state.paused { ", paused" } else { "" },
+            if state.procfs_fallback {
+                format!(". {}", PROCFS_FALLBACK_NOTICE)
+            } else {
+                String::new()
+            }
+        )),
+        Line::from(format!(
+            "Total disk read {}, total disk write {}{}",
+            format_bandwidth(total_io.0, duration),
+            format_bandwidth(total_io.1, duration),
+            diskstats::busiest(&state.devices).map_or(String::new(), |device| format!(
+                ", busiest disk {} {:.0}% busy with {:.1} requests queued",
+                device.name, device.utilization, device.queue_depth
+            ))
+        )),
+        Line::from(format!(
+            "Actual disk read {}, actual disk write {}{}{}",
+            format_bandwidth(actual_io.0, duration),
+            format_bandwidth(actual_io.1, duration),
+            state.dirty.map_or(String::new(), |dirty| format!(
+                ", dirty {}, writeback {}",
+                human_size(dirty.dirty_kb.saturating_mul(1024) as i64),
+                human_size(dirty.writeback_kb.saturating_mul(1024) as i64)
+            )),
+            state.swap.as_ref().map_or(String::new(), |swap| format!(
+                ", swap in {}/s, swap out {}/s",
+                human_size(swap.in_bytes_per_sec as i64),
+                human_size(swap.out_bytes_per_sec as i64)
+            ))
+        )),
+        Line::from(pressure_summary(state.pressure, state.iowait.total)),
+        Line::from(load_summary(state.load, state.uninterruptible)),
+        Line::from(status.join(", ")),
+        Line::from(state.announcement.clone().unwrap_or_default()),
+    ];
+    if state.show_devices {
+        lines.push(Line::from(device_summary(
+            &state.devices,
+            &state.nfs_mounts,
+            state.swap.as_ref(),
+        )));
+    }
+    if state.show_cgroups {
+        lines.push(Line::from(cgroup_summary(&state.cgroups)));
+    }
+    if state.quotas.is_some() {
+        lines.push(Line::from(quota_summary(&state.quota_usage)));
+    }
+    if state.show_cpus {
+        lines.push(Line::from(cpu_summary(&state.iowait.cpus)));
+    }
+
+    let mut header = format!(
+        "   {:>7} {:>5} {:<8} {:>11} {:>11}",
+        "TID", "PRIO", "USER", "DISK READ", "DISK WRITE"
+    );
+    if has_delay_acct {
+        header.push_str(&format!(" {:>8} {:>8}", "SWAPIN", "IO"));
+    }
+    for column in &state.extra_columns {
+        header.push_str(&format!(" {:>8}", column.header().trim_end_matches(':')));
+    }
+    header.push_str(" COMMAND");
+
+    let ctx = state.column_context(processes, &processes[state.scroll_offset..end], duration);
+    lines.push(Line::from(header));
+
+    for (idx, process) in processes[state.scroll_offset..end].iter().enumerate() {
+        let stats = if state.accumulated {
+            &process.stats_accum
+        } else {
+            &process.stats_delta
+        };
+        let (read_str, write_str) = format_io_pair(stats, duration, state.accumulated);
+
+        let is_selected =
+            state.selection_mode && state.selected_row == Some(state.scroll_offset + idx);
+        let mut line = format!(
+            "{}{} {:>7} {:>5} {:<8} {:>11} {:>11}",
+            if is_selected { ">" } else { " " },
+            if state.tagged.contains(&process.tid) {
+                "+"
+            } else if state.is_spiking(process.tid) {
+                "!"
+            } else if state.is_highlighted(process) {
+                "*"
+            } else {
+                " "
+            },
+            process.tid,
+            process.get_prio(),
+            process.get_user(),
+            read_str,
+            write_str
+        );
+        if has_delay_acct {
+            line.push_str(&format!(
+                " {:>8} {:>8}",
+                format_delay(stats.swapin_delay_total, duration, state.delay_ms),
+                format_delay(stats.blkio_delay_total, duration, state.delay_ms)
+            ));
+        }
+        for column in &state.extra_columns {
+            line.push_str(&format!(" {:>8}", column_text(*column, process, &ctx)));
+        }
+        line.push(' ');
+        line.push_str(&state.command_text(process));
+        for (thread, share) in state
+            .hot_thread_share()
+            .map_or_else(Vec::new, |share| process.hot_threads(share))
+        {
+            line.push_str(&format!(
+                ", thread {} {} does {:.0} percent of its I/O",
+                thread.tid,
+                proc_reader::thread_name(process.pid, thread.tid),
+                share * 100.0
+            ));
+        }
+        lines.push(Line::from(line));
+    }
 
-    render_header(f, chunks[0], total_io, actual_io, duration);
+    let totals = sum_stats(processes, state.accumulated);
+    let (read_str, write_str) = format_io_pair(&totals, duration, state.accumulated);
+    let mut footer = format!(
+        "Total of {} rows: read {}, write {}",
+        total_processes, read_str, write_str
+    );
+    if !state.tagged.is_empty() {
+        footer.push_str(&format!(", {} tagged", state.tagged.len()));
+    }
+    if has_delay_acct {
+        footer.push_str(&format!(
+            ", swapin {}, io {}",
+            format_delay(totals.swapin_delay_total, duration, state.delay_ms),
+            format_delay(totals.blkio_delay_total, duration, state.delay_ms)
+        ));
+    }
+    if let Some(share) = share_of_total(&totals, total_io, state.accumulated) {
+        footer.push_str(&format!(", {:.0}% of total I/O", share * 100.0));
+    }
+    lines.push(Line::from(footer));
 
-    render_process_table(f, chunks[1], processes, duration, state, has_delay_acct);
+    f.render_widget(Paragraph::new(lines), area);
 }
 
-fn render_header(
-    f: &mut Frame,
-    area: Rect,
-    total_io: (u64, u64),
-    actual_io: (u64, u64),
-    duration: f64,
-) {
-    let total_read_str = format_bandwidth(total_io.0, duration);
-    let total_write_str = format_bandwidth(total_io.1, duration);
-    let actual_read_str = format_bandwidth(actual_io.0, duration);
-    let actual_write_str = format_bandwidth(actual_io.1, duration);
+/// Number of lines above the process rows in accessible mode
+const A11Y_HEADER_HEIGHT: u16 = 8;
+
+/// Number of lines below the process rows in accessible mode
+const A11Y_FOOTER_HEIGHT: u16 = 1;
+
+/// Height of the header block in the default layout
+const HEADER_HEIGHT: u16 = 5;
+
+/// Table borders, column header row and totals footer row
+const TABLE_CHROME_HEIGHT: u16 = 4;
+
+/// First row to show so that row `selected` fits in `height` lines when row
+/// `idx` takes `lines(idx)` of them: `scroll_offset`, unless rows taller than
+/// one line would push the selection past the bottom
+fn first_fitting_row(
+    scroll_offset: usize,
+    selected: usize,
+    height: usize,
+    lines: impl Fn(usize) -> usize,
+) -> usize {
+    if selected < scroll_offset {
+        return selected;
+    }
+    let mut first = selected + 1;
+    let mut used = 0;
+    while first > scroll_offset {
+        let needed = lines(first - 1);
+        if used + needed > height && used > 0 {
+            break;
+        }
+        used += needed;
+        first -= 1;
+    }
+    first
+}
 
-    let text = vec![
-        Line::from(vec![
-            Span::styled("Total DISK READ: ", Style::default().fg(Color::White)),
-            Span::styled(
-                format!("{:>12}", total_read_str),
-                Style::default().fg(Color::White),
-            ),
-            Span::raw("  │  "),
-            Span::styled("Total DISK WRITE: ", Style::default().fg(Color::White)),
-            Span::styled(
-                format!("{:>12}", total_write_str),
-                Style::default().fg(Color::White),
-            ),
-        ]),
-        Line::from(vec![
-            Span::styled("Actual DISK READ: ", Style::default().fg(Color::White)),
-            Span::styled(
-                format!("{:>11}", actual_read_str),
-                Style::default().fg(Color::White),
-            ),
-            Span::raw("  │  "),
-            Span::styled("Actual DISK WRITE: ", Style::default().fg(Color::White)),
-            Span::styled(
-                format!("{:>11}", actual_write_str),
-                Style::default().fg(Color::White),
-            ),
-        ]),
-    ];
+/// Number of process rows that fit on a terminal of the given height
+pub fn visible_rows(terminal_height: u16, state: &UIState) -> usize {
+    let chrome = if state.a11y {
+        // The device, cgroup, quota and CPU summaries take one more line each
+        A11Y_HEADER_HEIGHT
+            + A11Y_FOOTER_HEIGHT
+            + u16::from(state.show_devices)
+            + u16::from(state.show_cgroups)
+            + u16::from(state.quotas.is_some())
+            + u16::from(state.show_cpus)
+    } else {
+        let mut chrome = HEADER_HEIGHT + TABLE_CHROME_HEIGHT;
+        if state.show_cgroups {
+            chrome += CGROUP_PANEL_HEIGHT;
+        }
+        if state.quotas.is_some() {
+            chrome += QUOTA_PANEL_HEIGHT;
+        }
+        chrome
+    };
+    terminal_height.saturating_sub(chrome) as usize
+}
 
-    let block = Block::default()
-        .title_top(
-            Line::from(vec![
-                Span::raw("┐"),
-                Span::styled(
-                    format!("{}", chrono::Local::now().format("%H:%M:%S")),
-                    Style::default().fg(Color::White).bold(),
-                ),
-                Span::raw("┌"),
-            ])
-            .centered(),
+/// Sum the displayed stats of every listed row
+fn sum_stats(processes: &[&ProcessInfo], accumulated: bool) -> TaskStats {
+    let mut totals = TaskStats::default();
+    for process in processes {
+        if accumulated {
+            totals.accumulate(&process.stats_accum);
+        } else {
+            totals.accumulate(&process.stats_delta);
+        }
+    }
+    totals
+}
+
+/// Format the read and write columns of a stats record
+fn format_io_pair(stats: &TaskStats, duration: f64, accumulated: bool) -> (String, String) {
+    let write_bytes = stats
+        .write_bytes
+        .saturating_sub(stats.cancelled_write_bytes);
+    if accumulated {
+        (
+            human_size(stats.read_bytes as i64),
+            human_size(write_bytes as i64),
         )
-        .borders(Borders::ALL)
-        .border_type(BorderType::Rounded)
-        .border_style(Style::default().fg(Color::Gray))
-        .bg(Color::Black)
-        .title(" iotop - I/O Monitor ");
+    } else {
+        (
+            format_bandwidth(stats.read_bytes, duration),
+            format_bandwidth(write_bytes, duration),
+        )
+    }
+}
 
-    let paragraph = Paragraph::new(text).block(block);
-    f.render_widget(paragraph, area);
+/// Fraction of this interval's total I/O accounted for by `totals`.
+///
+/// Only meaningful for bandwidth mode; accumulated totals are not tracked for
+/// processes hidden by filters.
+fn share_of_total(totals: &TaskStats, total_io: (u64, u64), accumulated: bool) -> Option<f64> {
+    let all = total_io.0.saturating_add(total_io.1);
+    if accumulated || all == 0 {
+        return None;
+    }
+    let shown = totals.read_bytes.saturating_add(totals.write_bytes);
+    Some((shown as f64 / all as f64).min(1.0))
 }
 
 const COMMON_HEADERS: [(&str, Alignment); 5] = [
@@ -440,12 +2993,20 @@ const COMMON_WIDTHS: [Constraint; 5] = [
 
 const DELAY_ACCT_WIDTHS: [Constraint; 2] = [
     Constraint::Length(9), // SWAPIN
-    Constraint::Length(5), // IO
+    Constraint::Length(9), // IO
+];
+
+const DELAY_MS_WIDTHS: [Constraint; 2] = [
+    Constraint::Length(11), // SWAPIN
+    Constraint::Length(11), // IO
 ];
 
 const COMMAND_WIDTH: Constraint = Constraint::Min(20);
 
 const COLOR_HIGHLIGHT: Color = Color::Rgb(100, 180, 255);
+const COLOR_READ: Color = Color::Rgb(100, 180, 255); // Soft blue
+const COLOR_WRITE: Color = Color::Rgb(255, 140, 140); // Soft red/pink
+const COLOR_IO: Color = Color::Rgb(180, 140, 255); // Soft purple
 
 fn create_toggle_title(hotkey: char, label: &'static str, is_active: bool) -> Line<'static> {
     let base_style = Style::default().fg(COLOR_HIGHLIGHT);
@@ -468,10 +3029,32 @@ fn create_toggle_title(hotkey: char, label: &'static str, is_active: bool) -> Li
     .left_aligned()
 }
 
+/// Title showing a setting's current value next to its hotkey, e.g. `┐w 30s┌`
+fn create_value_title(hotkey: impl ToString, value: String, is_active: bool) -> Line<'static> {
+    let base_style = Style::default().fg(COLOR_HIGHLIGHT);
+
+    Line::from(vec![
+        Span::raw("┐"),
+        if is_active {
+            Span::styled(hotkey.to_string(), base_style.bold())
+        } else {
+            Span::styled(hotkey.to_string(), base_style)
+        },
+        if is_active {
+            Span::raw(format!(" {}", value)).bold()
+        } else {
+            Span::raw(format!(" {}", value))
+        },
+        Span::raw("┌"),
+    ])
+    .left_aligned()
+}
+
 fn render_process_table(
     f: &mut Frame,
     area: Rect,
     processes: &[&ProcessInfo],
+    total_io: (u64, u64),
     duration: f64,
     state: &mut UIState,
     has_delay_acct: bool,
@@ -489,13 +3072,18 @@ fn render_process_table(
             header_cells.push(Cell::from(Text::from(*text).alignment(*align)));
         }
     }
+    for column in &state.extra_columns {
+        header_cells.push(Cell::from(
+            Text::from(column.header()).alignment(column.alignment()),
+        ));
+    }
     header_cells.push(Cell::from(
         Text::from(COMMAND_HEADER.0).alignment(COMMAND_HEADER.1),
     ));
 
     let header = Row::new(header_cells).style(header_style).height(1);
 
-    let available_height = area.height.saturating_sub(3) as usize;
+    let available_height = area.height.saturating_sub(TABLE_CHROME_HEIGHT) as usize;
     let total_processes = processes.len();
 
     if total_processes > 0 {
@@ -505,14 +3093,38 @@ fn render_process_table(
         state.scroll_offset = 0;
     }
 
-    let end = (state.scroll_offset + available_height).min(total_processes);
+    // Hot threads take lines of their own under their process, so fewer
+    // rows fit, and the selection must not be pushed off the bottom
+    let hot_share = state.hot_thread_share();
+    let hot_lines =
+        |process: &ProcessInfo| hot_share.map_or(0, |share| process.hot_threads(share).len());
+    if let Some(selected) = state
+        .selected_row
+        .filter(|&selected| state.selection_mode && selected < total_processes)
+    {
+        state.scroll_offset =
+            first_fitting_row(state.scroll_offset, selected, available_height, |idx| {
+                1 + hot_lines(processes[idx])
+            });
+    }
+    let mut end = state.scroll_offset;
+    let mut lines = 0;
+    while end < total_processes {
+        lines += 1 + hot_lines(processes[end]);
+        if lines > available_height && end > state.scroll_offset {
+            break;
+        }
+        end += 1;
+    }
     let visible_processes = &processes[state.scroll_offset..end];
 
-    const COLOR_READ: Color = Color::Rgb(100, 180, 255); // Soft blue
-    const COLOR_WRITE: Color = Color::Rgb(255, 140, 140); // Soft red/pink
-    const COLOR_IO: Color = Color::Rgb(180, 140, 255); // Soft purple
     const COLOR_ACTIVE: Color = Color::White;
     const COLOR_INACTIVE: Color = Color::Gray;
+    const COLOR_SPIKE: Color = Color::Rgb(110, 40, 40); // Dark red flash
+    const COLOR_TAGGED: Color = Color::Rgb(255, 210, 100); // Amber
+    const COLOR_MATCH: Color = Color::Rgb(120, 230, 140); // Soft green
+
+    let ctx = state.column_context(processes, visible_processes, duration);
 
     let rows = visible_processes.iter().map(|process| {
         let stats = if state.accumulated {
@@ -536,46 +3148,107 @@ fn render_process_table(
             format_bandwidth(write_bytes, duration)
         };
 
-        let row_style = if process.did_some_io(state.accumulated) {
+        let mut row_style = if process.did_some_io(state.accumulated) {
             Style::default().fg(COLOR_ACTIVE)
         } else {
             Style::default().fg(COLOR_INACTIVE)
         };
+        if state.is_highlighted(process) {
+            row_style = row_style.fg(COLOR_MATCH).add_modifier(Modifier::BOLD);
+        }
+        if state.is_spiking(process.tid) {
+            row_style = row_style.bg(COLOR_SPIKE).add_modifier(Modifier::BOLD);
+        }
+        if state.tagged.contains(&process.tid) {
+            row_style = row_style.fg(COLOR_TAGGED).add_modifier(Modifier::BOLD);
+        }
+        if process.stopped {
+            row_style = row_style.add_modifier(Modifier::DIM);
+        }
+
+        let mut tid_text = Text::from(process.tid.to_string());
+        let mut read_text = Text::from(read_str);
+        let mut write_text = Text::from(write_str);
+        let mut command_text = Text::from(state.command_text(process));
+        let hot = hot_share.map_or_else(Vec::new, |share| process.hot_threads(share));
+        for (thread, share) in &hot {
+            let stats = &thread.stats_delta;
+            let write_bytes = stats
+                .write_bytes
+                .saturating_sub(stats.cancelled_write_bytes);
+            tid_text.push_line(thread.tid.to_string());
+            read_text.push_line(format_bandwidth(stats.read_bytes, duration));
+            write_text.push_line(format_bandwidth(write_bytes, duration));
+            command_text.push_line(format!(
+                "  └ {} ({:.0}% of I/O)",
+                proc_reader::thread_name(process.pid, thread.tid),
+                share * 100.0
+            ));
+        }
 
         let mut cells = vec![
-            Cell::from(Text::from(process.tid.to_string()).alignment(Alignment::Right)),
+            Cell::from(tid_text.alignment(Alignment::Right)),
             Cell::from(Text::from(process.get_prio().to_string()).alignment(Alignment::Right)),
             Cell::from(Text::from(process.get_user()).alignment(Alignment::Left)),
-            Cell::from(Text::from(read_str).alignment(Alignment::Right))
+            Cell::from(read_text.alignment(Alignment::Right))
                 .style(Style::default().fg(COLOR_READ)),
-            Cell::from(Text::from(write_str).alignment(Alignment::Right))
+            Cell::from(write_text.alignment(Alignment::Right))
                 .style(Style::default().fg(COLOR_WRITE)),
         ];
 
         if has_delay_acct {
-            let swapin_delay = format_delay_percent(stats.swapin_delay_total, duration);
-            let io_delay = format_delay_percent(stats.blkio_delay_total, duration);
+            let swapin_delay = format_delay(stats.swapin_delay_total, duration, state.delay_ms);
+            let io_delay = format_delay(stats.blkio_delay_total, duration, state.delay_ms);
             cells.push(Cell::from(
                 Text::from(swapin_delay).alignment(Alignment::Right),
             ));
-            cells.push(
-                Cell::from(Text::from(io_delay).alignment(Alignment::Right))
-                    .style(Style::default().fg(COLOR_IO)),
+            // Waiting in D state right now, not just during the interval
+            let io_style = if process.uninterruptible {
+                Style::default().fg(COLOR_IO).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(COLOR_IO)
+            };
+            cells
+                .push(Cell::from(Text::from(io_delay).alignment(Alignment::Right)).style(io_style));
+        }
+
+        for column in &state.extra_columns {
+            let mut cell = Cell::from(
+                Text::from(column_text(*column, process, &ctx)).alignment(column.alignment()),
             );
+            if *column == ExtraColumn::Graph {
+                cell = cell.style(Style::default().fg(COLOR_IO));
+            }
+            if *column == ExtraColumn::Mount
+                && ctx
+                    .mounts
+                    .get(&process.pid)
+                    .is_some_and(|mounts| columns::writes_to_full(mounts, &ctx.full_mounts))
+            {
+                cell = cell.style(FULL_STYLE);
+            }
+            cells.push(cell);
         }
 
-        cells.push(Cell::from(
-            Text::from(process.get_cmdline()).alignment(Alignment::Left),
-        ));
+        cells.push(Cell::from(command_text.alignment(Alignment::Left)));
 
-        Row::new(cells).style(row_style)
+        Row::new(cells)
+            .style(row_style)
+            .height(1 + hot.len() as u16)
     });
 
+    let footer = totals_row(processes, total_io, duration, state, has_delay_acct);
+
     let mut widths = Vec::with_capacity(8);
     widths.extend_from_slice(&COMMON_WIDTHS);
     if has_delay_acct {
-        widths.extend_from_slice(&DELAY_ACCT_WIDTHS);
+        if state.delay_ms {
+            widths.extend_from_slice(&DELAY_MS_WIDTHS);
+        } else {
+            widths.extend_from_slice(&DELAY_ACCT_WIDTHS);
+        }
     }
+    widths.extend(state.extra_columns.iter().map(|c| c.width()));
     widths.push(COMMAND_WIDTH);
 
     let sort_row = state.sort_column.as_str();
@@ -599,8 +3272,51 @@ fn render_process_table(
     let mut block = Block::default()
         .title_top(create_toggle_title('a', "ccumulated", state.accumulated))
         .title_top(create_toggle_title('o', "nly-active", state.only_active))
+        .title_top(create_toggle_title('b', "locked", state.blocked_only))
         .title_top(create_toggle_title('p', "rocesses", state.show_processes))
-        .title_top(create_toggle_title('r', "everse", !state.sort_reverse))
+        .title_top(create_toggle_title('r', "everse", !state.sort_reverse));
+
+    if has_delay_acct {
+        block = block.title_top(create_toggle_title('m', "s-delay", state.delay_ms));
+    }
+
+    block = block
+        .title_top(create_value_title(
+            'w',
+            state.average_window.label().to_string(),
+            state.average_window != AverageWindow::Instant,
+        ))
+        .title_top(create_value_title(
+            'u',
+            state.user_filter_label(),
+            !state.user_filter.is_empty(),
+        ));
+
+    if state.rewind > 0 {
+        block = block.title_top(create_value_title('[', format!("-{}", state.rewind), true));
+    }
+
+    if !state.pid_filter.is_empty() {
+        block = block.title_top(create_value_title('#', state.pid_filter.to_string(), true));
+    }
+
+    if !state.unit_filter.is_empty() {
+        block = block.title_top(create_value_title('f', state.unit_filter.to_string(), true));
+    }
+
+    if let Some(pattern) = &state.highlight {
+        block = block.title_top(create_value_title('/', pattern.to_string(), true));
+    }
+
+    if let Some(preset) = &state.preset {
+        block = block.title_top(create_value_title('x', preset.clone(), true));
+    }
+
+    if let Some(condition) = &state.condition {
+        block = block.title_top(create_value_title("F4", condition.to_string(), true));
+    }
+
+    block = block
         .title_top(
             Line::from(vec![
                 Span::raw("┐"),
@@ -616,6 +3332,17 @@ fn render_process_table(
         .border_type(BorderType::Rounded)
         .border_style(Style::default().fg(Color::Gray));
 
+    if let Some(ref status) = state.status {
+        block = block.title_bottom(
+            Line::from(vec![
+                Span::raw("┘"),
+                Span::styled(status.clone(), Style::default().fg(COLOR_HIGHLIGHT)),
+                Span::raw("└"),
+            ])
+            .left_aligned(),
+        );
+    }
+
     if !scroll_indicator.is_empty() {
         block = block.title_top(
             Line::from(vec![
@@ -633,6 +3360,7 @@ fn render_process_table(
     let table = Table::default()
         .rows(rows)
         .header(header)
+        .footer(footer)
         .widths(widths)
         .block(block)
         .row_highlight_style(Style::default().bg(Color::Rgb(60, 60, 60))); // Dark gray background for selection
@@ -673,6 +3401,50 @@ fn render_process_table(
     }
 }
 
+/// Build the footer row summing the rows currently listed
+fn totals_row(
+    processes: &[&ProcessInfo],
+    total_io: (u64, u64),
+    duration: f64,
+    state: &UIState,
+    has_delay_acct: bool,
+) -> Row<'static> {
+    let totals = sum_stats(processes, state.accumulated);
+    let (read_str, write_str) = format_io_pair(&totals, duration, state.accumulated);
+
+    let mut cells = vec![
+        Cell::from(Text::from("TOTAL").alignment(Alignment::Right)),
+        Cell::from(""),
+        Cell::from(""),
+        Cell::from(Text::from(read_str).alignment(Alignment::Right)),
+        Cell::from(Text::from(write_str).alignment(Alignment::Right)),
+    ];
+    if has_delay_acct {
+        for delay in [totals.swapin_delay_total, totals.blkio_delay_total] {
+            cells.push(Cell::from(
+                Text::from(format_delay(delay, duration, state.delay_ms))
+                    .alignment(Alignment::Right),
+            ));
+        }
+    }
+    cells.extend(state.extra_columns.iter().map(|_| Cell::from("")));
+
+    let mut summary = format!("{} rows", processes.len());
+    if !state.tagged.is_empty() {
+        summary.push_str(&format!(", {} tagged", state.tagged.len()));
+    }
+    if let Some(share) = share_of_total(&totals, total_io, state.accumulated) {
+        summary.push_str(&format!(", {:.0}% of total I/O", share * 100.0));
+    }
+    cells.push(Cell::from(summary));
+
+    Row::new(cells).style(
+        Style::default()
+            .fg(COLOR_HIGHLIGHT)
+            .add_modifier(Modifier::BOLD),
+    )
+}
+
 pub fn format_bandwidth(bytes: u64, duration: f64) -> String {
     if duration <= 0.0 {
         return "0 B/s".to_string();
@@ -713,6 +3485,30 @@ pub fn human_size(bytes: i64) -> String {
     }
 }
 
+/// Format a duration in seconds as `M:SS`, or `H:MM:SS` from one hour on
+pub fn format_elapsed(secs: u64) -> String {
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Format a delay total as milliseconds, independent of the interval length
+pub fn format_delay_ms(delay_ns: u64) -> String {
+    format!("{:.2} ms", delay_ns as f64 / 1_000_000.0)
+}
+
+/// Format a delay total either as percent of the interval or in milliseconds
+pub fn format_delay(delay_ns: u64, duration: f64, in_ms: bool) -> String {
+    if in_ms {
+        format_delay_ms(delay_ns)
+    } else {
+        format_delay_percent(delay_ns, duration)
+    }
+}
+
 pub fn format_delay_percent(delay_ns: u64, duration: f64) -> String {
     if duration <= 0.0 {
         return "0.00 %".to_string();
@@ -768,19 +3564,109 @@ mod tests {
         assert_eq!(format_delay_percent(100, 0.0), "0.00 %");
     }
 
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(0), "0:00");
+        assert_eq!(format_elapsed(75), "1:15");
+        assert_eq!(format_elapsed(3723), "1:02:03");
+    }
+
+    #[test]
+    fn test_format_delay_ms() {
+        assert_eq!(format_delay_ms(0), "0.00 ms");
+        assert_eq!(format_delay_ms(1_500_000), "1.50 ms");
+        assert_eq!(format_delay_ms(2_000_000_000), "2000.00 ms");
+        assert_eq!(format_delay(500_000_000, 1.0, false), "50.00 %");
+        assert_eq!(format_delay(500_000_000, 1.0, true), "500.00 ms");
+    }
+
     #[test]
     fn test_sort_column_cycle() {
         let col = SortColumn::Pid;
-        assert_eq!(col.cycle_forward(true), SortColumn::Prio);
-        assert_eq!(col.cycle_backward(true), SortColumn::Command);
+        assert_eq!(col.cycle_forward(true, &[]), SortColumn::Prio);
+        assert_eq!(col.cycle_backward(true, &[]), SortColumn::Command);
 
         let col = SortColumn::Command;
-        assert_eq!(col.cycle_forward(true), SortColumn::Pid);
-        assert_eq!(col.cycle_backward(true), SortColumn::Io);
+        assert_eq!(col.cycle_forward(true, &[]), SortColumn::Pid);
+        assert_eq!(col.cycle_backward(true, &[]), SortColumn::Io);
 
         let col = SortColumn::Read;
-        let next = col.cycle_forward(false);
+        let next = col.cycle_forward(false, &[]);
         assert_eq!(next, SortColumn::Write);
+
+        // Shown delay columns become sortable, in display order
+        let extra = [ExtraColumn::Reclaim, ExtraColumn::Mem];
+        assert_eq!(
+            SortColumn::Io.cycle_forward(true, &extra),
+            SortColumn::Reclaim
+        );
+        assert_eq!(
+            SortColumn::Command.cycle_backward(true, &extra),
+            SortColumn::Reclaim
+        );
+        assert_eq!(SortColumn::Io.cycle_forward(false, &extra), SortColumn::Pid);
+
+        // Logical I/O columns do not need delay accounting
+        let extra = [ExtraColumn::ReadChar, ExtraColumn::WriteChar];
+        assert_eq!(
+            SortColumn::Write.cycle_forward(false, &extra),
+            SortColumn::ReadChar
+        );
+        assert_eq!(
+            SortColumn::Command.cycle_backward(false, &extra),
+            SortColumn::WriteChar
+        );
+    }
+
+    #[test]
+    fn test_subsample_window() {
+        let mut state = UIState::default();
+        assert_eq!(state.subsample_window(), None);
+        state.delay = 2.0;
+        state.subsamples = 4;
+        assert_eq!(state.subsample_window(), Some(Duration::from_millis(1750)));
+    }
+
+    #[test]
+    fn test_first_fitting_row() {
+        let single = |_| 1;
+        assert_eq!(first_fitting_row(0, 5, 10, single), 0);
+        assert_eq!(first_fitting_row(4, 2, 10, single), 2);
+        // Rows with two hot threads each: only three fit in ten lines
+        let tall = |_| 3;
+        assert_eq!(first_fitting_row(0, 2, 10, tall), 0);
+        assert_eq!(first_fitting_row(0, 5, 10, tall), 3);
+        // A selected row taller than the table is still shown
+        assert_eq!(first_fitting_row(0, 5, 2, tall), 5);
+    }
+
+    #[test]
+    fn test_is_spike() {
+        let factor = DEFAULT_SPIKE_FACTOR;
+        // Idle to a trickle is not a spike
+        assert!(!is_spike(0.0, 32.0 * 1024.0, factor));
+        // Idle to a burst is
+        assert!(is_spike(0.0, 10.0 * 1024.0 * 1024.0, factor));
+        // Steady high rate is not
+        assert!(!is_spike(
+            10.0 * 1024.0 * 1024.0,
+            12.0 * 1024.0 * 1024.0,
+            factor
+        ));
+        // Jump by more than the factor is
+        assert!(is_spike(1024.0 * 1024.0, 5.0 * 1024.0 * 1024.0, factor));
+    }
+
+    #[test]
+    fn test_share_of_total() {
+        let totals = TaskStats {
+            read_bytes: 300,
+            write_bytes: 200,
+            ..Default::default()
+        };
+        assert_eq!(share_of_total(&totals, (500, 500), false), Some(0.5));
+        assert_eq!(share_of_total(&totals, (500, 500), true), None);
+        assert_eq!(share_of_total(&totals, (0, 0), false), None);
     }
 
     #[test]
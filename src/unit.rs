@@ -0,0 +1,116 @@
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+use crate::cgroup;
+
+/// The systemd unit a process belongs to, from /proc/[pid]/cgroup content
+///
+/// Like `sd_pid_get_unit`, this is the first component of the systemd
+/// cgroup path below the slices, such as `nginx.service` or
+/// `session-2.scope`; the units of a user's own manager (`user@1000.service`)
+/// are not looked into. Processes outside any unit, kernel threads among
+/// them, have none.
+pub fn parse_unit(content: &str) -> Option<String> {
+    unit_of_path(cgroup::parse_path(content)?)
+}
+
+fn unit_of_path(path: &str) -> Option<String> {
+    let component = path
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .find(|c| !c.ends_with(".slice"))?;
+    let (name, kind) = component.rsplit_once('.')?;
+    let is_unit = !name.is_empty()
+        && matches!(
+            kind,
+            "service" | "scope" | "socket" | "mount" | "swap" | "timer"
+        );
+    is_unit.then(|| component.to_string())
+}
+
+/// Full name of a unit given on the command line or at a prompt, where
+/// `nginx` stands for `nginx.service`
+pub fn full_name(unit: &str) -> String {
+    if unit.contains('.') {
+        unit.to_string()
+    } else {
+        format!("{}.service", unit)
+    }
+}
+
+/// cgroup path of the active unit `unit`, such as
+/// `/system.slice/nginx.service`, found by looking for its directory in
+/// systemd's tree, closest to the root first
+pub fn find_cgroup(unit: &str) -> Result<String> {
+    // Named v1 hierarchy of hybrid and legacy systems, like parse_unit
+    let root = if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        Path::new("/sys/fs/cgroup")
+    } else {
+        Path::new("/sys/fs/cgroup/systemd")
+    };
+    let mut pending = VecDeque::from([root.to_path_buf()]);
+    while let Some(dir) = pending.pop_front() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_name() == unit {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    return Ok(format!("/{}", relative.display()));
+                }
+            }
+            pending.push_back(path);
+        }
+    }
+    bail!("Unit {} has no cgroup: is it running?", unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_name() {
+        assert_eq!(full_name("nginx"), "nginx.service");
+        assert_eq!(full_name("session-2.scope"), "session-2.scope");
+    }
+
+    #[test]
+    fn test_parse_unit() {
+        assert_eq!(
+            parse_unit("0::/system.slice/nginx.service\n").as_deref(),
+            Some("nginx.service")
+        );
+        assert_eq!(
+            parse_unit("0::/system.slice/system-getty.slice/getty@tty1.service\n").as_deref(),
+            Some("getty@tty1.service")
+        );
+        assert_eq!(
+            parse_unit("0::/user.slice/user-1000.slice/user@1000.service/app.slice/foot.service\n")
+                .as_deref(),
+            Some("user@1000.service")
+        );
+        assert_eq!(
+            parse_unit("0::/user.slice/user-1000.slice/session-2.scope\n").as_deref(),
+            Some("session-2.scope")
+        );
+        assert_eq!(
+            parse_unit(
+                "12:pids:/system.slice/sshd.service\n\
+                 1:name=systemd:/system.slice/sshd.service\n\
+                 0::/\n"
+            )
+            .as_deref(),
+            Some("sshd.service")
+        );
+        // Kernel threads, and cgroups systemd does not manage
+        assert_eq!(parse_unit("0::/\n"), None);
+        assert_eq!(parse_unit("0::/docker/3f2a9c1b7e4d\n"), None);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+/// Estimated disk writes of one row
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Writeback {
+    pub bytes_per_sec: f64,
+    /// Bytes estimated since iotop started
+    pub bytes: u64,
+}
+
+/// Estimated disk writes by row ID (PID, or TID in thread mode)
+pub type WritebackRates = HashMap<i32, Writeback>;
+
+/// Estimates how much of Actual DISK WRITE each row is responsible for
+///
+/// Buffered writes only dirty the page cache and reach the disk seconds
+/// later, from the kernel's flusher threads. The estimator keeps the bytes
+/// each row dirtied (writes less cancelled writes) that were not yet seen
+/// written out, and shares every interval's pgpgout among the rows in
+/// proportion to that backlog. It is a heuristic: it cannot tell which
+/// files the flusher wrote, and metadata, swap and journal writes are
+/// charged to whoever has a backlog.
+#[derive(Debug, Default)]
+pub struct WritebackEstimator {
+    pending: HashMap<i32, f64>,
+    totals: HashMap<i32, f64>,
+}
+
+impl WritebackEstimator {
+    /// Account one interval of `secs`: `dirtied` holds the bytes each row
+    /// dirtied, `written` the bytes that went to disk (pgpgout), and `limit`
+    /// the dirty and writeback memory left in the page cache, if known
+    ///
+    /// Rows missing from `dirtied` keep their backlog, as the pages of an
+    /// exited process are still written, but only rows in `dirtied` are
+    /// returned.
+    pub fn estimate(
+        &mut self,
+        dirtied: &HashMap<i32, u64>,
+        written: u64,
+        limit: Option<u64>,
+        secs: f64,
+    ) -> WritebackRates {
+        for (&id, &bytes) in dirtied {
+            *self.pending.entry(id).or_default() += bytes as f64;
+        }
+
+        let backlog: f64 = self.pending.values().sum();
+        let share = if backlog > 0.0 {
+            (written as f64 / backlog).min(1.0)
+        } else {
+            0.0
+        };
+        let mut interval: HashMap<i32, f64> = HashMap::new();
+        for (&id, pending) in self.pending.iter_mut() {
+            let bytes = *pending * share;
+            *pending -= bytes;
+            interval.insert(id, bytes);
+            *self.totals.entry(id).or_default() += bytes;
+        }
+
+        // What is still pending cannot exceed what the page cache holds;
+        // the rest was discarded, or written without being seen
+        if let Some(limit) = limit {
+            let backlog: f64 = self.pending.values().sum();
+            if backlog > limit as f64 {
+                let scale = limit as f64 / backlog;
+                self.pending.values_mut().for_each(|p| *p *= scale);
+            }
+        }
+        self.pending
+            .retain(|id, pending| *pending >= 1.0 || dirtied.contains_key(id));
+        self.totals
+            .retain(|id, _| dirtied.contains_key(id) || self.pending.contains_key(id));
+
+        dirtied
+            .keys()
+            .map(|&id| {
+                let bytes = interval.get(&id).copied().unwrap_or_default();
+                let writeback = Writeback {
+                    bytes_per_sec: if secs > 0.0 { bytes / secs } else { 0.0 },
+                    bytes: self.totals.get(&id).copied().unwrap_or_default() as u64,
+                };
+                (id, writeback)
+            })
+            .collect()
+    }
+}
+
+/// Combine the estimates of two consecutive intervals into ones over both,
+/// weighting each by its length; totals come from the newer one
+pub fn merge_rates(
+    older: &WritebackRates,
+    older_secs: f64,
+    newer: &WritebackRates,
+    newer_secs: f64,
+) -> WritebackRates {
+    let secs = older_secs + newer_secs;
+    if secs <= 0.0 {
+        return newer.clone();
+    }
+
+    let mut merged = newer.clone();
+    for writeback in merged.values_mut() {
+        writeback.bytes_per_sec *= newer_secs / secs;
+    }
+    for (&id, older) in older {
+        let writeback = merged.entry(id).or_insert(Writeback {
+            bytes: older.bytes,
+            ..Default::default()
+        });
+        writeback.bytes_per_sec += older.bytes_per_sec * older_secs / secs;
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let mut estimator = WritebackEstimator::default();
+
+        // 300 and 100 bytes dirtied, nothing written out yet
+        let dirtied = HashMap::from([(1, 300), (2, 100)]);
+        let rates = estimator.estimate(&dirtied, 0, None, 1.0);
+        assert_eq!(rates[&1].bytes_per_sec, 0.0);
+
+        // The flusher writes half of the backlog, shared 3:1
+        let idle = HashMap::from([(1, 0), (2, 0)]);
+        let rates = estimator.estimate(&idle, 200, None, 2.0);
+        assert_eq!(rates[&1].bytes_per_sec, 75.0);
+        assert_eq!(rates[&2].bytes_per_sec, 25.0);
+
+        // No more than the backlog is charged
+        let rates = estimator.estimate(&idle, 1000, None, 1.0);
+        assert_eq!(rates[&1].bytes, 300);
+        assert_eq!(rates[&2].bytes, 100);
+
+        // 150 bytes/s over an idle second before it
+        let idle_rates = WritebackRates::from([(1, Writeback::default())]);
+        let merged = merge_rates(&idle_rates, 1.0, &rates, 1.0);
+        assert_eq!(merged[&1].bytes_per_sec, 75.0);
+        assert_eq!(merged[&1].bytes, 300);
+    }
+
+    #[test]
+    fn test_estimate_limit() {
+        let mut estimator = WritebackEstimator::default();
+        // Only 100 bytes are left dirty, the rest was deleted before
+        // reaching the disk
+        estimator.estimate(&HashMap::from([(1, 1000)]), 0, Some(100), 1.0);
+        let rates = estimator.estimate(&HashMap::from([(1, 0)]), 500, Some(0), 1.0);
+        assert_eq!(rates[&1].bytes, 100);
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::filetrace::FileRate;
+use crate::history::RateHistory;
+use crate::proc_reader::thread_name;
+use crate::process::{ProcessInfo, ProcessSnapshot};
+use crate::taskstats::TaskStats;
+
+/// Rates of the zoomed row over one recorded interval
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IntervalRates {
+    /// Bytes per second
+    pub read: f64,
+    /// Bytes per second, not counting cancelled writes
+    pub write: f64,
+    /// Share of the interval spent waiting on block I/O, in percent
+    pub io_delay: f64,
+    /// Share of the interval spent waiting on swap-in, in percent
+    pub swapin_delay: f64,
+}
+
+impl IntervalRates {
+    fn new(stats: &TaskStats, duration: f64) -> Self {
+        if duration <= 0.0 {
+            return Self::default();
+        }
+        let percent = |delay_ns: u64| delay_ns as f64 / (duration * 1_000_000_000.0) * 100.0;
+        Self {
+            read: stats.read_bytes as f64 / duration,
+            write: stats
+                .write_bytes
+                .saturating_sub(stats.cancelled_write_bytes) as f64
+                / duration,
+            io_delay: percent(stats.blkio_delay_total),
+            swapin_delay: percent(stats.swapin_delay_total),
+        }
+    }
+}
+
+/// One thread of the zoomed process
+#[derive(Debug, Clone)]
+pub struct ThreadRow {
+    pub tid: i32,
+    /// Thread name from /proc/[pid]/task/[tid]/comm
+    pub name: String,
+    /// `None` in process mode, where only the thread group as a whole is
+    /// sampled
+    pub rates: Option<IntervalRates>,
+}
+
+/// Minimum time between two reads of the open files, as the view is also
+/// rebuilt on key presses and offset rates over a blink would mostly be zero
+const OPEN_FILES_REFRESH: Duration = Duration::from_secs(1);
+
+/// A regular file the zoomed process has open
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenFile {
+    pub fd: i32,
+    pub path: String,
+    /// Current offset, from /proc/[pid]/fdinfo/N
+    pub pos: u64,
+    /// Size of the file; `None` if it could not be read
+    pub size: Option<u64>,
+    /// Bytes per second the offset moved forward since the previous refresh;
+    /// `None` when the descriptor was just opened or seeked backwards
+    pub progress: Option<f64>,
+}
+
+impl OpenFile {
+    /// How far through the file the offset is, in percent
+    pub fn percent(&self) -> Option<f64> {
+        let size = self.size.filter(|&size| size > 0)?;
+        Some((self.pos as f64 / size as f64 * 100.0).min(100.0))
+    }
+}
+
+/// Live drill-down into a single row, shown full screen
+///
+/// The view is rebuilt from every displayed snapshot, so it follows pauses
+/// and rewinding like the table does. Threads are only listed, and named,
+/// again after a new sample arrives (`invalidate`), not on every frame.
+pub struct ZoomView {
+    /// Row being shown: a TID, or a PID in process mode
+    pub id: i32,
+    /// Whether rows are processes (`-P`) rather than threads
+    pub process_mode: bool,
+    /// Latest data of the row; `None` once it has exited
+    pub process: Option<ProcessInfo>,
+    /// Threads of the row's process, busiest first
+    pub threads: Vec<ThreadRow>,
+    /// Rates over the recorded history, oldest first
+    pub history: Vec<IntervalRates>,
+    pub duration: f64,
+    /// Files of the row's process, busiest first, when files are traced
+    pub files: Vec<FileRate>,
+    /// Regular files of the row's process, those moving fastest first
+    pub open_files: Vec<OpenFile>,
+    /// When `open_files` was read, to turn offset changes into rates
+    open_files_at: Option<Instant>,
+    /// Process and thread IDs listed from /proc in process mode
+    thread_ids: Vec<(i32, i32)>,
+    /// Thread names by TID, read once per sample
+    names: HashMap<i32, String>,
+    /// Whether threads have to be listed again
+    threads_stale: bool,
+}
+
+impl ZoomView {
+    pub fn new(id: i32, process_mode: bool) -> Self {
+        Self {
+            id,
+            process_mode,
+            process: None,
+            threads: Vec::new(),
+            history: Vec::new(),
+            duration: 0.0,
+            files: Vec::new(),
+            open_files: Vec::new(),
+            open_files_at: None,
+            thread_ids: Vec::new(),
+            names: HashMap::new(),
+            threads_stale: true,
+        }
+    }
+
+    /// List the threads again at the next update, once a new sample arrived
+    pub fn invalidate(&mut self) {
+        self.threads_stale = true;
+    }
+
+    /// Rates of the row in the current interval
+    pub fn current(&self) -> IntervalRates {
+        match &self.process {
+            Some(process) => IntervalRates::new(&process.stats_delta, self.duration),
+            None => IntervalRates::default(),
+        }
+    }
+
+    /// Refresh from the snapshot being displayed
+    pub fn update(&mut self, snapshot: &ProcessSnapshot, history: &RateHistory) {
+        self.process = snapshot.processes.get(&self.id).cloned();
+        self.duration = snapshot.duration;
+        self.history = history
+            .series(self.id)
+            .iter()
+            .map(|(duration, stats)| IntervalRates::new(stats, *duration))
+            .collect();
+
+        let Some(process) = &self.process else {
+            self.threads.clear();
+            self.files.clear();
+            self.open_files.clear();
+            return;
+        };
+        // Traced per process, so threads show all of their process's files
+        self.files = snapshot
+            .files
+            .get(&process.pid)
+            .cloned()
+            .unwrap_or_default();
+
+        // In thread mode the siblings are separate rows of the snapshot; in
+        // process mode only the thread group was sampled, so the threads are
+        // listed without rates, those of every member for
+        // process group and session rows
+        if self.threads_stale {
+            self.threads_stale = false;
+            self.names.clear();
+            self.thread_ids.clear();
+            if self.process_mode {
+                let pids = if process.members.is_empty() {
+                    vec![process.pid]
+                } else {
+                    process.members.clone()
+                };
+                self.thread_ids = pids
+                    .into_iter()
+                    .flat_map(|pid| thread_ids(pid).into_iter().map(move |tid| (pid, tid)))
+                    .collect();
+            }
+        }
+        let threads: Vec<(i32, i32, Option<TaskStats>)> = if self.process_mode {
+            self.thread_ids
+                .iter()
+                .map(|&(pid, tid)| (pid, tid, None))
+                .collect()
+        } else {
+            snapshot
+                .processes
+                .values()
+                .filter(|p| p.pid == process.pid)
+                .map(|p| (p.pid, p.tid, Some(p.stats_delta)))
+                .collect()
+        };
+
+        let names = &mut self.names;
+        self.threads = threads
+            .into_iter()
+            .map(|(pid, tid, stats)| ThreadRow {
+                tid,
+                name: names
+                    .entry(tid)
+                    .or_insert_with(|| thread_name(pid, tid))
+                    .clone(),
+                rates: stats.map(|stats| IntervalRates::new(&stats, snapshot.duration)),
+            })
+            .collect();
+        self.threads.sort_by(|a, b| {
+            let io = |t: &ThreadRow| t.rates.map_or(0.0, |r| r.read + r.write);
+            io(b).total_cmp(&io(a)).then(a.tid.cmp(&b.tid))
+        });
+
+        // Offsets are only available live, so they keep moving while the
+        // table is paused or rewound
+        let pid = process.pid;
+        self.refresh_open_files(pid);
+    }
+
+    fn refresh_open_files(&mut self, pid: i32) {
+        let now = Instant::now();
+        let elapsed = self.open_files_at.map(|at| now.duration_since(at));
+        if elapsed.is_some_and(|elapsed| elapsed < OPEN_FILES_REFRESH) {
+            return;
+        }
+        let elapsed = elapsed.map(|elapsed| elapsed.as_secs_f64());
+        let previous: HashMap<(i32, &str), u64> = self
+            .open_files
+            .iter()
+            .map(|file| ((file.fd, file.path.as_str()), file.pos))
+            .collect();
+        let mut files = open_files(pid);
+        for file in &mut files {
+            file.progress = previous
+                .get(&(file.fd, file.path.as_str()))
+                .zip(elapsed)
+                .and_then(|(&pos, elapsed)| {
+                    (file.pos >= pos).then(|| (file.pos - pos) as f64 / elapsed)
+                });
+        }
+        files.sort_by(|a, b| {
+            let progress = |f: &OpenFile| f.progress.unwrap_or(0.0);
+            progress(b).total_cmp(&progress(a)).then(a.fd.cmp(&b.fd))
+        });
+        self.open_files = files;
+        self.open_files_at = Some(now);
+    }
+}
+
+/// Offset of a descriptor, from /proc/[pid]/fdinfo/N content
+fn parse_fdinfo_pos(content: &str) -> Option<u64> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("pos:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Regular files `pid` has open, with their offsets, in descriptor order
+fn open_files(pid: i32) -> Vec<OpenFile> {
+    let Ok(entries) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<OpenFile> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            let target = fs::read_link(entry.path()).ok()?;
+            if !target.is_absolute() {
+                return None;
+            }
+            // Following the link reaches the open file even when its path has
+            // since been deleted or renamed
+            let metadata = fs::metadata(entry.path()).ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            let pos = fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, fd))
+                .ok()
+                .and_then(|content| parse_fdinfo_pos(&content))?;
+            Some(OpenFile {
+                fd,
+                path: target.to_string_lossy().into_owned(),
+                pos,
+                size: Some(metadata.len()),
+                progress: None,
+            })
+        })
+        .collect();
+    files.sort_by_key(|file| file.fd);
+    files
+}
+
+fn thread_ids(pid: i32) -> Vec<i32> {
+    fs::read_dir(format!("/proc/{}/task", pid))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.file_name().to_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    fn test_interval_rates() {
+        let stats = TaskStats {
+            read_bytes: 4096,
+            write_bytes: 3000,
+            cancelled_write_bytes: 1000,
+            blkio_delay_total: 500_000_000,
+            ..Default::default()
+        };
+        let rates = IntervalRates::new(&stats, 2.0);
+        assert_eq!(rates.read, 2048.0);
+        assert_eq!(rates.write, 1000.0);
+        assert_eq!(rates.io_delay, 25.0);
+        assert_eq!(IntervalRates::new(&stats, 0.0), IntervalRates::default());
+    }
+
+    #[test]
+    fn test_parse_fdinfo_pos() {
+        let fdinfo = "pos:\t1048576\nflags:\t0100000\nmnt_id:\t30\nino:\t12\n";
+        assert_eq!(parse_fdinfo_pos(fdinfo), Some(1_048_576));
+        assert_eq!(parse_fdinfo_pos("flags:\t02\n"), None);
+    }
+
+    #[test]
+    fn test_open_files() {
+        let path = std::env::temp_dir().join(format!("iotop-open-files-{}", std::process::id()));
+        let mut file = fs::File::create(&path).unwrap();
+        file.write_all(&[0; 4096]).unwrap();
+        file.seek(SeekFrom::Start(1024)).unwrap();
+
+        let fd = file.as_raw_fd();
+        let files = open_files(std::process::id() as i32);
+        fs::remove_file(&path).unwrap();
+        let open = files.iter().find(|f| f.fd == fd).unwrap();
+        assert_eq!(open.pos, 1024);
+        assert_eq!(open.size, Some(4096));
+        assert_eq!(open.percent(), Some(25.0));
+    }
+
+    #[test]
+    fn test_update_thread_mode() {
+        let processes = [(100, 10), (101, 500)]
+            .into_iter()
+            .map(|(tid, read_bytes)| {
+                let mut thread = ProcessInfo::new(100);
+                thread.tid = tid;
+                thread.stats_delta.read_bytes = read_bytes;
+                (tid, thread)
+            })
+            .collect();
+        let snapshot = ProcessSnapshot {
+            processes,
+            total_io: (510, 0),
+            actual_io: (510, 0),
+            duration: 1.0,
+            devices: Vec::new(),
+            cgroups: Vec::new(),
+            throttled: HashSet::new(),
+            nfs_mounts: Vec::new(),
+            pressure: None,
+            dirty: None,
+            swap: None,
+            iowait: Default::default(),
+            load: None,
+            uninterruptible: 0,
+            files: HashMap::new(),
+            net: HashMap::new(),
+            device_io: HashMap::new(),
+            syncs: HashMap::new(),
+            latency: HashMap::new(),
+            writeback: HashMap::new(),
+        };
+
+        let mut zoom = ZoomView::new(100, false);
+        zoom.update(&snapshot, &RateHistory::default());
+        assert!(zoom.process.is_some());
+        let tids: Vec<i32> = zoom.threads.iter().map(|t| t.tid).collect();
+        assert_eq!(tids, vec![101, 100]);
+        assert_eq!(zoom.threads[0].rates.map(|r| r.read), Some(500.0));
+        // Names are kept until the next sample
+        zoom.names.insert(101, "worker".to_string());
+        zoom.update(&snapshot, &RateHistory::default());
+        assert_eq!(zoom.threads[0].name, "worker");
+        zoom.invalidate();
+        zoom.update(&snapshot, &RateHistory::default());
+        assert_ne!(zoom.threads[0].name, "worker");
+
+        let exited = ProcessSnapshot {
+            processes: HashMap::new(),
+            ..snapshot
+        };
+        zoom.update(&exited, &RateHistory::default());
+        assert!(zoom.process.is_none());
+        assert!(zoom.threads.is_empty());
+    }
+}